@@ -0,0 +1,106 @@
+use aes_gcm::Aes256Gcm;
+#[cfg(feature = "full")]
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::XChaCha20Poly1305;
+use clap::ValueEnum;
+#[cfg(feature = "full")]
+use rand::Rng;
+
+/// AEAD constructions usable instead of crypto_box. The key is the crypto_box
+/// shared key (crypto_box_beforenm), so the same keypairs work for both.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum AeadAlg {
+    /// crypto_aead_xchacha20poly1305_ietf
+    #[value(name = "xchacha20poly1305")]
+    XChaCha20Poly1305,
+    /// crypto_aead_aes256gcm (requires AES-NI / ARMv8 Crypto Extensions)
+    #[value(name = "aes256gcm")]
+    Aes256Gcm,
+}
+
+impl AeadAlg {
+    pub fn nonce_len(self) -> usize {
+        match self {
+            AeadAlg::XChaCha20Poly1305 => 24,
+            AeadAlg::Aes256Gcm => 12,
+        }
+    }
+
+    /// Like libsodium, refuse AES-GCM without hardware support instead of silently
+    /// running a slow, potentially timing-leaky software implementation.
+    fn check_available(self) -> Result<(), String> {
+        match self {
+            AeadAlg::Aes256Gcm if !aes256gcm_is_available() => Err(
+                "AES-256-GCM needs hardware AES support (AES-NI+PCLMULQDQ or ARMv8 Crypto Extensions), \
+                 which this CPU lacks; use --aead xchacha20poly1305 instead"
+                    .to_string(),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Equivalent of crypto_aead_aes256gcm_is_available().
+pub fn aes256gcm_is_available() -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        std::arch::is_x86_feature_detected!("aes") && std::arch::is_x86_feature_detected!("pclmulqdq")
+    }
+    // Unless built with these cfgs in RUSTFLAGS, the aes and polyval crates only have
+    // their software backends on aarch64
+    #[cfg(target_arch = "aarch64")]
+    {
+        cfg!(all(aes_armv8, polyval_armv8)) && std::arch::is_aarch64_feature_detected!("aes")
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        false
+    }
+}
+
+/// Encrypt `msg`, authenticating `aad` as well. Returns nonce || ciphertext || tag.
+#[cfg(feature = "full")]
+pub fn seal(alg: AeadAlg, key: &[u8; 32], msg: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+    alg.check_available()?;
+    let mut combined = vec![0u8; alg.nonce_len()];
+    rand::rng().fill(&mut combined[..]);
+    let ciphertext = match alg {
+        AeadAlg::XChaCha20Poly1305 => {
+            let nonce: [u8; 24] = combined[..].try_into().unwrap();
+            XChaCha20Poly1305::new(key.into()).encrypt(&nonce.into(), Payload { msg, aad })
+        }
+        AeadAlg::Aes256Gcm => {
+            let nonce: [u8; 12] = combined[..].try_into().unwrap();
+            Aes256Gcm::new(key.into()).encrypt(&nonce.into(), Payload { msg, aad })
+        }
+    }
+    .map_err(|_| "Error encrypting data".to_string())?;
+    combined.extend_from_slice(&ciphertext);
+    Ok(combined)
+}
+
+/// Inverse of [`seal`], decrypting in place: returns the part of `combined` holding
+/// the plaintext. Fails if the ciphertext or `aad` was tampered with.
+pub fn open<'a>(alg: AeadAlg, key: &[u8; 32], combined: &'a mut [u8], aad: &[u8]) -> Result<&'a [u8], String> {
+    alg.check_available()?;
+    let nonce_len = alg.nonce_len();
+    if combined.len() < nonce_len + 16 {
+        return Err("Input too short; must contain nonce and ciphertext".to_string());
+    }
+    let (nonce, rest) = combined.split_at_mut(nonce_len);
+    let (msg, tag) = rest.split_at_mut(rest.len() - 16);
+    let tag = (&*tag).into();
+    match alg {
+        AeadAlg::XChaCha20Poly1305 => {
+            let nonce: [u8; 24] = (*nonce).try_into().unwrap();
+            XChaCha20Poly1305::new(key.into()).decrypt_in_place_detached(&nonce.into(), aad, msg, tag)
+        }
+        AeadAlg::Aes256Gcm => {
+            let nonce: [u8; 12] = (*nonce).try_into().unwrap();
+            Aes256Gcm::new(key.into()).decrypt_in_place_detached(&nonce.into(), aad, msg, tag)
+        }
+    }
+    .map_err(|_| "Error decrypting data: authentication failed (wrong key, ciphertext or --aad)".to_string())?;
+    Ok(msg)
+}
@@ -0,0 +1,428 @@
+use crate::cleanup::AtomicFile;
+use crate::container::read_full;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use bech32::Hrp;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::ChaCha20Poly1305;
+use dryoc::classic::crypto_core::{crypto_scalarmult, crypto_scalarmult_base};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+#[cfg(feature = "full")]
+use bech32::Bech32;
+#[cfg(feature = "full")]
+use rand::Rng;
+
+// age v1 files (https://age-encryption.org/v1) with X25519 recipients, readable and
+// writable by age and rage:
+//
+//   age-encryption.org/v1
+//   -> X25519 <ephemeral share>
+//   <file key wrapped for one recipient>
+//   --- <HMAC-SHA256 of the header up to and including "---">
+//   <16-byte nonce> <payload>
+//
+// with one "->" stanza per recipient and unpadded base64 throughout. The random 16-byte
+// file key is sealed with ChaCha20-Poly1305 (zero nonce) under
+// HKDF-SHA256(X25519(ephemeral, recipient), salt: share || recipient, X25519_INFO).
+// The header MAC key is HKDF-SHA256(file key, "header") and the payload key
+// HKDF-SHA256(file key, salt: nonce, "payload"); the payload is 64 KiB chunks sealed
+// with ChaCha20-Poly1305 under an 11-byte big-endian chunk counter followed by 1 for
+// the last chunk and 0 for the others.
+//
+// Recipients are age1... and identities AGE-SECRET-KEY-1... bech32 strings holding the
+// same X25519 keys sodix uses, so a sodix encryption key converts without change.
+// Armored files are the binary file as padded base64 between BEGIN/END AGE ENCRYPTED
+// FILE lines. Stanzas of other types (scrypt, ssh-ed25519) are skipped.
+const VERSION_LINE: &str = "age-encryption.org/v1";
+const X25519_INFO: &[u8] = b"age-encryption.org/v1/X25519";
+const RECIPIENT_HRP: Hrp = Hrp::parse_unchecked("age");
+const IDENTITY_HRP: Hrp = Hrp::parse_unchecked("age-secret-key-");
+const IDENTITY_PREFIX: &str = "AGE-SECRET-KEY-1";
+const ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+const ARMOR_END: &str = "-----END AGE ENCRYPTED FILE-----";
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+const COLUMNS: usize = 64;
+const MAX_HEADER_LINE: u64 = 4096;
+
+/// Whether `s` is an age1... recipient.
+pub fn is_recipient(s: &str) -> bool {
+    s.trim().to_ascii_lowercase().starts_with("age1")
+}
+
+pub fn decode_recipient(s: &str) -> Result<[u8; 32], String> {
+    let (hrp, data) = bech32::decode(s.trim()).map_err(|e| format!("Invalid age recipient: {}", e))?;
+    if hrp != RECIPIENT_HRP {
+        return Err(format!("Unknown age recipient prefix '{}'", hrp));
+    }
+    data.try_into().map_err(|_| "age recipient must be 32 bytes".to_string())
+}
+
+#[cfg(feature = "full")]
+pub fn encode_recipient(public_key: &[u8; 32]) -> String {
+    bech32::encode::<Bech32>(RECIPIENT_HRP, public_key).expect("32-byte keys fit in a bech32 string")
+}
+
+/// Whether `text` is, or is a key file holding, an AGE-SECRET-KEY-1... identity.
+pub fn is_identity(text: &str) -> bool {
+    identity_line(text).is_some()
+}
+
+/// The X25519 secret key of the first identity in `text`; '#' lines are comments, as
+/// age-keygen writes them.
+pub fn decode_identity(text: &str) -> Result<[u8; 32], String> {
+    let line = identity_line(text).ok_or("No AGE-SECRET-KEY-1 identity found")?;
+    let (hrp, data) = bech32::decode(line).map_err(|e| format!("Invalid age identity: {}", e))?;
+    if hrp != IDENTITY_HRP {
+        return Err(format!("Unknown age identity prefix '{}'", hrp));
+    }
+    data.try_into().map_err(|_| "age identity must be 32 bytes".to_string())
+}
+
+fn identity_line(text: &str) -> Option<&str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .find(|line| line.to_ascii_uppercase().starts_with(IDENTITY_PREFIX))
+}
+
+/// An identity file like age-keygen's, with the recipient in a comment.
+#[cfg(feature = "full")]
+pub fn encode_identity(secret_key: &[u8; 32]) -> String {
+    let mut public_key = [0u8; 32];
+    crypto_scalarmult_base(&mut public_key, secret_key);
+    let identity = bech32::encode_upper::<Bech32>(IDENTITY_HRP, secret_key).expect("32-byte keys fit in a bech32 string");
+    format!("# public key: {}\n{}\n", encode_recipient(&public_key), identity)
+}
+
+#[cfg(feature = "full")]
+pub fn encrypt<R: Read, W: Write>(mut reader: R, mut writer: W, recipients: &[[u8; 32]]) -> Result<(), String> {
+    if recipients.is_empty() {
+        return Err("age needs at least one recipient".to_string());
+    }
+    let mut rng = rand::rng();
+    let mut file_key = [0u8; 16];
+    rng.fill(&mut file_key);
+    let mut header = format!("{}\n", VERSION_LINE);
+    for recipient in recipients {
+        let mut ephemeral = [0u8; 32];
+        rng.fill(&mut ephemeral);
+        let mut share = [0u8; 32];
+        crypto_scalarmult_base(&mut share, &ephemeral);
+        let wrap_key = x25519_wrap_key(&ephemeral, recipient, &share, recipient)?;
+        let body = ChaCha20Poly1305::new(&wrap_key.into())
+            .encrypt(&[0u8; 12].into(), &file_key[..])
+            .map_err(|_| "Error wrapping the age file key".to_string())?;
+        header.push_str(&format!("-> X25519 {}\n{}\n", STANDARD_NO_PAD.encode(share), STANDARD_NO_PAD.encode(body)));
+    }
+    header.push_str("---");
+    let mac = header_mac(&file_key, header.as_bytes()).finalize().into_bytes();
+    header.push_str(&format!(" {}\n", STANDARD_NO_PAD.encode(mac)));
+    let mut nonce = [0u8; 16];
+    rng.fill(&mut nonce);
+    let write_error = |e: std::io::Error| format!("Failed to write output: {}", e);
+    writer.write_all(header.as_bytes()).map_err(write_error)?;
+    writer.write_all(&nonce).map_err(write_error)?;
+
+    let cipher = ChaCha20Poly1305::new(&hkdf(&file_key, &nonce, b"payload").into());
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    let mut next = vec![0u8; CHUNK_SIZE];
+    let mut len = read_full(&mut reader, &mut chunk)?;
+    for counter in 0u64.. {
+        // A full chunk is the last one only if nothing follows it
+        let next_len = if len == CHUNK_SIZE { read_full(&mut reader, &mut next)? } else { 0 };
+        let last = next_len == 0;
+        let sealed = cipher
+            .encrypt(&chunk_nonce(counter, last).into(), &chunk[..len])
+            .map_err(|_| "Error encrypting data".to_string())?;
+        writer.write_all(&sealed).map_err(write_error)?;
+        if last {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+    }
+    Ok(())
+}
+
+/// Decrypt a binary age file with the X25519 `secret_key`.
+pub fn decrypt<R: BufRead, W: Write>(mut reader: R, mut writer: W, secret_key: &[u8; 32]) -> Result<(), String> {
+    let mut public_key = [0u8; 32];
+    crypto_scalarmult_base(&mut public_key, secret_key);
+    let mut header = Vec::new();
+    if header_line(&mut reader, &mut header)? != VERSION_LINE {
+        return Err("Not an age v1 file".to_string());
+    }
+    let mut file_key = None;
+    let mac = loop {
+        let line = header_line(&mut reader, &mut header)?;
+        if let Some(mac) = line.strip_prefix("--- ") {
+            break mac.to_string();
+        }
+        let args: Vec<String> = line
+            .strip_prefix("-> ")
+            .ok_or("Malformed age header")?
+            .split(' ')
+            .map(str::to_string)
+            .collect();
+        let mut body = String::new();
+        loop {
+            let line = header_line(&mut reader, &mut header)?;
+            body.push_str(&line);
+            if line.len() < COLUMNS {
+                break;
+            }
+        }
+        if args[0] != "X25519" || file_key.is_some() {
+            continue;
+        }
+        let share = args.get(1).and_then(|share| decode_32(share)).filter(|_| args.len() == 2);
+        let (Some(share), Some(body)) = (share, STANDARD_NO_PAD.decode(&body).ok().filter(|b| b.len() == 32)) else {
+            return Err("Malformed age X25519 stanza".to_string());
+        };
+        let wrap_key = x25519_wrap_key(secret_key, &share, &share, &public_key)?;
+        // A stanza for another recipient fails to open; try the next one
+        if let Ok(key) = ChaCha20Poly1305::new(&wrap_key.into()).decrypt(&[0u8; 12].into(), &body[..]) {
+            file_key = Some(<[u8; 16]>::try_from(key).map_err(|_| "Malformed age X25519 stanza".to_string())?);
+        }
+    };
+    let file_key = file_key.ok_or("This age file is not encrypted to your key")?;
+    // The MAC covers the header up to and including "---"
+    let covered = header.len() - (mac.len() + 2);
+    let mac = STANDARD_NO_PAD.decode(&mac).map_err(|_| "Malformed age header MAC".to_string())?;
+    header_mac(&file_key, &header[..covered])
+        .verify_slice(&mac)
+        .map_err(|_| "age header MAC is invalid".to_string())?;
+
+    let mut nonce = [0u8; 16];
+    reader.read_exact(&mut nonce).map_err(|_| "Truncated age file".to_string())?;
+    let cipher = ChaCha20Poly1305::new(&hkdf(&file_key, &nonce, b"payload").into());
+    let write_error = |e: std::io::Error| format!("Failed to write output: {}", e);
+    let mut chunk = vec![0u8; CHUNK_SIZE + TAG_SIZE];
+    let mut next = vec![0u8; CHUNK_SIZE + TAG_SIZE];
+    let mut len = read_full(&mut reader, &mut chunk)?;
+    for counter in 0u64.. {
+        let next_len = if len == chunk.len() { read_full(&mut reader, &mut next)? } else { 0 };
+        let last = next_len == 0;
+        let plaintext = cipher
+            .decrypt(&chunk_nonce(counter, last).into(), &chunk[..len])
+            .map_err(|_| "age payload is corrupted or truncated".to_string())?;
+        if last && plaintext.is_empty() && counter > 0 {
+            return Err("age payload ends with an empty chunk".to_string());
+        }
+        writer.write_all(&plaintext).map_err(write_error)?;
+        if last {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+    }
+    Ok(())
+}
+
+/// Encrypt `input` to the age file `output`, armored if `armor`.
+#[cfg(feature = "full")]
+pub fn encrypt_file(input: &Path, output: &Path, recipients: &[[u8; 32]], armor: bool) -> Result<(), String> {
+    let reader = File::open(input).map_err(|e| format!("Failed to read input file {}: {}", input.display(), e))?;
+    let mut writer = AtomicFile::create(output).map_err(|e| format!("Failed to write encrypted file {}: {}", output.display(), e))?;
+    if armor {
+        let mut data = Vec::new();
+        encrypt(BufReader::new(reader), &mut data, recipients)?;
+        writer.write_all(self::armor(&data).as_bytes()).map_err(|e| format!("Failed to write encrypted file {}: {}", output.display(), e))?;
+    } else {
+        encrypt(BufReader::new(reader), &mut writer, recipients)?;
+    }
+    writer.commit().map_err(|e| format!("Failed to write encrypted file {}: {}", output.display(), e))
+}
+
+/// Decrypt the binary or armored age file `input` to `output`.
+pub fn decrypt_file(input: &Path, output: &Path, secret_key: &[u8; 32]) -> Result<(), String> {
+    let file = File::open(input).map_err(|e| format!("Failed to read encrypted file {}: {}", input.display(), e))?;
+    let mut reader = BufReader::new(file);
+    let armored = reader
+        .fill_buf()
+        .map_err(|e| format!("Failed to read encrypted file {}: {}", input.display(), e))?
+        .trim_ascii_start()
+        .starts_with(ARMOR_BEGIN.as_bytes());
+    // Never leave unauthenticated partial plaintext behind
+    let mut writer = AtomicFile::create(output).map_err(|e| format!("Failed to write decrypted file {}: {}", output.display(), e))?;
+    if armored {
+        let mut text = String::new();
+        reader.read_to_string(&mut text).map_err(|e| format!("Failed to read encrypted file {}: {}", input.display(), e))?;
+        decrypt(&dearmor(&text)?[..], &mut writer, secret_key)?;
+    } else {
+        decrypt(reader, &mut writer, secret_key)?;
+    }
+    writer.commit().map_err(|e| format!("Failed to write decrypted file {}: {}", output.display(), e))
+}
+
+#[cfg(feature = "full")]
+pub fn armor(data: &[u8]) -> String {
+    let mut out = format!("{}\n", ARMOR_BEGIN);
+    for line in STANDARD.encode(data).as_bytes().chunks(COLUMNS) {
+        out.push_str(std::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    out.push_str(ARMOR_END);
+    out.push('\n');
+    out
+}
+
+pub fn dearmor(text: &str) -> Result<Vec<u8>, String> {
+    let mut lines = text.trim().lines().map(str::trim);
+    if lines.next() != Some(ARMOR_BEGIN) {
+        return Err("Not an armored age file".to_string());
+    }
+    let body: Vec<&str> = lines.by_ref().take_while(|line| *line != ARMOR_END).collect();
+    STANDARD.decode(body.concat()).map_err(|e| format!("Invalid base64 in armored age file: {}", e))
+}
+
+/// The next header line without its newline; the raw line is appended to `header`.
+fn header_line<R: BufRead>(reader: &mut R, header: &mut Vec<u8>) -> Result<String, String> {
+    let start = header.len();
+    reader
+        .take(MAX_HEADER_LINE)
+        .read_until(b'\n', header)
+        .map_err(|e| format!("Failed to read input: {}", e))?;
+    if header.last() != Some(&b'\n') {
+        return Err("Malformed or truncated age header".to_string());
+    }
+    String::from_utf8(header[start..header.len() - 1].to_vec()).map_err(|_| "Malformed age header".to_string())
+}
+
+fn decode_32(encoded: &str) -> Option<[u8; 32]> {
+    STANDARD_NO_PAD.decode(encoded).ok()?.try_into().ok()
+}
+
+/// The key wrapping the file key for `recipient`; `secret` is the ephemeral secret when
+/// encrypting, with `peer` the recipient, and the identity when decrypting, with `peer`
+/// the share.
+fn x25519_wrap_key(secret: &[u8; 32], peer: &[u8; 32], share: &[u8; 32], recipient: &[u8; 32]) -> Result<[u8; 32], String> {
+    let mut shared = [0u8; 32];
+    crypto_scalarmult(&mut shared, secret, peer);
+    if shared == [0u8; 32] {
+        return Err("age X25519 key agreement produced the all-zero secret".to_string());
+    }
+    Ok(hkdf(&shared, &[&share[..], &recipient[..]].concat(), X25519_INFO))
+}
+
+fn hkdf(ikm: &[u8], salt: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut okm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(salt), ikm).expand(info, &mut okm).expect("32 bytes is a valid HKDF-SHA256 length");
+    okm
+}
+
+fn header_mac(file_key: &[u8; 16], header: &[u8]) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&hkdf(file_key, &[], b"header")).expect("HMAC takes any key length");
+    mac.update(header);
+    mac
+}
+
+fn chunk_nonce(counter: u64, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[3..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+#[cfg(all(test, feature = "full"))]
+mod tests {
+    use super::*;
+    use sha2::Digest;
+
+    // The key pair from the X25519 tests of rage, the Rust age implementation
+    const IDENTITY: &str = "AGE-SECRET-KEY-1GQ9778VQXMMJVE8SK7J6VT8UJ4HDQAJUVSFCWCM02D8GEWQ72PVQ2Y5J33";
+    const RECIPIENT: &str = "age1t7rxyev2z3rw82stdlrrepyc39nvn86l5078zqkf5uasdy86jp6svpy7pa";
+
+    // Files from the age testkit (C2SP CCTV): the file key "YELLOW SUBMARINE" wrapped
+    // for TESTKIT_IDENTITY, and a one-chunk payload whose plaintext has the SHA-256
+    // PLAINTEXT_SHA256. Each tampered file is named after its testkit vector.
+    const TESTKIT_IDENTITY: &str = "AGE-SECRET-KEY-1XMWWC06LY3EE5RYTXM9MFLAZ2U56JJJ36S0MYPDRWSVLUL66MV4QX3S7F6";
+    const TESTKIT_OTHER_IDENTITY: &str = "AGE-SECRET-KEY-1EGTZVFFV20835NWYV6270LXYVK2VKNX2MMDKWYKLMGR48UAWX40Q2P2LM0";
+    const X25519_HEADER: &str = "age-encryption.org/v1\n\
+        -> X25519 TEiF0ypqr+bpvcqXNyCVJpL7OuwPdVwPL7KQEbFDOCc\n\
+        EmECAEcKN+n/Vs9SbWiV+Hu0r+E8R77DdWYyd83nw7U\n\
+        --- Vn+54jqiiUCE+WZcEVY3f1sqHjlu/z1LCQ/T7Xm7qI0\n";
+    const X25519_PAYLOAD: &str = "eecf62c7ce91b433274e68d4f2f9134cb74c5bfef7beaa52c8f0bc0e992c1e8331fb66";
+    const PLAINTEXT_SHA256: &str = "013f54400c82da08037759ada907a8b864e97de81c088a182062c4b5622fd2ab";
+    const ARMORED: &str = "-----BEGIN AGE ENCRYPTED FILE-----
+YWdlLWVuY3J5cHRpb24ub3JnL3YxCi0+IFgyNTUxOSBURWlGMHlwcXIrYnB2Y3FY
+TnlDVkpwTDdPdXdQZFZ3UEw3S1FFYkZET0NjCkVtRUNBRWNLTituL1ZzOVNiV2lW
+K0h1MHIrRThSNzdEZFdZeWQ4M253N1UKLS0tIFZuKzU0anFpaVVDRStXWmNFVlkz
+ZjFzcUhqbHUvejFMQ1EvVDdYbTdxSTAK7s9ix86RtDMnTmjU8vkTTLdMW/73vqpS
+yPC8DpksHoMx+2Y=
+-----END AGE ENCRYPTED FILE-----
+";
+    const HMAC_BAD_HEADER: &str = "age-encryption.org/v1\n\
+        -> X25519 TEiF0ypqr+bpvcqXNyCVJpL7OuwPdVwPL7KQEbFDOCc\n\
+        hjabGXwSLQ9c3S6Lw2i+S2Tu2fiwQHHslbBN6B41FLE\n\
+        --- 8McE3ix9R34E/vLrQv3yepsHjo/LXhfs22Ab3UyInmg\n";
+    const STREAM_BAD_TAG_PAYLOAD: &str = "eecf62c7ce91b433274e68d4f2f9134cb74c5bfef7beaa52c8f0bc0e992c1e8331fb46";
+    const X25519_LOW_ORDER_HEADER: &str = "age-encryption.org/v1\n\
+        -> X25519 X5yVvKNQjCSx0LFVnIPvWwREXMRYHI6G2CJO3dCfEdc\n\
+        3E0NpFans/m0WLWF7+54ZBdNj3iqQqpraGDFiaRkvBA\n\
+        --- sXw327YMT1/ULXe+ZyRMbMY0Z2jnWHGgI9j1we6yQ8A\n";
+    const X25519_LOW_ORDER_PAYLOAD: &str = "ac5d3f3706e55071d3a604204697b9090595c2f7f5dbaee80d7a018c2872058af3ce7c";
+
+    fn file(header: &str, payload: &str) -> Vec<u8> {
+        [header.as_bytes(), &hex::decode(payload).unwrap()].concat()
+    }
+
+    fn open(file: &[u8], identity: &str) -> Result<Vec<u8>, String> {
+        let mut plaintext = Vec::new();
+        decrypt(file, &mut plaintext, &decode_identity(identity).unwrap()).map(|()| plaintext)
+    }
+
+    #[test]
+    fn identity_and_recipient_known_answer() {
+        let secret_key = decode_identity(IDENTITY).unwrap();
+        let mut public_key = [0u8; 32];
+        crypto_scalarmult_base(&mut public_key, &secret_key);
+        assert_eq!(encode_recipient(&public_key), RECIPIENT);
+        assert_eq!(decode_recipient(RECIPIENT).unwrap(), public_key);
+        assert_eq!(encode_identity(&secret_key), format!("# public key: {}\n{}\n", RECIPIENT, IDENTITY));
+    }
+
+    #[test]
+    fn testkit_x25519_decrypts() {
+        let plaintext = open(&file(X25519_HEADER, X25519_PAYLOAD), TESTKIT_IDENTITY).unwrap();
+        assert_eq!(hex::encode(Sha256::digest(plaintext)), PLAINTEXT_SHA256);
+    }
+
+    #[test]
+    fn testkit_armor_decrypts() {
+        let data = dearmor(ARMORED).unwrap();
+        assert_eq!(data, file(X25519_HEADER, X25519_PAYLOAD));
+        let plaintext = open(&data, TESTKIT_IDENTITY).unwrap();
+        assert_eq!(hex::encode(Sha256::digest(plaintext)), PLAINTEXT_SHA256);
+    }
+
+    #[test]
+    fn testkit_bad_header_mac_is_refused() {
+        let error = open(&file(HMAC_BAD_HEADER, X25519_PAYLOAD), TESTKIT_OTHER_IDENTITY).unwrap_err();
+        assert_eq!(error, "age header MAC is invalid");
+    }
+
+    #[test]
+    fn testkit_bad_payload_tag_is_refused() {
+        let error = open(&file(X25519_HEADER, STREAM_BAD_TAG_PAYLOAD), TESTKIT_IDENTITY).unwrap_err();
+        assert_eq!(error, "age payload is corrupted or truncated");
+    }
+
+    #[test]
+    fn testkit_low_order_share_is_refused() {
+        let error = open(&file(X25519_LOW_ORDER_HEADER, X25519_LOW_ORDER_PAYLOAD), TESTKIT_OTHER_IDENTITY).unwrap_err();
+        assert_eq!(error, "age X25519 key agreement produced the all-zero secret");
+    }
+
+    #[test]
+    fn other_identity_is_refused() {
+        let error = open(&file(X25519_HEADER, X25519_PAYLOAD), IDENTITY).unwrap_err();
+        assert_eq!(error, "This age file is not encrypted to your key");
+    }
+}
@@ -0,0 +1,93 @@
+use crate::sshsig::{self, Wire};
+use std::io::{Read, Write};
+use std::path::Path;
+
+// Signing with an Ed25519 key held by ssh-agent (sign --use-agent), so the secret key
+// never has to be on disk. Messages on $SSH_AUTH_SOCK (draft-miller-ssh-agent) are
+//
+//   u32 length | u8 type | contents
+//
+//   11 REQUEST_IDENTITIES                             -> 12 IDENTITIES_ANSWER:
+//        u32 count | (string key blob | string comment)*
+//   13 SIGN_REQUEST: string key blob | string data | u32 flags -> 14 SIGN_RESPONSE:
+//        string signature blob ("ssh-ed25519" | string signature)
+//
+// and 5 FAILURE for a refused request. An ssh-ed25519 signature of the data is a plain
+// Ed25519 signature, the same crypto_sign_detached makes with the secret key.
+const FAILURE: u8 = 5;
+const REQUEST_IDENTITIES: u8 = 11;
+const IDENTITIES_ANSWER: u8 = 12;
+const SIGN_REQUEST: u8 = 13;
+const SIGN_RESPONSE: u8 = 14;
+const MAX_MESSAGE: usize = 256 * 1024;
+
+trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+pub struct Agent {
+    stream: Box<dyn Stream>,
+}
+
+impl Agent {
+    pub fn connect() -> Result<Self, String> {
+        let socket = std::env::var_os("SSH_AUTH_SOCK").ok_or("SSH_AUTH_SOCK is not set; start ssh-agent and add the key with ssh-add")?;
+        #[cfg(unix)]
+        let stream = std::os::unix::net::UnixStream::connect(&socket);
+        // Windows' OpenSSH agent listens on a named pipe
+        #[cfg(not(unix))]
+        let stream = std::fs::OpenOptions::new().read(true).write(true).open(&socket);
+        let stream = stream.map_err(|e| format!("Failed to connect to ssh-agent at {}: {}", Path::new(&socket).display(), e))?;
+        Ok(Agent { stream: Box::new(stream) })
+    }
+
+    /// The agent's ssh-ed25519 keys with their comments; other key types are left out.
+    pub fn keys(&mut self) -> Result<Vec<([u8; 32], String)>, String> {
+        let answer = self.request(REQUEST_IDENTITIES, &[], IDENTITIES_ANSWER)?;
+        let mut wire = Wire(&answer);
+        let mut keys = Vec::new();
+        for _ in 0..wire.u32()? {
+            let blob = wire.string()?;
+            let comment = String::from_utf8_lossy(wire.string()?).into_owned();
+            if let Ok(public_key) = sshsig::parse_key_blob(blob) {
+                keys.push((public_key, comment));
+            }
+        }
+        Ok(keys)
+    }
+
+    pub fn sign(&mut self, public_key: &[u8; 32], data: &[u8]) -> Result<[u8; 64], String> {
+        let mut request = Vec::new();
+        sshsig::put_string(&mut request, &sshsig::key_blob(public_key));
+        sshsig::put_string(&mut request, data);
+        request.extend_from_slice(&0u32.to_be_bytes());
+        let response = self.request(SIGN_REQUEST, &request, SIGN_RESPONSE)?;
+        let mut signature = Wire(Wire(&response).string()?);
+        if signature.string()? != b"ssh-ed25519" {
+            return Err("ssh-agent answered with a signature that is not ssh-ed25519".to_string());
+        }
+        signature.string()?.try_into().map_err(|_| "Invalid ssh-ed25519 signature from ssh-agent".to_string())
+    }
+
+    fn request(&mut self, kind: u8, contents: &[u8], expected: u8) -> Result<Vec<u8>, String> {
+        let io_error = |e: std::io::Error| format!("ssh-agent connection failed: {}", e);
+        let len = u32::try_from(contents.len() + 1).map_err(|_| "Message too large for ssh-agent".to_string())?;
+        let mut message = len.to_be_bytes().to_vec();
+        message.push(kind);
+        message.extend_from_slice(contents);
+        self.stream.write_all(&message).map_err(io_error)?;
+        let mut len = [0u8; 4];
+        self.stream.read_exact(&mut len).map_err(io_error)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 || len > MAX_MESSAGE {
+            return Err("Invalid message from ssh-agent".to_string());
+        }
+        let mut reply = vec![0u8; len];
+        self.stream.read_exact(&mut reply).map_err(io_error)?;
+        match reply[0] {
+            k if k == expected => Ok(reply.split_off(1)),
+            FAILURE if kind == SIGN_REQUEST => Err("ssh-agent refused to sign (key not added, or confirmation denied)".to_string()),
+            FAILURE => Err("ssh-agent refused the request".to_string()),
+            k => Err(format!("Unexpected ssh-agent message type {}", k)),
+        }
+    }
+}
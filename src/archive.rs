@@ -0,0 +1,140 @@
+use crate::cleanup::AtomicFile;
+use crate::container::{Metadata, PlaintextReader};
+use crate::progress::Progress;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+use tar::{Archive, EntryType};
+
+// Tar archives inside .x containers. The container decrypts by chunk, so an
+// uncompressed archive is walked header by header, seeking over file contents
+// without decrypting them. A compressed payload has no usable offsets and is read
+// through the decompressor front to back, though still never written out whole.
+
+/// One member of an archive, as `tar tv` would show it.
+pub struct Entry {
+    pub path: String,
+    pub kind: EntryType,
+    pub mode: u32,
+    pub size: u64,
+    pub mtime: u64,
+    pub link: Option<String>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            EntryType::Directory => 'd',
+            EntryType::Symlink => 'l',
+            EntryType::Link => 'h',
+            EntryType::Char => 'c',
+            EntryType::Block => 'b',
+            EntryType::Fifo => 'p',
+            t if t.is_file() => '-',
+            _ => '?',
+        };
+        let mut mode = String::with_capacity(9);
+        for shift in [6, 3, 0] {
+            let bits = self.mode >> shift;
+            mode.push(if bits & 4 != 0 { 'r' } else { '-' });
+            mode.push(if bits & 2 != 0 { 'w' } else { '-' });
+            mode.push(if bits & 1 != 0 { 'x' } else { '-' });
+        }
+        let mtime = humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(self.mtime));
+        write!(f, "{}{} {:>12} {} {}", kind, mode, self.size, mtime, self.path)?;
+        match &self.link {
+            Some(target) if self.kind == EntryType::Symlink => write!(f, " -> {}", target),
+            Some(target) => write!(f, " link to {}", target),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The members of the tar archive in a container, in archive order.
+pub fn list(reader: PlaintextReader) -> Result<Vec<Entry>, String> {
+    if reader.is_compressed() {
+        let mut archive = Archive::new(reader.into_decompressed()?);
+        let entries = archive.entries().map_err(archive_error)?;
+        return collect(entries);
+    }
+    let mut archive = Archive::new(reader);
+    let entries = archive.entries_with_seek().map_err(archive_error)?;
+    collect(entries)
+}
+
+/// Write the regular file `member` of the archive to `output`, decrypting only the
+/// chunks it spans (plus the headers before it), with its mode and mtime if
+/// `preserve`. Returns its size.
+pub fn extract(reader: PlaintextReader, member: &str, output: &Path, preserve: bool) -> Result<u64, String> {
+    if reader.is_compressed() {
+        let mut archive = Archive::new(reader.into_decompressed()?);
+        let entries = archive.entries().map_err(archive_error)?;
+        return extract_from(entries, member, output, preserve);
+    }
+    let mut archive = Archive::new(reader);
+    let entries = archive.entries_with_seek().map_err(archive_error)?;
+    extract_from(entries, member, output, preserve)
+}
+
+fn extract_from<R: Read>(entries: tar::Entries<R>, member: &str, output: &Path, preserve: bool) -> Result<u64, String> {
+    let wanted = normalize(member);
+    for entry in entries {
+        let mut entry = entry.map_err(archive_error)?;
+        if normalize(&entry.path().map_err(archive_error)?.to_string_lossy()) != wanted {
+            continue;
+        }
+        if !entry.header().entry_type().is_file() {
+            return Err(format!("{} is not a regular file in the archive", member));
+        }
+        let mut writer = AtomicFile::create(output)
+            .map_err(|e| format!("Failed to write extracted file {}: {}", output.display(), e))?;
+        // Chunks are authenticated as they are read, but a failure halfway would
+        // leave a truncated file, so it only takes the output name once complete
+        let progress = Progress::start("extract", output, Some(entry.size()));
+        let size = io::copy(&mut progress.reader(&mut entry), &mut writer).map_err(|e| format!("Failed to extract {}: {}", member, e))?;
+        writer.commit().map_err(|e| format!("Failed to write extracted file {}: {}", output.display(), e))?;
+        progress.finish();
+        if preserve {
+            let header = entry.header();
+            let metadata = Metadata {
+                mode: header.mode().map_err(archive_error)?,
+                mtime: UNIX_EPOCH + Duration::from_secs(header.mtime().map_err(archive_error)?),
+                symlink: None,
+            };
+            metadata.restore(output)?;
+        }
+        return Ok(size);
+    }
+    Err(format!("{} is not in the archive", member))
+}
+
+/// Archive paths compare without a leading "./" or trailing "/".
+fn normalize(path: &str) -> &str {
+    let mut path = path.trim_end_matches('/');
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    path
+}
+
+fn collect<R: Read>(entries: tar::Entries<R>) -> Result<Vec<Entry>, String> {
+    let mut listing = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(archive_error)?;
+        let header = entry.header();
+        listing.push(Entry {
+            path: entry.path().map_err(archive_error)?.to_string_lossy().into_owned(),
+            kind: header.entry_type(),
+            mode: header.mode().map_err(archive_error)?,
+            size: entry.size(),
+            mtime: header.mtime().map_err(archive_error)?,
+            link: entry.link_name().map_err(archive_error)?.map(|l| l.to_string_lossy().into_owned()),
+        });
+    }
+    Ok(listing)
+}
+
+fn archive_error(e: io::Error) -> String {
+    format!("Failed to read the archive: {}", e)
+}
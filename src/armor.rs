@@ -0,0 +1,119 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+// PEM-style ASCII armor (--armor) for keys, signatures and ciphertexts, which survives
+// mail and ticketing systems that rewrap or mangle long hex lines:
+//
+//   -----BEGIN SODIX SIGNATURE-----
+//   Version: sodix 0.1.2
+//   Key-Fingerprint: blake2b:<hex>
+//
+//   <base64, 64 columns>
+//   -----END SODIX SIGNATURE-----
+//
+// The headers are informational only; nothing in them is authenticated. Text that starts
+// with a BEGIN SODIX line is de-armored wherever sodix reads hex.
+const BEGIN_PREFIX: &str = "-----BEGIN SODIX ";
+const END_PREFIX: &str = "-----END SODIX ";
+const DASHES: &str = "-----";
+
+pub const SIGNATURE: &str = "SIGNATURE";
+pub const MESSAGE: &str = "MESSAGE";
+#[cfg(feature = "full")]
+pub const SIGNING_PUBLIC_KEY: &str = "SIGNING PUBLIC KEY";
+#[cfg(feature = "full")]
+pub const SIGNING_SECRET_KEY: &str = "SIGNING SECRET KEY";
+#[cfg(feature = "full")]
+pub const ENCRYPTION_PUBLIC_KEY: &str = "ENCRYPTION PUBLIC KEY";
+#[cfg(feature = "full")]
+pub const ENCRYPTION_SECRET_KEY: &str = "ENCRYPTION SECRET KEY";
+
+/// `data` armored as a `label` block, ending in a newline.
+#[cfg(feature = "full")]
+pub fn armor(label: &str, headers: &[(&str, String)], data: &[u8]) -> String {
+    let mut out = format!("{}{}{}\nVersion: sodix {}\n", BEGIN_PREFIX, label, DASHES, env!("CARGO_PKG_VERSION"));
+    for (name, value) in headers {
+        out.push_str(&format!("{}: {}\n", name, value));
+    }
+    out.push('\n');
+    let encoded = STANDARD.encode(data);
+    for line in encoded.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+    out.push_str(&format!("{}{}{}\n", END_PREFIX, label, DASHES));
+    out
+}
+
+pub fn is_armored(text: &str) -> bool {
+    text.trim_start().starts_with(BEGIN_PREFIX)
+}
+
+/// Every armored block in `text` as (label, data).
+pub fn dearmor_all(text: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut blocks = Vec::new();
+    let mut lines = text.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        let Some(label) = line.strip_prefix(BEGIN_PREFIX).and_then(|l| l.strip_suffix(DASHES)) else {
+            continue;
+        };
+        let end = format!("{}{}{}", END_PREFIX, label, DASHES);
+        let mut body = String::new();
+        let mut in_headers = true;
+        let mut closed = false;
+        for line in lines.by_ref() {
+            if line == end {
+                closed = true;
+                break;
+            }
+            if in_headers && line.contains(": ") {
+                continue;
+            }
+            in_headers = false;
+            body.push_str(line);
+        }
+        if !closed {
+            return Err(format!("Armored {} is missing its END line", label.to_lowercase()));
+        }
+        let data = STANDARD
+            .decode(body)
+            .map_err(|e| format!("Invalid base64 in armored {}: {}", label.to_lowercase(), e))?;
+        blocks.push((label.to_string(), data));
+    }
+    if blocks.is_empty() {
+        return Err("No armored block found".to_string());
+    }
+    Ok(blocks)
+}
+
+/// The data of every `label` block in `text`; other blocks are an error.
+pub fn dearmor(text: &str, label: &str) -> Result<Vec<Vec<u8>>, String> {
+    dearmor_all(text)?
+        .into_iter()
+        .map(|(found, data)| {
+            if found == label {
+                Ok(data)
+            } else {
+                Err(format!("Expected an armored {}, found a {}", label.to_lowercase(), found.to_lowercase()))
+            }
+        })
+        .collect()
+}
+
+/// The data of the one `label` block in `text`.
+pub fn dearmor_one(text: &str, label: &str) -> Result<Vec<u8>, String> {
+    let mut blocks = dearmor(text, label)?;
+    if blocks.len() > 1 {
+        return Err(format!("Expected one armored {}, found several", label.to_lowercase()));
+    }
+    Ok(blocks.remove(0))
+}
+
+/// The data of an armored key of any kind.
+pub fn dearmor_key(text: &str) -> Result<Vec<u8>, String> {
+    match dearmor_all(text)?.as_slice() {
+        [(label, data)] if label.ends_with(" KEY") => Ok(data.clone()),
+        [(label, _)] => Err(format!("Expected an armored key, found a {}", label.to_lowercase())),
+        _ => Err("Expected one armored key, found several".to_string()),
+    }
+}
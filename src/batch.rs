@@ -0,0 +1,128 @@
+use crate::bech32key::KeyKind;
+use crate::envelope::Envelope;
+use ed25519_dalek::{Signature, VerifyingKey};
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Manifest for `check --batch`, one item per line, '#' starts a comment:
+//
+//   <file> <signature: hex, or path to a .sig file> [<public key: hex, bech32, did:key or path>]
+//
+// Relative paths are resolved against the manifest's directory. Items without a
+// key use the key given on the command line.
+
+// Items verified together in one batch equation. A group that fails is re-checked
+// one by one, so a single bad signature costs at most one extra group.
+const GROUP_SIZE: usize = 64;
+
+pub struct Item {
+    pub file: PathBuf,
+    signature: Vec<u8>,
+    key: [u8; 32],
+}
+
+pub fn read_manifest(path: &Path, default_key: Option<[u8; 32]>) -> Result<Vec<Item>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read manifest {}: {}", path.display(), e))?;
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut items = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = |what: String| format!("{} line {}: {}", path.display(), i + 1, what);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (file, signature, key) = match fields.as_slice() {
+            [file, signature] => (file, signature, None),
+            [file, signature, key] => (file, signature, Some(key)),
+            _ => return Err(invalid("expected <file> <signature> [<public key>]".to_string())),
+        };
+        let signature = match hex::decode(signature) {
+            Ok(sig) => sig,
+            Err(_) => {
+                let sig_path = base.join(signature);
+                let sig_hex = fs::read_to_string(&sig_path)
+                    .map_err(|e| invalid(format!("Failed to read signature from {}: {}", sig_path.display(), e)))?;
+                hex::decode(sig_hex.trim()).map_err(|e| invalid(format!("Invalid hex signature in {}: {}", sig_path.display(), e)))?
+            }
+        };
+        let key = match key {
+            Some(key) if crate::is_text_key(key) => crate::decode_text_key(key, Some(KeyKind::Signing)).map_err(invalid)?,
+            Some(key) => match hex::decode(key) {
+                Ok(bytes) if bytes.len() == 32 => bytes.try_into().unwrap(),
+                _ => crate::load_key(&base.join(key), 32).map_err(invalid)?.as_slice().try_into().unwrap(),
+            },
+            None => default_key.ok_or_else(|| invalid("no public key given".to_string()))?,
+        };
+        items.push(Item { file: base.join(file), signature, key });
+    }
+    Ok(items)
+}
+
+/// Verify every item, in parallel, returning the results in manifest order. A group
+/// that passes the batch equation is accepted as a whole; its rules differ from a
+/// single check only for signatures with small-order components, which the signer
+/// (not a forger) could produce, and only for messages the key really signed.
+pub fn verify_all(items: &[Item]) -> Vec<Result<(), String>> {
+    items.par_chunks(GROUP_SIZE).flat_map_iter(verify_group).collect()
+}
+
+/// A plain Ed25519 signature ready for batch verification.
+struct Batchable {
+    data: Vec<u8>,
+    signature: Signature,
+    key: VerifyingKey,
+}
+
+fn verify_group(items: &[Item]) -> Vec<Result<(), String>> {
+    let mut results: Vec<Result<(), String>> = Vec::with_capacity(items.len());
+    let mut batch = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        match prepare(item) {
+            Ok(Some(b)) => {
+                batch.push((index, b));
+                results.push(Ok(()));
+            }
+            Ok(None) => results.push(Ok(())),
+            Err(e) => results.push(Err(e)),
+        }
+    }
+    if batch.is_empty() {
+        return results;
+    }
+
+    let messages: Vec<&[u8]> = batch.iter().map(|(_, b)| b.data.as_slice()).collect();
+    let signatures: Vec<Signature> = batch.iter().map(|(_, b)| b.signature).collect();
+    let keys: Vec<VerifyingKey> = batch.iter().map(|(_, b)| b.key).collect();
+    if ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_err() {
+        // Find the culprits with the same strict check as a plain `check`
+        for (index, b) in &batch {
+            results[*index] = crate::verify_signature(&b.signature.to_bytes(), &b.data, b.key.as_bytes());
+        }
+    }
+    results
+}
+
+/// Load an item's file. Returns `None` when the item was already verified on its own
+/// (prehashed envelopes are streamed rather than batched).
+fn prepare(item: &Item) -> Result<Option<Batchable>, String> {
+    let (signature, key) = if Envelope::is_envelope(&item.signature) {
+        let env = Envelope::decode(&item.signature)?;
+        if env.prehashed {
+            crate::verify_file_signature(&item.signature, &item.file, &item.key)?;
+            return Ok(None);
+        }
+        let signer = crate::envelope_signer(&env, &item.key)?;
+        env.verify_attributes(&signer)?;
+        (env.signature, signer)
+    } else {
+        let sig: [u8; 64] = item.signature.as_slice().try_into().map_err(|_| "Signature must be 64 bytes")?;
+        (sig, item.key)
+    };
+    let key = VerifyingKey::from_bytes(&key).map_err(|e| format!("Invalid public key: {}", e))?;
+    let data = fs::read(&item.file)
+        .map_err(|e| format!("Failed to read input file {}: {}", item.file.display(), e))?;
+    Ok(Some(Batchable { data, signature: Signature::from_bytes(&signature), key }))
+}
@@ -0,0 +1,63 @@
+use bech32::Hrp;
+#[cfg(feature = "full")]
+use bech32::Bech32;
+
+// Public keys as bech32 strings (BIP 173), like age recipients:
+//
+//   sodix1...       X25519 encryption public key
+//   sodixsign1...   Ed25519 signing public key
+//
+// The checksum catches mistyped or swapped characters in keys copied by hand, and the
+// prefix keeps the two kinds apart. Hex keys stay accepted everywhere.
+const ENCRYPTION_HRP: Hrp = Hrp::parse_unchecked("sodix");
+const SIGNING_HRP: Hrp = Hrp::parse_unchecked("sodixsign");
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum KeyKind {
+    Encryption,
+    Signing,
+}
+
+impl KeyKind {
+    #[cfg(feature = "full")]
+    fn hrp(self) -> Hrp {
+        match self {
+            KeyKind::Encryption => ENCRYPTION_HRP,
+            KeyKind::Signing => SIGNING_HRP,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyKind::Encryption => "an encryption key",
+            KeyKind::Signing => "a signing key",
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+pub fn encode(kind: KeyKind, public_key: &[u8]) -> String {
+    bech32::encode::<Bech32>(kind.hrp(), public_key).expect("32-byte keys fit in a bech32 string")
+}
+
+/// Whether `s` looks like a bech32 sodix key rather than hex or a path.
+pub fn is_bech32(s: &str) -> bool {
+    let s = s.trim().to_ascii_lowercase();
+    [ENCRYPTION_HRP, SIGNING_HRP].iter().any(|hrp| s.starts_with(&format!("{}1", hrp.as_str())))
+}
+
+/// Decode a bech32 public key; `expected` rejects keys of the other kind.
+pub fn decode(s: &str, expected: Option<KeyKind>) -> Result<[u8; 32], String> {
+    let (hrp, data) = bech32::decode(s.trim()).map_err(|e| format!("Invalid bech32 key: {}", e))?;
+    let kind = if hrp == ENCRYPTION_HRP {
+        KeyKind::Encryption
+    } else if hrp == SIGNING_HRP {
+        KeyKind::Signing
+    } else {
+        return Err(format!("Unknown bech32 key prefix '{}'", hrp));
+    };
+    if let Some(expected) = expected.filter(|&e| e != kind) {
+        return Err(format!("Expected {}, got {}", expected.name(), kind.name()));
+    }
+    data.try_into().map_err(|_| "Public key must be 32 bytes".to_string())
+}
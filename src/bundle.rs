@@ -0,0 +1,98 @@
+use crate::keyfile;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// Passphrase-encrypted archive of key directory files (key export / key import), for
+// moving keys, identities and contacts to another machine:
+//
+//   "sodix-key-bundle 1\n" | keyfile::seal(entries)
+//
+// where entries are, one after another:
+//
+//   path length u32 BE | path (UTF-8, '/'-separated, relative) | data length u64 BE | data
+//
+// Directories are stored as the files in them. Paths that would leave the key directory
+// are rejected on import.
+const MAGIC: &[u8] = b"sodix-key-bundle 1\n";
+
+pub struct Entry {
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+/// The entries for `names` (files or directories) in `dir`; missing names are skipped.
+pub fn collect(dir: &Path, names: &[&str]) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for name in names {
+        add(&dir.join(name), name.to_string(), &mut entries)?;
+    }
+    Ok(entries)
+}
+
+fn add(path: &Path, name: String, entries: &mut Vec<Entry>) -> Result<(), String> {
+    if path.is_dir() {
+        let mut children: Vec<_> = fs::read_dir(path)
+            .map_err(|e| format!("Failed to read directory {}: {}", path.display(), e))?
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Failed to read directory {}: {}", path.display(), e))?;
+        children.sort_by_key(|c| c.file_name());
+        for child in children {
+            add(&child.path(), format!("{}/{}", name, child.file_name().to_string_lossy()), entries)?;
+        }
+    } else if path.is_file() {
+        let data = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        entries.push(Entry { path: name, data });
+    }
+    Ok(())
+}
+
+pub fn seal(entries: &[Entry], passphrase: &str) -> Result<Vec<u8>, String> {
+    let mut plain = Vec::new();
+    for entry in entries {
+        plain.extend_from_slice(&(entry.path.len() as u32).to_be_bytes());
+        plain.extend_from_slice(entry.path.as_bytes());
+        plain.extend_from_slice(&(entry.data.len() as u64).to_be_bytes());
+        plain.extend_from_slice(&entry.data);
+    }
+    let mut bundle = MAGIC.to_vec();
+    bundle.extend(keyfile::seal(&plain, passphrase)?);
+    Ok(bundle)
+}
+
+pub fn is_bundle(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn open(bundle: &[u8], passphrase: &str) -> Result<Vec<Entry>, String> {
+    let payload = bundle.strip_prefix(MAGIC).ok_or("Not a sodix key bundle")?;
+    let plain = keyfile::open(payload, passphrase).map_err(|_| "Wrong passphrase or corrupt key bundle".to_string())?;
+    let mut pos = 0usize;
+    let mut take = |n: usize| {
+        let field = plain.get(pos..pos.checked_add(n)?)?;
+        pos += n;
+        Some(field)
+    };
+    let mut entries = Vec::new();
+    while let Some(len) = take(4) {
+        let truncated = || "Truncated key bundle".to_string();
+        let path_len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+        let path = String::from_utf8(take(path_len).ok_or_else(truncated)?.to_vec())
+            .map_err(|_| "Invalid path in key bundle".to_string())?;
+        let data_len = u64::from_be_bytes(take(8).ok_or_else(truncated)?.try_into().unwrap()) as usize;
+        let data = take(data_len).ok_or_else(truncated)?.to_vec();
+        entries.push(Entry { path, data });
+    }
+    if pos != plain.len() {
+        return Err("Truncated key bundle".to_string());
+    }
+    Ok(entries)
+}
+
+/// Where `entry` goes under `dir`, refusing absolute paths and `..`.
+pub fn destination(dir: &Path, entry: &Entry) -> Result<PathBuf, String> {
+    let relative = Path::new(&entry.path);
+    if entry.path.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(format!("Unsafe path '{}' in key bundle", entry.path));
+    }
+    Ok(dir.join(relative))
+}
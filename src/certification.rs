@@ -0,0 +1,107 @@
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+use std::fs;
+use std::path::Path;
+
+// A statement by one key (the certifier) that a public key belongs to a name:
+//
+//   sodix-certification 1
+//   key <hex public key>
+//   name <name>
+//   certified <unix seconds>
+//   certifier <hex signing public key>
+//   signature <hex Ed25519 signature over every line above>
+//
+// `check --require-certified` and `encrypt --require-certified` accept a key only if
+// a certification of it, by sign_public.key or a key in introducers/, is in
+// certifications/ in the key directory.
+const HEADER: &str = "sodix-certification 1";
+const SIGNATURE_PREFIX: &str = "signature ";
+
+pub struct Certification {
+    pub key: [u8; 32],
+    pub name: String,
+    pub certified: u64,
+    pub certifier: [u8; 32],
+}
+
+/// Certify `key` as `name` with the signing key `sk`.
+#[cfg(feature = "full")]
+pub fn create(key: &[u8; 32], name: &str, certified: u64, sk: &[u8; 64]) -> Result<String, String> {
+    if name.is_empty() || name.contains('\n') {
+        return Err("The name must be a single non-empty line".to_string());
+    }
+    let mut text = format!(
+        "{}\nkey {}\nname {}\ncertified {}\ncertifier {}\n",
+        HEADER,
+        hex::encode(key),
+        name,
+        certified,
+        hex::encode(&sk[32..])
+    );
+    let mut signature = [0u8; 64];
+    crypto_sign_detached(&mut signature, text.as_bytes(), sk)
+        .map_err(|e| format!("Error signing certification: {}", e))?;
+    text.push_str(&format!("{}{}\n", SIGNATURE_PREFIX, hex::encode(signature)));
+    Ok(text)
+}
+
+/// Parse a certification and check its certifier's signature.
+pub fn verify(text: &str) -> Result<Certification, String> {
+    let body_len = text
+        .rfind(&format!("\n{}", SIGNATURE_PREFIX))
+        .map(|i| i + 1)
+        .ok_or("Certification is not signed")?;
+    let (body, signature_line) = text.split_at(body_len);
+    let mut lines = body.lines();
+    if lines.next() != Some(HEADER) {
+        return Err("Not a sodix certification".to_string());
+    }
+    let mut field = |name: &str| {
+        lines
+            .next()
+            .and_then(|l| l.strip_prefix(name))
+            .and_then(|l| l.strip_prefix(' '))
+            .ok_or_else(|| format!("Certification is missing '{}'", name))
+    };
+    let key = decode_key(field("key")?)?;
+    let name = field("name")?.to_string();
+    let certified = field("certified")?.parse().map_err(|_| "Invalid certification time".to_string())?;
+    let certifier = decode_key(field("certifier")?)?;
+    let signature: [u8; 64] = hex::decode(signature_line[SIGNATURE_PREFIX.len()..].trim())
+        .ok()
+        .and_then(|s| s.try_into().ok())
+        .ok_or("Invalid certification signature")?;
+    crypto_sign_verify_detached(&signature, body.as_bytes(), &certifier)
+        .map_err(|e| format!("Certification signature is invalid: {}", e))?;
+    Ok(Certification { key, name, certified, certifier })
+}
+
+/// A valid certification of `key` by one of `introducers` among the files in `dir`.
+/// Files that are not valid certifications are skipped.
+pub fn find(dir: &Path, key: &[u8; 32], introducers: &[[u8; 32]]) -> Result<Option<Certification>, String> {
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?.path();
+        let Ok(text) = fs::read_to_string(&path) else {
+            continue;
+        };
+        if let Ok(cert) = verify(&text) {
+            if cert.key == *key && introducers.contains(&cert.certifier) {
+                return Ok(Some(cert));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn decode_key(hex_key: &str) -> Result<[u8; 32], String> {
+    hex::decode(hex_key)
+        .ok()
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| "Invalid key in certification".to_string())
+}
@@ -0,0 +1,278 @@
+use crate::ignore::Ignore;
+use crate::mmap;
+use dryoc::classic::crypto_generichash::{crypto_generichash, crypto_generichash_final, crypto_generichash_init, crypto_generichash_update};
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Signed release channel metadata, kept next to the releases it describes:
+//
+//   sodix-channel 1
+//   version 7
+//   published 1760000000
+//   expires 1762592000
+//   previous <BLAKE2b-256 of the version 6 metadata, or "none">
+//   file <BLAKE2b-256> <size> <path relative to the channel directory>
+//
+// channel.sig holds the hex signature over channel.meta. Clients remember the last
+// version they accepted per signing key, so an older (rollback) or stale (freeze)
+// snapshot is rejected even though its signature is valid.
+pub const META_FILE: &str = "channel.meta";
+pub const SIG_FILE: &str = "channel.sig";
+const HEADER: &str = "sodix-channel 1";
+
+struct Metadata {
+    version: u64,
+    published: u64,
+    expires: u64,
+    previous: Option<[u8; 32]>,
+    files: Vec<(String, u64, [u8; 32])>,
+}
+
+impl Metadata {
+    #[cfg(feature = "full")]
+    fn to_text(&self) -> String {
+        let mut out = format!(
+            "{}\nversion {}\npublished {}\nexpires {}\nprevious {}\n",
+            HEADER,
+            self.version,
+            self.published,
+            self.expires,
+            self.previous.map(hex::encode).unwrap_or_else(|| "none".to_string())
+        );
+        for (path, size, hash) in &self.files {
+            out.push_str(&format!("file {} {} {}\n", hex::encode(hash), size, path));
+        }
+        out
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err("Not a sodix channel metadata file".to_string());
+        }
+        let mut field = |name: &str| -> Result<String, String> {
+            lines
+                .next()
+                .and_then(|l| l.strip_prefix(name))
+                .and_then(|v| v.strip_prefix(' '))
+                .map(str::to_string)
+                .ok_or_else(|| format!("Channel metadata is missing '{}'", name))
+        };
+        let number = |name: &str, v: String| v.parse::<u64>().map_err(|e| format!("Invalid channel {} '{}': {}", name, v, e));
+        let version = number("version", field("version")?)?;
+        let published = number("published", field("published")?)?;
+        let expires = number("expires", field("expires")?)?;
+        let previous = match field("previous")?.as_str() {
+            "none" => None,
+            h => Some(parse_hash(h)?),
+        };
+        let mut files = Vec::new();
+        for line in lines {
+            let mut parts = line.splitn(4, ' ');
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some("file"), Some(hash), Some(size), Some(path)) => {
+                    let size = number("file size", size.to_string())?;
+                    files.push((path.to_string(), size, parse_hash(hash)?));
+                }
+                _ => return Err(format!("Invalid channel metadata line '{}'", line)),
+            }
+        }
+        Ok(Metadata { version, published, expires, previous, files })
+    }
+}
+
+/// Write the next version of the channel metadata for `dir` and sign it with `sk`.
+/// Returns the new version number.
+#[cfg(feature = "full")]
+pub fn publish(dir: &Path, sk: &[u8; 64], validity_secs: u64, now: u64) -> Result<u64, String> {
+    let meta_path = dir.join(META_FILE);
+    let (version, previous) = match fs::read(&meta_path) {
+        Ok(old) => {
+            let old_meta = Metadata::parse(&String::from_utf8_lossy(&old))
+                .map_err(|e| format!("{}: {}", meta_path.display(), e))?;
+            (old_meta.version + 1, Some(hash_bytes(&old)))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (1, None),
+        Err(e) => return Err(format!("Failed to read channel metadata {}: {}", meta_path.display(), e)),
+    };
+
+    let mut files = Vec::new();
+    for path in list_files(dir)? {
+        let rel = relative_name(dir, &path)?;
+        if rel == META_FILE || rel == SIG_FILE {
+            continue;
+        }
+        let (size, hash) = hash_file(&path)?;
+        files.push((rel, size, hash));
+    }
+    files.sort();
+
+    let meta = Metadata { version, published: now, expires: now + validity_secs, previous, files };
+    let text = meta.to_text();
+    let mut signature = [0u8; 64];
+    crypto_sign_detached(&mut signature, text.as_bytes(), sk)
+        .map_err(|e| format!("Error signing data: {}", e))?;
+    fs::write(&meta_path, &text)
+        .map_err(|e| format!("Failed to write channel metadata {}: {}", meta_path.display(), e))?;
+    let sig_path = dir.join(SIG_FILE);
+    fs::write(&sig_path, hex::encode(signature))
+        .map_err(|e| format!("Failed to write channel signature {}: {}", sig_path.display(), e))?;
+    Ok(version)
+}
+
+/// Verify the signature, freshness and file hashes of the channel in `dir`, check it
+/// against the last version recorded in `state_path`, then record this version.
+/// Returns the verified version number.
+pub fn verify(dir: &Path, pk: &[u8; 32], state_path: &Path, now: u64) -> Result<u64, String> {
+    let meta_path = dir.join(META_FILE);
+    let sig_path = dir.join(SIG_FILE);
+    let meta_bytes = fs::read(&meta_path)
+        .map_err(|e| format!("Failed to read channel metadata {}: {}", meta_path.display(), e))?;
+    let sig_hex = fs::read_to_string(&sig_path)
+        .map_err(|e| format!("Failed to read channel signature {}: {}", sig_path.display(), e))?;
+    let sig = hex::decode(sig_hex.trim()).map_err(|e| format!("Invalid hex signature: {}", e))?;
+    crate::verify_signature(&sig, &meta_bytes, pk)
+        .map_err(|e| format!("Channel signature is invalid: {}", e))?;
+
+    let meta = Metadata::parse(&String::from_utf8_lossy(&meta_bytes))?;
+    if now >= meta.expires {
+        return Err(format!(
+            "Channel metadata version {} expired at {} (unix time); the mirror may be serving a frozen snapshot",
+            meta.version, meta.expires
+        ));
+    }
+    if meta.published > now {
+        return Err(format!("Channel metadata version {} is published in the future", meta.version));
+    }
+
+    for (name, size, hash) in &meta.files {
+        if name.split('/').any(|c| c == ".." || c.is_empty()) {
+            return Err(format!("Channel metadata lists an unsafe path '{}'", name));
+        }
+        let path = dir.join(name);
+        let (actual_size, actual_hash) = hash_file(&path)?;
+        if actual_size != *size || actual_hash != *hash {
+            return Err(format!("{} does not match the signed channel metadata", path.display()));
+        }
+    }
+
+    let meta_hash = hash_bytes(&meta_bytes);
+    let key = hex::encode(pk);
+    let mut state = read_state(state_path)?;
+    if let Some((last_version, last_hash)) = state.iter().find(|(k, _, _)| *k == key).map(|(_, v, h)| (*v, *h)) {
+        if meta.version < last_version {
+            return Err(format!("Channel rollback: got version {}, already accepted version {}", meta.version, last_version));
+        }
+        if meta.version == last_version && meta_hash != last_hash {
+            return Err(format!("Channel version {} differs from the copy accepted earlier", meta.version));
+        }
+        if meta.version == last_version + 1 && meta.previous != Some(last_hash) {
+            return Err(format!("Channel version {} does not chain to accepted version {}", meta.version, last_version));
+        }
+    }
+    state.retain(|(k, _, _)| *k != key);
+    state.push((key, meta.version, meta_hash));
+    write_state(state_path, &state)?;
+    Ok(meta.version)
+}
+
+/// Trusted client state: one "<public key> <version> <metadata hash>" line per channel key.
+fn read_state(path: &Path) -> Result<Vec<(String, u64, [u8; 32])>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read channel state {}: {}", path.display(), e)),
+    };
+    text.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                [key, version, hash] => Ok((
+                    key.to_string(),
+                    version.parse().map_err(|_| format!("Invalid channel state line '{}'", line))?,
+                    parse_hash(hash)?,
+                )),
+                _ => Err(format!("Invalid channel state line '{}' in {}", line, path.display())),
+            }
+        })
+        .collect()
+}
+
+fn write_state(path: &Path, state: &[(String, u64, [u8; 32])]) -> Result<(), String> {
+    let text: String = state
+        .iter()
+        .map(|(key, version, hash)| format!("{} {} {}\n", key, version, hex::encode(hash)))
+        .collect();
+    fs::write(path, text).map_err(|e| format!("Failed to write channel state {}: {}", path.display(), e))
+}
+
+fn parse_hash(s: &str) -> Result<[u8; 32], String> {
+    hex::decode(s)
+        .ok()
+        .and_then(|h| h.try_into().ok())
+        .ok_or_else(|| format!("Invalid hash '{}'", s))
+}
+
+fn hash_bytes(data: &[u8]) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    crypto_generichash(&mut digest, data, None).unwrap();
+    digest
+}
+
+/// Size and BLAKE2b-256 of a file.
+pub fn hash_file(path: &Path) -> Result<(u64, [u8; 32]), String> {
+    let mut state = crypto_generichash_init(None, 32).unwrap();
+    let mut size = 0u64;
+    mmap::read_chunks(path, |chunk| {
+        crypto_generichash_update(&mut state, chunk);
+        size += chunk.len() as u64;
+    })
+    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut digest = [0u8; 32];
+    crypto_generichash_final(state, &mut digest).unwrap();
+    Ok((size, digest))
+}
+
+/// Every regular file under `dir`, recursively.
+pub fn list_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    list_files_except(dir, &Ignore::default())
+}
+
+/// Every file under `dir` that `ignore` does not match; ignored directories are
+/// not entered.
+pub fn list_files_except(dir: &Path, ignore: &Ignore) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    walk(dir, dir, ignore, &mut files)?;
+    Ok(files)
+}
+
+fn walk(top: &Path, dir: &Path, ignore: &Ignore, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?.path();
+        let is_dir = path.is_dir();
+        if !ignore.is_empty() && ignore.matches(&relative_name(top, &path)?, is_dir) {
+            continue;
+        }
+        if is_dir {
+            walk(top, &path, ignore, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// `path` relative to `dir` with '/' separators, as listed in metadata files.
+pub fn relative_name(dir: &Path, path: &Path) -> Result<String, String> {
+    let rel = path.strip_prefix(dir).map_err(|_| format!("{} is outside {}", path.display(), dir.display()))?;
+    let parts: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+    let name = parts.join("/");
+    if name.contains('\n') {
+        return Err(format!("File name {:?} contains a newline and cannot be listed", name));
+    }
+    Ok(name)
+}
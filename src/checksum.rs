@@ -0,0 +1,132 @@
+use crate::mmap;
+use crate::progress::Progress;
+use clap::ValueEnum;
+use sodix::multipart::Hasher;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+// Checksum files as written by b2sum and sha256sum:
+//
+//   <hex digest>  <file name>          (a '*' instead of the second space marks binary mode)
+//   BLAKE2b (<file name>) = <hex>      (BSD-style --tag lines, also accepted)
+//
+// Names containing a newline or backslash are escaped and the line starts with '\'.
+// b2sum digests may be shorter than 512 bits (b2sum -l); the length is taken from
+// the hex when checking.
+
+/// Digest used in a checksum file.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum HashAlg {
+    /// BLAKE2b-512, as b2sum
+    #[value(name = "blake2b")]
+    Blake2b,
+    /// SHA-256, as sha256sum
+    #[value(name = "sha256")]
+    Sha256,
+}
+
+impl HashAlg {
+    /// The algorithm a checksum file's name suggests (SHA256SUMS, *.sha256), else BLAKE2b.
+    pub fn for_sums_file(path: &Path) -> Self {
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+        if name.contains("sha256") { HashAlg::Sha256 } else { HashAlg::Blake2b }
+    }
+
+    /// A hasher for this algorithm; `len` is the BLAKE2b output size in bytes.
+    pub fn hasher(self, len: usize) -> Result<Hasher, String> {
+        match self {
+            HashAlg::Blake2b => Hasher::blake2b(len),
+            HashAlg::Sha256 => Ok(Hasher::sha256()),
+        }
+    }
+}
+
+/// One entry of a checksum file.
+pub struct Entry {
+    pub name: String,
+    pub digest: Vec<u8>,
+    pub alg: HashAlg,
+}
+
+/// Digest of a file, or of stdin for `-`; `len` is the BLAKE2b output size in bytes.
+pub fn hash_file(path: &Path, alg: HashAlg, len: usize) -> Result<Vec<u8>, String> {
+    if path == Path::new("-") {
+        return hash_reader(io::stdin().lock(), alg, len);
+    }
+    let mut hasher = alg.hasher(len)?;
+    let progress = Progress::start("hash", path, fs::metadata(path).ok().map(|m| m.len()));
+    mmap::read_chunks(path, |chunk| {
+        hasher.update(chunk);
+        progress.inc(chunk.len() as u64);
+    })
+    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    progress.finish();
+    Ok(hasher.finalize())
+}
+
+/// Digest of a stream such as stdin, hashed as it arrives.
+pub fn hash_reader<R: Read>(mut reader: R, alg: HashAlg, len: usize) -> Result<Vec<u8>, String> {
+    let mut hasher = alg.hasher(len)?;
+    io::copy(&mut reader, &mut hasher).map_err(|e| format!("Failed to read input: {}", e))?;
+    Ok(hasher.finalize())
+}
+
+/// A checksum line in the coreutils format, without the trailing newline.
+pub fn format_line(name: &str, digest: &[u8]) -> String {
+    if name.contains(['\n', '\\']) {
+        format!("\\{}  {}", hex::encode(digest), name.replace('\\', "\\\\").replace('\n', "\\n"))
+    } else {
+        format!("{}  {}", hex::encode(digest), name)
+    }
+}
+
+/// Parse one line of a checksum file; `alg` applies to untagged lines.
+pub fn parse_line(line: &str, alg: HashAlg) -> Result<Entry, String> {
+    let invalid = || format!("Improperly formatted checksum line '{}'", line);
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let tagged = line.split_once(" (").filter(|(tag, _)| !tag.contains(' '));
+    let (alg, name, hex_digest) = if let Some((tag, rest)) = tagged {
+        let (name, hex_digest) = rest.rsplit_once(") = ").ok_or_else(invalid)?;
+        let alg = match tag {
+            "SHA256" => HashAlg::Sha256,
+            t if t == "BLAKE2b" || t.starts_with("BLAKE2b-") => HashAlg::Blake2b,
+            _ => return Err(format!("Unsupported checksum algorithm '{}'", tag)),
+        };
+        (alg, name, hex_digest)
+    } else {
+        let (hex_digest, rest) = line.split_once(' ').ok_or_else(invalid)?;
+        let name = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*')).ok_or_else(invalid)?;
+        (alg, name, hex_digest)
+    };
+    let digest = hex::decode(hex_digest).map_err(|_| invalid())?;
+    let valid_len = match alg {
+        HashAlg::Blake2b => (16..=64).contains(&digest.len()),
+        HashAlg::Sha256 => digest.len() == 32,
+    };
+    if !valid_len || name.is_empty() {
+        return Err(invalid());
+    }
+    let name = if escaped { unescape(name) } else { name.to_string() };
+    Ok(Entry { name, digest, alg })
+}
+
+fn unescape(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
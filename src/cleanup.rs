@@ -0,0 +1,258 @@
+#[cfg(feature = "full")]
+use rand::Rng;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+// Outputs that are still being written. If the user hits Ctrl-C, the handler removes
+// them so no truncated `.x` (or half-decrypted plaintext) is left behind to fail
+// confusingly later.
+static PARTIAL: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+static TEMP_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+// A run over several files (sign, encrypt, decrypt or recipients with more than one
+// input) is a transaction unless --keep-partial is given. Committed outputs stay under
+// their temporary names, headers rewritten in place keep a copy of their old bytes, and
+// inputs to --shred wait, until every file has succeeded. Then the outputs are renamed
+// into place and the inputs shredded; if a file failed (or on Ctrl-C), the temporary
+// files are removed and the old headers written back, leaving the tree as it was.
+static TRANSACTION: Mutex<Option<Transaction>> = Mutex::new(None);
+
+#[derive(Default)]
+struct Transaction {
+    /// (temporary name, destination) of each committed output
+    staged: Vec<(PathBuf, PathBuf)>,
+    /// Files changed in place, with the bytes they started with
+    undo: Vec<(PathBuf, Vec<u8>)>,
+    #[cfg(feature = "full")]
+    shred: Vec<PathBuf>,
+}
+
+/// Install the Ctrl-C handler: delete partial outputs, then exit with 130 like a
+/// shell does for SIGINT.
+pub fn install() {
+    let _ = ctrlc::set_handler(|| {
+        let paths = PARTIAL.lock().map(|p| p.clone()).unwrap_or_default();
+        for path in paths {
+            let _ = fs::remove_file(&path);
+        }
+        roll_back();
+        eprintln!("Interrupted");
+        std::process::exit(130);
+    });
+}
+
+/// Hold back the outputs of everything written from here on until `commit` or
+/// `roll_back`.
+pub fn begin() {
+    *TRANSACTION.lock().unwrap() = Some(Transaction::default());
+}
+
+/// End the transaction: rename the held-back outputs into place, then shred the inputs
+/// queued for it.
+pub fn commit() -> Result<(), String> {
+    let Some(transaction) = TRANSACTION.lock().unwrap().take() else {
+        return Ok(());
+    };
+    let mut staged = transaction.staged.iter();
+    for (temp, path) in staged.by_ref() {
+        if let Err(e) = fs::rename(temp, path) {
+            for (temp, _) in staged {
+                let _ = fs::remove_file(temp);
+            }
+            return Err(format!("Failed to move {} into place: {}; the files before it were committed", path.display(), e));
+        }
+    }
+    // As in AtomicFile::commit, the renames are durable once the directories are synced
+    #[cfg(unix)]
+    {
+        let dirs: std::collections::BTreeSet<&Path> = transaction.staged.iter().filter_map(|(_, path)| path.parent()).collect();
+        for dir in dirs {
+            let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+            File::open(dir).and_then(|dir| dir.sync_all()).map_err(|e| format!("Failed to sync {}: {}", dir.display(), e))?;
+        }
+    }
+    #[cfg(feature = "full")]
+    for path in &transaction.shred {
+        shred(path).map_err(|e| format!("Failed to shred {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// End the transaction, undoing what it wrote: remove the held-back outputs and write
+/// back the old bytes of files changed in place.
+pub fn roll_back() {
+    // Also run from the Ctrl-C handler, which must not panic on a poisoned lock
+    let Some(transaction) = TRANSACTION.lock().ok().and_then(|mut t| t.take()) else {
+        return;
+    };
+    for (temp, _) in &transaction.staged {
+        let _ = fs::remove_file(temp);
+    }
+    for (path, old) in transaction.undo.iter().rev() {
+        if let Err(e) = OpenOptions::new().write(true).open(path).and_then(|mut file| file.write_all(old).and_then(|_| file.sync_all())) {
+            eprintln!("Failed to restore {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Before `path` is overwritten from the start with as many bytes as it starts with
+/// now (`len`), keep those bytes for `roll_back`. Does nothing outside a transaction.
+#[cfg(feature = "full")]
+pub fn keep_for_undo(path: &Path, len: usize) -> io::Result<()> {
+    let mut transaction = TRANSACTION.lock().unwrap();
+    let Some(transaction) = transaction.as_mut() else {
+        return Ok(());
+    };
+    let mut old = vec![0u8; len];
+    io::Read::read_exact(&mut File::open(path)?, &mut old)?;
+    transaction.undo.push((path.to_path_buf(), old));
+    Ok(())
+}
+
+/// Where the output for `path` is until the transaction commits: its temporary name
+/// if it is held back, else `path` itself.
+pub fn staged_path(path: &Path) -> PathBuf {
+    let transaction = TRANSACTION.lock().unwrap();
+    let staged = transaction.as_ref().and_then(|t| t.staged.iter().find(|(_, p)| p == path));
+    staged.map_or_else(|| path.to_path_buf(), |(temp, _)| temp.clone())
+}
+
+/// Queue `path` to be shredded once the transaction commits. Returns false outside a
+/// transaction, for the caller to shred it now.
+#[cfg(feature = "full")]
+pub fn shred_on_commit(path: &Path) -> bool {
+    match TRANSACTION.lock().unwrap().as_mut() {
+        Some(transaction) => {
+            transaction.shred.push(path.to_path_buf());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Marks `path` as partial while alive. Dropping it (on success or after the
+/// caller's own error cleanup) only forgets the path; it never deletes anything.
+pub struct PartialFile(PathBuf);
+
+impl PartialFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        if let Ok(mut partial) = PARTIAL.lock() {
+            partial.push(path.clone());
+        }
+        PartialFile(path)
+    }
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if let Ok(mut partial) = PARTIAL.lock() {
+            if let Some(i) = partial.iter().position(|p| *p == self.0) {
+                partial.swap_remove(i);
+            }
+        }
+    }
+}
+
+/// An output written under a temporary name in its destination directory and
+/// renamed over `path` by `commit`, so `path` only ever holds its old contents or the
+/// complete new file. Until then the temporary file is partial, and dropping it
+/// uncommitted (after an error) removes it.
+pub struct AtomicFile {
+    path: PathBuf,
+    temp: PathBuf,
+    writer: BufWriter<File>,
+    committed: bool,
+    _partial: PartialFile,
+}
+
+impl AtomicFile {
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let temp = temp_path(&path);
+        let partial = PartialFile::new(&temp);
+        let file = OpenOptions::new().write(true).create_new(true).open(&temp)?;
+        Ok(AtomicFile { path, temp, writer: BufWriter::new(file), committed: false, _partial: partial })
+    }
+
+    /// Flush the file to disk and move it into place, or in a transaction hand it over
+    /// to be moved when the transaction commits.
+    pub fn commit(mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        if let Some(transaction) = TRANSACTION.lock().unwrap().as_mut() {
+            transaction.staged.push((self.temp.clone(), self.path.clone()));
+            self.committed = true;
+            return Ok(());
+        }
+        fs::rename(&self.temp, &self.path)?;
+        self.committed = true;
+        // The rename itself is only durable once the directory is synced
+        #[cfg(unix)]
+        if let Some(dir) = self.path.parent() {
+            let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// Write `data` to `path` through an `AtomicFile`.
+pub fn write_atomic(path: impl Into<PathBuf>, data: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(data.as_ref())?;
+    file.commit()
+}
+
+/// A hidden name next to `path` that no other write in flight uses.
+fn temp_path(path: &Path) -> PathBuf {
+    let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}-{}.tmp", name, std::process::id(), n))
+}
+
+/// Overwrite the file at `path` with random bytes, sync that to disk and delete it; a
+/// symlink is only removed, never its target. This only reaches the blocks the file
+/// occupies now: SSDs remap writes, copy-on-write and journaling filesystems,
+/// snapshots and backups can all keep earlier copies.
+#[cfg(feature = "full")]
+pub fn shred(path: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_file() {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut rng = rand::rng();
+        let mut remaining = metadata.len();
+        while remaining > 0 {
+            let n = remaining.min(buf.len() as u64) as usize;
+            rng.fill(&mut buf[..n]);
+            file.write_all(&buf[..n])?;
+            remaining -= n as u64;
+        }
+        file.sync_all()?;
+    } else if !metadata.file_type().is_symlink() {
+        return Err(io::Error::other("not a regular file"));
+    }
+    fs::remove_file(path)
+}
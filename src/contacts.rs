@@ -0,0 +1,110 @@
+use crate::fingerprint::{fingerprint, FingerprintAlg};
+use crate::keylog::{self, KeyLog};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Address book of recipients, contacts.toml in the key directory:
+//
+//   [alice]
+//   key = "<hex X25519 public key>"
+//   fingerprint = "blake2b:<hex>"
+//
+// The fingerprint is recorded when a contact is added and checked whenever the key is
+// used, so a key edited in the file is refused instead of encrypted to.
+// `contact add`, `key fetch` and `key import` never point a saved name at a different
+// key on their own: the user picks keep or replace, when asked or with --on-conflict.
+// Once a key log is started beside the file, every saved change is appended to it first.
+pub const FILE_NAME: &str = "contacts.toml";
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Contact {
+    pub key: String,
+    pub fingerprint: String,
+}
+
+pub struct Contacts {
+    path: PathBuf,
+    entries: BTreeMap<String, Contact>,
+}
+
+impl Contacts {
+    /// Read the address book; a missing file is an empty one.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(path, &text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Contacts { path: path.to_path_buf(), entries: BTreeMap::new() }),
+            Err(e) => Err(format!("Failed to read contacts from {}: {}", path.display(), e)),
+        }
+    }
+
+    /// An address book read from `text`, e.g. one in a key bundle, saved to `path`.
+    pub fn parse(path: &Path, text: &str) -> Result<Self, String> {
+        let entries = toml::from_str(text).map_err(|e| format!("Invalid contacts file {}: {}", path.display(), e))?;
+        Ok(Contacts { path: path.to_path_buf(), entries })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+        }
+        let log_path = self.path.with_file_name(keylog::FILE_NAME);
+        if log_path.exists() {
+            let mut log = KeyLog::load(&log_path)?;
+            log.record(&self.keys()?);
+            log.save()?;
+        }
+        let text = toml::to_string(&self.entries).map_err(|e| format!("Failed to encode contacts: {}", e))?;
+        fs::write(&self.path, text).map_err(|e| format!("Failed to write contacts to {}: {}", self.path.display(), e))
+    }
+
+    pub fn add(&mut self, name: &str, public_key: &[u8; 32]) -> Result<&Contact, String> {
+        if name.is_empty() || name.chars().any(char::is_whitespace) {
+            return Err(format!("Invalid contact name '{}'", name));
+        }
+        if self.entries.contains_key(name) {
+            return Err(format!("Contact '{}' already exists; remove it first to change its key", name));
+        }
+        let contact = Contact {
+            key: hex::encode(public_key),
+            fingerprint: fingerprint(public_key, FingerprintAlg::default()),
+        };
+        Ok(self.entries.entry(name.to_string()).or_insert(contact))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        self.entries.remove(name).map(|_| ()).ok_or_else(|| unknown(name))
+    }
+
+    /// A contact's public key, after checking it against the recorded fingerprint.
+    pub fn key(&self, name: &str) -> Result<[u8; 32], String> {
+        let contact = self.entries.get(name).ok_or_else(|| unknown(name))?;
+        let public_key: [u8; 32] = hex::decode(&contact.key)
+            .ok()
+            .and_then(|k| k.try_into().ok())
+            .ok_or_else(|| format!("Contact '{}' has an invalid key", name))?;
+        if !crate::fingerprint::matches(&contact.fingerprint, &public_key)? {
+            return Err(format!("The key of contact '{}' does not match its recorded fingerprint", name));
+        }
+        Ok(public_key)
+    }
+
+    /// Every contact's key, checked against its fingerprint.
+    pub fn keys(&self) -> Result<BTreeMap<String, [u8; 32]>, String> {
+        self.entries.keys().map(|name| Ok((name.clone(), self.key(name)?))).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Contact)> {
+        self.entries.iter()
+    }
+}
+
+fn unknown(name: &str) -> String {
+    format!("Unknown contact '{}'; add it with `sodix contact add {} <public key>`", name, name)
+}
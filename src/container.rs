@@ -0,0 +1,1579 @@
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::XChaCha20Poly1305;
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+use dryoc::classic::crypto_secretstream_xchacha20poly1305::{
+    crypto_secretstream_xchacha20poly1305_init_pull, crypto_secretstream_xchacha20poly1305_pull, State,
+};
+use dryoc::constants::{CRYPTO_SECRETSTREAM_XCHACHA20POLY1305_ABYTES, CRYPTO_SECRETSTREAM_XCHACHA20POLY1305_TAG_FINAL};
+use dryoc::classic::crypto_core::crypto_scalarmult_base;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_generichash::crypto_generichash;
+use dryoc::classic::crypto_generichash::{crypto_generichash_final, crypto_generichash_init, crypto_generichash_update};
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+#[cfg(feature = "full")]
+use rand::Rng;
+use sodix::sharedkey::{SharedKey, SharedKeys};
+use crate::cleanup::AtomicFile;
+use crate::memory;
+use crate::progress::Progress;
+#[cfg(feature = "full")]
+use crate::split;
+use crate::split::Input;
+#[cfg(feature = "full")]
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "full")]
+use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+#[cfg(feature = "full")]
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+// Encrypted file container. Every version starts with MAGIC | version.
+//
+// v2 (written by encrypt --file), random access:
+//   MAGIC | 2 | header_len u32 | chunk_size u32 | plaintext_len u64 | base nonce (16)
+//   | recipient count u16 | per recipient: box nonce (24) | crypto_box(file key) (48)
+//   | records (tag u8, len u16 BE, value) up to header_len, unknown tags skipped
+//   followed by ceil(plaintext_len / chunk_size) chunks (at least one), each the
+//   XChaCha20-Poly1305 encryption of chunk_size plaintext bytes (the last may be shorter)
+//   under nonce = base nonce || chunk index (u64 BE), with
+//   AAD = chunk_size || plaintext_len || final flag. Chunk i therefore lives at a
+//   computable offset and can be decrypted on its own. Readers refuse a chunk_size
+//   over 16 MiB (sodix writes 64 KiB).
+//
+// Header records:
+//   0x00 padding, left behind when the header shrinks in place
+//   0x01 ephemeral sender key
+//   0x02 compression codec u8 (1 = zstd): plaintext_len and the chunks are then those
+//        of the compressed stream, and the chunk AAD ends with the codec byte so the
+//        record cannot be dropped or added unnoticed
+//   0x03 sealed file metadata (mode, mtime, symlink target)
+//   0x04 sealed original file name
+//   0x05 header binding, empty: the chunk AAD ends with
+//        BLAKE2b-256(HEADER_CONTEXT || chunk_size || plaintext_len || base nonce
+//        || every record but padding, in order), so no record can be changed, dropped
+//        or added unnoticed. Key slots and padding are left out, as `recipients` and
+//        rewriting the header in place change them. Containers written before this
+//        record existed are read without the binding
+//
+// The header can also be kept in a separate file (encrypt --detached-header), leaving
+// only the chunks in the payload file. Concatenating the two gives a regular container.
+//
+// A container written from a pipe (encrypt --pipe) has plaintext_len = 2^64 - 1, as
+// the length is not known when the header goes out. Its chunks are all full-size
+// except the last, which is the one ending the input and carries the final flag; a
+// stream cut at a chunk boundary therefore still fails to authenticate. Having no
+// computable offsets, such a container can only be decrypted front to back.
+//
+// Every recipient unwraps the same file key, so with several key slots any recipient
+// could re-encrypt new content under it and pass it off as the sender's. Opening such
+// a container through a key slot from someone else's sender key (-k, or a --signer
+// for an ephemeral key) is therefore refused unless --trust-recipients is given;
+// signcrypt is the way to prove who wrote a message to a group.
+//
+// v1 (read only): one crypto_box-wrapped key and a secretstream_xchacha20poly1305 stream.
+pub const MAGIC: &[u8; 8] = b"SODIXENC";
+const VERSION_STREAM: u8 = 1;
+const VERSION_CHUNKED: u8 = 2;
+const V2_FIXED_LEN: usize = 8 + 1 + 4 + 4 + 8 + 16 + 2;
+const STANZA_LEN: usize = 24 + 48;
+const MAX_RECORDS_LEN: usize = 64 * 1024;
+const STREAMED_LEN: u64 = u64::MAX;
+/// Largest chunk size accepted when reading: chunk buffers are allocated before the
+/// chunk is authenticated, so the untrusted header must not size them freely
+const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+const NO_RANDOM_ACCESS: &str = "The file was encrypted from a pipe and can only be decrypted whole";
+// Filler left behind when the header shrinks in place
+const TAG_PADDING: u8 = 0x00;
+// Ephemeral X25519 public key (32) | Ed25519 signature over EPHEMERAL_CONTEXT || key (64)
+const TAG_EPHEMERAL_SENDER: u8 = 0x01;
+const EPHEMERAL_CONTEXT: &[u8] = b"sodix ephemeral sender v1";
+const TAG_COMPRESSION: u8 = 0x02;
+const CODEC_NONE: u8 = 0;
+const CODEC_ZSTD: u8 = 1;
+const TAG_METADATA: u8 = 0x03;
+const METADATA_CONTEXT: &[u8] = b"sodix metadata v1";
+const TAG_NAME: u8 = 0x04;
+const NAME_CONTEXT: &[u8] = b"sodix name v1";
+const TAG_HEADER_BINDING: u8 = 0x05;
+const HEADER_CONTEXT: &[u8] = b"sodix header v1";
+const KIND_FILE: u8 = 0;
+const KIND_SYMLINK: u8 = 1;
+#[cfg(feature = "full")]
+const CONVERGENT_CONTEXT: &[u8] = b"sodix convergent v1";
+const SENDER_KEY_NEEDED: &str = "The file was encrypted from a static sender key; the sender's public key is needed to open it";
+const SENDER_NOT_PROVEN: &str = "The file has several key slots, so any of its recipients could have written it in the sender's name; pass --trust-recipients to decrypt it anyway, or have the sender use signcrypt";
+#[cfg(feature = "full")]
+const NO_RECIPIENT_LEFT: &str = "Refusing to remove every recipient; nobody could decrypt the file";
+#[cfg(feature = "full")]
+const CHUNK_SIZE: u32 = 64 * 1024;
+// Chunks of a file are sealed on every core; each thread may have this many read
+// ahead of the one being written, fewer under --max-memory.
+#[cfg(feature = "full")]
+const CHUNKS_AHEAD_PER_THREAD: usize = 4;
+const TAG_LEN: usize = 16;
+
+/// How the file key is wrapped to the recipients.
+#[cfg(feature = "full")]
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub enum Sender {
+    /// crypto_box from the sender's long-term X25519 secret key
+    Static([u8; 32]),
+    /// crypto_box from a fresh X25519 key pair whose public key is signed with this
+    /// Ed25519 secret key; the sender's long-term keys can no longer open the file
+    Ephemeral([u8; 64]),
+}
+
+/// Keys for opening a container: our secret key and what we trust as the sender.
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub struct OpenKeys {
+    pub recipient_sk: [u8; 32],
+    /// Sender's X25519 public key, for files boxed from a static sender key
+    pub sender_pk: Option<[u8; 32]>,
+    /// Sender's Ed25519 public key, for files with a signed ephemeral sender key; without
+    /// one the default signing public key is read, and only once a file has such a key
+    pub signer_pk: Option<[u8; 32]>,
+}
+
+static TRUST_RECIPIENTS: AtomicBool = AtomicBool::new(false);
+
+/// Open containers with several key slots from another party's sender key, whose
+/// content any of the recipients could have written, for the rest of the run
+/// (--trust-recipients).
+pub fn trust_recipients() {
+    TRUST_RECIPIENTS.store(true, Ordering::Relaxed);
+}
+
+// Shared keys of static key pairs, computed once per run: a batch over many files,
+// or a file with many key slots, pays one scalar multiplication per peer instead of
+// one per slot. Ephemeral sender keys are used once and not kept.
+static SHARED_KEYS: Mutex<SharedKeys> = Mutex::new(SharedKeys::new());
+
+/// The crypto_box shared key of a static key pair, cached after the first use.
+pub fn shared_key(their_pk: &[u8; 32], our_sk: &[u8; 32]) -> SharedKey {
+    SHARED_KEYS.lock().unwrap().get(their_pk, our_sk)
+}
+
+/// File attributes kept in the header, sealed with the file key so only recipients
+/// see them and nobody can alter them without it. A symlink is stored as its target
+/// with an empty payload.
+pub struct Metadata {
+    pub mode: u32,
+    pub mtime: SystemTime,
+    pub symlink: Option<PathBuf>,
+}
+
+impl Metadata {
+    /// The attributes of `path` itself, not of what a symlink points to.
+    #[cfg(feature = "full")]
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let meta = fs::symlink_metadata(path)
+            .map_err(|e| format!("Failed to read input file {}: {}", path.display(), e))?;
+        let symlink = if meta.file_type().is_symlink() {
+            Some(fs::read_link(path).map_err(|e| format!("Failed to read symlink {}: {}", path.display(), e))?)
+        } else {
+            None
+        };
+        #[cfg(unix)]
+        let mode = std::os::unix::fs::PermissionsExt::mode(&meta.permissions()) & 0o777;
+        #[cfg(not(unix))]
+        let mode = if meta.permissions().readonly() { 0o444 } else { 0o644 };
+        Ok(Metadata {
+            mode,
+            mtime: meta.modified().unwrap_or(UNIX_EPOCH),
+            symlink,
+        })
+    }
+
+    /// Apply the attributes to `path`, which holds the decrypted payload. For a
+    /// symlink, the (empty) file is replaced by the link.
+    pub fn restore(&self, path: &Path) -> Result<(), String> {
+        if let Some(target) = &self.symlink {
+            #[cfg(not(unix))]
+            return Err(format!("{} is a symlink to {}, which can only be restored on Unix", path.display(), target.display()));
+            #[cfg(unix)]
+            {
+                fs::remove_file(path).map_err(|e| format!("Failed to replace {} with a symlink: {}", path.display(), e))?;
+                return std::os::unix::fs::symlink(target, path)
+                    .map_err(|e| format!("Failed to create symlink {}: {}", path.display(), e));
+            }
+        }
+        let file = File::options()
+            .write(true)
+            .open(path)
+            .map_err(|e| format!("Failed to set attributes of {}: {}", path.display(), e))?;
+        file.set_modified(self.mtime)
+            .map_err(|e| format!("Failed to set modification time of {}: {}", path.display(), e))?;
+        #[cfg(unix)]
+        let permissions = std::os::unix::fs::PermissionsExt::from_mode(self.mode & 0o777);
+        #[cfg(not(unix))]
+        let permissions = {
+            let mut permissions = file.metadata().map_err(|e| format!("Failed to read {}: {}", path.display(), e))?.permissions();
+            permissions.set_readonly(self.mode & 0o200 == 0);
+            permissions
+        };
+        fs::set_permissions(path, permissions).map_err(|e| format!("Failed to set permissions of {}: {}", path.display(), e))
+    }
+
+    #[cfg(feature = "full")]
+    fn seal(&self, file_key: &[u8; 32]) -> Result<Vec<u8>, String> {
+        let since_epoch = self.mtime.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut plain = Vec::new();
+        plain.push(if self.symlink.is_some() { KIND_SYMLINK } else { KIND_FILE });
+        plain.extend_from_slice(&self.mode.to_be_bytes());
+        plain.extend_from_slice(&since_epoch.as_secs().to_be_bytes());
+        plain.extend_from_slice(&since_epoch.subsec_nanos().to_be_bytes());
+        if let Some(target) = &self.symlink {
+            plain.extend_from_slice(target.to_string_lossy().as_bytes());
+        }
+        seal_record(file_key, METADATA_CONTEXT, plain).map_err(|_| "Symlink target is too long".to_string())
+    }
+
+    fn open(value: &[u8], file_key: &[u8; 32]) -> Result<Self, String> {
+        let plain = open_record(file_key, METADATA_CONTEXT, value)
+            .ok_or("Error decrypting data: file metadata authentication failed")?;
+        if plain.len() < 17 {
+            return Err("Invalid file metadata record".to_string());
+        }
+        let mode = u32::from_be_bytes(plain[1..5].try_into().unwrap());
+        let secs = u64::from_be_bytes(plain[5..13].try_into().unwrap());
+        let nanos = u32::from_be_bytes(plain[13..17].try_into().unwrap());
+        let symlink = match plain[0] {
+            KIND_FILE => None,
+            KIND_SYMLINK => Some(PathBuf::from(String::from_utf8(plain[17..].to_vec()).map_err(|_| "Invalid symlink target in file metadata")?)),
+            _ => return Err("Unsupported file kind in file metadata".to_string()),
+        };
+        let mtime = UNIX_EPOCH
+            .checked_add(Duration::new(secs, nanos.min(999_999_999)))
+            .ok_or("Invalid modification time in file metadata")?;
+        Ok(Metadata { mode, mtime, symlink })
+    }
+}
+
+/// Payload layout parameters of a v2 container.
+struct Chunking {
+    header_len: u64,
+    chunk_size: u32,
+    plaintext_len: u64,
+    base_nonce: [u8; 16],
+    codec: u8,
+    /// Hash of the header the chunks are bound to (record 0x05)
+    header_hash: Option<[u8; 32]>,
+}
+
+impl Chunking {
+    fn chunk_count(&self) -> u64 {
+        self.plaintext_len.div_ceil(self.chunk_size as u64).max(1)
+    }
+
+    fn chunk_len(&self, index: u64) -> usize {
+        let start = index * self.chunk_size as u64;
+        (self.plaintext_len - start).min(self.chunk_size as u64) as usize
+    }
+
+    /// Length of the header and all chunks, or None if it does not fit in a u64.
+    fn container_len(&self) -> Option<u64> {
+        self.chunk_count().checked_mul(TAG_LEN as u64)?.checked_add(self.plaintext_len)?.checked_add(self.header_len)
+    }
+
+    fn chunk_offset(&self, index: u64) -> u64 {
+        self.header_len + index * (self.chunk_size as u64 + TAG_LEN as u64)
+    }
+
+    fn nonce(&self, index: u64) -> [u8; 24] {
+        let mut nonce = [0u8; 24];
+        nonce[..16].copy_from_slice(&self.base_nonce);
+        nonce[16..].copy_from_slice(&index.to_be_bytes());
+        nonce
+    }
+
+    /// Written from a pipe, so the chunk count is unknown until the input ends.
+    fn is_streamed(&self) -> bool {
+        self.plaintext_len == STREAMED_LEN
+    }
+
+    fn aad(&self, index: u64) -> Vec<u8> {
+        self.final_aad(index + 1 == self.chunk_count())
+    }
+
+    fn final_aad(&self, last: bool) -> Vec<u8> {
+        let mut aad = Vec::with_capacity(14 + 32);
+        aad.extend_from_slice(&self.chunk_size.to_be_bytes());
+        aad.extend_from_slice(&self.plaintext_len.to_be_bytes());
+        aad.push(last as u8);
+        if self.codec != CODEC_NONE {
+            aad.push(self.codec);
+        }
+        if let Some(header_hash) = &self.header_hash {
+            aad.extend_from_slice(header_hash);
+        }
+        aad
+    }
+}
+
+/// Whether `path` starts with the container magic (as opposed to a legacy hex `.x` file).
+pub fn is_container_file(path: &Path) -> bool {
+    let mut magic = [0u8; 8];
+    Input::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .map(|_| &magic == MAGIC)
+        .unwrap_or(false)
+}
+
+/// Encrypt `input` into a container at `output`, or with `header_output` into a
+/// header file and a payload file holding only the chunks. With `compression`, the
+/// input is zstd-compressed at that level before encryption. With `preserve`, its
+/// mode, mtime and symlink target are recorded too, except in convergent mode, where
+/// equal contents must still give equal ciphertexts. With `name`, the file name is
+/// sealed in the header for decrypt to restore, so `output` can be named opaquely.
+/// With `split_size`, `output` is written as parts of at most that many bytes.
+#[cfg(feature = "full")]
+#[allow(clippy::too_many_arguments)]
+pub fn encrypt_file(input: &Path, output: &Path, header_output: Option<&Path>, split_size: Option<u64>, recipients: &[[u8; 32]], sender: &Sender, convergence_secret: Option<&[u8; 32]>, compression: Option<i32>, preserve: bool, name: Option<&str>) -> Result<(), String> {
+    let metadata = match preserve && convergence_secret.is_none() {
+        true => Some(Metadata::read(input)?),
+        false => None,
+    };
+    if metadata.as_ref().is_some_and(|m| m.symlink.is_some()) {
+        return encrypt_input(io::Cursor::new([]), 0, input, output, header_output, split_size, recipients, sender, None, compression, metadata.as_ref(), name);
+    }
+    let reader = File::open(input)
+        .map_err(|e| format!("Failed to read input file {}: {}", input.display(), e))?;
+    let plaintext_len = reader
+        .metadata()
+        .map_err(|e| format!("Failed to read input file {}: {}", input.display(), e))?
+        .len();
+    encrypt_input(reader, plaintext_len, input, output, header_output, split_size, recipients, sender, convergence_secret, compression, metadata.as_ref(), name)
+}
+
+/// encrypt_file from an open `reader` holding `plaintext_len` bytes.
+#[cfg(feature = "full")]
+#[allow(clippy::too_many_arguments)]
+fn encrypt_input<R: Read + Seek>(mut reader: R, plaintext_len: u64, input: &Path, output: &Path, header_output: Option<&Path>, split_size: Option<u64>, recipients: &[[u8; 32]], sender: &Sender, convergence_secret: Option<&[u8; 32]>, compression: Option<i32>, metadata: Option<&Metadata>, name: Option<&str>) -> Result<(), String> {
+    // Convergent mode and compression read the input twice: once for the key or the
+    // compressed length, once to encrypt
+    let content_key = match (convergence_secret, compression) {
+        (Some(_), Some(_)) => return Err("Convergent encryption cannot be combined with compression".to_string()),
+        (Some(secret), None) => {
+            let progress = Progress::start("hash", input, Some(plaintext_len));
+            let content_key = convergent_key(secret, BufReader::new(progress.reader(&mut reader)), plaintext_len)?;
+            progress.finish();
+            Some(content_key)
+        }
+        (None, _) => None,
+    };
+    let payload_len = match compression {
+        Some(level) => {
+            let progress = Progress::start("compress", input, Some(plaintext_len));
+            let len = compressed_len(BufReader::new(progress.reader(&mut reader)), level)
+                .map_err(|e| format!("Failed to compress input file {}: {}", input.display(), e))?;
+            progress.finish();
+            len
+        }
+        None => plaintext_len,
+    };
+    reader.seek(SeekFrom::Start(0))
+        .map_err(|e| format!("Failed to read input file {}: {}", input.display(), e))?;
+    let mut header_writer = match header_output {
+        Some(path) => Some(AtomicFile::create(path)
+            .map_err(|e| format!("Failed to write header file {}: {}", path.display(), e))?),
+        None => None,
+    };
+    let mut writer = split::Output::create(output, split_size)
+        .map_err(|e| format!("Failed to write encrypted file {}: {}", output.display(), e))?;
+    let progress = Progress::start("encrypt", input, Some(plaintext_len));
+    let reader = progress.reader(reader);
+    let (payload, codec): (Box<dyn Read>, u8) = match compression {
+        Some(level) => {
+            let encoder = zstd_encoder(BufReader::new(reader), level)
+                .map_err(|e| format!("Failed to compress input file {}: {}", input.display(), e))?;
+            (Box::new(encoder), CODEC_ZSTD)
+        }
+        None => (Box::new(BufReader::new(reader)), CODEC_NONE),
+    };
+    encrypt_chunked(payload, &mut writer, header_writer.as_mut().map(|w| w as &mut dyn Write), payload_len, codec, recipients, sender, content_key, metadata, name)?;
+    progress.finish();
+    if let (Some(header_writer), Some(path)) = (header_writer, header_output) {
+        header_writer.commit().map_err(|e| format!("Failed to write header file {}: {}", path.display(), e))?;
+    }
+    writer.commit().map_err(|e| format!("Failed to write encrypted file {}: {}", output.display(), e))
+}
+
+/// Encrypt an in-memory message into a complete container.
+#[cfg(feature = "full")]
+pub fn encrypt_bytes(data: &[u8], recipients: &[[u8; 32]], sender: &Sender, convergence_secret: Option<&[u8; 32]>) -> Result<Vec<u8>, String> {
+    let content_key = match convergence_secret {
+        Some(secret) => Some(convergent_key(secret, data, data.len() as u64)?),
+        None => None,
+    };
+    let mut out = Vec::new();
+    encrypt_chunked(data, &mut out, None, data.len() as u64, CODEC_NONE, recipients, sender, content_key, None, None)?;
+    Ok(out)
+}
+
+/// Length of the zstd compression of `reader` at `level`. Compression is deterministic,
+/// so the second pass produces exactly this many bytes.
+#[cfg(feature = "full")]
+fn compressed_len<R: Read>(reader: R, level: i32) -> io::Result<u64> {
+    let mut encoder = zstd_encoder(reader, level)?;
+    io::copy(&mut encoder, &mut io::sink())
+}
+
+/// A zstd compressor of `reader` at `level`, its window and tables cut to fit
+/// --max-memory.
+#[cfg(feature = "full")]
+fn zstd_encoder<R: Read>(reader: R, level: i32) -> io::Result<zstd::stream::read::Encoder<'static, BufReader<R>>> {
+    let mut encoder = zstd::stream::read::Encoder::new(reader, level)?;
+    if let Some((window, chain, hash)) = memory::zstd_logs(level) {
+        encoder.window_log(window)?;
+        encoder.set_parameter(zstd::zstd_safe::CParameter::ChainLog(chain))?;
+        encoder.set_parameter(zstd::zstd_safe::CParameter::HashLog(hash))?;
+    }
+    Ok(encoder)
+}
+
+/// File key and base nonce fixed by the plaintext (convergent mode) instead of random.
+#[cfg(feature = "full")]
+struct ContentKey {
+    file_key: [u8; 32],
+    base_nonce: [u8; 16],
+}
+
+/// Convergent content key: BLAKE2b keyed with the convergence secret over the
+/// plaintext. Without the secret, nobody can confirm a guess of the file contents.
+#[cfg(feature = "full")]
+fn convergent_key<R: Read>(secret: &[u8; 32], mut reader: R, plaintext_len: u64) -> Result<ContentKey, String> {
+    let mut state = crypto_generichash_init(Some(secret), 64).map_err(|e| e.to_string())?;
+    crypto_generichash_update(&mut state, CONVERGENT_CONTEXT);
+    crypto_generichash_update(&mut state, &plaintext_len.to_be_bytes());
+    let mut buf = vec![0u8; CHUNK_SIZE as usize];
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        crypto_generichash_update(&mut state, &buf[..n]);
+        if n < buf.len() {
+            break;
+        }
+    }
+    let mut digest = [0u8; 64];
+    crypto_generichash_final(state, &mut digest).map_err(|e| e.to_string())?;
+    Ok(ContentKey {
+        file_key: digest[..32].try_into().unwrap(),
+        base_nonce: digest[32..48].try_into().unwrap(),
+    })
+}
+
+/// Convergence secret derived from an encryption secret key, so one user's files
+/// deduplicate without managing a separate secret.
+#[cfg(feature = "full")]
+pub fn derive_convergence_secret(sk: &[u8; 32]) -> [u8; 32] {
+    let mut secret = [0u8; 32];
+    crypto_generichash(&mut secret, b"sodix convergence secret v1", Some(sk)).unwrap();
+    secret
+}
+
+/// Decrypt the container `input` to `output`; with `header`, `input` is the payload
+/// of a container whose header was written to that separate file. Returns the
+/// recorded file metadata for the caller to restore.
+pub fn decrypt_file(input: &Path, header: Option<&Path>, output: &Path, keys: &OpenKeys) -> Result<Option<Metadata>, String> {
+    let reader = Input::open(input)
+        .map_err(|e| format!("Failed to read encrypted file {}: {}", input.display(), e))?;
+    let progress = Progress::start("decrypt", input, reader.size().ok());
+    // Never leave unauthenticated partial plaintext behind: it only takes the output
+    // name once every chunk has been verified
+    let mut writer = AtomicFile::create(output)
+        .map_err(|e| format!("Failed to write decrypted file {}: {}", output.display(), e))?;
+    let metadata = match header {
+        Some(header) => decrypt_detached(input, header, &mut writer, keys, &progress)?,
+        None => decrypt_stream(BufReader::new(progress.reader(reader)), &mut writer, keys)?,
+    };
+    writer.commit().map_err(|e| format!("Failed to write decrypted file {}: {}", output.display(), e))?;
+    progress.finish();
+    Ok(metadata)
+}
+
+#[cfg(feature = "full")]
+#[allow(clippy::too_many_arguments)]
+fn encrypt_chunked<R: Read, W: Write>(mut reader: R, mut writer: W, header_writer: Option<&mut dyn Write>, plaintext_len: u64, codec: u8, recipients: &[[u8; 32]], sender: &Sender, content_key: Option<ContentKey>, metadata: Option<&Metadata>, name: Option<&str>) -> Result<(), String> {
+    if recipients.is_empty() || recipients.len() > u16::MAX as usize {
+        return Err(format!("Number of recipients must be between 1 and {}", u16::MAX));
+    }
+    let convergent = content_key.is_some();
+    let mut rng = rand::rng();
+    let ContentKey { file_key, base_nonce } = content_key.unwrap_or_else(|| {
+        let mut file_key = [0u8; 32];
+        let mut base_nonce = [0u8; 16];
+        rng.fill(&mut file_key);
+        rng.fill(&mut base_nonce);
+        ContentKey { file_key, base_nonce }
+    });
+    let chunking = Chunking { header_len: 0, chunk_size: CHUNK_SIZE, plaintext_len, base_nonce, codec, header_hash: None };
+
+    let mut records = Vec::new();
+    if codec != CODEC_NONE {
+        put_record(&mut records, TAG_COMPRESSION, &[codec]);
+    }
+    if let Some(metadata) = metadata {
+        put_record(&mut records, TAG_METADATA, &metadata.seal(&file_key)?);
+    }
+    if let Some(name) = name {
+        let sealed = seal_record(&file_key, NAME_CONTEXT, name.as_bytes().to_vec()).map_err(|_| "File name is too long".to_string())?;
+        put_record(&mut records, TAG_NAME, &sealed);
+    }
+    let sender_sk = match sender {
+        Sender::Static(sk) => *sk,
+        Sender::Ephemeral(_) if convergent => {
+            return Err("Convergent encryption needs a static sender key".to_string());
+        }
+        Sender::Ephemeral(sign_sk) => {
+            let mut eph_sk = [0u8; 32];
+            rng.fill(&mut eph_sk);
+            let mut eph_pk = [0u8; 32];
+            crypto_scalarmult_base(&mut eph_pk, &eph_sk);
+            let mut signature = [0u8; 64];
+            crypto_sign_detached(&mut signature, &[EPHEMERAL_CONTEXT, &eph_pk].concat(), sign_sk)
+                .map_err(|e| format!("Error signing ephemeral key: {}", e))?;
+            put_record(&mut records, TAG_EPHEMERAL_SENDER, &[&eph_pk[..], &signature].concat());
+            eph_sk
+        }
+    };
+    // The same file key is wrapped to every recipient
+    let stanzas = recipients
+        .iter()
+        .map(|recipient_pk| {
+            let shared = match sender {
+                Sender::Static(_) => shared_key(recipient_pk, &sender_sk),
+                Sender::Ephemeral(_) => SharedKey::new(recipient_pk, &sender_sk),
+            };
+            wrap_stanza(&file_key, recipient_pk, &shared, convergent)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    put_record(&mut records, TAG_HEADER_BINDING, &[]);
+    let mut header = V2Header { chunking, stanzas, records };
+    header.chunking.header_len = header.len() as u64;
+    header.chunking.header_hash = header.binding()?;
+    match header_writer {
+        Some(header_writer) => header_writer.write_all(&header.to_bytes()),
+        None => writer.write_all(&header.to_bytes()),
+    }
+    .map_err(|e| format!("Failed to write output: {}", e))?;
+    let chunking = &header.chunking;
+
+    let cipher = XChaCha20Poly1305::new(&file_key.into());
+    if chunking.is_streamed() {
+        return encrypt_streamed(reader, writer, chunking, &cipher);
+    }
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(chunking.chunk_count().try_into().unwrap_or(usize::MAX));
+    let ahead = memory::buffers(chunking.chunk_size as usize + TAG_LEN, threads * CHUNKS_AHEAD_PER_THREAD);
+    let threads = threads.min(ahead);
+    let (jobs, queue) = mpsc::sync_channel::<SealJob>(threads);
+    let queue = Mutex::new(queue);
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                // Taken out of the `let` so the lock is only held while waiting
+                let job = queue.lock().unwrap().recv();
+                let Ok((index, mut buf, done)) = job else {
+                    break;
+                };
+                let sealed = cipher
+                    .encrypt_in_place(&chunking.nonce(index).into(), &chunking.aad(index), &mut buf)
+                    .map(|()| buf)
+                    .map_err(|_| "Error encrypting data".to_string());
+                let _ = done.send(sealed);
+            });
+        }
+        seal_in_order(&mut reader, &mut writer, chunking, jobs, ahead)
+    })?;
+    let mut extra = [0u8; 1];
+    if read_full(&mut reader, &mut extra)? != 0 {
+        return Err("Input file grew while encrypting".to_string());
+    }
+    Ok(())
+}
+
+/// A chunk to seal on a worker thread: index, plaintext, and where to send it back.
+#[cfg(feature = "full")]
+type SealJob = (u64, Vec<u8>, mpsc::SyncSender<Result<Vec<u8>, String>>);
+
+/// Read the chunks of an input of known length and hand them to the sealing threads
+/// through `jobs`, writing them back in order as they come out. At most `ahead`
+/// chunks are in flight, so memory stays bounded however far the writer falls behind.
+/// Returning drops `jobs`, which stops the threads.
+#[cfg(feature = "full")]
+fn seal_in_order<R: Read, W: Write>(mut reader: R, mut writer: W, chunking: &Chunking, jobs: mpsc::SyncSender<SealJob>, ahead: usize) -> Result<(), String> {
+    let mut pending = VecDeque::with_capacity(ahead);
+    let mut spare = Vec::new();
+    for index in 0..chunking.chunk_count() {
+        if pending.len() == ahead {
+            write_sealed(&mut writer, pending.pop_front().unwrap(), &mut spare)?;
+        }
+        let len = chunking.chunk_len(index);
+        let mut buf = spare.pop().unwrap_or_else(|| Vec::with_capacity(chunking.chunk_size as usize + TAG_LEN));
+        buf.resize(len, 0);
+        if read_full(&mut reader, &mut buf)? != len {
+            return Err("Input file shrank while encrypting".to_string());
+        }
+        let (done, sealed) = mpsc::sync_channel(1);
+        jobs.send((index, buf, done)).map_err(|_| "An encryption thread failed".to_string())?;
+        pending.push_back(sealed);
+    }
+    for sealed in pending {
+        write_sealed(&mut writer, sealed, &mut spare)?;
+    }
+    Ok(())
+}
+
+/// Wait for a chunk to come back sealed and write it, keeping its buffer for reuse.
+#[cfg(feature = "full")]
+fn write_sealed<W: Write>(mut writer: W, sealed: mpsc::Receiver<Result<Vec<u8>, String>>, spare: &mut Vec<Vec<u8>>) -> Result<(), String> {
+    let buf = sealed.recv().map_err(|_| "An encryption thread failed".to_string())??;
+    writer.write_all(&buf).map_err(|e| format!("Failed to write output: {}", e))?;
+    spare.push(buf);
+    Ok(())
+}
+
+/// The chunks of an input of unknown length. Each is read one ahead, so the last
+/// one is known when it is sealed.
+#[cfg(feature = "full")]
+fn encrypt_streamed<R: Read, W: Write>(mut reader: R, mut writer: W, chunking: &Chunking, cipher: &XChaCha20Poly1305) -> Result<(), String> {
+    let size = chunking.chunk_size as usize;
+    let mut chunk = vec![0u8; size];
+    let mut next = vec![0u8; size];
+    let mut len = read_full(&mut reader, &mut chunk)?;
+    let mut buf = Vec::with_capacity(size + TAG_LEN);
+    for index in 0.. {
+        let next_len = if len == size { read_full(&mut reader, &mut next)? } else { 0 };
+        let last = next_len == 0;
+        buf.clear();
+        buf.extend_from_slice(&chunk[..len]);
+        cipher
+            .encrypt_in_place(&chunking.nonce(index).into(), &chunking.final_aad(last), &mut buf)
+            .map_err(|_| "Error encrypting data".to_string())?;
+        writer.write_all(&buf).map_err(|e| format!("Failed to write output: {}", e))?;
+        if last {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+    }
+    Ok(())
+}
+
+/// Encrypt everything `reader` yields to `writer` without knowing its length in
+/// advance, as from a pipe, optionally zstd-compressed on the way.
+#[cfg(feature = "full")]
+pub fn encrypt_pipe<R: Read, W: Write>(reader: R, writer: W, recipients: &[[u8; 32]], sender: &Sender, compression: Option<i32>) -> Result<(), String> {
+    match compression {
+        Some(level) => {
+            let encoder = zstd_encoder(reader, level).map_err(|e| format!("Failed to compress input: {}", e))?;
+            encrypt_chunked(encoder, writer, None, STREAMED_LEN, CODEC_ZSTD, recipients, sender, None, None, None)
+        }
+        None => encrypt_chunked(reader, writer, None, STREAMED_LEN, CODEC_NONE, recipients, sender, None, None, None),
+    }
+}
+
+/// Give `new_recipients` a key slot wrapped from `sender_sk`, rewriting only the
+/// header; the payload is not re-encrypted. `keys` must open the file. Returns the
+/// number of slots added (recipients that already have one from this sender are skipped).
+/// `path` may be a whole container or a detached header file.
+#[cfg(feature = "full")]
+pub fn add_recipients(path: &Path, keys: &OpenKeys, sender_sk: &[u8; 32], new_recipients: &[[u8; 32]]) -> Result<usize, String> {
+    rotate_recipients(path, keys, sender_sk, &[], new_recipients).map(|(added, _)| added)
+}
+
+/// Drop the key slots that `sender_sk` wrapped for any of `recipients`, rewriting only
+/// the header. Slots wrapped by someone else (or from an ephemeral key) cannot be
+/// attributed and are kept. A removed recipient who kept the file key, or a copy of
+/// the old file, can still decrypt; re-encrypt if that matters.
+#[cfg(feature = "full")]
+pub fn remove_recipients(path: &Path, sender_sk: &[u8; 32], recipients: &[[u8; 32]]) -> Result<usize, String> {
+    let mut header = read_v2_file_header(path)?;
+    let removed = drop_slots(&mut header, sender_sk, recipients);
+    if header.stanzas.is_empty() {
+        return Err(NO_RECIPIENT_LEFT.to_string());
+    }
+    if removed > 0 {
+        rewrite_header(path, header)?;
+    }
+    Ok(removed)
+}
+
+/// Replace `old_recipients` by `new_recipients` in one header rewrite, as
+/// `remove_recipients` and then `add_recipients` would; it is an error if none of the
+/// old ones had a slot from `sender_sk`. Returns the numbers of slots added and removed.
+#[cfg(feature = "full")]
+pub fn rotate_recipients(path: &Path, keys: &OpenKeys, sender_sk: &[u8; 32], old_recipients: &[[u8; 32]], new_recipients: &[[u8; 32]]) -> Result<(usize, usize), String> {
+    let mut header = read_v2_file_header(path)?;
+    let (file_key, _) = header.file_key(keys)?;
+    let removed = drop_slots(&mut header, sender_sk, old_recipients);
+    if removed == 0 && !old_recipients.is_empty() {
+        return Err(format!("{} has no key slot wrapped by your key for the old recipients", path.display()));
+    }
+    let mut added = 0;
+    for recipient_pk in new_recipients {
+        let shared = shared_key(recipient_pk, sender_sk);
+        if header.stanzas.iter().any(|s| open_stanza(s, &shared).is_some()) {
+            continue;
+        }
+        header.stanzas.push(wrap_stanza(&file_key, recipient_pk, &shared, false)?);
+        added += 1;
+    }
+    if header.stanzas.is_empty() {
+        return Err(NO_RECIPIENT_LEFT.to_string());
+    }
+    if header.stanzas.len() > u16::MAX as usize {
+        return Err(format!("Number of recipients must be between 1 and {}", u16::MAX));
+    }
+    if added + removed > 0 {
+        rewrite_header(path, header)?;
+    }
+    Ok((added, removed))
+}
+
+/// Remove the key slots `sender_sk` wrapped for any of `recipients`, returning how
+/// many went.
+#[cfg(feature = "full")]
+fn drop_slots(header: &mut V2Header, sender_sk: &[u8; 32], recipients: &[[u8; 32]]) -> usize {
+    let before = header.stanzas.len();
+    let shared: Vec<_> = recipients.iter().map(|pk| shared_key(pk, sender_sk)).collect();
+    header.stanzas.retain(|s| !shared.iter().any(|shared| open_stanza(s, shared).is_some()));
+    before - header.stanzas.len()
+}
+
+#[cfg(feature = "full")]
+fn read_v2_file_header(path: &Path) -> Result<V2Header, String> {
+    let mut file = File::open(path)
+        .map_err(|e| format!("Failed to read encrypted file {}: {}", path.display(), e))?;
+    let mut prefix = [0u8; 9];
+    if read_full(&mut file, &mut prefix)? != prefix.len() || &prefix[..8] != MAGIC {
+        return Err(format!("{} is not a sodix encrypted file", path.display()));
+    }
+    if prefix[8] != VERSION_CHUNKED {
+        return Err(format!("{} uses container version {}, which has a fixed recipient", path.display(), prefix[8]));
+    }
+    V2Header::read(&mut file)
+}
+
+/// Replace the header of the container at `path`, whose current length is still
+/// `header.chunking.header_len`. A header that shrinks is padded and written in
+/// place; one that grows is written to a copy that then replaces the file.
+#[cfg(feature = "full")]
+fn rewrite_header(path: &Path, mut header: V2Header) -> Result<(), String> {
+    let old_len = header.chunking.header_len as usize;
+    strip_padding(&mut header.records)?;
+    let pad = old_len.saturating_sub(header.len());
+    if header.len() <= old_len && (pad == 0 || (pad >= 3 && header.records.len() + pad <= MAX_RECORDS_LEN)) {
+        if pad > 0 {
+            put_record(&mut header.records, TAG_PADDING, &vec![0u8; pad - 3]);
+        }
+        crate::cleanup::keep_for_undo(path, old_len).map_err(|e| format!("Failed to read encrypted file {}: {}", path.display(), e))?;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(|e| format!("Failed to write encrypted file {}: {}", path.display(), e))?;
+        return file
+            .write_all(&header.to_bytes())
+            .and_then(|_| file.sync_all())
+            .map_err(|e| format!("Failed to write encrypted file {}: {}", path.display(), e));
+    }
+
+    let copy = || -> io::Result<()> {
+        let mut src = File::open(path)?;
+        src.seek(SeekFrom::Start(old_len as u64))?;
+        let mut dst = AtomicFile::create(path)?;
+        dst.write_all(&header.to_bytes())?;
+        io::copy(&mut src, &mut dst)?;
+        dst.commit()
+    };
+    copy().map_err(|e| format!("Failed to write encrypted file {}: {}", path.display(), e))
+}
+
+#[cfg(feature = "full")]
+fn strip_padding(records: &mut Vec<u8>) -> Result<(), String> {
+    let mut kept = Vec::new();
+    for (tag, value) in parse_records(records)? {
+        if tag != TAG_PADDING {
+            put_record(&mut kept, tag, value);
+        }
+    }
+    *records = kept;
+    Ok(())
+}
+
+/// Decrypt a whole container of any supported version, returning the file metadata
+/// recorded at encryption time, if any.
+pub fn decrypt_stream<R: Read, W: Write>(mut reader: R, writer: W, keys: &OpenKeys) -> Result<Option<Metadata>, String> {
+    let mut prefix = [0u8; 9];
+    if read_full(&mut reader, &mut prefix)? != prefix.len() || &prefix[..8] != MAGIC {
+        return Err("Not a sodix encrypted file".to_string());
+    }
+    match prefix[8] {
+        VERSION_STREAM => {
+            let sender_pk = keys.sender_pk.as_ref().ok_or(SENDER_KEY_NEEDED)?;
+            decrypt_secretstream(reader, writer, sender_pk, &keys.recipient_sk).map(|_| None)
+        }
+        VERSION_CHUNKED => {
+            let (header, file_key) = read_v2_header(&mut reader, keys)?;
+            let metadata = header.metadata(&file_key)?;
+            decrypt_payload(&mut reader, writer, &header.chunking, &file_key)?;
+            let mut extra = [0u8; 1];
+            if read_full(&mut reader, &mut extra)? != 0 {
+                return Err("Unexpected data after final chunk".to_string());
+            }
+            Ok(metadata)
+        }
+        v => Err(format!("Unsupported encrypted file version {}", v)),
+    }
+}
+
+fn decrypt_detached<W: Write>(input: &Path, header: &Path, writer: W, keys: &OpenKeys, progress: &Progress) -> Result<Option<Metadata>, String> {
+    let (file, header, file_key) = open_chunked(input, Some(header), keys)?;
+    let metadata = header.metadata(&file_key)?;
+    let mut reader = BufReader::new(progress.reader(file));
+    decrypt_payload(&mut reader, writer, &header.chunking, &file_key)?;
+    let mut extra = [0u8; 1];
+    if read_full(&mut reader, &mut extra)? != 0 {
+        return Err("Unexpected data after final chunk".to_string());
+    }
+    Ok(metadata)
+}
+
+/// The original file name sealed in a v2 container, if it was encrypted with a
+/// hidden name. Only the header is read.
+pub fn stored_name(input: &Path, header: Option<&Path>, keys: &OpenKeys) -> Result<Option<String>, String> {
+    let (_, header, file_key) = open_chunked(input, header, keys)?;
+    header.name(&file_key)
+}
+
+/// Decrypt only plaintext bytes `offset..offset + len` of a v2 container, touching
+/// just the chunks that overlap the range.
+pub fn decrypt_range<W: Write>(input: &Path, header: Option<&Path>, offset: u64, len: Option<u64>, writer: W, keys: &OpenKeys) -> Result<(), String> {
+    let (mut file, V2Header { chunking, .. }, file_key) = open_chunked(input, header, keys)?;
+    if chunking.is_streamed() {
+        return Err(NO_RANDOM_ACCESS.to_string());
+    }
+    if chunking.codec != CODEC_NONE {
+        return Err("The file is compressed; decrypt it whole instead of a range".to_string());
+    }
+    let len = len.unwrap_or(chunking.plaintext_len.saturating_sub(offset));
+    let end = offset.checked_add(len).ok_or("Range overflows")?;
+    if end > chunking.plaintext_len {
+        return Err(format!("Range {}..{} is beyond the plaintext length {}", offset, end, chunking.plaintext_len));
+    }
+    file.seek(SeekFrom::Start(chunking.chunk_offset(offset / chunking.chunk_size as u64)))
+        .map_err(|e| format!("Failed to read encrypted file {}: {}", input.display(), e))?;
+    decrypt_chunks(&mut file, writer, &chunking, &file_key, offset, len)
+}
+
+/// The plaintext of a v2 container as a `Read + Seek` stream. Only the chunks that
+/// are read get decrypted, each one authenticated before any of it is returned.
+pub struct PlaintextReader {
+    file: BufReader<Input>,
+    chunking: Chunking,
+    cipher: XChaCha20Poly1305,
+    position: u64,
+    chunk: Option<(u64, Vec<u8>)>,
+}
+
+impl PlaintextReader {
+    pub fn open(input: &Path, header: Option<&Path>, keys: &OpenKeys) -> Result<Self, String> {
+        let (file, V2Header { chunking, .. }, file_key) = open_chunked(input, header, keys)?;
+        if chunking.is_streamed() {
+            return Err(NO_RANDOM_ACCESS.to_string());
+        }
+        Ok(PlaintextReader {
+            file: BufReader::new(file),
+            chunking,
+            cipher: XChaCha20Poly1305::new(&file_key.into()),
+            position: 0,
+            chunk: None,
+        })
+    }
+
+    /// Whether the payload is compressed, so offsets are those of the compressed stream.
+    pub fn is_compressed(&self) -> bool {
+        self.chunking.codec != CODEC_NONE
+    }
+
+    /// The plaintext, decompressed if needed; sequential reads only.
+    pub fn into_decompressed(self) -> Result<Box<dyn Read>, String> {
+        if !self.is_compressed() {
+            return Ok(Box::new(self));
+        }
+        let mut decoder = zstd::stream::read::Decoder::new(self).map_err(|e| format!("Failed to decompress data: {}", e))?;
+        if let Some(window_log) = memory::zstd_window_log_max() {
+            decoder.window_log_max(window_log).map_err(|e| format!("Failed to decompress data: {}", e))?;
+        }
+        Ok(Box::new(decoder))
+    }
+
+    fn load_chunk(&mut self, index: u64) -> io::Result<()> {
+        if matches!(&self.chunk, Some((i, _)) if *i == index) {
+            return Ok(());
+        }
+        self.file.seek(SeekFrom::Start(self.chunking.chunk_offset(index)))?;
+        let mut buf = vec![0u8; self.chunking.chunk_len(index) + TAG_LEN];
+        self.file.read_exact(&mut buf).map_err(|_| io::Error::new(io::ErrorKind::UnexpectedEof, "Encrypted file is truncated"))?;
+        self.cipher
+            .decrypt_in_place(&self.chunking.nonce(index).into(), &self.chunking.aad(index), &mut buf)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Error decrypting data: chunk authentication failed"))?;
+        self.chunk = Some((index, buf));
+        Ok(())
+    }
+}
+
+impl Read for PlaintextReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.chunking.plaintext_len || out.is_empty() {
+            return Ok(0);
+        }
+        let chunk_size = self.chunking.chunk_size as u64;
+        let index = self.position / chunk_size;
+        self.load_chunk(index)?;
+        let chunk = &self.chunk.as_ref().unwrap().1;
+        let from = (self.position - index * chunk_size) as usize;
+        let n = out.len().min(chunk.len() - from);
+        out[..n].copy_from_slice(&chunk[from..from + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for PlaintextReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.chunking.plaintext_len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of the plaintext"))?;
+        Ok(self.position)
+    }
+}
+
+/// Open a v2 container and unwrap its file key. With `header`, the header is read
+/// from that file and `input` holds only the chunks, starting at offset 0.
+fn open_chunked(input: &Path, header: Option<&Path>, keys: &OpenKeys) -> Result<(Input, V2Header, [u8; 32]), String> {
+    let header_path = header.unwrap_or(input);
+    let mut file = Input::open(header_path)
+        .map_err(|e| format!("Failed to read encrypted file {}: {}", header_path.display(), e))?;
+    let mut prefix = [0u8; 9];
+    if read_full(&mut file, &mut prefix)? != prefix.len() || &prefix[..8] != MAGIC {
+        return Err(format!("{} is not a sodix encrypted file", header_path.display()));
+    }
+    if prefix[8] != VERSION_CHUNKED {
+        return Err(format!("{} uses container version {}, which does not support random access", header_path.display(), prefix[8]));
+    }
+    let (mut v2_header, file_key) = read_v2_header(&mut file, keys)?;
+    if header.is_some() {
+        v2_header.chunking.header_len = 0;
+        file = Input::open(input)
+            .map_err(|e| format!("Failed to read encrypted file {}: {}", input.display(), e))?;
+    }
+    Ok((file, v2_header, file_key))
+}
+
+fn read_v2_header<R: Read>(reader: &mut R, keys: &OpenKeys) -> Result<(V2Header, [u8; 32]), String> {
+    let header = V2Header::read(reader)?;
+    let (file_key, from_other) = header.file_key(keys)?;
+    if from_other && header.stanzas.len() > 1 && !TRUST_RECIPIENTS.load(Ordering::Relaxed) {
+        return Err(SENDER_NOT_PROVEN.to_string());
+    }
+    Ok((header, file_key))
+}
+
+/// A parsed v2 header. Key slots and padding can change after encryption because
+/// the chunk AAD covers the payload parameters and the other records, not the key
+/// slots.
+struct V2Header {
+    chunking: Chunking,
+    stanzas: Vec<[u8; STANZA_LEN]>,
+    records: Vec<u8>,
+}
+
+impl V2Header {
+    /// Parse the header following the MAGIC | version prefix.
+    fn read<R: Read>(reader: &mut R) -> Result<Self, String> {
+        let mut fixed = [0u8; V2_FIXED_LEN - 9];
+        if read_full(reader, &mut fixed)? != fixed.len() {
+            return Err("Encrypted file header is truncated".to_string());
+        }
+        let header_len = u32::from_be_bytes(fixed[0..4].try_into().unwrap()) as usize;
+        let count = u16::from_be_bytes(fixed[32..34].try_into().unwrap()) as usize;
+        let stanzas_end = V2_FIXED_LEN + count * STANZA_LEN;
+        if count == 0 || header_len < stanzas_end || header_len - stanzas_end > MAX_RECORDS_LEN {
+            return Err("Invalid encrypted file header".to_string());
+        }
+        let mut chunking = Chunking {
+            header_len: header_len as u64,
+            chunk_size: u32::from_be_bytes(fixed[4..8].try_into().unwrap()),
+            plaintext_len: u64::from_be_bytes(fixed[8..16].try_into().unwrap()),
+            base_nonce: fixed[16..32].try_into().unwrap(),
+            codec: CODEC_NONE,
+            header_hash: None,
+        };
+        if chunking.chunk_size == 0 || chunking.chunk_size > MAX_CHUNK_SIZE {
+            return Err("Invalid chunk size in encrypted file header".to_string());
+        }
+        // Every chunk offset must fit in a u64; a streamed container has no fixed length
+        if !chunking.is_streamed() && chunking.container_len().is_none() {
+            return Err("Invalid plaintext length in encrypted file header".to_string());
+        }
+        let mut rest = vec![0u8; header_len - V2_FIXED_LEN];
+        if read_full(reader, &mut rest)? != rest.len() {
+            return Err("Encrypted file header is truncated".to_string());
+        }
+        let records = rest.split_off(count * STANZA_LEN);
+        for (tag, value) in parse_records(&records)? {
+            if tag == TAG_COMPRESSION {
+                chunking.codec = match value {
+                    [CODEC_ZSTD] => CODEC_ZSTD,
+                    _ => return Err("Unsupported compression in encrypted file header".to_string()),
+                };
+            }
+        }
+        let stanzas = rest.chunks(STANZA_LEN).map(|s| s.try_into().unwrap()).collect();
+        let mut header = V2Header { chunking, stanzas, records };
+        header.chunking.header_hash = header.binding()?;
+        Ok(header)
+    }
+
+    /// The hash the chunks are bound to, if the header has the binding record.
+    fn binding(&self) -> Result<Option<[u8; 32]>, String> {
+        let records = parse_records(&self.records)?;
+        if !records.iter().any(|(tag, _)| *tag == TAG_HEADER_BINDING) {
+            return Ok(None);
+        }
+        let chunking = &self.chunking;
+        let mut state = crypto_generichash_init(None, 32).unwrap();
+        crypto_generichash_update(&mut state, HEADER_CONTEXT);
+        crypto_generichash_update(&mut state, &chunking.chunk_size.to_be_bytes());
+        crypto_generichash_update(&mut state, &chunking.plaintext_len.to_be_bytes());
+        crypto_generichash_update(&mut state, &chunking.base_nonce);
+        for (tag, value) in records.into_iter().filter(|(tag, _)| *tag != TAG_PADDING) {
+            crypto_generichash_update(&mut state, &[tag]);
+            crypto_generichash_update(&mut state, &(value.len() as u16).to_be_bytes());
+            crypto_generichash_update(&mut state, value);
+        }
+        let mut hash = [0u8; 32];
+        crypto_generichash_final(state, &mut hash).unwrap();
+        Ok(Some(hash))
+    }
+
+    #[cfg(feature = "full")]
+    fn len(&self) -> usize {
+        V2_FIXED_LEN + self.stanzas.len() * STANZA_LEN + self.records.len()
+    }
+
+    #[cfg(feature = "full")]
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(self.len());
+        header.extend_from_slice(MAGIC);
+        header.push(VERSION_CHUNKED);
+        header.extend_from_slice(&(self.len() as u32).to_be_bytes());
+        header.extend_from_slice(&self.chunking.chunk_size.to_be_bytes());
+        header.extend_from_slice(&self.chunking.plaintext_len.to_be_bytes());
+        header.extend_from_slice(&self.chunking.base_nonce);
+        header.extend_from_slice(&(self.stanzas.len() as u16).to_be_bytes());
+        for stanza in &self.stanzas {
+            header.extend_from_slice(stanza);
+        }
+        header.extend_from_slice(&self.records);
+        header
+    }
+
+    /// The sealed file metadata record, if the sender recorded one.
+    fn metadata(&self, file_key: &[u8; 32]) -> Result<Option<Metadata>, String> {
+        parse_records(&self.records)?
+            .into_iter()
+            .find(|(tag, _)| *tag == TAG_METADATA)
+            .map(|(_, value)| Metadata::open(value, file_key))
+            .transpose()
+    }
+
+    /// The sealed original file name, for files encrypted with a hidden name.
+    fn name(&self, file_key: &[u8; 32]) -> Result<Option<String>, String> {
+        let Some((_, value)) = parse_records(&self.records)?.into_iter().find(|(tag, _)| *tag == TAG_NAME) else {
+            return Ok(None);
+        };
+        let name = open_record(file_key, NAME_CONTEXT, value)
+            .ok_or("Error decrypting data: file name authentication failed")?;
+        String::from_utf8(name).map(Some).map_err(|_| "Invalid file name in encrypted file header".to_string())
+    }
+
+    /// Unwrap the file key from whichever key slot opens with our keys. Slots are
+    /// wrapped from the signed ephemeral sender key or, for slots added later with
+    /// `recipients add`, from the static key of whoever added them. Also returns
+    /// whether that slot came from someone else's key: a sender key other than our
+    /// own, or an ephemeral key checked against a --signer rather than our default
+    /// signing key.
+    fn file_key(&self, keys: &OpenKeys) -> Result<([u8; 32], bool), String> {
+        let mut senders = Vec::new();
+        let mut ephemeral_error = None;
+        for (tag, value) in parse_records(&self.records)? {
+            if tag != TAG_EPHEMERAL_SENDER {
+                continue;
+            }
+            if value.len() != 32 + 64 {
+                return Err("Invalid ephemeral sender record".to_string());
+            }
+            let eph_pk: [u8; 32] = value[..32].try_into().unwrap();
+            let signature: [u8; 64] = value[32..].try_into().unwrap();
+            let signer_pk = match keys.signer_pk {
+                Some(signer_pk) => Some(signer_pk),
+                None => crate::default_signing_public_key()?,
+            };
+            match &signer_pk {
+                None => ephemeral_error = Some(
+                    "The file was encrypted with a signed ephemeral key; pass the sender's signing public key with --signer",
+                ),
+                Some(signer_pk) => match crypto_sign_verify_detached(&signature, &[EPHEMERAL_CONTEXT, &eph_pk].concat(), signer_pk) {
+                    Ok(_) => senders.push(eph_pk),
+                    Err(_) => ephemeral_error = Some("The ephemeral sender key is not signed by the expected sender"),
+                },
+            }
+        }
+        let mut own_pk = [0u8; 32];
+        crypto_scalarmult_base(&mut own_pk, &keys.recipient_sk);
+        let mut shared: Vec<_> = senders.iter().map(|pk| (SharedKey::new(pk, &keys.recipient_sk), keys.signer_pk.is_some())).collect();
+        shared.extend(keys.sender_pk.map(|pk| (shared_key(&pk, &keys.recipient_sk), pk != own_pk)));
+
+        // Recipients are not named in the header, so try each key slot in turn
+        for (shared, from_other) in &shared {
+            for stanza in &self.stanzas {
+                if let Some(file_key) = open_stanza(stanza, shared) {
+                    return Ok((file_key, *from_other));
+                }
+            }
+        }
+        Err(match ephemeral_error {
+            Some(e) => e.to_string(),
+            None if senders.is_empty() => SENDER_KEY_NEEDED.to_string(),
+            None => "Error decrypting data: the file is not encrypted to this key pair".to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "full")]
+fn wrap_stanza(file_key: &[u8; 32], recipient_pk: &[u8; 32], shared: &SharedKey, deterministic: bool) -> Result<[u8; STANZA_LEN], String> {
+    let mut stanza = [0u8; STANZA_LEN];
+    let mut box_nonce = [0u8; 24];
+    if deterministic {
+        // Unique per (file key, recipient), so reuse only ever re-encrypts the same key
+        crypto_generichash(&mut box_nonce, recipient_pk, Some(file_key)).unwrap();
+    } else {
+        rand::rng().fill(&mut box_nonce);
+    }
+    let boxed = shared.seal(file_key, &box_nonce).map_err(|e| format!("Error encrypting file key: {}", e))?;
+    stanza[..24].copy_from_slice(&box_nonce);
+    stanza[24..].copy_from_slice(&boxed);
+    Ok(stanza)
+}
+
+/// crypto_box is symmetric in the two key pairs, so a sender can also open the slots
+/// it wrapped with the shared key of the recipient's public key and its own secret key.
+fn open_stanza(stanza: &[u8; STANZA_LEN], shared: &SharedKey) -> Option<[u8; 32]> {
+    let box_nonce: [u8; 24] = stanza[..24].try_into().unwrap();
+    shared.open(&stanza[24..], &box_nonce).ok()?.try_into().ok()
+}
+
+/// Encrypt a header record value under the file key with a random nonce, so records
+/// stay confidential to recipients and cannot be altered without the key. Fails if
+/// the result does not fit in a record.
+#[cfg(feature = "full")]
+fn seal_record(file_key: &[u8; 32], context: &[u8], mut plain: Vec<u8>) -> Result<Vec<u8>, ()> {
+    let mut nonce = [0u8; 24];
+    rand::rng().fill(&mut nonce);
+    XChaCha20Poly1305::new(file_key.into())
+        .encrypt_in_place(&nonce.into(), context, &mut plain)
+        .map_err(|_| ())?;
+    if nonce.len() + plain.len() > u16::MAX as usize {
+        return Err(());
+    }
+    Ok([&nonce[..], &plain].concat())
+}
+
+fn open_record(file_key: &[u8; 32], context: &[u8], value: &[u8]) -> Option<Vec<u8>> {
+    if value.len() < 24 + TAG_LEN {
+        return None;
+    }
+    let nonce: [u8; 24] = value[..24].try_into().unwrap();
+    let mut plain = value[24..].to_vec();
+    XChaCha20Poly1305::new(file_key.into())
+        .decrypt_in_place(&nonce.into(), context, &mut plain)
+        .ok()
+        .map(|_| plain)
+}
+
+fn parse_records(mut records: &[u8]) -> Result<Vec<(u8, &[u8])>, String> {
+    let mut parsed = Vec::new();
+    while !records.is_empty() {
+        if records.len() < 3 {
+            return Err("Encrypted file header is truncated".to_string());
+        }
+        let len = u16::from_be_bytes([records[1], records[2]]) as usize;
+        let value = records.get(3..3 + len).ok_or("Encrypted file header is truncated")?;
+        parsed.push((records[0], value));
+        records = &records[3 + len..];
+    }
+    Ok(parsed)
+}
+
+/// Decrypt all chunks, decompressing a compressed payload on the way out.
+fn decrypt_payload<R: Read, W: Write>(reader: &mut R, writer: W, chunking: &Chunking, file_key: &[u8; 32]) -> Result<(), String> {
+    let decrypt_all = |reader: &mut R, writer: &mut dyn Write| match chunking.is_streamed() {
+        true => decrypt_streamed(reader, writer, chunking, file_key),
+        false => decrypt_chunks(reader, writer, chunking, file_key, 0, chunking.plaintext_len),
+    };
+    if chunking.codec == CODEC_NONE {
+        let mut writer = writer;
+        return decrypt_all(reader, &mut writer);
+    }
+    let mut decoder = zstd::stream::write::Decoder::new(writer).map_err(|e| format!("Failed to decompress data: {}", e))?;
+    if let Some(window_log) = memory::zstd_window_log_max() {
+        decoder.window_log_max(window_log).map_err(|e| format!("Failed to decompress data: {}", e))?;
+    }
+    decrypt_all(reader, &mut decoder)?;
+    decoder.flush().map_err(|e| format!("Failed to decompress data: {}", e))
+}
+
+/// Decrypt the chunks of a streamed container, reading one ahead to recognize the
+/// last: the chunk the input ends with must be the one sealed as final.
+fn decrypt_streamed<R: Read, W: Write>(reader: &mut R, mut writer: W, chunking: &Chunking, file_key: &[u8; 32]) -> Result<(), String> {
+    let cipher = XChaCha20Poly1305::new(file_key.into());
+    let size = chunking.chunk_size as usize + TAG_LEN;
+    let mut chunk = vec![0u8; size];
+    let mut next = vec![0u8; size];
+    let mut len = read_full(reader, &mut chunk)?;
+    let mut buf = Vec::with_capacity(size);
+    for index in 0.. {
+        if len < TAG_LEN {
+            return Err("Encrypted file is truncated".to_string());
+        }
+        let next_len = if len == size { read_full(reader, &mut next)? } else { 0 };
+        let last = next_len == 0;
+        buf.clear();
+        buf.extend_from_slice(&chunk[..len]);
+        cipher
+            .decrypt_in_place(&chunking.nonce(index).into(), &chunking.final_aad(last), &mut buf)
+            .map_err(|_| "Error decrypting data: chunk authentication failed".to_string())?;
+        writer.write_all(&buf).map_err(|e| format!("Failed to write output: {}", e))?;
+        if last {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+    }
+    Ok(())
+}
+
+/// Decrypt plaintext bytes `offset..offset + len`. The reader must be positioned
+/// at the start of the chunk containing `offset`.
+fn decrypt_chunks<R: Read, W: Write>(reader: &mut R, mut writer: W, chunking: &Chunking, file_key: &[u8; 32], offset: u64, len: u64) -> Result<(), String> {
+    let cipher = XChaCha20Poly1305::new(file_key.into());
+    let chunk_size = chunking.chunk_size as u64;
+    let first = offset / chunk_size;
+    let last = if len == 0 { first } else { (offset + len - 1) / chunk_size };
+    let last = last.min(chunking.chunk_count() - 1);
+
+    // The first chunk is the longest; a short file needs no full-size buffer
+    let mut buf = Vec::with_capacity(chunking.chunk_len(first) + TAG_LEN);
+    for index in first..=last {
+        let ct_len = chunking.chunk_len(index) + TAG_LEN;
+        buf.resize(ct_len, 0);
+        if read_full(reader, &mut buf)? != ct_len {
+            return Err("Encrypted file is truncated".to_string());
+        }
+        cipher
+            .decrypt_in_place(&chunking.nonce(index).into(), &chunking.aad(index), &mut buf)
+            .map_err(|_| "Error decrypting data: chunk authentication failed".to_string())?;
+        let chunk_start = index * chunk_size;
+        let from = offset.saturating_sub(chunk_start).min(buf.len() as u64) as usize;
+        let to = (offset + len - chunk_start).min(buf.len() as u64) as usize;
+        writer.write_all(&buf[from..to]).map_err(|e| format!("Failed to write output: {}", e))?;
+    }
+    Ok(())
+}
+
+fn decrypt_secretstream<R: Read, W: Write>(mut reader: R, mut writer: W, sender_pk: &[u8; 32], recipient_sk: &[u8; 32]) -> Result<(), String> {
+    const ABYTES: usize = CRYPTO_SECRETSTREAM_XCHACHA20POLY1305_ABYTES;
+    const CHUNK: usize = 64 * 1024;
+    let mut header = [0u8; 9 + 24 + 48 + 24];
+    header[..8].copy_from_slice(MAGIC);
+    header[8] = VERSION_STREAM;
+    if read_full(&mut reader, &mut header[9..])? != header.len() - 9 {
+        return Err("Encrypted file header is truncated".to_string());
+    }
+    let box_nonce: [u8; 24] = header[9..33].try_into().unwrap();
+    let stream_header: [u8; 24] = header[81..105].try_into().unwrap();
+    let file_key: [u8; 32] = shared_key(sender_pk, recipient_sk)
+        .open(&header[33..81], &box_nonce)
+        .map_err(|e| format!("Error decrypting data: {}", e))?
+        .try_into()
+        .unwrap();
+
+    let mut state = State::new();
+    crypto_secretstream_xchacha20poly1305_init_pull(&mut state, &stream_header, &file_key);
+
+    let mut buf = vec![0u8; CHUNK + ABYTES];
+    let mut msg = vec![0u8; CHUNK];
+    let mut first = true;
+    loop {
+        let n = read_full(&mut reader, &mut buf)?;
+        if n < ABYTES {
+            return Err("Encrypted file is truncated".to_string());
+        }
+        let ad = if first { Some(&header[..]) } else { None };
+        let mut tag = 0u8;
+        let mlen = crypto_secretstream_xchacha20poly1305_pull(&mut state, &mut msg[..n - ABYTES], &mut tag, &buf[..n], ad)
+            .map_err(|e| format!("Error decrypting data: {}", e))?;
+        writer.write_all(&msg[..mlen]).map_err(|e| format!("Failed to write output: {}", e))?;
+        if tag == CRYPTO_SECRETSTREAM_XCHACHA20POLY1305_TAG_FINAL {
+            let mut extra = [0u8; 1];
+            if read_full(&mut reader, &mut extra)? != 0 {
+                return Err("Unexpected data after final chunk".to_string());
+            }
+            return Ok(());
+        }
+        if n < buf.len() {
+            return Err("Encrypted file is truncated".to_string());
+        }
+        first = false;
+    }
+}
+
+#[cfg(feature = "full")]
+fn put_record(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+/// Fill `buf` as far as the reader allows; short only at end of input.
+pub fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, String> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(format!("Failed to read input: {}", e)),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(all(test, feature = "full"))]
+mod tests {
+    use super::*;
+
+    const CHUNK: usize = CHUNK_SIZE as usize;
+
+    /// An X25519 key pair as (secret, public).
+    fn key_pair() -> ([u8; 32], [u8; 32]) {
+        let mut sk = [0u8; 32];
+        rand::rng().fill(&mut sk);
+        let mut pk = [0u8; 32];
+        crypto_scalarmult_base(&mut pk, &sk);
+        (sk, pk)
+    }
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 + i / 251) as u8).collect()
+    }
+
+    struct Fixture {
+        sender: ([u8; 32], [u8; 32]),
+        recipient: ([u8; 32], [u8; 32]),
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Fixture { sender: key_pair(), recipient: key_pair() }
+        }
+
+        fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+            encrypt_bytes(plaintext, &[self.recipient.1], &Sender::Static(self.sender.0), None).unwrap()
+        }
+
+        fn seal_streamed(&self, plaintext: &[u8], compression: Option<i32>) -> Vec<u8> {
+            let mut out = Vec::new();
+            encrypt_pipe(plaintext, &mut out, &[self.recipient.1], &Sender::Static(self.sender.0), compression).unwrap();
+            out
+        }
+
+        fn open(&self, container: &[u8]) -> Result<Vec<u8>, String> {
+            let keys = OpenKeys { recipient_sk: self.recipient.0, sender_pk: Some(self.sender.1), signer_pk: None };
+            let mut out = Vec::new();
+            decrypt_stream(container, &mut out, &keys)?;
+            Ok(out)
+        }
+    }
+
+    fn header_len(container: &[u8]) -> usize {
+        u32::from_be_bytes(container[9..13].try_into().unwrap()) as usize
+    }
+
+    /// Byte range of chunk `index` of a container written with CHUNK_SIZE.
+    fn chunk(container: &[u8], index: usize) -> std::ops::Range<usize> {
+        let start = header_len(container) + index * (CHUNK + TAG_LEN);
+        start..(start + CHUNK + TAG_LEN).min(container.len())
+    }
+
+    /// Offset of the first header record with `tag`.
+    fn record(container: &[u8], tag: u8) -> usize {
+        let count = u16::from_be_bytes(container[41..43].try_into().unwrap()) as usize;
+        let mut at = V2_FIXED_LEN + count * STANZA_LEN;
+        while container[at] != tag {
+            at += 3 + u16::from_be_bytes(container[at + 1..at + 3].try_into().unwrap()) as usize;
+        }
+        at
+    }
+
+    #[test]
+    fn round_trip() {
+        let fixture = Fixture::new();
+        for len in [0, 1, CHUNK - 1, CHUNK, CHUNK + 1, 3 * CHUNK + 5] {
+            let plaintext = data(len);
+            assert_eq!(fixture.open(&fixture.seal(&plaintext)).unwrap(), plaintext, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn streamed_round_trip() {
+        let fixture = Fixture::new();
+        for len in [0, 1, CHUNK, 2 * CHUNK, 2 * CHUNK + 7] {
+            let plaintext = data(len);
+            assert_eq!(fixture.open(&fixture.seal_streamed(&plaintext, None)).unwrap(), plaintext, "length {}", len);
+            assert_eq!(fixture.open(&fixture.seal_streamed(&plaintext, Some(3))).unwrap(), plaintext, "compressed length {}", len);
+        }
+    }
+
+    #[test]
+    fn truncation_is_detected() {
+        let fixture = Fixture::new();
+        let container = fixture.seal(&data(3 * CHUNK + 5));
+        assert!(fixture.open(&container[..container.len() - 1]).is_err());
+        // Cut at a chunk boundary: the remaining chunks all authenticate on their own
+        assert!(fixture.open(&container[..chunk(&container, 3).start]).is_err());
+        assert!(fixture.open(&container[..header_len(&container)]).is_err());
+    }
+
+    #[test]
+    fn streamed_truncation_at_chunk_boundary_is_detected() {
+        let fixture = Fixture::new();
+        let container = fixture.seal_streamed(&data(2 * CHUNK + 7), None);
+        // Chunk 1 was sealed as not final, so a stream ending after it is incomplete
+        let cut = &container[..chunk(&container, 2).start];
+        assert!(fixture.open(cut).unwrap_err().contains("authentication failed"));
+    }
+
+    #[test]
+    fn extra_chunk_is_detected() {
+        let fixture = Fixture::new();
+        let container = fixture.seal_streamed(&data(CHUNK + 7), None);
+        // Append a copy of the final chunk: the first chunk is then no longer the one
+        // before the final one, nor is the final chunk last
+        let mut extended = container.clone();
+        extended.extend_from_slice(&container[chunk(&container, 1)]);
+        assert!(fixture.open(&extended).is_err());
+    }
+
+    #[test]
+    fn reordered_chunks_are_detected() {
+        let fixture = Fixture::new();
+        let container = fixture.seal(&data(3 * CHUNK + 5));
+        let mut swapped = container[..chunk(&container, 0).start].to_vec();
+        swapped.extend_from_slice(&container[chunk(&container, 1)]);
+        swapped.extend_from_slice(&container[chunk(&container, 0)]);
+        swapped.extend_from_slice(&container[chunk(&container, 2).start..]);
+        assert_eq!(swapped.len(), container.len());
+        assert!(fixture.open(&swapped).unwrap_err().contains("authentication failed"));
+    }
+
+    #[test]
+    fn header_edits_are_detected() {
+        let fixture = Fixture::new();
+        let plaintext = data(2 * CHUNK + 5);
+        let container = fixture.seal(&plaintext);
+        // plaintext_len one chunk shorter would make chunk 1 the final one
+        let mut edited = container.clone();
+        edited[17..25].copy_from_slice(&((CHUNK + 5) as u64).to_be_bytes());
+        assert!(fixture.open(&edited).is_err());
+        // Base nonce
+        let mut edited = container.clone();
+        edited[25] ^= 1;
+        assert!(fixture.open(&edited).is_err());
+        // A smaller chunk size that still fits the file
+        let mut edited = container.clone();
+        edited[13..17].copy_from_slice(&(CHUNK_SIZE / 2).to_be_bytes());
+        assert!(fixture.open(&edited).is_err());
+        assert_eq!(fixture.open(&container).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn header_records_are_bound_to_the_chunks() {
+        let fixture = Fixture::new();
+        let plaintext = data(CHUNK + 5);
+        let mut container = Vec::new();
+        let sender = Sender::Static(fixture.sender.0);
+        encrypt_chunked(&plaintext[..], &mut container, None, plaintext.len() as u64, CODEC_NONE, &[fixture.recipient.1], &sender, None, None, Some("a.txt")).unwrap();
+        assert_eq!(fixture.open(&container).unwrap(), plaintext);
+        // Turning a record into padding or an unknown tag would otherwise go unnoticed
+        for tag in [TAG_NAME, TAG_HEADER_BINDING] {
+            for replacement in [TAG_PADDING, 0x7f] {
+                let mut edited = container.clone();
+                edited[record(&container, tag)] = replacement;
+                assert!(fixture.open(&edited).is_err(), "record {} as {}", tag, replacement);
+            }
+        }
+        // Dropping the compression record of a compressed stream
+        let compressed = fixture.seal_streamed(&plaintext, Some(3));
+        let mut edited = compressed.clone();
+        edited[record(&compressed, TAG_COMPRESSION)] = TAG_PADDING;
+        assert!(fixture.open(&edited).is_err());
+    }
+
+    #[test]
+    fn oversized_chunk_size_is_refused_before_reading_chunks() {
+        let fixture = Fixture::new();
+        for container in [fixture.seal(&data(10)), fixture.seal_streamed(&data(10), None)] {
+            let mut edited = container.clone();
+            edited[13..17].copy_from_slice(&(MAX_CHUNK_SIZE + 1).to_be_bytes());
+            assert_eq!(fixture.open(&edited).unwrap_err(), "Invalid chunk size in encrypted file header");
+            edited[13..17].copy_from_slice(&u32::MAX.to_be_bytes());
+            assert_eq!(fixture.open(&edited).unwrap_err(), "Invalid chunk size in encrypted file header");
+        }
+    }
+
+    #[test]
+    fn other_senders_are_not_trusted_with_several_key_slots() {
+        let fixture = Fixture::new();
+        let (_, other_pk) = key_pair();
+        let container = encrypt_bytes(b"hi", &[fixture.recipient.1, other_pk], &Sender::Static(fixture.sender.0), None).unwrap();
+        assert_eq!(fixture.open(&container).unwrap_err(), SENDER_NOT_PROVEN);
+    }
+
+    #[test]
+    fn removing_a_recipient_keeps_the_header_binding() {
+        let fixture = Fixture::new();
+        let (_, other_pk) = key_pair();
+        let plaintext = data(CHUNK + 5);
+        let container = encrypt_bytes(&plaintext, &[fixture.recipient.1, other_pk], &Sender::Static(fixture.sender.0), None).unwrap();
+        let path = std::env::temp_dir().join(format!("sodix-test-{}-{}.x", std::process::id(), hex::encode(rand::rng().random::<[u8; 8]>())));
+        fs::write(&path, &container).unwrap();
+        // The header shrinks in place, leaving padding that the binding must skip
+        let removed = remove_recipients(&path, &fixture.sender.0, &[other_pk]);
+        let rewritten = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(removed.unwrap(), 1);
+        assert_eq!(rewritten.len(), container.len());
+        assert_eq!(fixture.open(&rewritten).unwrap(), plaintext);
+    }
+}
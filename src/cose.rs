@@ -0,0 +1,290 @@
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+
+// COSE_Sign1 signatures (RFC 9052) with EdDSA, for CBOR-based protocols:
+//
+//   COSE_Sign1 = 18([protected: bstr .cbor {1: -8}, unprotected: {}, payload: bstr / nil,
+//                    signature: bstr])
+//
+// The signature is Ed25519 over the CBOR encoding of the Sig_structure
+//
+//   ["Signature1", protected, external_aad: h'', payload]
+//
+// A detached signature (the default) has a nil payload and is checked against the
+// message given separately; --attached embeds the message. Untagged COSE_Sign1 and
+// unprotected headers (which are not covered by the signature) are accepted and ignored.
+const TAG_SIGN1: u64 = 18;
+const CONTEXT: &str = "Signature1";
+const ALG_EDDSA: i64 = -8;
+const HEADER_ALG: i64 = 1;
+#[cfg(feature = "full")]
+const PROTECTED: &[u8] = &[0xa1, 0x01, 0x27]; // {1: -8}
+
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+const SIMPLE: u8 = 7;
+const NIL: u8 = 0xf6;
+const MAX_DEPTH: usize = 16;
+
+pub struct Sign1<'a> {
+    pub payload: Option<&'a [u8]>,
+    protected: &'a [u8],
+    signature: [u8; 64],
+}
+
+/// COSE_Sign1 of `message`, which is embedded if `attached`.
+#[cfg(feature = "full")]
+pub fn sign(message: &[u8], secret_key: &[u8; 64], attached: bool) -> Result<Vec<u8>, String> {
+    let mut signature = [0u8; 64];
+    crypto_sign_detached(&mut signature, &sig_structure(PROTECTED, message), secret_key)
+        .map_err(|e| format!("Error signing data: {}", e))?;
+    let mut out = Vec::new();
+    put_header(&mut out, TAG, TAG_SIGN1);
+    put_header(&mut out, ARRAY, 4);
+    put_bytes(&mut out, PROTECTED);
+    put_header(&mut out, MAP, 0);
+    if attached {
+        put_bytes(&mut out, message);
+    } else {
+        out.push(NIL);
+    }
+    put_bytes(&mut out, &signature);
+    Ok(out)
+}
+
+pub fn parse(data: &[u8]) -> Result<Sign1<'_>, String> {
+    let mut cbor = Cbor(data);
+    let (mut major, mut value) = cbor.header()?;
+    if major == TAG {
+        if value != TAG_SIGN1 {
+            return Err(format!("Not a COSE_Sign1 (CBOR tag {})", value));
+        }
+        (major, value) = cbor.header()?;
+    }
+    if major != ARRAY || value != 4 {
+        return Err("Not a COSE_Sign1 structure".to_string());
+    }
+    let protected = cbor.bytes()?;
+    cbor.skip(0)?; // unprotected headers
+    let payload = if cbor.0.first() == Some(&NIL) {
+        cbor.0 = &cbor.0[1..];
+        None
+    } else {
+        Some(cbor.bytes()?)
+    };
+    let signature = cbor.bytes()?.try_into().map_err(|_| "COSE Ed25519 signature must be 64 bytes".to_string())?;
+    if !cbor.0.is_empty() {
+        return Err("Trailing data after COSE_Sign1".to_string());
+    }
+    if algorithm(protected)? != Some(ALG_EDDSA) {
+        return Err("COSE_Sign1 algorithm must be EdDSA (-8)".to_string());
+    }
+    Ok(Sign1 { payload, protected, signature })
+}
+
+impl Sign1<'_> {
+    /// Verify against `detached` for a signature without payload, else the embedded one.
+    pub fn verify(&self, detached: Option<&[u8]>, public_key: &[u8; 32]) -> Result<(), String> {
+        let message = match (self.payload, detached) {
+            (Some(_), Some(_)) => return Err("The COSE_Sign1 has an embedded payload; check it with --attached".to_string()),
+            (None, None) => return Err("The COSE_Sign1 is detached; give the signed message too".to_string()),
+            (Some(message), None) | (None, Some(message)) => message,
+        };
+        crypto_sign_verify_detached(&self.signature, &sig_structure(self.protected, message), public_key)
+            .map_err(|_| "COSE signature is invalid".to_string())
+    }
+}
+
+fn sig_structure(protected: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    put_header(&mut out, ARRAY, 4);
+    put_header(&mut out, TEXT, CONTEXT.len() as u64);
+    out.extend_from_slice(CONTEXT.as_bytes());
+    put_bytes(&mut out, protected);
+    put_bytes(&mut out, &[]);
+    put_bytes(&mut out, payload);
+    out
+}
+
+/// The alg (label 1) of a protected header map.
+fn algorithm(protected: &[u8]) -> Result<Option<i64>, String> {
+    if protected.is_empty() {
+        return Ok(None);
+    }
+    let mut cbor = Cbor(protected);
+    let (major, entries) = cbor.header()?;
+    if major != MAP {
+        return Err("COSE protected header must be a map".to_string());
+    }
+    let mut alg = None;
+    for _ in 0..entries {
+        let label = cbor.int()?;
+        if label == Some(HEADER_ALG) {
+            alg = cbor.int()?;
+        } else {
+            cbor.skip(0)?;
+        }
+    }
+    Ok(alg)
+}
+
+fn put_header(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..=23 => out.push(major | value as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, value as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_header(out, BYTES, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Reader for the definite-length CBOR that COSE uses.
+struct Cbor<'a>(&'a [u8]);
+
+impl<'a> Cbor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let (value, rest) = self.0.split_at_checked(n).ok_or("Truncated CBOR")?;
+        self.0 = rest;
+        Ok(value)
+    }
+
+    /// Major type and argument of the next item.
+    fn header(&mut self) -> Result<(u8, u64), String> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let value = match info {
+            0..=23 => info as u64,
+            24..=27 => self.take(1 << (info - 24))?.iter().fold(0, |n, b| n << 8 | *b as u64),
+            _ => return Err("Indefinite-length CBOR is not supported".to_string()),
+        };
+        Ok((major, value))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        match self.header()? {
+            (BYTES, len) => self.take(usize::try_from(len).map_err(|_| "Truncated CBOR")?),
+            _ => Err("Expected a CBOR byte string".to_string()),
+        }
+    }
+
+    /// The next item if it is an integer that fits in i64; other items are skipped.
+    fn int(&mut self) -> Result<Option<i64>, String> {
+        let before = self.0;
+        match self.header()? {
+            (UNSIGNED, n) => Ok(i64::try_from(n).ok()),
+            (NEGATIVE, n) => Ok(i64::try_from(n).ok().map(|n| -1 - n)),
+            _ => {
+                self.0 = before;
+                self.skip(0)?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn skip(&mut self, depth: usize) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err("CBOR nested too deeply".to_string());
+        }
+        let (major, value) = self.header()?;
+        match major {
+            BYTES | TEXT => {
+                self.take(usize::try_from(value).map_err(|_| "Truncated CBOR")?)?;
+            }
+            ARRAY => (0..value).try_for_each(|_| self.skip(depth + 1))?,
+            MAP => (0..value.saturating_mul(2)).try_for_each(|_| self.skip(depth + 1))?,
+            TAG => self.skip(depth + 1)?,
+            UNSIGNED | NEGATIVE | SIMPLE => {}
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "full"))]
+mod tests {
+    use super::*;
+
+    // COSE_Sign1 of MESSAGE with the RFC 8032 TEST 1 key, built by hand from the
+    // RFC 9052 section 4.4 Sig_structure: tag 18, protected {1: -8}, no unprotected
+    // headers, and the payload nil (detached) or embedded.
+    const SECRET_KEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60\
+        d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const MESSAGE: &[u8] = b"This is the content.";
+    const SIG_STRUCTURE: &str = "846a5369676e61747572653143a101274054546869732069732074686520636f6e74656e742e";
+    const DETACHED: &str = "d28443a10127a0f658406354488f9f290e36cd80e23762e664a5cb03e4267c66a8cffaef7c66d89a40bf2cbb\
+        8222432a08e5ee410d8b540c6931d26fb6af673f7e2100655d8bae765c04";
+    const ATTACHED: &str = "d28443a10127a054546869732069732074686520636f6e74656e742e58406354488f9f290e36cd80e23762e6\
+        64a5cb03e4267c66a8cffaef7c66d89a40bf2cbb8222432a08e5ee410d8b540c6931d26fb6af673f7e2100655d8bae765c04";
+
+    fn secret_key() -> [u8; 64] {
+        hex::decode(SECRET_KEY).unwrap().try_into().unwrap()
+    }
+
+    fn public_key() -> [u8; 32] {
+        secret_key()[32..].try_into().unwrap()
+    }
+
+    #[test]
+    fn sig_structure_known_answer() {
+        assert_eq!(hex::encode(sig_structure(PROTECTED, MESSAGE)), SIG_STRUCTURE);
+    }
+
+    #[test]
+    fn signatures_known_answer() {
+        assert_eq!(hex::encode(sign(MESSAGE, &secret_key(), false).unwrap()), DETACHED);
+        assert_eq!(hex::encode(sign(MESSAGE, &secret_key(), true).unwrap()), ATTACHED);
+    }
+
+    #[test]
+    fn known_signatures_verify() {
+        let detached = hex::decode(DETACHED).unwrap();
+        parse(&detached).unwrap().verify(Some(MESSAGE), &public_key()).unwrap();
+        let attached = hex::decode(ATTACHED).unwrap();
+        let sign1 = parse(&attached).unwrap();
+        assert_eq!(sign1.payload, Some(MESSAGE));
+        sign1.verify(None, &public_key()).unwrap();
+        // Untagged COSE_Sign1 is accepted too
+        parse(&detached[1..]).unwrap().verify(Some(MESSAGE), &public_key()).unwrap();
+    }
+
+    #[test]
+    fn tampering_is_refused() {
+        let detached = hex::decode(DETACHED).unwrap();
+        let error = parse(&detached).unwrap().verify(Some(b"This is the content!"), &public_key()).unwrap_err();
+        assert_eq!(error, "COSE signature is invalid");
+
+        let mut attached = hex::decode(ATTACHED).unwrap();
+        attached[10] ^= 1; // in the payload
+        assert_eq!(parse(&attached).unwrap().verify(None, &public_key()).unwrap_err(), "COSE signature is invalid");
+
+        // Another algorithm in the protected header: {1: -7} (ES256)
+        let mut es256 = hex::decode(DETACHED).unwrap();
+        es256[5] = 0x26;
+        assert_eq!(parse(&es256).err().unwrap(), "COSE_Sign1 algorithm must be EdDSA (-8)");
+
+        let mut trailing = hex::decode(DETACHED).unwrap();
+        trailing.push(0);
+        assert!(parse(&trailing).is_err());
+    }
+}
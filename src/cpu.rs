@@ -0,0 +1,76 @@
+// CPU features behind the fast paths. The cipher, MAC and hash crates check for them
+// once at runtime (CPUID on x86, hwcaps on aarch64) and fall back to portable code
+// without them, so one binary runs everywhere; `version --features` shows what this
+// machine gets. On aarch64 the ARMv8 AES/PMULL and NEON ChaCha20 backends are only
+// compiled in when RUSTFLAGS sets their cfg flags (see the README); a plain
+// `cargo install` gets the portable code there.
+
+/// A CPU feature, whether this machine has it and this build has code for it, and
+/// what uses it.
+pub struct Feature {
+    pub name: &'static str,
+    pub detected: bool,
+    pub built: bool,
+    pub used_for: &'static str,
+}
+
+impl Feature {
+    pub fn status(&self) -> &'static str {
+        match (self.built, self.detected) {
+            (false, _) => "not built",
+            (true, true) => "active",
+            (true, false) => "absent",
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn features() -> Vec<Feature> {
+    use std::arch::is_x86_feature_detected as detected;
+    vec![
+        Feature {
+            name: "avx2",
+            detected: detected!("avx2"),
+            built: true,
+            used_for: "ChaCha20 and Poly1305 (containers, --aead xchacha20poly1305), Ed25519 batch verification, hex",
+        },
+        Feature { name: "sse2", detected: detected!("sse2"), built: true, used_for: "ChaCha20 without AVX2" },
+        Feature { name: "sse4.1", detected: detected!("sse4.1"), built: true, used_for: "Hex without AVX2" },
+        Feature { name: "aes", detected: detected!("aes"), built: true, used_for: "AES-256-GCM (--aead aes256gcm)" },
+        Feature { name: "pclmulqdq", detected: detected!("pclmulqdq"), built: true, used_for: "GHASH for AES-256-GCM" },
+        Feature {
+            name: "sha",
+            detected: detected!("sha") && detected!("ssse3") && detected!("sse4.1"),
+            built: true,
+            used_for: "SHA-256 (hash --alg sha256, SHA-256 fingerprints)",
+        },
+    ]
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn features() -> Vec<Feature> {
+    use std::arch::is_aarch64_feature_detected as detected;
+    vec![
+        Feature {
+            name: "neon",
+            detected: detected!("neon"),
+            built: true,
+            used_for: if cfg!(chacha20_force_neon) {
+                "ChaCha20 (containers, --aead xchacha20poly1305), hex"
+            } else {
+                "Hex (ChaCha20 only when built with --cfg chacha20_force_neon)"
+            },
+        },
+        Feature {
+            name: "aes",
+            detected: detected!("aes"),
+            built: cfg!(all(aes_armv8, polyval_armv8)),
+            used_for: "AES-256-GCM (--aead aes256gcm), with PMULL for GHASH",
+        },
+    ]
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn features() -> Vec<Feature> {
+    Vec::new()
+}
@@ -0,0 +1,84 @@
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+
+const CERT_MAGIC: &[u8; 4] = b"SXDC";
+const CERT_VERSION: u8 = 1;
+pub const CERT_LEN: usize = 4 + 1 + 32 + 32 + 8 + 8 + 64;
+
+/// A root key's statement that `subkey_public_key` may sign on its behalf
+/// between `not_before` and `not_after` (unix seconds).
+pub struct DelegationCert {
+    pub root_public_key: [u8; 32],
+    pub subkey_public_key: [u8; 32],
+    pub not_before: u64,
+    pub not_after: u64,
+    pub signature: [u8; 64],
+}
+
+impl DelegationCert {
+    #[cfg(feature = "full")]
+    pub fn issue(root_secret_key: &[u8; 64], subkey_public_key: [u8; 32], not_before: u64, not_after: u64) -> Result<Self, String> {
+        let mut cert = DelegationCert {
+            root_public_key: root_secret_key[32..].try_into().unwrap(),
+            subkey_public_key,
+            not_before,
+            not_after,
+            signature: [0u8; 64],
+        };
+        let signed = cert.signed_bytes();
+        crypto_sign_detached(&mut cert.signature, &signed, root_secret_key)
+            .map_err(|e| format!("Error signing delegation certificate: {}", e))?;
+        Ok(cert)
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(CERT_LEN - 64);
+        out.extend_from_slice(CERT_MAGIC);
+        out.push(CERT_VERSION);
+        out.extend_from_slice(&self.root_public_key);
+        out.extend_from_slice(&self.subkey_public_key);
+        out.extend_from_slice(&self.not_before.to_be_bytes());
+        out.extend_from_slice(&self.not_after.to_be_bytes());
+        out
+    }
+
+    #[cfg(feature = "full")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.signed_bytes();
+        out.extend_from_slice(&self.signature);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() != CERT_LEN || &bytes[..4] != CERT_MAGIC {
+            return Err("Not a sodix delegation certificate".to_string());
+        }
+        if bytes[4] != CERT_VERSION {
+            return Err(format!("Unsupported delegation certificate version {}", bytes[4]));
+        }
+        Ok(DelegationCert {
+            root_public_key: bytes[5..37].try_into().unwrap(),
+            subkey_public_key: bytes[37..69].try_into().unwrap(),
+            not_before: u64::from_be_bytes(bytes[69..77].try_into().unwrap()),
+            not_after: u64::from_be_bytes(bytes[77..85].try_into().unwrap()),
+            signature: bytes[85..].try_into().unwrap(),
+        })
+    }
+
+    /// Check that the certificate was issued by `trusted_root` and is valid at `now`.
+    pub fn verify(&self, trusted_root: &[u8; 32], now: u64) -> Result<(), String> {
+        if &self.root_public_key != trusted_root {
+            return Err("Delegation certificate was issued by a different root key".to_string());
+        }
+        crypto_sign_verify_detached(&self.signature, &self.signed_bytes(), trusted_root)
+            .map_err(|e| format!("Delegation certificate signature is invalid: {}", e))?;
+        if now < self.not_before {
+            return Err(format!("Delegation certificate is not valid before {}", self.not_before));
+        }
+        if now > self.not_after {
+            return Err(format!("Delegation certificate expired at {}", self.not_after));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+use crate::memlock;
+use dryoc::classic::crypto_box::crypto_box_seed_keypair;
+use dryoc::classic::crypto_generichash::crypto_generichash;
+use dryoc::classic::crypto_pwhash::{crypto_pwhash, PasswordHashAlgorithm};
+use dryoc::classic::crypto_sign::crypto_sign_seed_keypair;
+use dryoc::constants::{
+    CRYPTO_PWHASH_MEMLIMIT_INTERACTIVE, CRYPTO_PWHASH_MEMLIMIT_MODERATE, CRYPTO_PWHASH_OPSLIMIT_INTERACTIVE, CRYPTO_PWHASH_OPSLIMIT_MODERATE,
+};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+// Key pairs derived from a passphrase (generate --from-passphrase), so the same keys
+// can be recreated on another machine:
+//
+//   seed = Argon2id13(passphrase, BLAKE2b-128(salt), opslimit, memlimit), 64 bytes
+//   signing key pair    = crypto_sign_seed_keypair(seed[..32])
+//   encryption key pair = crypto_box_seed_keypair(seed[32..])
+//
+// The salt is a memorable text such as an email address. Everything but the
+// passphrase is written to derivation.params next to the keys:
+//
+//   sodix-derivation 1
+//   salt <salt text>
+//   opslimit <Argon2 passes>
+//   memlimit <bytes>
+//
+// Anyone who guesses the passphrase has the keys, so it must be long and unique; the
+// salt only stops guesses precomputed for everyone at once.
+pub const PARAMS_FILE: &str = "derivation.params";
+const HEADER: &str = "sodix-derivation 1";
+// Refuse parameter files that would make Argon2 allocate more than keyfile allows or
+// run for minutes, or that are cheaper than libsodium's interactive profile
+const MAX_MEMLIMIT: u64 = 4 << 30;
+const MAX_OPSLIMIT: u64 = 32;
+
+pub struct Params {
+    pub salt: String,
+    pub opslimit: u64,
+    pub memlimit: u64,
+}
+
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub struct DerivedKeys {
+    pub sign_public: [u8; 32],
+    pub sign_secret: [u8; 64],
+    pub enc_public: [u8; 32],
+    pub enc_secret: [u8; 32],
+}
+
+impl Params {
+    /// Default cost parameters for `salt`.
+    pub fn new(salt: &str) -> Result<Self, String> {
+        if salt.is_empty() || salt.contains('\n') {
+            return Err("The salt must be a single non-empty line".to_string());
+        }
+        Ok(Params {
+            salt: salt.to_string(),
+            opslimit: CRYPTO_PWHASH_OPSLIMIT_MODERATE,
+            memlimit: CRYPTO_PWHASH_MEMLIMIT_MODERATE as u64,
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            return Err("Not a sodix derivation parameters file".to_string());
+        }
+        let mut field = |name: &str| {
+            lines
+                .next()
+                .and_then(|l| l.strip_prefix(name))
+                .and_then(|l| l.strip_prefix(' '))
+                .ok_or_else(|| format!("Derivation parameters are missing '{}'", name))
+        };
+        let salt = field("salt")?.to_string();
+        let opslimit: u64 = field("opslimit")?.trim().parse().map_err(|_| "Invalid opslimit".to_string())?;
+        let memlimit: u64 = field("memlimit")?.trim().parse().map_err(|_| "Invalid memlimit".to_string())?;
+        if !(CRYPTO_PWHASH_OPSLIMIT_INTERACTIVE..=MAX_OPSLIMIT).contains(&opslimit) {
+            return Err(format!("opslimit {} is outside {}..={}; refusing", opslimit, CRYPTO_PWHASH_OPSLIMIT_INTERACTIVE, MAX_OPSLIMIT));
+        }
+        if !(CRYPTO_PWHASH_MEMLIMIT_INTERACTIVE as u64..=MAX_MEMLIMIT).contains(&memlimit) {
+            return Err(format!(
+                "memlimit of {} MiB is outside {}..={} MiB; refusing",
+                memlimit >> 20,
+                CRYPTO_PWHASH_MEMLIMIT_INTERACTIVE >> 20,
+                MAX_MEMLIMIT >> 20
+            ));
+        }
+        Ok(Params { salt, opslimit, memlimit })
+    }
+
+    pub fn to_text(&self) -> String {
+        format!("{}\nsalt {}\nopslimit {}\nmemlimit {}\n", HEADER, self.salt, self.opslimit, self.memlimit)
+    }
+}
+
+pub fn derive(passphrase: &str, params: &Params) -> Result<DerivedKeys, String> {
+    let mut salt = [0u8; 16];
+    crypto_generichash(&mut salt, params.salt.as_bytes(), None).map_err(|e| e.to_string())?;
+    let mut seed = Zeroizing::new(vec![0u8; 64]);
+    memlock::lock(&seed);
+    crypto_pwhash(
+        &mut seed,
+        passphrase.as_bytes(),
+        &salt,
+        params.opslimit,
+        params.memlimit as usize,
+        PasswordHashAlgorithm::Argon2id13,
+    )
+    .map_err(|e| format!("Failed to derive keys from passphrase: {}", e))?;
+    let (sign_public, sign_secret) = crypto_sign_seed_keypair(seed[..32].try_into().unwrap());
+    let (enc_public, enc_secret) = crypto_box_seed_keypair(&seed[32..]);
+    Ok(DerivedKeys { sign_public, sign_secret, enc_public, enc_secret })
+}
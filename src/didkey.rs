@@ -0,0 +1,52 @@
+use crate::bech32key::KeyKind;
+
+// Public keys as did:key identifiers (W3C did:key method) for decentralized-identity
+// tooling:
+//
+//   did:key:z6Mk...   Ed25519 signing public key
+//   did:key:z6LS...   X25519 encryption public key
+//
+// The part after "did:key:" is a multibase string: 'z' for base58btc of the multicodec
+// varint (0xed 0x01 for ed25519-pub, 0xec 0x01 for x25519-pub) followed by the key.
+// The bare multibase form and DID URLs with a "#fragment" are accepted too.
+const PREFIX: &str = "did:key:";
+const BASE58BTC: char = 'z';
+const ED25519_PUB: [u8; 2] = [0xed, 0x01];
+const X25519_PUB: [u8; 2] = [0xec, 0x01];
+
+#[cfg(feature = "full")]
+pub fn encode(kind: KeyKind, public_key: &[u8]) -> String {
+    let codec = match kind {
+        KeyKind::Signing => ED25519_PUB,
+        KeyKind::Encryption => X25519_PUB,
+    };
+    format!("{}{}{}", PREFIX, BASE58BTC, bs58::encode([&codec[..], public_key].concat()).into_string())
+}
+
+/// Whether `s` looks like a did:key or multibase key rather than hex or a path.
+pub fn is_did_key(s: &str) -> bool {
+    let s = s.trim();
+    // Every 32-byte Ed25519 and X25519 key encodes to one of these prefixes
+    s.starts_with(PREFIX) || s.starts_with("z6Mk") || s.starts_with("z6LS")
+}
+
+/// Decode a did:key public key; `expected` rejects keys of the other kind.
+pub fn decode(s: &str, expected: Option<KeyKind>) -> Result<[u8; 32], String> {
+    let s = s.trim();
+    let multibase = s.strip_prefix(PREFIX).unwrap_or(s);
+    let multibase = multibase.split_once('#').map_or(multibase, |(id, _)| id);
+    let encoded = multibase
+        .strip_prefix(BASE58BTC)
+        .ok_or("Only base58btc ('z') multibase keys are supported")?;
+    let bytes = bs58::decode(encoded).into_vec().map_err(|e| format!("Invalid base58 in did:key: {}", e))?;
+    let (codec, key) = bytes.split_at_checked(2).ok_or("Truncated did:key")?;
+    let kind = match codec {
+        c if c == ED25519_PUB => KeyKind::Signing,
+        c if c == X25519_PUB => KeyKind::Encryption,
+        _ => return Err("Only Ed25519 and X25519 did:keys are supported".to_string()),
+    };
+    if let Some(expected) = expected.filter(|&e| e != kind) {
+        return Err(format!("Expected {}, got {}", expected.name(), kind.name()));
+    }
+    key.try_into().map_err(|_| "Public key must be 32 bytes".to_string())
+}
@@ -0,0 +1,254 @@
+use crate::delegation::DelegationCert;
+use crate::fingerprint;
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+
+// Signature envelope: MAGIC | version | records of (tag u8, len u16 BE, value).
+// A bare 64-byte signature is still accepted everywhere an envelope is.
+pub const MAGIC: &[u8; 4] = b"SXSG";
+const VERSION: u8 = 1;
+
+const TAG_SIGNATURE: u8 = 0x01;
+const TAG_CERT: u8 = 0x02;
+// Signature mode (u8); absent means plain Ed25519 over the message
+const TAG_MODE: u8 = 0x03;
+const MODE_ED25519PH: u8 = 0x01;
+// Free text that is carried along but not signed
+const TAG_COMMENT: u8 = 0x04;
+// Free text covered by TAG_ATTRIBUTES_SIGNATURE, as are the records below
+const TAG_TRUSTED_COMMENT: u8 = 0x05;
+// Ed25519 by the signer over the message signature followed by the signed records,
+// like minisign's global signature, so attributes can't be swapped between signatures
+const TAG_ATTRIBUTES_SIGNATURE: u8 = 0x06;
+// Signed metadata: signing time (u64 BE unix seconds), the signer's key fingerprint
+// (`<alg>:<hex>`) and what the signature is for
+const TAG_TIMESTAMP: u8 = 0x07;
+const TAG_SIGNER: u8 = 0x08;
+const TAG_PURPOSE: u8 = 0x09;
+
+pub struct Envelope {
+    pub signature: [u8; 64],
+    pub cert: Option<DelegationCert>,
+    /// Ed25519ph: the signature is over the SHA-512 of the message, so it can be
+    /// created and checked while streaming
+    pub prehashed: bool,
+    /// Unsigned note; anyone can change it
+    pub comment: Option<String>,
+    pub trusted_comment: Option<String>,
+    pub timestamp: Option<u64>,
+    pub signer: Option<String>,
+    pub purpose: Option<String>,
+    pub attributes_signature: Option<[u8; 64]>,
+}
+
+impl Envelope {
+    pub fn is_envelope(bytes: &[u8]) -> bool {
+        bytes.len() > MAGIC.len() && &bytes[..MAGIC.len()] == MAGIC
+    }
+
+    #[cfg(feature = "full")]
+    pub fn new(signature: [u8; 64]) -> Self {
+        Envelope {
+            signature,
+            cert: None,
+            prehashed: false,
+            comment: None,
+            trusted_comment: None,
+            timestamp: None,
+            signer: None,
+            purpose: None,
+            attributes_signature: None,
+        }
+    }
+
+    #[cfg(feature = "full")]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        put_record(&mut out, TAG_SIGNATURE, &self.signature);
+        if let Some(cert) = &self.cert {
+            put_record(&mut out, TAG_CERT, &cert.to_bytes());
+        }
+        if self.prehashed {
+            put_record(&mut out, TAG_MODE, &[MODE_ED25519PH]);
+        }
+        if let Some(comment) = &self.comment {
+            put_record(&mut out, TAG_COMMENT, comment.as_bytes());
+        }
+        out.extend_from_slice(&self.signed_records());
+        if let Some(attributes_signature) = &self.attributes_signature {
+            put_record(&mut out, TAG_ATTRIBUTES_SIGNATURE, attributes_signature);
+        }
+        out
+    }
+
+    /// Sign the trusted attributes with the same key that made `signature`.
+    #[cfg(feature = "full")]
+    pub fn sign_attributes(&mut self, secret_key: &[u8; 64]) -> Result<(), String> {
+        self.sign_attributes_with(|message| {
+            let mut attributes_signature = [0u8; 64];
+            crypto_sign_detached(&mut attributes_signature, message, secret_key)
+                .map_err(|e| format!("Error signing data: {}", e))?;
+            Ok(attributes_signature)
+        })
+    }
+
+    /// `sign_attributes` with a signer that does not expose the secret key (ssh-agent).
+    #[cfg(feature = "full")]
+    pub fn sign_attributes_with(&mut self, sign: impl FnOnce(&[u8]) -> Result<[u8; 64], String>) -> Result<(), String> {
+        if self.signed_records().is_empty() {
+            return Ok(());
+        }
+        self.attributes_signature = Some(sign(&self.attributes_message())?);
+        Ok(())
+    }
+
+    /// Check the trusted attributes against the key that made `signature`.
+    pub fn verify_attributes(&self, signer: &[u8; 32]) -> Result<(), String> {
+        if self.signed_records().is_empty() {
+            return Ok(());
+        }
+        let attributes_signature = self.attributes_signature.ok_or("Signed attributes have no signature")?;
+        crypto_sign_verify_detached(&attributes_signature, &self.attributes_message(), signer)
+            .map_err(|_| "Signed attributes signature is invalid".to_string())?;
+        if let Some(fingerprint) = &self.signer {
+            if !fingerprint::matches(fingerprint, signer)? {
+                return Err(format!("Signature claims signer {}, which is not the signing key", fingerprint));
+            }
+        }
+        Ok(())
+    }
+
+    fn attributes_message(&self) -> Vec<u8> {
+        [&self.signature[..], &self.signed_records()].concat()
+    }
+
+    /// The records covered by the attributes signature, in their encoded form.
+    fn signed_records(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if let Some(trusted_comment) = &self.trusted_comment {
+            put_record(&mut out, TAG_TRUSTED_COMMENT, trusted_comment.as_bytes());
+        }
+        if let Some(timestamp) = self.timestamp {
+            put_record(&mut out, TAG_TIMESTAMP, &timestamp.to_be_bytes());
+        }
+        if let Some(signer) = &self.signer {
+            put_record(&mut out, TAG_SIGNER, signer.as_bytes());
+        }
+        if let Some(purpose) = &self.purpose {
+            put_record(&mut out, TAG_PURPOSE, purpose.as_bytes());
+        }
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        if !Self::is_envelope(bytes) {
+            return Err("Not a sodix signature envelope".to_string());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("Unsupported signature envelope version {}", bytes[4]));
+        }
+        let mut signature = None;
+        let mut cert = None;
+        let mut prehashed = false;
+        let mut comment = None;
+        let mut trusted_comment = None;
+        let mut timestamp = None;
+        let mut signer = None;
+        let mut purpose = None;
+        let mut attributes_signature = None;
+        let mut rest = &bytes[5..];
+        while !rest.is_empty() {
+            if rest.len() < 3 {
+                return Err("Truncated signature envelope".to_string());
+            }
+            let tag = rest[0];
+            let len = u16::from_be_bytes([rest[1], rest[2]]) as usize;
+            if rest.len() < 3 + len {
+                return Err("Truncated signature envelope".to_string());
+            }
+            let value = &rest[3..3 + len];
+            match tag {
+                TAG_SIGNATURE => {
+                    signature = Some(value.try_into().map_err(|_| "Signature must be 64 bytes")?);
+                }
+                TAG_CERT => cert = Some(DelegationCert::from_bytes(value)?),
+                TAG_MODE => match value {
+                    [MODE_ED25519PH] => prehashed = true,
+                    _ => return Err("Unsupported signature mode in envelope".to_string()),
+                },
+                TAG_COMMENT => comment = Some(String::from_utf8_lossy(value).into_owned()),
+                TAG_TRUSTED_COMMENT => {
+                    trusted_comment = Some(String::from_utf8(value.to_vec()).map_err(|_| "Trusted comment is not valid UTF-8")?);
+                }
+                TAG_TIMESTAMP => {
+                    timestamp = Some(u64::from_be_bytes(value.try_into().map_err(|_| "Timestamp must be 8 bytes")?));
+                }
+                TAG_SIGNER => {
+                    // <alg>:<hex>, as fingerprint() writes it; anything else is refused here
+                    // rather than trusted by whoever displays it
+                    let valid = std::str::from_utf8(value).ok().and_then(|fp| fp.split_once(':')).is_some_and(|(alg, hex)| {
+                        !alg.is_empty()
+                            && alg.bytes().all(|b| b.is_ascii_alphanumeric())
+                            && !hex.is_empty()
+                            && hex.bytes().all(|b| b.is_ascii_hexdigit())
+                    });
+                    if !valid {
+                        return Err("Signer fingerprint must be <alg>:<hex>".to_string());
+                    }
+                    signer = Some(String::from_utf8(value.to_vec()).unwrap());
+                }
+                TAG_PURPOSE => purpose = Some(String::from_utf8(value.to_vec()).map_err(|_| "Purpose is not valid UTF-8")?),
+                TAG_ATTRIBUTES_SIGNATURE => {
+                    attributes_signature = Some(value.try_into().map_err(|_| "Attributes signature must be 64 bytes")?);
+                }
+                // Unknown records are skipped so older versions can read newer envelopes
+                _ => {}
+            }
+            rest = &rest[3 + len..];
+        }
+        Ok(Envelope {
+            signature: signature.ok_or("Signature envelope has no signature")?,
+            cert,
+            prehashed,
+            comment,
+            trusted_comment,
+            timestamp,
+            signer,
+            purpose,
+            attributes_signature,
+        })
+    }
+}
+
+fn put_record(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+#[cfg(all(test, feature = "full"))]
+mod tests {
+    use super::*;
+
+    fn with_signer(signer: &str) -> Vec<u8> {
+        let mut envelope = Envelope::new([7; 64]);
+        envelope.signer = Some(signer.to_string());
+        envelope.encode()
+    }
+
+    #[test]
+    fn signer_fingerprint_round_trips() {
+        let decoded = Envelope::decode(&with_signer("blake2b:00ff")).unwrap();
+        assert_eq!(decoded.signer.as_deref(), Some("blake2b:00ff"));
+    }
+
+    #[test]
+    fn signer_that_is_not_a_fingerprint_is_refused() {
+        for signer in ["blake2b:€€€€€€", "blake2b:", ":00ff", "00ff", "blake2b:00 ff"] {
+            assert!(Envelope::decode(&with_signer(signer)).is_err(), "{}", signer);
+        }
+    }
+}
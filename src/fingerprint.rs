@@ -0,0 +1,56 @@
+use clap::ValueEnum;
+use dryoc::classic::crypto_generichash::crypto_generichash;
+use sha2::{Digest, Sha256};
+
+/// Hash used for key fingerprints. Printed fingerprints carry the algorithm as a
+/// prefix (`blake2b:…`, `sha256:…`) so pins survive a change of default.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum FingerprintAlg {
+    /// BLAKE2b-256 (crypto_generichash)
+    #[default]
+    #[value(name = "blake2b")]
+    Blake2b,
+    /// SHA-256, for tools that expect it
+    #[value(name = "sha256")]
+    Sha256,
+}
+
+impl FingerprintAlg {
+    fn name(self) -> &'static str {
+        match self {
+            FingerprintAlg::Blake2b => "blake2b",
+            FingerprintAlg::Sha256 => "sha256",
+        }
+    }
+
+    fn digest(self, public_key: &[u8]) -> [u8; 32] {
+        match self {
+            FingerprintAlg::Blake2b => {
+                let mut digest = [0u8; 32];
+                crypto_generichash(&mut digest, public_key, None).unwrap();
+                digest
+            }
+            FingerprintAlg::Sha256 => Sha256::digest(public_key).into(),
+        }
+    }
+}
+
+/// Fingerprint of a raw public key: `<alg>:<hex digest>`.
+pub fn fingerprint(public_key: &[u8], alg: FingerprintAlg) -> String {
+    format!("{}:{}", alg.name(), hex::encode(alg.digest(public_key)))
+}
+
+/// Whether `pinned` is a fingerprint of `public_key` under any supported algorithm.
+/// Unprefixed hex is read as BLAKE2b, the format printed before prefixes existed.
+pub fn matches(pinned: &str, public_key: &[u8]) -> Result<bool, String> {
+    let pinned = pinned.trim();
+    let (alg, digest_hex) = match pinned.split_once(':') {
+        Some((name, digest_hex)) => (
+            FingerprintAlg::from_str(name, true).map_err(|_| format!("Unknown fingerprint algorithm '{}'", name))?,
+            digest_hex,
+        ),
+        None => (FingerprintAlg::Blake2b, pinned),
+    };
+    let digest = hex::decode(digest_hex).map_err(|e| format!("Invalid fingerprint '{}': {}", pinned, e))?;
+    Ok(digest == alg.digest(public_key))
+}
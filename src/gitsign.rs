@@ -0,0 +1,119 @@
+use crate::fingerprint::{fingerprint, FingerprintAlg};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+#[cfg(feature = "full")]
+use std::fmt::Write as _;
+
+// The slice of the gpg command line that git uses for gpg.program:
+//
+//   sign:    gpg --status-fd=2 -bsau <user.signingkey>        data on stdin, armored signature on stdout
+//   verify:  gpg --keyid-format=long --status-fd=1 --verify <signature file> -
+//
+// Git looks for "[GNUPG:] SIG_CREATED" after signing and GOODSIG / VALIDSIG / TRUST_*
+// lines after verifying. The signature itself is a sodix signature envelope in PGP
+// armor, since git finds signatures in commits and tags by the armor lines; it is not
+// an OpenPGP packet, so only sodix can verify it.
+const BEGIN: &str = "-----BEGIN PGP SIGNATURE-----";
+const END: &str = "-----END PGP SIGNATURE-----";
+// Armor git uses with gpg.format=x509, accepted when verifying as well
+const BEGIN_X509: &str = "-----BEGIN SIGNED MESSAGE-----";
+const END_X509: &str = "-----END SIGNED MESSAGE-----";
+
+pub enum Mode {
+    /// Detached signature by this key (user.signingkey), or the default key
+    Sign { key: Option<String> },
+    /// Verify the signature in this file against data on stdin
+    Verify { signature_file: String },
+}
+
+pub struct GpgArgs {
+    pub mode: Mode,
+    /// File descriptor for "[GNUPG:]" status lines
+    pub status_fd: Option<u32>,
+}
+
+/// Whether the command line looks like git calling its gpg.program.
+pub fn is_gpg_invocation(args: &[String]) -> bool {
+    args.get(1).is_some_and(|a| a.starts_with("--status-fd") || a.starts_with("--keyid-format") || a.starts_with("-bsau"))
+}
+
+pub fn parse_args(args: &[String]) -> Result<GpgArgs, String> {
+    let mut status_fd = None;
+    let mut key = None;
+    let mut sign = false;
+    let mut verify = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().cloned().ok_or_else(|| format!("gpg option {} needs a value", name));
+        match arg.as_str() {
+            "--status-fd" => status_fd = Some(parse_fd(&value("--status-fd")?)?),
+            "--keyid-format" => {
+                value("--keyid-format")?;
+            }
+            "--local-user" => key = Some(value("--local-user")?),
+            "--verify" => verify = Some(value("--verify")?),
+            "--detach-sign" | "--sign" => sign = true,
+            "--armor" | "-" => {}
+            a if a.starts_with("--status-fd=") => status_fd = Some(parse_fd(&a["--status-fd=".len()..])?),
+            a if a.starts_with("--keyid-format=") => {}
+            a if a.starts_with('-') && !a.starts_with("--") => {
+                // Clustered short options, e.g. -bsau <key>
+                for (i, c) in a.char_indices().skip(1) {
+                    match c {
+                        'b' | 's' => sign = true,
+                        'a' => {}
+                        'u' => {
+                            let rest = &a[i + 1..];
+                            key = Some(if rest.is_empty() { value("-u")? } else { rest.to_string() });
+                            break;
+                        }
+                        _ => return Err(format!("Unsupported gpg option -{}", c)),
+                    }
+                }
+            }
+            a => return Err(format!("Unsupported gpg argument '{}'", a)),
+        }
+    }
+    let mode = match (verify, sign) {
+        (Some(signature_file), false) => Mode::Verify { signature_file },
+        (None, true) => Mode::Sign { key },
+        _ => return Err("Expected gpg -bsau <key> or --verify <file> -".to_string()),
+    };
+    Ok(GpgArgs { mode, status_fd })
+}
+
+fn parse_fd(fd: &str) -> Result<u32, String> {
+    fd.parse().map_err(|_| format!("Invalid status fd '{}'", fd))
+}
+
+/// Signature bytes in PGP armor.
+#[cfg(feature = "full")]
+pub fn armor(signature: &[u8]) -> String {
+    let encoded = STANDARD.encode(signature);
+    let mut out = format!("{}\n\n", BEGIN);
+    for line in encoded.as_bytes().chunks(64) {
+        let _ = writeln!(out, "{}", std::str::from_utf8(line).unwrap());
+    }
+    let _ = writeln!(out, "{}", END);
+    out
+}
+
+/// Signature bytes from PGP or x509-style armor.
+pub fn dearmor(text: &str) -> Result<Vec<u8>, String> {
+    let mut lines = text.lines().map(str::trim).skip_while(|l| *l != BEGIN && *l != BEGIN_X509);
+    let end = match lines.next() {
+        Some(BEGIN) => END,
+        Some(_) => END_X509,
+        None => return Err("No armored signature found".to_string()),
+    };
+    // Armor headers ("Key: value") end at the first blank line, if there are any
+    let body: Vec<&str> = lines.take_while(|l| *l != end).filter(|l| !l.is_empty() && !l.contains(": ")).collect();
+    STANDARD.decode(body.concat()).map_err(|e| format!("Invalid armored signature: {}", e))
+}
+
+/// gpg-style (key id, fingerprint) for a sodix public key: the BLAKE2b fingerprint in
+/// upper-case hex and its last 16 digits.
+pub fn key_ids(public_key: &[u8]) -> (String, String) {
+    let fpr = fingerprint(public_key, FingerprintAlg::Blake2b)["blake2b:".len()..].to_ascii_uppercase();
+    (fpr[fpr.len() - 16..].to_string(), fpr)
+}
@@ -0,0 +1,68 @@
+#[cfg(feature = "full")]
+use std::io::Write;
+use std::io::{self, BufRead, BufReader, Read};
+
+// Hex text of whole messages (legacy .x files, hex output) converted in pieces, so
+// the text never sits in memory next to the bytes it encodes. faster_hex picks its
+// AVX2/SSE4.1 or NEON code at runtime. Whitespace between the digits, such as the
+// newline a shell redirect leaves at the end, is skipped when decoding.
+
+/// Bytes encoded or decoded per step.
+const PIECE: usize = 64 * 1024;
+
+/// Write `data` to `writer` as lowercase hex.
+#[cfg(feature = "full")]
+pub fn write_hex<W: Write>(mut writer: W, data: &[u8]) -> io::Result<()> {
+    let mut text = vec![0u8; PIECE * 2];
+    for piece in data.chunks(PIECE) {
+        let hex = faster_hex::hex_encode(piece, &mut text).map_err(|e| io::Error::other(e.to_string()))?;
+        writer.write_all(hex.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Decodes the hex text read from the inner reader.
+pub struct HexReader<R> {
+    inner: BufReader<R>,
+    text: Vec<u8>,
+    /// The first digit of a pair split across two reads
+    pending: Option<u8>,
+}
+
+impl<R: Read> HexReader<R> {
+    pub fn new(inner: R) -> Self {
+        HexReader { inner: BufReader::with_capacity(PIECE * 2, inner), text: Vec::with_capacity(PIECE * 2), pending: None }
+    }
+}
+
+impl<R: Read> Read for HexReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        while !buf.is_empty() {
+            let available = self.inner.fill_buf()?;
+            if available.is_empty() {
+                return match self.pending {
+                    Some(_) => Err(invalid("odd number of hex digits".to_string())),
+                    None => Ok(0),
+                };
+            }
+            self.text.clear();
+            self.text.extend(self.pending.take());
+            let take = available.len().min(buf.len() * 2 - self.text.len());
+            self.text.extend_from_slice(&available[..take]);
+            self.inner.consume(take);
+            if !faster_hex::hex_check(&self.text) {
+                self.text.retain(|b| !b.is_ascii_whitespace());
+            }
+            if self.text.len() % 2 == 1 {
+                self.pending = self.text.pop();
+            }
+            if self.text.is_empty() {
+                continue;
+            }
+            let decoded = faster_hex::hex_decode(&self.text, buf).map_err(|_| invalid("invalid hex digit".to_string()))?;
+            return Ok(decoded.len());
+        }
+        Ok(0)
+    }
+}
@@ -0,0 +1,203 @@
+use crate::keyfile;
+use crate::keyperms;
+use crate::memlock;
+use dryoc::classic::crypto_core::crypto_scalarmult_base;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use zeroize::Zeroizing;
+#[cfg(feature = "full")]
+use crate::fingerprint::{fingerprint, FingerprintAlg};
+
+// Both key pairs and their metadata in one armored file (generate --single-file),
+// used in place of the key directory's four .key files with --identity FILE:
+//
+//   -----BEGIN SODIX IDENTITY-----
+//   name: alice
+//   created: 2026-10-16T12:00:00Z
+//   sign-fingerprint: blake2b:<hex>
+//   enc-fingerprint: blake2b:<hex>
+//   sign_public: <hex>
+//   sign_secret: <hex, or "encrypted <hex>" for a passphrase-protected key>
+//   enc_public: <hex>
+//   enc_secret: <hex, or "encrypted <hex>">
+//   -----END SODIX IDENTITY-----
+//
+// Encrypted secrets carry the payload of the encrypted key file format (see keyfile).
+// The public keys are checked against the fingerprints, and each secret key against its
+// public key, whenever they are loaded. Inside sodix a single key of an identity is
+// addressed as FILE#sign_secret, FILE#enc_public and so on.
+const BEGIN: &str = "-----BEGIN SODIX IDENTITY-----";
+const END: &str = "-----END SODIX IDENTITY-----";
+const KEY_TYPES: [&str; 4] = ["sign_public", "sign_secret", "enc_public", "enc_secret"];
+const ENCRYPTED_PREFIX: &str = "encrypted ";
+
+// The active identity is read and checked once, by set_active; its keys are then
+// taken from memory rather than by re-reading the file for each one.
+static ACTIVE: OnceLock<(PathBuf, Identity)> = OnceLock::new();
+
+#[derive(Clone)]
+pub struct Identity {
+    pub name: String,
+    pub created: String,
+    pub sign_public: [u8; 32],
+    pub enc_public: [u8; 32],
+    sign_secret: Zeroizing<String>,
+    enc_secret: Zeroizing<String>,
+}
+
+/// Use `path` for the default keys for the rest of the run (--identity).
+pub fn set_active(path: PathBuf) -> Result<Identity, String> {
+    let identity = Identity::load(&path)?;
+    ACTIVE.set((path, identity.clone())).map_err(|_| "An identity is already in use".to_string())?;
+    Ok(identity)
+}
+
+pub fn active() -> Option<&'static Path> {
+    ACTIVE.get().map(|(path, _)| path.as_path())
+}
+
+/// The identity in `file`, from memory if it is the active one.
+fn load(file: &Path) -> Result<Identity, String> {
+    match ACTIVE.get() {
+        Some((path, identity)) if path == file => Ok(identity.clone()),
+        _ => Identity::load(file),
+    }
+}
+
+/// The path that addresses one key of the identity in `file`.
+pub fn key_path(file: &Path, key_type: &str) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(format!("#{}", key_type));
+    PathBuf::from(path)
+}
+
+/// The identity file and key type a path from `key_path` refers to.
+pub fn split_path(path: &Path) -> Option<(PathBuf, &'static str)> {
+    let (file, key_type) = path.to_str()?.rsplit_once('#')?;
+    let key_type = KEY_TYPES.into_iter().find(|t| *t == key_type)?;
+    is_identity_file(Path::new(file)).then(|| (PathBuf::from(file), key_type))
+}
+
+pub fn is_identity_file(path: &Path) -> bool {
+    active() == Some(path) || path.is_file() && fs::read_to_string(path).map(Zeroizing::new).is_ok_and(|text| text.trim_start().starts_with(BEGIN))
+}
+
+/// One key of the identity in `file`, unlocking it if it is an encrypted secret key.
+pub fn load_key(file: &Path, key_type: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    let identity = load(file)?;
+    let path = key_path(file, key_type);
+    match key_type {
+        "sign_public" => Ok(Zeroizing::new(identity.sign_public.to_vec())),
+        "enc_public" => Ok(Zeroizing::new(identity.enc_public.to_vec())),
+        "sign_secret" => {
+            let secret_key = secret(&identity.sign_secret, &path)?;
+            if secret_key.len() != 64 || secret_key[32..] != identity.sign_public {
+                return Err(format!("{}: the signing secret key does not match its public key", path.display()));
+            }
+            Ok(secret_key)
+        }
+        "enc_secret" => {
+            let secret_key = secret(&identity.enc_secret, &path)?;
+            let mut public_key = [0u8; 32];
+            match <[u8; 32]>::try_from(secret_key.as_slice()) {
+                Ok(sk) => crypto_scalarmult_base(&mut public_key, &sk),
+                Err(_) => return Err(format!("{}: invalid encryption secret key", path.display())),
+            }
+            if public_key != identity.enc_public {
+                return Err(format!("{}: the encryption secret key does not match its public key", path.display()));
+            }
+            Ok(secret_key)
+        }
+        _ => Err(format!("Unknown key type '{}'", key_type)),
+    }
+}
+
+/// Identity file contents; `sign_secret` and `enc_secret` are the hex or encrypted
+/// key file contents as written for the key directory.
+#[cfg(feature = "full")]
+pub fn create(name: &str, created: &str, sign: (&[u8], &str), enc: (&[u8], &str)) -> Result<String, String> {
+    if name.is_empty() || name.contains('\n') {
+        return Err("The name must be a single non-empty line".to_string());
+    }
+    let secret_value = |contents: &str| {
+        if keyfile::is_encrypted(contents) {
+            format!("{}{}", ENCRYPTED_PREFIX, contents.lines().nth(1).unwrap_or_default().trim())
+        } else {
+            contents.trim().to_string()
+        }
+    };
+    Ok(format!(
+        "{}\nname: {}\ncreated: {}\nsign-fingerprint: {}\nenc-fingerprint: {}\nsign_public: {}\nsign_secret: {}\nenc_public: {}\nenc_secret: {}\n{}\n",
+        BEGIN,
+        name,
+        created,
+        fingerprint(sign.0, FingerprintAlg::default()),
+        fingerprint(enc.0, FingerprintAlg::default()),
+        hex::encode(sign.0),
+        secret_value(sign.1),
+        hex::encode(enc.0),
+        secret_value(enc.1),
+        END
+    ))
+}
+
+impl Identity {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        keyperms::check(path)?;
+        let text = fs::read_to_string(path)
+            .map(Zeroizing::new)
+            .map_err(|e| format!("Failed to read identity from {}: {}", path.display(), e))?;
+        memlock::lock(text.as_bytes());
+        Identity::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let body = text
+            .trim()
+            .strip_prefix(BEGIN)
+            .and_then(|rest| rest.strip_suffix(END))
+            .ok_or("Not a sodix identity")?;
+        let fields: Vec<(&str, &str)> = body
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|l| l.split_once(':').map(|(k, v)| (k.trim(), v.trim())).ok_or("Malformed identity line"))
+            .collect::<Result<_, _>>()?;
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| *v)
+                .ok_or_else(|| format!("Identity is missing '{}'", name))
+        };
+        let public_key = |name: &str, fpr_name: &str| -> Result<[u8; 32], String> {
+            let key: [u8; 32] = hex::decode(field(name)?)
+                .ok()
+                .and_then(|k| k.try_into().ok())
+                .ok_or_else(|| format!("Invalid {} in identity", name))?;
+            if !crate::fingerprint::matches(field(fpr_name)?, &key)? {
+                return Err(format!("The {} does not match the identity's {}", name, fpr_name));
+            }
+            Ok(key)
+        };
+        let identity = Identity {
+            name: field("name")?.to_string(),
+            created: field("created")?.to_string(),
+            sign_public: public_key("sign_public", "sign-fingerprint")?,
+            enc_public: public_key("enc_public", "enc-fingerprint")?,
+            sign_secret: Zeroizing::new(field("sign_secret")?.to_string()),
+            enc_secret: Zeroizing::new(field("enc_secret")?.to_string()),
+        };
+        memlock::lock(identity.sign_secret.as_bytes());
+        memlock::lock(identity.enc_secret.as_bytes());
+        Ok(identity)
+    }
+}
+
+fn secret(value: &str, path: &Path) -> Result<Zeroizing<Vec<u8>>, String> {
+    match value.strip_prefix(ENCRYPTED_PREFIX) {
+        Some(payload) => keyfile::unlock(&format!("{}\n{}\n", keyfile::HEADER, payload.trim()), path),
+        None => hex::decode(value).map(Zeroizing::new).map_err(|e| format!("Invalid hex in {}: {}", path.display(), e)),
+    }
+}
@@ -0,0 +1,110 @@
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::Path;
+
+// Paths to skip when walking a directory, in gitignore syntax: read from
+// <dir>/.sodixignore and extended with --exclude patterns.
+//
+//   # comment
+//   target/        directories only
+//   *.o            any file or directory named like this, at any depth
+//   /dist          anchored: only dist at the top of the walk
+//   docs/**/*.tmp  a pattern with '/' matches the whole path from the top
+//   !keep.o        re-include something an earlier pattern ignored
+//
+// The last matching pattern wins. An ignored directory is not entered at all, so a
+// negated pattern cannot bring back a file below it, as in git.
+pub const FILE_NAME: &str = ".sodixignore";
+
+const OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+struct Rule {
+    pattern: Pattern,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+#[derive(Default)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// The rules in `dir`/.sodixignore, if there is one, followed by `excludes`.
+    pub fn load(dir: &Path, excludes: &[String]) -> Result<Self, String> {
+        let mut ignore = Ignore::default();
+        let path = dir.join(FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                for (i, line) in text.lines().enumerate() {
+                    ignore.add(line).map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))?;
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+        for exclude in excludes {
+            ignore.add(exclude).map_err(|e| format!("--exclude '{}': {}", exclude, e))?;
+        }
+        Ok(ignore)
+    }
+
+    fn add(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        let pattern = Pattern::new(line).map_err(|e| format!("Invalid pattern: {}", e))?;
+        self.rules.push(Rule { pattern, negated, dir_only, anchored });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Whether the entry at `rel` ('/'-separated, relative to the top of the walk)
+    /// is ignored, not looking at the directories above it.
+    pub fn matches(&self, rel: &str, is_dir: bool) -> bool {
+        let name = rel.rsplit('/').next().unwrap_or(rel);
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            let subject = if rule.anchored { rel } else { name };
+            if rule.pattern.matches_with(subject, OPTIONS) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+
+    /// Whether the file at `rel` or any directory above it is ignored.
+    pub fn is_ignored(&self, rel: &str) -> bool {
+        let mut end = 0;
+        while let Some(i) = rel[end..].find('/') {
+            end += i;
+            if self.matches(&rel[..end], true) {
+                return true;
+            }
+            end += 1;
+        }
+        self.matches(rel, false)
+    }
+}
@@ -0,0 +1,73 @@
+use crate::ignore::Ignore;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Whether `path` is a glob pattern rather than a file name. A file that exists
+/// under the literal name is taken as-is.
+pub fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '[']) && !Path::new(path).exists()
+}
+
+/// The files named by `paths`, expanding glob patterns in sorted order. Matches that
+/// `ignore` covers (relative to the current directory) are dropped; a pattern that
+/// matches no file is an error, as it would be for a missing file.
+pub fn expand(paths: &[String], ignore: &Ignore) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        if is_pattern(path) {
+            let entries = glob::glob(path).map_err(|e| format!("Invalid pattern '{}': {}", path, e))?;
+            let mut matches = Vec::new();
+            for entry in entries {
+                let entry = entry.map_err(|e| format!("Failed to read {}: {}", e.path().display(), e.error()))?;
+                let name = entry.to_string_lossy().into_owned();
+                if !entry.is_dir() && !ignore.is_ignored(name.strip_prefix("./").unwrap_or(&name)) {
+                    matches.push(name);
+                }
+            }
+            if matches.is_empty() {
+                return Err(format!("No files match '{}'", path));
+            }
+            files.extend(matches);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// The file names listed in `list` ("-" or None for stdin), one per line or, with
+/// `null`, separated by NUL bytes as `find -print0` writes them. Names are taken
+/// literally; empty entries are skipped.
+pub fn read_list(list: Option<&Path>, null: bool) -> Result<Vec<String>, String> {
+    let data = match list {
+        Some(path) if path != Path::new("-") => {
+            fs::read(path).map_err(|e| format!("Failed to read file list {}: {}", path.display(), e))?
+        }
+        _ => {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data).map_err(|e| format!("Failed to read file list: {}", e))?;
+            data
+        }
+    };
+    let separator = if null { b'\0' } else { b'\n' };
+    let mut files = Vec::new();
+    for entry in data.split(|&b| b == separator) {
+        let entry = if null { entry } else { entry.strip_suffix(b"\r").unwrap_or(entry) };
+        if entry.is_empty() {
+            continue;
+        }
+        let name = String::from_utf8(entry.to_vec())
+            .map_err(|_| format!("File list entry is not valid UTF-8: {}", String::from_utf8_lossy(entry)))?;
+        files.push(name);
+    }
+    Ok(files)
+}
+
+/// `files` without repeats, keeping the first occurrence of each.
+pub fn unique(mut files: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    files
+}
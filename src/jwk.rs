@@ -0,0 +1,105 @@
+use crate::pkcs8::Algorithm;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use dryoc::classic::crypto_core::crypto_scalarmult_base;
+use dryoc::classic::crypto_sign::crypto_sign_seed_keypair;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+// Ed25519 and X25519 keys as JSON Web Keys (RFC 8037), for JOSE-based services:
+//
+//   {"kty":"OKP","crv":"Ed25519","x":"<public key>","d":"<seed>","kid":"<thumbprint>"}
+//
+// with base64url values without padding, crv "X25519" for encryption keys and "d" only
+// in secret keys. "kid" is the RFC 7638 thumbprint, the base64url SHA-256 of
+// {"crv":...,"kty":"OKP","x":...}. A JWK Set ({"keys":[...]}) is read as its keys.
+
+/// Whether `data` looks like a JWK or JWK Set.
+pub fn is_jwk(data: &[u8]) -> bool {
+    std::str::from_utf8(data).is_ok_and(|text| text.trim_start().starts_with('{'))
+}
+
+/// `key` (as sodix stores it) as a JWK.
+pub fn encode(algorithm: Algorithm, secret: bool, key: &[u8]) -> String {
+    let public_key = if secret { public_key(algorithm, key[..32].try_into().unwrap()) } else { key.try_into().unwrap() };
+    let x = URL_SAFE_NO_PAD.encode(public_key);
+    let mut jwk = json!({"kty": "OKP", "crv": curve(algorithm), "x": x, "kid": thumbprint(algorithm, &x)});
+    if secret {
+        jwk["d"] = Value::String(URL_SAFE_NO_PAD.encode(&key[..32]));
+    }
+    format!("{}\n", serde_json::to_string_pretty(&jwk).unwrap())
+}
+
+/// Every key in a JWK or JWK Set as (algorithm, whether it is a secret key, key bytes as
+/// sodix stores them).
+pub fn parse(text: &str) -> Result<Vec<(Algorithm, bool, Vec<u8>)>, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+    match value.get("keys") {
+        Some(Value::Array(keys)) => keys.iter().map(parse_key).collect(),
+        Some(_) => Err("\"keys\" of a JWK Set must be an array".to_string()),
+        None => Ok(vec![parse_key(&value)?]),
+    }
+}
+
+fn parse_key(value: &Value) -> Result<(Algorithm, bool, Vec<u8>), String> {
+    let jwk = value.as_object().ok_or("A JWK must be a JSON object")?;
+    let field = |name: &str| jwk.get(name).and_then(Value::as_str);
+    if field("kty") != Some("OKP") {
+        return Err("Only OKP keys (Ed25519, X25519) are supported".to_string());
+    }
+    let algorithm = match field("crv") {
+        Some("Ed25519") => Algorithm::Ed25519,
+        Some("X25519") => Algorithm::X25519,
+        _ => return Err("Only the Ed25519 and X25519 curves are supported".to_string()),
+    };
+    let decode = |name: &str| -> Result<[u8; 32], String> {
+        let encoded = field(name).ok_or_else(|| format!("JWK is missing \"{}\"", name))?;
+        URL_SAFE_NO_PAD
+            .decode(encoded)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format!("\"{}\" must be 32 bytes of base64url", name))
+    };
+    let x = decode("x")?;
+    if jwk.get("d").is_none() {
+        return Ok((algorithm, false, x.to_vec()));
+    }
+    let d = decode("d")?;
+    if public_key(algorithm, &d) != x {
+        return Err("The JWK's \"x\" does not belong to its \"d\"".to_string());
+    }
+    let secret_key = match algorithm {
+        Algorithm::Ed25519 => crypto_sign_seed_keypair(&d).1.to_vec(),
+        Algorithm::X25519 => d.to_vec(),
+    };
+    Ok((algorithm, true, secret_key))
+}
+
+/// The "kid" of the JWK for Ed25519 `public_key`.
+pub fn key_id(public_key: &[u8; 32]) -> String {
+    thumbprint(Algorithm::Ed25519, &URL_SAFE_NO_PAD.encode(public_key))
+}
+
+fn public_key(algorithm: Algorithm, private_key: &[u8; 32]) -> [u8; 32] {
+    match algorithm {
+        Algorithm::Ed25519 => crypto_sign_seed_keypair(private_key).0,
+        Algorithm::X25519 => {
+            let mut public_key = [0u8; 32];
+            crypto_scalarmult_base(&mut public_key, private_key);
+            public_key
+        }
+    }
+}
+
+fn curve(algorithm: Algorithm) -> &'static str {
+    match algorithm {
+        Algorithm::Ed25519 => "Ed25519",
+        Algorithm::X25519 => "X25519",
+    }
+}
+
+fn thumbprint(algorithm: Algorithm, x: &str) -> String {
+    // Members in lexicographic order, no whitespace (RFC 7638, section 3)
+    let canonical = format!(r#"{{"crv":"{}","kty":"OKP","x":"{}"}}"#, curve(algorithm), x);
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical))
+}
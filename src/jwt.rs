@@ -0,0 +1,93 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+use serde_json::Value;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+#[cfg(feature = "full")]
+use serde_json::json;
+
+// JSON Web Tokens signed with Ed25519 (RFC 7519 with the RFC 8037 "EdDSA" algorithm):
+//
+//   base64url(header) "." base64url(claims) "." base64url(signature)
+//
+// where the signature is a plain Ed25519 signature of the first two parts as they
+// appear in the token, and the header is {"alg":"EdDSA","typ":"JWT","kid":...} with the
+// key's JWK thumbprint as kid. Verification checks the signature, then "exp" and "nbf"
+// (allowing `leeway` seconds of clock skew) and "aud".
+const ALGORITHM: &str = "EdDSA";
+
+/// The token for `claims` (a JSON object), signed with `secret_key`.
+#[cfg(feature = "full")]
+pub fn sign(claims: &Value, secret_key: &[u8; 64], kid: &str) -> Result<String, String> {
+    if !claims.is_object() {
+        return Err("JWT claims must be a JSON object".to_string());
+    }
+    let header = json!({"alg": ALGORITHM, "typ": "JWT", "kid": kid});
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let mut signature = [0u8; 64];
+    crypto_sign_detached(&mut signature, signing_input.as_bytes(), secret_key)
+        .map_err(|e| format!("Error signing data: {}", e))?;
+    Ok(format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature)))
+}
+
+/// The header and claims of `token` if `public_key` signed it and it is valid at `now`
+/// for `audience`. A token with an "aud" claim is only accepted for an audience it names.
+pub fn verify(token: &str, public_key: &[u8; 32], now: u64, audience: Option<&str>, leeway: u64) -> Result<(Value, Value), String> {
+    let token = token.trim();
+    let (signing_input, signature) = token.rsplit_once('.').ok_or("Not a JWT")?;
+    let (header, claims) = signing_input.split_once('.').ok_or("Not a JWT")?;
+    let part = |encoded: &str, name: &str| -> Result<Value, String> {
+        let json = URL_SAFE_NO_PAD.decode(encoded).map_err(|e| format!("Invalid base64url in JWT {}: {}", name, e))?;
+        serde_json::from_slice(&json).map_err(|e| format!("Invalid JSON in JWT {}: {}", name, e))
+    };
+    let header = part(header, "header")?;
+    if header.get("alg").and_then(Value::as_str) != Some(ALGORITHM) {
+        return Err(format!("JWT algorithm must be {}", ALGORITHM));
+    }
+    let signature: [u8; 64] = URL_SAFE_NO_PAD
+        .decode(signature)
+        .ok()
+        .and_then(|s| s.try_into().ok())
+        .ok_or("Invalid JWT signature encoding")?;
+    crypto_sign_verify_detached(&signature, signing_input.as_bytes(), public_key)
+        .map_err(|_| "JWT signature is invalid".to_string())?;
+
+    let claims = part(claims, "claims")?;
+    let time = |name: &str| -> Result<Option<u64>, String> {
+        match claims.get(name) {
+            None => Ok(None),
+            Some(value) => value.as_u64().map(Some).ok_or_else(|| format!("JWT \"{}\" must be a NumericDate", name)),
+        }
+    };
+    if let Some(exp) = time("exp")? {
+        if now >= exp.saturating_add(leeway) {
+            return Err(format!("JWT expired at {}", crate::keymeta::format_time(exp)));
+        }
+    }
+    if let Some(nbf) = time("nbf")? {
+        if now.saturating_add(leeway) < nbf {
+            return Err(format!("JWT is not valid before {}", crate::keymeta::format_time(nbf)));
+        }
+    }
+    let audiences: Vec<&str> = match claims.get("aud") {
+        None => Vec::new(),
+        Some(Value::String(aud)) => vec![aud.as_str()],
+        Some(Value::Array(auds)) => auds.iter().filter_map(Value::as_str).collect(),
+        Some(_) => return Err("JWT \"aud\" must be a string or an array of strings".to_string()),
+    };
+    match audience {
+        Some(audience) if !audiences.contains(&audience) => {
+            return Err(format!("JWT is not for audience \"{}\"", audience))
+        }
+        None if !audiences.is_empty() => {
+            return Err(format!("JWT is for audience {}; pass --aud to accept it", audiences.join(", ")))
+        }
+        _ => {}
+    }
+    Ok((header, claims))
+}
@@ -0,0 +1,164 @@
+use crate::memlock;
+use dryoc::classic::crypto_pwhash::{crypto_pwhash, PasswordHashAlgorithm};
+use dryoc::classic::crypto_secretbox::crypto_secretbox_open_easy;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_secretbox::crypto_secretbox_easy;
+#[cfg(feature = "full")]
+use dryoc::constants::{CRYPTO_PWHASH_MEMLIMIT_MODERATE, CRYPTO_PWHASH_OPSLIMIT_MODERATE};
+#[cfg(feature = "full")]
+use rand::Rng;
+use std::path::Path;
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+// Passphrase-protected secret key file, two lines of text:
+//
+//   sodix-encrypted-key 1
+//   <hex: salt (16) | opslimit u64 BE | memlimit u64 BE | nonce (24) | secretbox(secret key)>
+//
+// The secretbox (XSalsa20-Poly1305) key is Argon2id13 (crypto_pwhash) of the passphrase
+// with the salt and limits stored in the file, so the cost can be raised without a new
+// format. A wrong passphrase fails the secretbox authentication. Unprotected key files
+// are a single hex line and are read as before. The same payload (seal/open) protects
+// key bundles.
+pub const HEADER: &str = "sodix-encrypted-key 1";
+/// Passphrase for scripts; when set, sodix does not prompt.
+const PASSPHRASE_ENV: &str = "SODIX_PASSPHRASE";
+/// The new passphrase for `key passwd` in scripts, when it differs from the current one.
+#[cfg(feature = "full")]
+const NEW_PASSPHRASE_ENV: &str = "SODIX_NEW_PASSPHRASE";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const MAC_LEN: usize = 16;
+const PARAMS_LEN: usize = SALT_LEN + 8 + 8 + NONCE_LEN;
+// Refuse files that would make Argon2 allocate more than this
+const MAX_MEMLIMIT: u64 = 4 << 30;
+
+// The last passphrase that unlocked a key, so commands that load both secret keys ask once
+static PASSPHRASE: Mutex<Option<Zeroizing<String>>> = Mutex::new(None);
+
+/// Whether a key file's contents are passphrase-protected.
+pub fn is_encrypted(text: &str) -> bool {
+    text.lines().next().map(str::trim) == Some(HEADER)
+}
+
+/// Encrypted key file contents for `secret_key`.
+#[cfg(feature = "full")]
+pub fn encrypt(secret_key: &[u8], passphrase: &str) -> Result<String, String> {
+    Ok(format!("{}\n{}\n", HEADER, hex::encode(seal(secret_key, passphrase)?)))
+}
+
+/// `data` encrypted under `passphrase`, with the parameters needed to decrypt it.
+#[cfg(feature = "full")]
+pub fn seal(data: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    let salt: [u8; SALT_LEN] = rand::rng().random();
+    let nonce: [u8; NONCE_LEN] = rand::rng().random();
+    let opslimit = CRYPTO_PWHASH_OPSLIMIT_MODERATE;
+    let memlimit = CRYPTO_PWHASH_MEMLIMIT_MODERATE as u64;
+    let key = derive_key(passphrase, &salt, opslimit, memlimit)?;
+    let mut ciphertext = vec![0u8; data.len() + MAC_LEN];
+    crypto_secretbox_easy(&mut ciphertext, data, &nonce, &key)
+        .map_err(|e| format!("Failed to encrypt key: {}", e))?;
+
+    let mut payload = Vec::with_capacity(PARAMS_LEN + ciphertext.len());
+    payload.extend_from_slice(&salt);
+    payload.extend_from_slice(&opslimit.to_be_bytes());
+    payload.extend_from_slice(&memlimit.to_be_bytes());
+    payload.extend_from_slice(&nonce);
+    payload.extend_from_slice(&ciphertext);
+    Ok(payload)
+}
+
+/// The secret key from encrypted key file contents.
+pub fn decrypt(text: &str, passphrase: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    if lines.next() != Some(HEADER) {
+        return Err("Not a sodix encrypted key".to_string());
+    }
+    let payload = hex::decode(lines.next().unwrap_or_default()).map_err(|e| format!("Invalid hex in encrypted key: {}", e))?;
+    open(&payload, passphrase)
+}
+
+/// The data in a payload from `seal`.
+pub fn open(payload: &[u8], passphrase: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    if payload.len() <= PARAMS_LEN + MAC_LEN {
+        return Err("Truncated encrypted key".to_string());
+    }
+    let (salt, rest) = payload.split_at(SALT_LEN);
+    let (opslimit, rest) = rest.split_at(8);
+    let (memlimit, rest) = rest.split_at(8);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let opslimit = u64::from_be_bytes(opslimit.try_into().unwrap());
+    let memlimit = u64::from_be_bytes(memlimit.try_into().unwrap());
+    if memlimit > MAX_MEMLIMIT {
+        return Err(format!("Encrypted key asks for {} MiB of memory; refusing", memlimit >> 20));
+    }
+    let key = derive_key(passphrase, salt, opslimit, memlimit)?;
+    let mut secret_key = Zeroizing::new(vec![0u8; ciphertext.len() - MAC_LEN]);
+    memlock::lock(&secret_key);
+    crypto_secretbox_open_easy(&mut secret_key, ciphertext, nonce.try_into().unwrap(), &key)
+        .map_err(|_| "Wrong passphrase or corrupt key file".to_string())?;
+    Ok(secret_key)
+}
+
+/// Decrypt a key file read from `path`, asking for its passphrase unless the one that
+/// unlocked the previous key (or $SODIX_PASSPHRASE) works.
+pub fn unlock(text: &str, path: &Path) -> Result<Zeroizing<Vec<u8>>, String> {
+    let mut cached = PASSPHRASE.lock().unwrap();
+    if let Some(passphrase) = cached.as_deref() {
+        if let Ok(secret_key) = decrypt(text, passphrase) {
+            return Ok(secret_key);
+        }
+    }
+    let passphrase = ask_passphrase(path)?;
+    let secret_key = decrypt(text, &passphrase).map_err(|e| format!("{}: {}", path.display(), e))?;
+    *cached = Some(passphrase);
+    Ok(secret_key)
+}
+
+/// The passphrase for `path` from $SODIX_PASSPHRASE, or typed at the terminal.
+pub fn ask_passphrase(path: &Path) -> Result<Zeroizing<String>, String> {
+    let passphrase = match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => Zeroizing::new(passphrase),
+        Err(_) => rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))
+            .map(Zeroizing::new)
+            .map_err(|e| format!("Failed to read passphrase: {}", e))?,
+    };
+    memlock::lock(passphrase.as_bytes());
+    Ok(passphrase)
+}
+
+/// A new passphrase from $SODIX_NEW_PASSPHRASE or $SODIX_PASSPHRASE, or typed twice at
+/// the terminal.
+#[cfg(feature = "full")]
+pub fn new_passphrase() -> Result<Zeroizing<String>, String> {
+    if let Ok(passphrase) = std::env::var(NEW_PASSPHRASE_ENV).or_else(|_| std::env::var(PASSPHRASE_ENV)) {
+        return non_empty(Zeroizing::new(passphrase));
+    }
+    let read = |prompt: &str| {
+        rpassword::prompt_password(prompt)
+            .map(Zeroizing::new)
+            .map_err(|e| format!("Failed to read passphrase: {}", e))
+    };
+    let passphrase = read("New passphrase: ")?;
+    if read("Repeat passphrase: ")? != passphrase {
+        return Err("Passphrases do not match".to_string());
+    }
+    non_empty(passphrase)
+}
+
+#[cfg(feature = "full")]
+fn non_empty(passphrase: Zeroizing<String>) -> Result<Zeroizing<String>, String> {
+    if passphrase.is_empty() {
+        return Err("The passphrase must not be empty".to_string());
+    }
+    memlock::lock(passphrase.as_bytes());
+    Ok(passphrase)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], opslimit: u64, memlimit: u64) -> Result<Zeroizing<[u8; 32]>, String> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    crypto_pwhash(&mut *key, passphrase.as_bytes(), salt, opslimit, memlimit as usize, PasswordHashAlgorithm::Argon2id13)
+        .map_err(|e| format!("Failed to derive key from passphrase: {}", e))?;
+    Ok(key)
+}
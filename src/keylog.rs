@@ -0,0 +1,222 @@
+use dryoc::classic::crypto_sign::{crypto_sign_detached, crypto_sign_verify_detached};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Key transparency log of the contact store, keylog in the key directory. Once started
+// with `sodix keylog init`, every change saved to contacts.toml is appended, one entry
+// per line:
+//
+//   <seq> <unix seconds> add|replace|remove <name> <hex key> <hex previous>
+//   <seq> <unix seconds> checkpoint <hex signing public key> <hex signature> <hex previous>
+//
+// <previous> is the SHA-256 of the line before (zeros for the first), so no entry can
+// be changed or dropped without changing every hash after it. A checkpoint signs
+// "sodix-keylog-checkpoint <seq> <time> <hex previous>" with an Ed25519 key, vouching
+// for the whole history up to it. Whoever keeps the head hash of a log they have seen
+// (`keylog verify` prints it) can later check that the log still extends it, and see
+// every key added, replaced or removed since.
+pub const FILE_NAME: &str = "keylog";
+const CHECKPOINT_CONTEXT: &str = "sodix-keylog-checkpoint";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Add,
+    Replace,
+    Remove,
+}
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Add => "add",
+            Action::Replace => "replace",
+            Action::Remove => "remove",
+        }
+    }
+}
+
+pub enum Kind {
+    /// A contact's key was added, replaced (the new key) or removed (the key it had)
+    Change { action: Action, name: String, key: [u8; 32] },
+    Checkpoint { signer: [u8; 32], signature: [u8; 64] },
+}
+
+pub struct Entry {
+    pub seq: u64,
+    pub time: u64,
+    pub kind: Kind,
+    pub previous: [u8; 32],
+    pub hash: [u8; 32],
+}
+
+pub struct KeyLog {
+    path: PathBuf,
+    entries: Vec<Entry>,
+    /// Entries already in the file; `save` appends the rest
+    written: usize,
+}
+
+impl KeyLog {
+    /// Start a log at `path` with an add entry for each of `keys`.
+    pub fn create(path: &Path, keys: &BTreeMap<String, [u8; 32]>) -> Result<Self, String> {
+        if path.exists() {
+            return Err(format!("The key log {} already exists", path.display()));
+        }
+        let mut log = KeyLog { path: path.to_path_buf(), entries: Vec::new(), written: 0 };
+        log.record(keys);
+        Ok(log)
+    }
+
+    /// Read the log, checking the hash chain and the checkpoint signatures.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read the key log {}: {}", path.display(), e))?;
+        let mut entries: Vec<Entry> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let at = |e: String| format!("{}:{}: {}", path.display(), i + 1, e);
+            let entry = parse(line).map_err(at)?;
+            let previous = entries.last().map_or([0; 32], |e| e.hash);
+            if entry.seq != i as u64 + 1 {
+                return Err(at(format!("entry {} out of sequence", entry.seq)));
+            }
+            if entry.previous != previous {
+                return Err(at("the previous hash does not match: the log was changed before this line".to_string()));
+            }
+            if entries.last().is_some_and(|e| entry.time < e.time) {
+                return Err(at("the time goes backwards".to_string()));
+            }
+            if let Kind::Checkpoint { signer, signature } = &entry.kind {
+                crypto_sign_verify_detached(signature, checkpoint_text(entry.seq, entry.time, &entry.previous).as_bytes(), signer)
+                    .map_err(|_| at("the checkpoint signature is invalid".to_string()))?;
+            }
+            entries.push(entry);
+        }
+        let written = entries.len();
+        Ok(KeyLog { path: path.to_path_buf(), entries, written })
+    }
+
+    /// Append the new lines to the file.
+    pub fn save(&mut self) -> Result<(), String> {
+        let text: String = self.entries[self.written..].iter().map(|e| format!("{}\n", format_entry(e))).collect();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open the key log {}: {}", self.path.display(), e))?;
+        file.write_all(text.as_bytes())
+            .and_then(|()| file.sync_all())
+            .map_err(|e| format!("Failed to write the key log {}: {}", self.path.display(), e))?;
+        self.written = self.entries.len();
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The hash of the last entry, zeros for an empty log.
+    pub fn head(&self) -> [u8; 32] {
+        self.entries.last().map_or([0; 32], |e| e.hash)
+    }
+
+    /// The contacts' keys after replaying every change.
+    pub fn state(&self) -> BTreeMap<String, [u8; 32]> {
+        let mut keys = BTreeMap::new();
+        for entry in &self.entries {
+            if let Kind::Change { action, name, key } = &entry.kind {
+                match action {
+                    Action::Remove => keys.remove(name),
+                    Action::Add | Action::Replace => keys.insert(name.clone(), *key),
+                };
+            }
+        }
+        keys
+    }
+
+    /// Append a change for every way `keys` differs from the logged state; how many.
+    pub fn record(&mut self, keys: &BTreeMap<String, [u8; 32]>) -> usize {
+        let state = self.state();
+        let before = self.entries.len();
+        let time = self.now();
+        for (name, key) in &state {
+            if !keys.contains_key(name) {
+                self.push(time, Kind::Change { action: Action::Remove, name: name.clone(), key: *key });
+            }
+        }
+        for (name, key) in keys {
+            match state.get(name) {
+                Some(logged) if logged == key => {}
+                Some(_) => self.push(time, Kind::Change { action: Action::Replace, name: name.clone(), key: *key }),
+                None => self.push(time, Kind::Change { action: Action::Add, name: name.clone(), key: *key }),
+            }
+        }
+        self.entries.len() - before
+    }
+
+    /// Append a checkpoint signed with `sk`, vouching for every entry so far.
+    pub fn checkpoint(&mut self, sk: &[u8; 64]) -> Result<(), String> {
+        let time = self.now();
+        let mut signature = [0u8; 64];
+        crypto_sign_detached(&mut signature, checkpoint_text(self.entries.len() as u64 + 1, time, &self.head()).as_bytes(), sk)
+            .map_err(|e| format!("Error signing the checkpoint: {}", e))?;
+        self.push(time, Kind::Checkpoint { signer: sk[32..].try_into().unwrap(), signature });
+        Ok(())
+    }
+
+    /// The time for new entries, which never goes backwards, even if the clock does.
+    fn now(&self) -> u64 {
+        crate::unix_now().max(self.entries.last().map_or(0, |e| e.time))
+    }
+
+    fn push(&mut self, time: u64, kind: Kind) {
+        let mut entry = Entry { seq: self.entries.len() as u64 + 1, time, kind, previous: self.head(), hash: [0; 32] };
+        entry.hash = Sha256::digest(format_entry(&entry)).into();
+        self.entries.push(entry);
+    }
+}
+
+fn checkpoint_text(seq: u64, time: u64, previous: &[u8; 32]) -> String {
+    format!("{} {} {} {}", CHECKPOINT_CONTEXT, seq, time, hex::encode(previous))
+}
+
+fn format_entry(entry: &Entry) -> String {
+    let fields = match &entry.kind {
+        Kind::Change { action, name, key } => format!("{} {} {}", action.as_str(), name, hex::encode(key)),
+        Kind::Checkpoint { signer, signature } => format!("checkpoint {} {}", hex::encode(signer), hex::encode(signature)),
+    };
+    format!("{} {} {} {}", entry.seq, entry.time, fields, hex::encode(entry.previous))
+}
+
+fn parse(line: &str) -> Result<Entry, String> {
+    let invalid = || "expected '<seq> <time> <action> <name or signer> <key or signature> <previous>'".to_string();
+    let fields: Vec<&str> = line.split(' ').collect();
+    let [seq, time, action, first, second, previous] = fields[..] else {
+        return Err(invalid());
+    };
+    let kind = match action {
+        "checkpoint" => Kind::Checkpoint { signer: decode(first).ok_or_else(invalid)?, signature: decode(second).ok_or_else(invalid)? },
+        _ => {
+            let action = [Action::Add, Action::Replace, Action::Remove]
+                .into_iter()
+                .find(|a| a.as_str() == action)
+                .ok_or_else(|| format!("unknown action '{}'", action))?;
+            if first.is_empty() {
+                return Err(invalid());
+            }
+            Kind::Change { action, name: first.to_string(), key: decode(second).ok_or_else(invalid)? }
+        }
+    };
+    Ok(Entry {
+        seq: seq.parse().map_err(|_| invalid())?,
+        time: time.parse().map_err(|_| invalid())?,
+        kind,
+        previous: decode(previous).ok_or_else(invalid)?,
+        hash: Sha256::digest(line).into(),
+    })
+}
+
+fn decode<const N: usize>(hex_value: &str) -> Option<[u8; N]> {
+    hex::decode(hex_value).ok().and_then(|v| v.try_into().ok())
+}
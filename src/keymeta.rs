@@ -0,0 +1,94 @@
+use crate::fingerprint::{fingerprint, FingerprintAlg};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// Key metadata, keys.meta in a key directory, one line per public key:
+//
+//   <fingerprint> expires <RFC 3339 time>
+//
+// `generate --expires` records both new key pairs. Sign refuses a key that has expired,
+// and check flags signatures by a key known to have expired unless their signed
+// timestamp shows they were made before it did.
+pub const FILE_NAME: &str = "keys.meta";
+
+#[derive(Default)]
+pub struct KeyMeta {
+    expires: HashMap<String, u64>,
+}
+
+impl KeyMeta {
+    /// Metadata from keys.meta in each of `dirs`; directories without one are skipped.
+    pub fn load(dirs: &[PathBuf]) -> Result<Self, String> {
+        let mut meta = KeyMeta::default();
+        for dir in dirs {
+            let path = dir.join(FILE_NAME);
+            if !path.is_file() {
+                continue;
+            }
+            for (fpr, expires) in read(&path)? {
+                meta.expires.insert(fpr, expires);
+            }
+        }
+        Ok(meta)
+    }
+
+    /// When `public_key` expires (Unix seconds), if that is known.
+    pub fn expires(&self, public_key: &[u8]) -> Option<u64> {
+        self.expires.get(&fingerprint(public_key, FingerprintAlg::default())).copied()
+    }
+}
+
+/// Record that `public_keys` expire at `expires` in `dir`'s keys.meta, replacing any
+/// earlier entries for them.
+#[cfg(feature = "full")]
+pub fn record_expiry(dir: &Path, public_keys: &[&[u8]], expires: u64) -> Result<(), String> {
+    let path = dir.join(FILE_NAME);
+    let fprs: Vec<String> = public_keys.iter().map(|pk| fingerprint(pk, FingerprintAlg::default())).collect();
+    let mut entries = if path.is_file() { read(&path)? } else { Vec::new() };
+    entries.retain(|(fpr, _)| !fprs.contains(fpr));
+    entries.extend(fprs.into_iter().map(|fpr| (fpr, expires)));
+    let text: String = entries
+        .iter()
+        .map(|(fpr, expires)| format!("{} expires {}\n", fpr, format_time(*expires)))
+        .collect();
+    fs::write(&path, text).map_err(|e| format!("Failed to write key metadata to {}: {}", path.display(), e))
+}
+
+/// An expiry given as a date (2027-01-31), an RFC 3339 time, or a duration from now (1y, 90d).
+#[cfg(feature = "full")]
+pub fn parse_expiry(value: &str, now: u64) -> Result<u64, String> {
+    if let Ok(duration) = humantime::parse_duration(value) {
+        return Ok(now + duration.as_secs());
+    }
+    let time = if value.len() == 10 { format!("{}T00:00:00Z", value) } else { value.to_string() };
+    humantime::parse_rfc3339_weak(&time)
+        .map(|t| t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()))
+        .map_err(|_| format!("Invalid expiry '{}': expected a date, an RFC 3339 time or a duration", value))
+}
+
+pub fn format_time(secs: u64) -> String {
+    humantime::format_rfc3339_seconds(UNIX_EPOCH + std::time::Duration::from_secs(secs)).to_string()
+}
+
+fn read(path: &Path) -> Result<Vec<(String, u64)>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read key metadata from {}: {}", path.display(), e))?;
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || format!("{}:{}: expected '<fingerprint> expires <time>'", path.display(), i + 1);
+        let mut fields = line.split_whitespace();
+        let (fpr, expires) = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(fpr), Some("expires"), Some(time), None) => (fpr, time),
+            _ => return Err(invalid()),
+        };
+        let expires = humantime::parse_rfc3339_weak(expires).map_err(|_| invalid())?;
+        entries.push((fpr.to_string(), expires.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())));
+    }
+    Ok(entries)
+}
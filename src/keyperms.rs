@@ -0,0 +1,145 @@
+#[cfg(feature = "full")]
+use std::fs::{File, OpenOptions};
+#[cfg(feature = "full")]
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Secret key files (sodix keys, identities, minisign/signify and exported keys) are
+// created readable by their owner only: mode 0600 on Unix, an ACL granting only the
+// current user on Windows. A file being overwritten is restricted before the secret
+// goes into it.
+//
+// On Unix a secret key file that group or others can access is refused when loaded,
+// as ssh does, since the key must be considered leaked; --insecure-key-perms turns
+// that into a warning, for keys on filesystems without Unix permissions. Windows
+// ACLs are not checked.
+
+static INSECURE_ALLOWED: AtomicBool = AtomicBool::new(false);
+
+/// Warn about secret key files others can read instead of refusing them, for the rest
+/// of the run (--insecure-key-perms).
+pub fn allow_insecure() {
+    INSECURE_ALLOWED.store(true, Ordering::Relaxed);
+}
+
+#[cfg(feature = "full")]
+/// Write `data` to `path` readable by the owner only.
+pub fn write_secret(path: &Path, data: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    restrict(&file, path)?;
+    file.write_all(data.as_ref())
+}
+
+#[cfg(all(feature = "full", unix))]
+fn restrict(file: &File, _path: &Path) -> io::Result<()> {
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))
+}
+
+#[cfg(all(feature = "full", windows))]
+fn restrict(_file: &File, path: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Foundation::{CloseHandle, LocalFree, ERROR_SUCCESS, HANDLE};
+    use windows_sys::Win32::Security::Authorization::{
+        SetEntriesInAclW, SetNamedSecurityInfoW, EXPLICIT_ACCESS_W, NO_MULTIPLE_TRUSTEE, SET_ACCESS, SE_FILE_OBJECT, TRUSTEE_IS_SID,
+        TRUSTEE_IS_USER, TRUSTEE_W,
+    };
+    use windows_sys::Win32::Security::{
+        GetTokenInformation, TokenUser, ACL, DACL_SECURITY_INFORMATION, NO_INHERITANCE, PROTECTED_DACL_SECURITY_INFORMATION, TOKEN_QUERY,
+        TOKEN_USER,
+    };
+    use windows_sys::Win32::Storage::FileSystem::FILE_ALL_ACCESS;
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    // SAFETY: every pointer passed is to a live local or to memory the calls allocated;
+    // the token handle and the ACL are released before returning.
+    unsafe {
+        // The SID of the user this process runs as, from its token
+        let mut token: HANDLE = std::ptr::null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut len = 0u32;
+        GetTokenInformation(token, TokenUser, std::ptr::null_mut(), 0, &mut len);
+        // u64s keep the buffer aligned for the TOKEN_USER it receives
+        let mut buf = vec![0u64; (len as usize).div_ceil(8)];
+        let queried = GetTokenInformation(token, TokenUser, buf.as_mut_ptr().cast(), len, &mut len);
+        let error = io::Error::last_os_error();
+        CloseHandle(token);
+        if queried == 0 {
+            return Err(error);
+        }
+        let user = &*(buf.as_ptr() as *const TOKEN_USER);
+
+        // A DACL with that user as the only entry, not inheriting from the directory
+        let access = EXPLICIT_ACCESS_W {
+            grfAccessPermissions: FILE_ALL_ACCESS,
+            grfAccessMode: SET_ACCESS,
+            grfInheritance: NO_INHERITANCE,
+            Trustee: TRUSTEE_W {
+                pMultipleTrustee: std::ptr::null_mut(),
+                MultipleTrusteeOperation: NO_MULTIPLE_TRUSTEE,
+                TrusteeForm: TRUSTEE_IS_SID,
+                TrusteeType: TRUSTEE_IS_USER,
+                ptstrName: user.User.Sid.cast(),
+            },
+        };
+        let mut acl: *mut ACL = std::ptr::null_mut();
+        let status = SetEntriesInAclW(1, &access, std::ptr::null(), &mut acl);
+        if status != ERROR_SUCCESS {
+            return Err(io::Error::from_raw_os_error(status as i32));
+        }
+        let status = SetNamedSecurityInfoW(
+            wide.as_ptr(),
+            SE_FILE_OBJECT,
+            DACL_SECURITY_INFORMATION | PROTECTED_DACL_SECURITY_INFORMATION,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            acl,
+            std::ptr::null(),
+        );
+        LocalFree(acl.cast());
+        if status != ERROR_SUCCESS {
+            return Err(io::Error::from_raw_os_error(status as i32));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "full", not(any(unix, windows))))]
+fn restrict(_file: &File, _path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Refuse the secret key file at `path` if others can access it, or only warn under
+/// --insecure-key-perms.
+#[cfg(unix)]
+pub fn check(path: &Path) -> Result<(), String> {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return Ok(());
+    };
+    let mode = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o777;
+    if mode & 0o077 == 0 {
+        return Ok(());
+    }
+    if INSECURE_ALLOWED.load(Ordering::Relaxed) {
+        eprintln!("Warning: secret key {} is accessible by others (mode {:03o}); run `chmod 600 {}`", path.display(), mode, path.display());
+        return Ok(());
+    }
+    Err(format!(
+        "Secret key {} is accessible by others (mode {:03o}); run `chmod 600 {}`, or pass --insecure-key-perms to use it anyway",
+        path.display(),
+        mode,
+        path.display()
+    ))
+}
+
+#[cfg(not(unix))]
+pub fn check(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
@@ -0,0 +1,6 @@
+//! Library half of sodix: pieces meant to be embedded in other programs rather
+//! than driven through the CLI.
+
+pub mod license;
+pub mod multipart;
+pub mod sharedkey;
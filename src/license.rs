@@ -0,0 +1,122 @@
+//! Offline license tokens: a vendor signs a customer id, feature list and expiry
+//! with an Ed25519 key, and the application checks the token against the vendor's
+//! public key with [`verify`] — no server, no network.
+//!
+//! ```ignore
+//! let license = sodix::license::verify(&token, &VENDOR_PUBLIC_KEY, now)?;
+//! if license.has_feature("export") { /* ... */ }
+//! ```
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+
+// Token: PREFIX followed by base64url (no padding) of
+//
+//   version | expires u64 BE (0 = never) | customer len u8 | customer
+//   | feature count u8 | (feature len u8 | feature)... | signature (64)
+//
+// The signature covers CONTEXT and everything before it.
+const PREFIX: &str = "sxl1.";
+const VERSION: u8 = 1;
+const CONTEXT: &[u8] = b"sodix license v1";
+
+/// The entitlements granted by a license token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct License {
+    pub customer: String,
+    pub features: Vec<String>,
+    /// Unix time after which the license is no longer valid; `None` never expires
+    pub expires: Option<u64>,
+}
+
+impl License {
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    #[cfg(feature = "full")]
+    fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut out = vec![VERSION];
+        out.extend_from_slice(&self.expires.unwrap_or(0).to_be_bytes());
+        put_field(&mut out, "customer id", &self.customer)?;
+        let count: u8 = self.features.len().try_into().map_err(|_| "A license can list at most 255 features")?;
+        out.push(count);
+        for feature in &self.features {
+            put_field(&mut out, "feature name", feature)?;
+        }
+        Ok(out)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let truncated = || "Truncated license token".to_string();
+        if bytes.len() < 9 {
+            return Err(truncated());
+        }
+        if bytes[0] != VERSION {
+            return Err(format!("Unsupported license version {}", bytes[0]));
+        }
+        let expires = u64::from_be_bytes(bytes[1..9].try_into().unwrap());
+        let mut rest = &bytes[9..];
+        let customer = take_field(&mut rest).ok_or_else(truncated)?;
+        let (&count, tail) = rest.split_first().ok_or_else(truncated)?;
+        rest = tail;
+        let mut features = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            features.push(take_field(&mut rest).ok_or_else(truncated)?);
+        }
+        if !rest.is_empty() {
+            return Err("Trailing data in license token".to_string());
+        }
+        Ok(License { customer, features, expires: (expires != 0).then_some(expires) })
+    }
+}
+
+/// Sign `license` with the vendor's Ed25519 secret key and return the token string.
+#[cfg(feature = "full")]
+pub fn issue(license: &License, secret_key: &[u8; 64]) -> Result<String, String> {
+    let mut token = license.to_bytes()?;
+    let mut signature = [0u8; 64];
+    crypto_sign_detached(&mut signature, &[CONTEXT, &token].concat(), secret_key)
+        .map_err(|e| format!("Error signing license: {}", e))?;
+    token.extend_from_slice(&signature);
+    Ok(format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(token)))
+}
+
+/// Check `token` against the vendor's public key and return its contents if it is
+/// authentic and not expired at `now` (unix seconds).
+pub fn verify(token: &str, public_key: &[u8; 32], now: u64) -> Result<License, String> {
+    let encoded = token.trim().strip_prefix(PREFIX).ok_or("Not a sodix license token")?;
+    let bytes = URL_SAFE_NO_PAD.decode(encoded).map_err(|e| format!("Invalid license token encoding: {}", e))?;
+    if bytes.len() < 64 {
+        return Err("Truncated license token".to_string());
+    }
+    let (body, signature) = bytes.split_at(bytes.len() - 64);
+    crypto_sign_verify_detached(signature.try_into().unwrap(), &[CONTEXT, body].concat(), public_key)
+        .map_err(|e| format!("License signature is invalid: {}", e))?;
+
+    let license = License::from_bytes(body)?;
+    if let Some(expires) = license.expires {
+        if now >= expires {
+            return Err(format!("License for '{}' expired at {} (unix time)", license.customer, expires));
+        }
+    }
+    Ok(license)
+}
+
+#[cfg(feature = "full")]
+fn put_field(out: &mut Vec<u8>, what: &str, value: &str) -> Result<(), String> {
+    let len: u8 = value.len().try_into().map_err(|_| format!("License {} '{}' is longer than 255 bytes", what, value))?;
+    out.push(len);
+    out.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn take_field(rest: &mut &[u8]) -> Option<String> {
+    let (&len, tail) = rest.split_first()?;
+    let value = tail.get(..len as usize)?;
+    *rest = &tail[len as usize..];
+    Some(String::from_utf8_lossy(value).into_owned())
+}
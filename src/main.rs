@@ -1,14 +1,115 @@
-use clap::{Parser, Subcommand};
-use dryoc::classic::crypto_box::{crypto_box_easy, crypto_box_open_easy};
-use dryoc::classic::crypto_sign::{crypto_sign_detached, crypto_sign_verify_detached};
+use aead::AeadAlg;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bech32key::KeyKind;
+use checksum::HashAlg;
+use clap::{Parser, Subcommand, ValueEnum};
+use directories::ProjectDirs;
+use dryoc::classic::crypto_sign::{crypto_sign_open, crypto_sign_verify_detached};
+use envelope::Envelope;
+use fingerprint::{fingerprint, FingerprintAlg};
+use sodix::license;
+use sodix::multipart::Ed25519ph;
+use sodix::sharedkey;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::io::{self, BufRead, BufWriter, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+// Everything that creates keys, signatures or ciphertexts. Without the default
+// `full` feature only the verification and decryption paths are compiled in.
+#[cfg(feature = "full")]
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+#[cfg(feature = "full")]
+use chacha20::XChaCha20;
+#[cfg(feature = "full")]
+use delegation::DelegationCert;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_core::{crypto_scalarmult, crypto_scalarmult_base};
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign_ed25519::{crypto_sign_ed25519_pk_to_curve25519, crypto_sign_ed25519_sk_to_curve25519};
+#[cfg(feature = "full")]
 use dryoc::keypair::StackKeyPair;
+#[cfg(feature = "full")]
 use dryoc::sign::SigningKeyPair;
+#[cfg(feature = "full")]
 use dryoc::types::StackByteArray;
-use std::fs;
-use std::path::{Path, PathBuf};
-use std::io::{self, Write};
-use rand::rngs::StdRng;
-use rand::{Rng, SeedableRng};
+#[cfg(feature = "full")]
+use rand::Rng;
+#[cfg(feature = "full")]
+use std::fs::OpenOptions;
+#[cfg(feature = "full")]
+use std::io::IsTerminal;
+
+mod aead;
+mod age;
+mod archive;
+#[cfg(feature = "full")]
+mod agent;
+mod armor;
+mod batch;
+mod bech32key;
+#[cfg(feature = "full")]
+mod bundle;
+mod certification;
+mod channel;
+mod checksum;
+mod cleanup;
+#[cfg(feature = "full")]
+mod contacts;
+mod container;
+mod cpu;
+mod cose;
+mod delegation;
+#[cfg(feature = "full")]
+mod derive;
+mod didkey;
+mod envelope;
+mod fingerprint;
+mod gitsign;
+mod hexstream;
+mod identity;
+mod ignore;
+mod inputs;
+#[cfg(feature = "full")]
+mod jwk;
+mod jwt;
+mod keyfile;
+#[cfg(feature = "full")]
+mod keylog;
+mod keyperms;
+mod keymeta;
+mod manifest;
+mod memlock;
+mod memory;
+mod mime;
+mod minisign;
+mod mmap;
+#[cfg(feature = "openpgp")]
+mod openpgp;
+#[cfg(feature = "full")]
+mod pair;
+mod pkcs8;
+mod progress;
+mod saltpack;
+mod signcrypt;
+mod signify;
+mod split;
+mod sshsig;
+mod trust;
+#[cfg(feature = "full")]
+mod vanity;
+#[cfg(feature = "full")]
+mod watch;
+#[cfg(feature = "full")]
+mod wellknown;
+#[cfg(feature = "full")]
+mod workflow;
 
 #[derive(Parser)]
 #[command(name = "sodix", about = "sodix - libsodium compatible cli tool")]
@@ -18,77 +119,1224 @@ struct Cli {
     /// Enable verbose output for debugging
     #[arg(long, short = 'v', global = true)]
     verbose: bool,
+    /// Use the keys in a single-file identity (see `generate --single-file`) instead of
+    /// the key files in the key directory
+    #[arg(long, global = true, value_name = "FILE")]
+    identity: Option<PathBuf>,
+    /// Read input files with read() instead of memory-mapping large ones; for network
+    /// filesystems, where a mapped read that fails kills the process
+    #[arg(long, global = true)]
+    no_mmap: bool,
+    /// Keep memory use within SIZE (e.g. 64M): fewer chunks in flight, a smaller zstd
+    /// window, and large files kept to the streaming paths
+    #[arg(long, global = true, value_name = "SIZE")]
+    max_memory: Option<String>,
+    /// Leave secret keys and passphrases in swappable memory instead of locking them
+    /// into RAM (mlock), e.g. where the locked-memory limit is too small to bother
+    #[arg(long, global = true)]
+    no_mlock: bool,
+    /// Let a crash dump core although secret keys are loaded, for debugging; by
+    /// default core dumps are turned off as soon as a secret is read
+    #[arg(long, global = true)]
+    allow_coredumps: bool,
+    /// Use secret key files that group or others can read, with a warning, instead of
+    /// refusing them
+    #[arg(long, global = true)]
+    insecure_key_perms: bool,
+    /// Decrypt files with several key slots from another party's sender key, although
+    /// any of their recipients could have written them in the sender's name
+    #[arg(long, global = true)]
+    trust_recipients: bool,
+    /// With several files, keep the outputs of those that succeeded when another one
+    /// fails, instead of writing nothing
+    #[arg(long, global = true)]
+    keep_partial: bool,
+    /// Draw no progress bars for long file operations
+    #[arg(long, short = 'q', global = true)]
+    quiet: bool,
+    /// Report the progress of long file operations on stderr as JSON lines
+    #[arg(long, global = true, conflicts_with = "quiet")]
+    progress_json: bool,
+}
+
+/// Signature and key file formats for generate, sign and check.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+enum SignatureFormat {
+    /// Hex signatures and envelopes, hex key files
+    #[default]
+    Sodix,
+    /// minisign / rsign2: .minisig signatures, minisign.pub and minisign.key
+    Minisign,
+    /// OpenBSD signify: .sig signatures, signify.pub and signify.sec
+    Signify,
+    /// SSH signatures (ssh-keygen -Y) with ssh-ed25519 keys, as used by git
+    Sshsig,
+    /// COSE_Sign1 (RFC 9052, CBOR) EdDSA signatures with the sodix signing keys
+    Cose,
+    /// Armored saltpack signed messages and detached signatures with the sodix signing keys
+    Saltpack,
+    #[cfg(feature = "openpgp")]
+    /// OpenPGP Ed25519 detached signatures (.asc) with the sodix signing keys, for gpg
+    Openpgp,
+}
+
+/// Message formats for encrypt and decrypt.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+enum MessageFormat {
+    /// crypto_box messages and the chunked .x container
+    #[default]
+    Sodix,
+    /// age v1 files (.age) with X25519 recipients, for age and rage
+    Age,
+    /// Armored saltpack encrypted messages (.saltpack), for Keybase and saltpack tools
+    Saltpack,
+}
+
+#[cfg(feature = "full")]
+/// What `key export` writes.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+enum ExportFormat {
+    /// Passphrase-encrypted bundle of the key directory, for `key import`
+    #[default]
+    Bundle,
+    /// PKCS#8 and SubjectPublicKeyInfo PEM files, for OpenSSL-based tools
+    Pkcs8,
+    /// JSON Web Keys (RFC 8037), for JOSE-based services
+    Jwk,
+    /// Base64 encryption keys as `wg genkey` and `wg pubkey` write them, for WireGuard
+    Wireguard,
+    /// age identity (AGE-SECRET-KEY-1...) and recipient (age1...) for the encryption keys
+    Age,
+    #[cfg(feature = "openpgp")]
+    /// OpenPGP certificate (sign_public.asc) of the signing key, for `gpg --import`
+    Openpgp,
+}
+
+#[cfg(feature = "full")]
+/// How an import settles a name that is already saved with a different key.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum ConflictPolicy {
+    /// Keep the key you have and skip the imported one
+    Keep,
+    /// Take the imported key
+    Replace,
+    /// Stop without importing anything
+    Fail,
+}
+
+#[cfg(feature = "full")]
+/// Which key pairs generate writes.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+enum KeyType {
+    /// Signing key pair only: sign_public.key and sign_secret.key
+    Sign,
+    /// Encryption key pair only: enc_public.key and enc_secret.key
+    Enc,
+    /// Both key pairs
+    #[default]
+    All,
+}
+
+#[cfg(feature = "full")]
+/// What watch does with a changed file.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum WatchAction {
+    /// Write a detached signature <file>.sig
+    Sign,
+    /// Write an encrypted copy <file>.x
+    Encrypt,
 }
 
-#[derive(Subcommand)]
+#[cfg(feature = "full")]
+impl KeyType {
+    fn sign(self) -> bool {
+        self != KeyType::Enc
+    }
+
+    fn enc(self) -> bool {
+        self != KeyType::Sign
+    }
+}
+
+#[derive(Clone, Subcommand)]
 enum Commands {
+    #[cfg(feature = "full")]
     /// Sign a message or file
     #[command(visible_alias = "s")]
     Sign {
+        #[arg(required_unless_present_any = ["files_from", "null"], default_value = "", hide_default_value = true)]
         input: String,
+        /// More files to sign with -f; INPUT may also be a glob pattern such as
+        /// 'dist/*.tar.gz'. Each gets its own signature file
+        #[arg(requires = "file")]
+        more: Vec<String>,
+        /// Also process the files listed in LIST ("-" for stdin), one per line; names are
+        /// taken literally, not as patterns
+        #[arg(long, value_name = "LIST", requires = "file")]
+        files_from: Option<PathBuf>,
+        /// The file list is NUL-delimited (find -print0); without --files-from it is read
+        /// from stdin
+        #[arg(short = '0', long = "null", requires = "file")]
+        null: bool,
+        /// Skip pattern matches covered by PATTERN (gitignore syntax), on top of the rules
+        /// in ./.sodixignore
+        #[arg(long, value_name = "PATTERN", requires = "file")]
+        exclude: Vec<String>,
         #[arg(long, short = 'k')]
         key: Option<String>,  // Changed from PathBuf to String
         #[arg(long, short = 'f')]
         file: bool,
+        /// Delegation certificate to embed (when signing with a delegated subkey)
+        #[arg(long)]
+        cert: Option<PathBuf>,
+        /// Write the signature to a file instead of stdout, default <input>.sig
+        #[arg(long, num_args = 0..=1, requires = "file")]
+        sig_file: Option<Option<PathBuf>>,
+        /// Output signature and message together (crypto_sign); -f writes <input>.signed
+        #[arg(long, conflicts_with_all = ["cert", "sig_file"])]
+        attached: bool,
+        /// Sign the SHA-512 of the input (Ed25519ph) so huge files are streamed in
+        /// constant memory; the mode is recorded in the signature envelope. Files of
+        /// 4 GiB and more are always signed this way
+        #[arg(long, conflicts_with = "attached")]
+        prehash: bool,
+        /// Signature format; minisign writes <input>.minisig, signify and sshsig <input>.sig,
+        /// cose <input>.cose, saltpack <input>.saltpack.sig (<input>.saltpack attached) and
+        /// openpgp <input>.asc for files (hex or armor on stdout otherwise), and --attached with signify, cose
+        /// or saltpack embeds the message. sshsig signs with an OpenSSH private key,
+        /// default ~/.ssh/id_ed25519
+        #[arg(long, value_enum, default_value_t)]
+        format: SignatureFormat,
+        /// Namespace of an sshsig signature (ssh-keygen -n); git uses "git"
+        #[arg(long, default_value = "file")]
+        namespace: String,
+        /// Untrusted (unsigned) comment; sodix stores it in the signature envelope
+        #[arg(long)]
+        comment: Option<String>,
+        /// Comment covered by the signature, shown by check -v; not for sshsig
+        #[arg(long)]
+        trusted_comment: Option<String>,
+        /// Record the signing time and the key's fingerprint in the signed envelope
+        #[arg(long)]
+        timestamp: bool,
+        /// What the signature approves, recorded in the signed envelope with the
+        /// timestamp; check --purpose requires it to match
+        #[arg(long)]
+        purpose: Option<String>,
+        /// Add the signature as a new line of the signature file instead of replacing
+        /// it, to collect sign-offs for check --require
+        #[arg(long, requires = "sig_file")]
+        append: bool,
+        /// Replace an existing signature file (or <input>.signed) instead of refusing
+        #[arg(long, requires = "file", conflicts_with = "append")]
+        force: bool,
+        /// Sign with the named identity's key (see `key add`)
+        #[arg(long, conflicts_with = "key")]
+        id: Option<String>,
+        /// Sign even if keys.meta says the key has expired
+        #[arg(long)]
+        ignore_expiry: bool,
+        /// Write the signature as a -----BEGIN SODIX SIGNATURE----- block instead of hex
+        #[arg(long, conflicts_with = "attached")]
+        armor: bool,
+        /// Have ssh-agent ($SSH_AUTH_SOCK) sign with one of its Ed25519 keys instead of
+        /// reading a secret key file; -k then picks the agent key by its public key
+        #[arg(long, conflicts_with_all = ["prehash", "id"])]
+        use_agent: bool,
     },
     /// Verify a signature
     #[command(visible_alias = "c")]
     Check {
+        #[arg(required_unless_present_any = ["files_from", "null"], default_value = "", hide_default_value = true)]
         input: String,
-        signature: String,
+        #[arg(required_unless_present_any = ["sig_file", "attached", "batch", "format"], allow_hyphen_values = true)]
+        signature: Option<String>,
+        /// More files to check with -f and --sig-file, which also makes SIGNATURE a file
+        /// to check; INPUT may be a glob pattern. Prints "valid FILE" or "invalid FILE"
+        /// for each and fails if any is invalid
+        #[arg(requires_all = ["file", "sig_file"])]
+        more: Vec<String>,
+        /// Also process the files listed in LIST ("-" for stdin), one per line; names are
+        /// taken literally, not as patterns
+        #[arg(long, value_name = "LIST", requires_all = ["file", "sig_file"])]
+        files_from: Option<PathBuf>,
+        /// The file list is NUL-delimited (find -print0); without --files-from it is read
+        /// from stdin
+        #[arg(short = '0', long = "null", requires_all = ["file", "sig_file"])]
+        null: bool,
+        /// Public key (hex, file, or a directory of key files); repeatable. Any of the
+        /// keys may match, or --require of them
         #[arg(long, short = 'k')]
-        key: Option<String>,  // Changed from PathBuf to String
+        key: Vec<String>,
         #[arg(long, short = 'f')]
         file: bool,
+        /// Read the signature from a file, default <input>.sig
+        #[arg(long, num_args = 0..=1, requires = "file")]
+        sig_file: Option<Option<PathBuf>>,
+        /// Input is a signed message from sign --attached; prints the message if valid
+        #[arg(long, conflicts_with_all = ["signature", "sig_file"])]
+        attached: bool,
+        /// Input is a manifest of "<file> <signature> [<public key>]" lines, verified in
+        /// parallel with batched Ed25519; prints valid/invalid per file
+        #[arg(long, conflicts_with_all = ["signature", "sig_file", "attached", "file"])]
+        batch: bool,
+        /// Signature format; for minisign, signify, sshsig, cose and saltpack, SIGNATURE is a
+        /// signature file (default <input>.minisig, <input>.sig, <input>.cose or
+        /// <input>.saltpack.sig; cose also takes hex, saltpack the armored text; openpgp
+        /// <input>.asc or <input>.sig and -k an OpenPGP certificate or sodix key) and
+        /// --attached reads a signify -e file, a COSE_Sign1 with embedded payload or a
+        /// saltpack signed message.
+        /// sshsig takes the signer's key from a .pub, authorized_keys or allowed_signers file
+        #[arg(long, value_enum, default_value_t, conflicts_with = "batch")]
+        format: SignatureFormat,
+        /// Namespace the sshsig signature must have been made for
+        #[arg(long, default_value = "file")]
+        namespace: String,
+        /// Reject signatures whose signed timestamp is older than this, e.g. "15m" or "7d"
+        #[arg(long, conflicts_with = "batch")]
+        max_age: Option<String>,
+        /// Require the signature to be signed for this purpose
+        #[arg(long, conflicts_with = "batch")]
+        purpose: Option<String>,
+        /// Pass only if this many distinct --key have a valid signature; the signature
+        /// (file) then holds one hex signature per line, as written by sign --append
+        #[arg(long, default_value_t = 1, conflicts_with_all = ["attached", "batch"])]
+        require: usize,
+        /// Accept signatures by keys that keys.meta says have expired
+        #[arg(long)]
+        ignore_expiry: bool,
+        /// Only trust keys certified by you or an introducer (see `key certify`)
+        #[arg(long, conflicts_with = "batch")]
+        require_certified: bool,
+        /// Trust on first use: remember the key that verifies for NAME, and fail if a
+        /// different key signs for NAME later (see `sodix trust`)
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["batch", "attached"])]
+        tofu: Option<String>,
     },
+    #[cfg(feature = "full")]
     /// Encrypt a message or file
     #[command(visible_alias = "e")] 
     Encrypt {
+        #[arg(required_unless_present_any = ["files_from", "null", "pipe"], default_value = "", hide_default_value = true)]
         input: String,
+        /// More files to encrypt with -f, each to its own .x; INPUT may also be a glob pattern
+        #[arg(requires = "file")]
+        more: Vec<String>,
+        /// Also process the files listed in LIST ("-" for stdin), one per line; names are
+        /// taken literally, not as patterns
+        #[arg(long, value_name = "LIST", requires = "file")]
+        files_from: Option<PathBuf>,
+        /// The file list is NUL-delimited (find -print0); without --files-from it is read
+        /// from stdin
+        #[arg(short = '0', long = "null", requires = "file")]
+        null: bool,
+        /// Skip pattern matches covered by PATTERN (gitignore syntax), on top of the rules
+        /// in ./.sodixignore
+        #[arg(long, value_name = "PATTERN", requires = "file")]
+        exclude: Vec<String>,
         #[arg(long, short = 'k')]
-        pubkey: Option<String>,  // Receiver's public key in hex
+        pubkey: Vec<String>,  // Receivers' public keys in hex, repeatable for files
+        /// File with one recipient public key (hex) per line; '#' starts a comment
+        #[arg(long, requires = "file")]
+        recipients: Option<PathBuf>,
+        /// Also encrypt to your own key pair from the default key files, so you can decrypt it later
+        #[arg(long, conflicts_with = "seckey")]
+        to_self: bool,
         #[arg(long, short = 's')]
         seckey: Option<String>,  // Sender's secret key in hex
+        /// Wrap the key from a one-off X25519 key pair signed with your signing key, so
+        /// your long-term encryption key cannot decrypt this message later
+        #[arg(long, conflicts_with_all = ["seckey", "aead"])]
+        ephemeral: bool,
+        /// Signing secret key (hex or path) for --ephemeral, default sign_secret.key
+        #[arg(long, requires = "ephemeral")]
+        sign_key: Option<String>,
+        /// Deterministic output: the same input, recipients and secret give the same
+        /// ciphertext, so backups can deduplicate it. Reveals which files are equal.
+        #[arg(long, conflicts_with_all = ["ephemeral", "aead"])]
+        convergent: bool,
+        /// Convergence secret (hex or path); default derived from your secret key
+        #[arg(long, requires = "convergent")]
+        convergence_key: Option<String>,
         #[arg(long, short = 'f')]
         file: bool,
+        /// Use an AEAD construction instead of crypto_box
+        #[arg(long, value_enum)]
+        aead: Option<AeadAlg>,
+        /// Additional authenticated (not encrypted) data, e.g. filename or recipient id
+        #[arg(long, requires = "aead")]
+        aad: Option<String>,
+        /// Print a multipart MIME email with the ciphertext attached
+        #[arg(long)]
+        mime: bool,
+        /// Hash for the key fingerprints in MIME headers
+        #[arg(long, value_enum, requires = "mime")]
+        fingerprint_alg: Option<FingerprintAlg>,
+        /// Write the header (key slots, nonce, sizes) to its own file, default <input>.x.hdr;
+        /// <input>.x then holds only the chunks
+        #[arg(long, num_args = 0..=1, requires = "file", conflicts_with_all = ["aead", "mime"], value_name = "PATH")]
+        detached_header: Option<Option<PathBuf>>,
+        /// Use the named identity's keys instead of the default key files (see `key add`)
+        #[arg(long)]
+        id: Option<String>,
+        /// Recipient from your contacts (see `contact add`), repeatable
+        #[arg(long)]
+        to: Vec<String>,
+        /// Only encrypt to keys certified by you or an introducer (see `key certify`)
+        #[arg(long)]
+        require_certified: bool,
+        /// Output a -----BEGIN SODIX MESSAGE----- block instead of hex; with -f the file
+        /// is encrypted in memory rather than streamed
+        #[arg(long, conflicts_with_all = ["mime", "detached_header"])]
+        armor: bool,
+        /// Compress the file with zstd (level 1-22, default 3) before encrypting it; decrypt
+        /// decompresses it again
+        #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "3", value_name = "LEVEL", requires = "file",
+              conflicts_with_all = ["aead", "mime", "armor", "convergent"], value_parser = clap::value_parser!(i32).range(1..=22))]
+        compress: Option<i32>,
+        /// Do not record the file's mode, mtime or symlink target; a symlink is then
+        /// encrypted as the file it points to
+        #[arg(long, requires = "file")]
+        no_preserve: bool,
+        /// Name the output <random hex>.x in the input's directory and seal the file name
+        /// inside, for decrypt -f to restore; the size stays visible
+        #[arg(long, requires = "file", conflicts_with_all = ["aead", "mime", "armor"])]
+        hide_name: bool,
+        /// Replace an existing <input>.x (or .hdr, .age, .saltpack) instead of refusing
+        #[arg(long, requires = "file")]
+        force: bool,
+        /// Encrypt stdin to stdout as it arrives, with constant memory, e.g.
+        /// `tar c . | sodix e --pipe --to alice | ssh host 'cat > b.x'`
+        #[arg(long, conflicts_with_all = ["input", "file", "aead", "mime", "armor", "convergent"])]
+        pipe: bool,
+        /// Write the encrypted file as parts <input>.x.001, .002, ... of at most SIZE
+        /// (e.g. 4000M, 1G) plus the index <input>.x.parts; decrypt -f reassembles them
+        #[arg(long, value_name = "SIZE", requires = "file", conflicts_with_all = ["aead", "mime", "armor"])]
+        split_size: Option<String>,
+        /// Overwrite the input with random bytes and delete it once the encrypted file
+        /// is on disk. Best effort: SSDs, copy-on-write filesystems, snapshots and
+        /// backups may still hold the plaintext
+        #[arg(long, requires = "file")]
+        shred: bool,
+        /// Message format; age writes <input>.age with -f and armored age text otherwise,
+        /// saltpack always armors and writes <input>.saltpack with -f
+        #[arg(long, value_enum, default_value_t)]
+        format: MessageFormat,
     },
     /// Decrypt a message or file
     #[command(visible_alias = "d")]
     Decrypt {
+        #[arg(allow_hyphen_values = true, required_unless_present_any = ["files_from", "null", "pipe"], default_value = "", hide_default_value = true)]
         input: String,
+        /// More files to decrypt with -f; INPUT may also be a glob pattern such as 'backup/*.x'
+        #[arg(requires = "file")]
+        more: Vec<String>,
+        /// Also process the files listed in LIST ("-" for stdin), one per line; names are
+        /// taken literally, not as patterns
+        #[arg(long, value_name = "LIST", requires = "file")]
+        files_from: Option<PathBuf>,
+        /// The file list is NUL-delimited (find -print0); without --files-from it is read
+        /// from stdin
+        #[arg(short = '0', long = "null", requires = "file")]
+        null: bool,
         #[arg(long, short = 'k')]
         pubkey: Option<String>,  // Sender's public key in hex
         #[arg(long, short = 's')]
         seckey: Option<String>,  // Receiver's secret key in hex
+        /// Sender's signing public key (hex or path) for --ephemeral messages, default sign_public.key
+        #[arg(long)]
+        signer: Option<String>,
         #[arg(long, short = 'f')]
         file: bool,
+        /// AEAD construction the input was encrypted with
+        #[arg(long, value_enum)]
+        aead: Option<AeadAlg>,
+        /// Additional authenticated data given at encryption time
+        #[arg(long, requires = "aead")]
+        aad: Option<String>,
+        /// Input is a saved email produced by `encrypt --mime`
+        #[arg(long)]
+        mime: bool,
+        /// Decrypt only LEN plaintext bytes starting at OFFSET (to the end if LEN is
+        /// omitted) and write them to stdout
+        #[arg(long, requires = "file", conflicts_with = "mime", value_name = "OFFSET..LEN")]
+        range: Option<String>,
+        /// Read the header from a separate file written by encrypt --detached-header,
+        /// default <input>.x.hdr
+        #[arg(long, num_args = 0..=1, requires = "file", conflicts_with_all = ["aead", "mime"], value_name = "PATH")]
+        detached_header: Option<Option<PathBuf>>,
+        /// Write only this file from an encrypted tar archive to its base name here,
+        /// decrypting just the chunks it needs
+        #[arg(long, requires = "file", conflicts_with_all = ["mime", "range"], value_name = "PATH")]
+        extract: Option<String>,
+        /// Leave the mode and mtime of the output as created instead of restoring the
+        /// recorded ones
+        #[arg(long, requires = "file")]
+        no_preserve: bool,
+        /// Replace an existing output file instead of refusing
+        #[arg(long, requires = "file")]
+        force: bool,
+        /// Decrypt stdin to stdout as it arrives, with constant memory. Output is written
+        /// chunk by chunk as each authenticates, so a failure (non-zero exit) can come
+        /// after part of it
+        #[arg(long, conflicts_with_all = ["input", "file", "aead", "mime", "range", "extract", "detached_header"])]
+        pipe: bool,
+        /// Decrypt with the named identity's keys instead of the default key files
+        #[arg(long)]
+        id: Option<String>,
+        /// Message format; age reads <input>.age with -f and armored age text otherwise.
+        /// -s also takes an AGE-SECRET-KEY-1... identity. saltpack reads <input>.saltpack
+        /// with -f and checks the sender against -k if given
+        #[arg(long, value_enum, default_value_t)]
+        format: MessageFormat,
+    },
+    #[cfg(feature = "full")]
+    /// Sign with your signing key and encrypt to the recipients in one message
+    Signcrypt {
+        input: String,
+        #[arg(long, short = 'k')]
+        pubkey: Vec<String>,  // Receivers' public keys in hex, repeatable
+        /// Signing secret key (hex or path), default sign_secret.key
+        #[arg(long)]
+        sign_key: Option<String>,
+        #[arg(long, short = 'f')]
+        file: bool,  // Writes <input>.sx
+        /// Replace an existing <input>.sx instead of refusing
+        #[arg(long, requires = "file")]
+        force: bool,
+    },
+    /// Decrypt a signcrypted message and verify the sender's signature
+    Unsigncrypt {
+        input: String,
+        #[arg(long, short = 's')]
+        seckey: Option<String>,  // Receiver's secret key in hex
+        /// Sender's signing public key (hex or path), default sign_public.key
+        #[arg(long)]
+        signer: Option<String>,
+        #[arg(long, short = 'f')]
+        file: bool,  // Reads <input>.sx
+        /// Replace an existing output file instead of refusing
+        #[arg(long, requires = "file")]
+        force: bool,
     },
+    #[cfg(feature = "full")]
     /// Generate new keypairs
     #[command(visible_alias = "g")]
     Generate {
         #[arg(long, short = 'k')]
         key: Option<PathBuf>,
+        /// Key file format; minisign writes minisign.pub and an unencrypted minisign.key,
+        /// signify writes signify.pub and an unencrypted signify.sec
+        #[arg(long, value_enum, default_value_t)]
+        format: SignatureFormat,
+        /// Key pairs to write; commands that need the other pair create it when first used
+        #[arg(long = "type", value_enum, default_value_t)]
+        key_type: KeyType,
+
+        /// Protect the secret keys with a passphrase (asked twice, or $SODIX_PASSPHRASE)
+        #[arg(long)]
+        passphrase: bool,
+        /// Record an expiry in keys.meta: a date (2027-01-31), RFC 3339 time, or duration (1y)
+        #[arg(long)]
+        expires: Option<String>,
+        /// Derive the keys from a passphrase (Argon2id) instead of at random, so the same
+        /// passphrase and salt recreate them anywhere; writes derivation.params
+        #[arg(long, conflicts_with = "passphrase")]
+        from_passphrase: bool,
+        /// Salt for --from-passphrase, e.g. your email address
+        #[arg(long, requires = "from_passphrase", conflicts_with = "params")]
+        salt: Option<String>,
+        /// Recreate keys with the parameters recorded in a derivation.params file
+        #[arg(long, requires = "from_passphrase")]
+        params: Option<PathBuf>,
+        /// Generate signing keys on all cores until the public key starts with PREFIX
+        /// (hex, or bech32 when it starts with sodixsign1)
+        #[arg(long, value_name = "PREFIX", conflicts_with = "from_passphrase")]
+        vanity: Option<String>,
+        /// Write both key pairs to one identity file (-k names the file, default
+        /// identity.sodix in the key directory) for use with --identity
+        #[arg(long, conflicts_with_all = ["from_passphrase", "vanity", "key_type"])]
+        single_file: bool,
+        /// Name recorded in the identity file (default: the file name)
+        #[arg(long, requires = "single_file")]
+        name: Option<String>,
+        /// Derive the encryption key pair from the signing key pair (Ed25519 to X25519), so
+        /// anyone with your signing public key can encrypt to you
+        #[arg(long, conflicts_with_all = ["from_passphrase", "key_type", "single_file"])]
+        unified: bool,
+        /// Replace existing key files; anything encrypted to or signed by the old keys
+        /// can no longer be opened or attributed with them
+        #[arg(long)]
+        force: bool,
     },
+    #[cfg(feature = "full")]
     /// Print keys
     #[command(visible_alias = "p")]
     Print {
         #[arg(long, short = 'k')]
         key: Option<PathBuf>,
+        /// Print the public keys as bech32 (sodixsign1..., sodix1...) instead of hex
+        #[arg(long)]
+        bech32: bool,
+        /// Print each key as a -----BEGIN SODIX ... KEY----- block; key files may hold these
+        #[arg(long, conflicts_with = "bech32")]
+        armor: bool,
+        /// Print the public keys as did:key identifiers (did:key:z6Mk..., did:key:z6LS...)
+        #[arg(long, conflicts_with_all = ["bech32", "armor"])]
+        did: bool,
+    },
+    #[cfg(feature = "full")]
+    /// XOR stdin with the raw XChaCha20 keystream (NO authentication, for debugging/test vectors)
+    Stream {
+        #[arg(long, short = 'k')]
+        key: String,    // 32-byte key in hex
+        #[arg(long, short = 'n')]
+        nonce: String,  // 24-byte nonce in hex
+    },
+    #[cfg(feature = "full")]
+    /// X25519 scalar multiplication: derive a public key, or a raw shared secret with [public]
+    Scalarmult {
+        secret: String,          // 32-byte scalar (secret key) in hex
+        public: Option<String>,  // 32-byte group element (public key) in hex
+    },
+    #[cfg(feature = "full")]
+    /// Run a declarative workflow of sodix operations from a TOML file, or with --key
+    /// verify a signed program and only then execute it
+    Run {
+        /// Workflow file, or with --key the program to verify and execute
+        workflow: PathBuf,
+        /// The program's detached signature (hex or file), default <program>.sig
+        #[arg(requires = "key")]
+        signature: Option<String>,
+        /// Signing public key the program must be signed with
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Public key in hex or path
+        /// Set or override a workflow variable
+        #[arg(long = "var", value_name = "NAME=VALUE", conflicts_with = "key")]
+        vars: Vec<String>,
+        /// Arguments for the program, after --
+        #[arg(last = true, requires = "key")]
+        args: Vec<String>,
+    },
+    #[cfg(feature = "full")]
+    /// Key management
+    Key {
+        #[command(subcommand)]
+        command: KeyCommands,
+    },
+    /// Print a public key's fingerprint, or check it against a pinned one
+    Fingerprint {
+        key: Option<String>,  // Public key in hex or path, default enc_public.key
+        #[arg(long, value_enum)]
+        alg: Option<FingerprintAlg>,
+        /// Pinned fingerprint in any supported algorithm; prints valid/invalid
+        #[arg(long, conflicts_with = "alg")]
+        expect: Option<String>,
+    },
+    /// Manage the signers remembered by check --tofu
+    Trust {
+        #[command(subcommand)]
+        command: TrustCommands,
+    },
+    #[cfg(feature = "full")]
+    /// Address book of recipients' public keys, used with encrypt --to
+    Contact {
+        #[command(subcommand)]
+        command: ContactCommands,
+    },
+    #[cfg(feature = "full")]
+    /// Hash-chained log of every change to the contacts, with signed checkpoints
+    Keylog {
+        #[command(subcommand)]
+        command: KeylogCommands,
+    },
+    #[cfg(feature = "full")]
+    /// Add or remove recipients of an encrypted file without re-encrypting its contents
+    Recipients {
+        #[command(subcommand)]
+        command: RecipientsCommands,
+    },
+    /// Inspect tar archives encrypted with encrypt -f
+    Archive {
+        #[command(subcommand)]
+        command: ArchiveCommands,
+    },
+    /// Signed, versioned release metadata with rollback and freeze protection
+    Channel {
+        #[command(subcommand)]
+        command: ChannelCommands,
+    },
+    /// Print checksums of files, or check a b2sum/sha256sum-style checksum file
+    Hash {
+        /// Files to hash; directories are hashed recursively, and `-` (or no files)
+        /// hashes stdin as it streams in
+        #[arg(conflicts_with = "check")]
+        files: Vec<PathBuf>,
+        /// BLAKE2b-512 (b2sum) or SHA-256 (sha256sum); by default taken from the
+        /// checksum file name (SHA256SUMS) and otherwise blake2b
+        #[arg(long, value_enum)]
+        alg: Option<HashAlg>,
+        /// Write the checksum lines to SUMSFILE instead of stdout
+        #[arg(long, value_name = "SUMSFILE")]
+        create: Option<PathBuf>,
+        /// Verify the files listed in SUMSFILE; names are relative to the current directory
+        #[arg(long, value_name = "SUMSFILE", conflicts_with = "create")]
+        check: Option<PathBuf>,
+        /// Replace an existing --create SUMSFILE instead of refusing
+        #[arg(long, requires = "create")]
+        force: bool,
+    },
+    /// gpg-compatible signing for git; `git config gpg.program sodix` calls this
+    /// directly. Verification trusts sign_public.key and the keys in git_signers/
+    GitSign {
+        /// gpg arguments as git passes them, e.g. --status-fd=2 -bsau <key>
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        args: Vec<String>,
+    },
+    /// Signed listings of a directory tree to detect added, removed or changed files
+    Manifest {
+        #[command(subcommand)]
+        command: ManifestCommands,
+    },
+    /// Issue or verify offline license tokens (customer, features, expiry)
+    License {
+        #[command(subcommand)]
+        command: LicenseCommands,
+    },
+    /// Sign or verify JSON Web Tokens with the Ed25519 signing key (EdDSA)
+    Jwt {
+        #[command(subcommand)]
+        command: JwtCommands,
+    },
+    #[cfg(feature = "full")]
+    /// Exchange public keys with another device over the network using a short shared code
+    Pair {
+        /// Wait for the other device on ADDR (e.g. 0.0.0.0:7070) and print a fresh code
+        #[arg(long, conflicts_with = "connect", required_unless_present = "connect")]
+        listen: Option<String>,
+        /// Connect to the device listening on ADDR
+        #[arg(long)]
+        connect: Option<String>,
+        /// Code shown by the listening device; prompted for if omitted
+        #[arg(long)]
+        code: Option<String>,
+        /// Directory to save the peer's sign_public.key and enc_public.key
+        #[arg(long, short = 'o')]
+        out: Option<PathBuf>,
+        /// Replace keys already in the -o directory instead of refusing
+        #[arg(long, requires = "out")]
+        force: bool,
+    },
+    #[cfg(feature = "full")]
+    /// Keep signatures or encrypted copies of a directory current as its files change
+    Watch {
+        dir: PathBuf,
+        #[arg(long, value_enum)]
+        on_change: WatchAction,
+        /// Write outputs at the same relative paths under OUT instead of next to the files
+        #[arg(long, short = 'o')]
+        out: Option<PathBuf>,
+        /// Signing secret key for sign; recipients' public keys for encrypt (repeatable)
+        #[arg(long, short = 'k')]
+        key: Vec<String>,
+        /// Files to skip, in .sodixignore syntax, in addition to DIR/.sodixignore
+        #[arg(long)]
+        exclude: Vec<String>,
+        #[arg(long)]
+        id: Option<String>,  // Identity whose keys to use
+    },
+    #[cfg(feature = "full")]
+    /// Summarize your identities' keys and when they expire (keys.meta), for periodic key
+    /// hygiene reviews. Strictly offline: reads only the key directory
+    Report {
+        /// Flag keys that expire within this long
+        #[arg(long, default_value = "30d")]
+        within: String,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the version
+    Version {
+        /// Also list the build features and the CPU accelerations active on this machine
+        #[arg(long)]
+        features: bool,
     },
 }
-fn get_default_key_path(key_type: &str) -> PathBuf {
+
+#[cfg(feature = "full")]
+#[derive(Clone, Subcommand)]
+enum RecipientsCommands {
+    /// Wrap the file key to more recipients; you must be able to decrypt the file
+    Add {
+        file: String,
+        /// More files to change; FILE may also be a glob pattern
+        more: Vec<String>,
+        #[arg(long, short = 'k', required = true)]
+        pubkey: Vec<String>,  // New receivers' public keys in hex
+        #[arg(long, short = 's')]
+        seckey: Option<String>,  // Your secret key in hex
+        /// Public key the file was encrypted from (hex), default your own
+        #[arg(long)]
+        sender: Option<String>,
+        /// Sender's signing public key (hex or path) for --ephemeral files, default sign_public.key
+        #[arg(long)]
+        signer: Option<String>,
+    },
+    /// Drop the key slots you wrapped for these recipients
+    Remove {
+        file: String,
+        /// More files to change; FILE may also be a glob pattern
+        more: Vec<String>,
+        #[arg(long, short = 'k', required = true)]
+        pubkey: Vec<String>,  // Receivers' public keys in hex
+        #[arg(long, short = 's')]
+        seckey: Option<String>,  // Your secret key in hex
+    },
+    /// Replace recipients in one header rewrite: drop the slots you wrapped for --old
+    /// and wrap the file key to --new, e.g. when a teammate's key changed
+    Rotate {
+        file: String,
+        /// More files to change; FILE may also be a glob pattern
+        more: Vec<String>,
+        /// Recipient public key (hex) to drop, repeatable
+        #[arg(long, required = true)]
+        old: Vec<String>,
+        /// Recipient public key (hex) to add, repeatable
+        #[arg(long, required = true)]
+        new: Vec<String>,
+        #[arg(long, short = 's')]
+        seckey: Option<String>,  // Your secret key in hex
+        /// Public key the file was encrypted from (hex), default your own
+        #[arg(long)]
+        sender: Option<String>,
+        /// Sender's signing public key (hex or path) for --ephemeral files, default sign_public.key
+        #[arg(long)]
+        signer: Option<String>,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum ArchiveCommands {
+    /// Print the names, sizes and mtimes in FILE.x, decrypting only the tar headers
+    List {
+        file: String,
+        #[arg(long, short = 'k')]
+        pubkey: Option<String>,  // Sender's public key in hex
+        #[arg(long, short = 's')]
+        seckey: Option<String>,  // Receiver's secret key in hex
+        /// Sender's signing public key (hex or path) for --ephemeral files, default sign_public.key
+        #[arg(long)]
+        signer: Option<String>,
+        /// Read the header from a separate file, default <file>.x.hdr
+        #[arg(long, num_args = 0..=1, value_name = "PATH")]
+        detached_header: Option<Option<PathBuf>>,
+        /// Decrypt with the named identity's keys instead of the default key files
+        #[arg(long)]
+        id: Option<String>,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum ChannelCommands {
+    #[cfg(feature = "full")]
+    /// Sign the next version of DIR/channel.meta listing every file in DIR
+    Publish {
+        dir: PathBuf,
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Signing secret key in hex or path
+        /// How long clients accept this version, e.g. 30d; re-publish before it lapses
+        #[arg(long, default_value = "30d")]
+        expires: String,
+    },
+    /// Verify DIR against its signed metadata and the last version accepted here
+    Verify {
+        dir: PathBuf,
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Signing public key in hex or path
+        /// File recording the last accepted version per channel key
+        #[arg(long)]
+        state: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum ManifestCommands {
+    #[cfg(feature = "full")]
+    /// List every file under DIR with its BLAKE2b digest and sign the listing
+    Create {
+        dir: PathBuf,
+        /// Where to write the manifest, default manifest.sdx
+        #[arg(long, short = 'o')]
+        out: Option<PathBuf>,
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Signing secret key in hex or path
+        /// Leave out paths matching PATTERN (gitignore syntax), on top of DIR/.sodixignore
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+        /// Replace an existing manifest instead of refusing
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check DIR against a signed manifest; prints every added, removed or modified file
+    Verify {
+        dir: PathBuf,
+        manifest: PathBuf,
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Signing public key in hex or path
+        /// Do not report unlisted paths matching PATTERN as added, on top of DIR/.sodixignore
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum LicenseCommands {
+    #[cfg(feature = "full")]
+    /// Sign a license token for a customer
+    Issue {
+        #[arg(long)]
+        customer: String,
+        /// Feature the license unlocks; repeat for several
+        #[arg(long)]
+        feature: Vec<String>,
+        /// How long the license is valid, e.g. 365d; never expires if omitted
+        #[arg(long)]
+        expires: Option<String>,
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Vendor signing secret key in hex or path
+    },
+    /// Check a license token and print what it grants
+    Verify {
+        token: String,  // Token, or path to a file containing it
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Vendor signing public key in hex or path
+        /// Also require that the license unlocks this feature
+        #[arg(long)]
+        feature: Option<String>,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum JwtCommands {
+    #[cfg(feature = "full")]
+    /// Sign the claims in a JSON file (- for stdin) and print the token
+    Sign {
+        #[arg(long)]
+        claims: PathBuf,
+        /// Set "iat" to now and "exp" this far ahead, e.g. 15m or 1h
+        #[arg(long)]
+        expires: Option<String>,
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Signing secret key in hex or path
+    },
+    /// Check a token's signature, expiry, not-before time and audience, and print its claims
+    Verify {
+        token: String,  // Token, or path to a file containing it
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Signing public key in hex or path
+        /// Audience the token must name; tokens with an "aud" claim need it
+        #[arg(long)]
+        aud: Option<String>,
+        /// Clock skew allowed for "exp" and "nbf"
+        #[arg(long, default_value = "60s")]
+        leeway: String,
+    },
+}
+
+#[derive(Clone, Subcommand)]
+enum TrustCommands {
+    /// List known signers with their key fingerprints
+    List,
+    /// Forget a signer, so the next check --tofu accepts its new key
+    Remove {
+        name: String,
+    },
+}
+
+#[cfg(feature = "full")]
+#[derive(Clone, Subcommand)]
+enum ContactCommands {
+    /// Save a recipient's encryption public key under a name
+    Add {
+        name: String,
+        key: String,  // Public key in hex, bech32, did:key or path
+        /// Refuse the key unless it has this fingerprint (compare it with the owner's)
+        #[arg(long)]
+        fingerprint: Option<String>,
+        /// If NAME is saved with a different key, do this instead of asking
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+    },
+    /// List contacts with their key fingerprints
+    List,
+    /// Print a contact's key in hex and bech32 and its fingerprint
+    Show {
+        name: String,
+    },
+    Remove {
+        name: String,
+    },
+}
+
+#[cfg(feature = "full")]
+#[derive(Clone, Subcommand)]
+enum KeylogCommands {
+    /// Start logging every change to the contacts, beginning with the current ones
+    Init,
+    /// Print the entries: changes with the key's fingerprint, checkpoints with the signer's
+    List {
+        /// Key log to read instead of the one in the key directory
+        #[arg(long)]
+        log: Option<PathBuf>,
+    },
+    /// Sign the log as it is now, vouching for every change so far
+    Checkpoint {
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Signing secret key in hex or path
+    },
+    /// Check the hash chain and the checkpoints, and that the contacts match the log;
+    /// prints the head hash to pass as --since next time
+    Verify {
+        /// Key log to check instead of the one in the key directory
+        #[arg(long)]
+        log: Option<PathBuf>,
+        /// Contacts the log must describe; default contacts.toml beside the log
+        #[arg(long)]
+        contacts: Option<PathBuf>,
+        /// Require a checkpoint, and every checkpoint to be signed by one of these
+        /// signing public keys (hex or path)
+        #[arg(long, short = 'k')]
+        signer: Vec<String>,
+        /// Head hash printed by an earlier verify: the log must still extend it, and
+        /// the changes since are listed
+        #[arg(long)]
+        since: Option<String>,
+    },
+}
+
+#[cfg(feature = "full")]
+#[derive(Clone, Subcommand)]
+enum KeyCommands {
+    /// Issue a short-lived signing subkey certified by a long-term (root) signing key
+    Delegate {
+        #[arg(long, short = 'k')]
+        key: Option<String>,  // Root signing secret key in hex or path
+        /// Validity of the subkey, e.g. 12h, 7d
+        #[arg(long, default_value = "7d")]
+        ttl: String,
+        /// Directory for delegate_public.key, delegate_secret.key and delegate.cert
+        #[arg(long, short = 'o')]
+        out: Option<PathBuf>,
+        /// Replace an existing delegated key and certificate instead of refusing
+        #[arg(long)]
+        force: bool,
+    },
+    /// Create a named identity: its own signing and encryption key pairs, used with --id
+    Add {
+        #[arg(long)]
+        name: String,
+        /// Protect the secret keys with a passphrase
+        #[arg(long)]
+        passphrase: bool,
+    },
+    /// List named identities with their signing key fingerprints
+    List,
+    /// Copy keys from next to the executable (the old default) into the default key directory
+    Migrate {
+        /// Delete the old files once they are copied
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Certify that someone's public key belongs to a name, by signing it with your key.
+    /// Saved to certifications/ in the key directory unless -o is given
+    Certify {
+        pubkey: String,  // Their public key in hex, bech32, did:key or path
+        #[arg(long)]
+        name: String,
+        /// Your signing secret key (hex or path), default sign_secret.key
+        #[arg(long, short = 'k')]
+        key: Option<String>,
+        #[arg(long, short = 'o')]
+        out: Option<PathBuf>,
+        /// Replace an existing certification file instead of refusing
+        #[arg(long)]
+        force: bool,
+    },
+    /// Write keys, identities and contacts to a passphrase-encrypted bundle for another
+    /// machine, or the key pairs as unencrypted PKCS#8 or JWK files for other tools
+    Export {
+        /// Everything in the key directory (bundles only)
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+        /// Only these named identities (repeatable; at most one for the key file formats)
+        #[arg(long)]
+        id: Vec<String>,
+        /// Bundle file, or the directory for the pkcs8, jwk, wireguard, age and openpgp files
+        /// (sign_secret.pem, sign_public.pem, enc_secret.pem and enc_public.pem, or .jwk;
+        /// enc_secret.wg and enc_public.wg, or .txt; sign_public.asc)
+        #[arg(long, short = 'o')]
+        out: PathBuf,
+        #[arg(long, value_enum, default_value_t)]
+        format: ExportFormat,
+        /// DER (.der files) instead of PEM for --format pkcs8
+        #[arg(long)]
+        der: bool,
+        /// User ID of the --format openpgp certificate, e.g. "Alice <alice@example.com>";
+        /// default the identity name or "sodix"
+        #[arg(long)]
+        user_id: Option<String>,
+        /// Replace an existing bundle or key files instead of refusing
+        #[arg(long)]
+        force: bool,
+    },
+    /// Unpack a bundle from `key export` into the key directory, or add Ed25519 or X25519
+    /// keys in PKCS#8 or SubjectPublicKeyInfo form (PEM or DER, e.g. from `openssl
+    /// genpkey`) or as a JWK or JWK Set
+    Import {
+        file: PathBuf,
+        /// Replace files that already exist with different contents. Contacts and known
+        /// signers are merged name by name instead
+        #[arg(long)]
+        force: bool,
+        /// For a contact or known signer saved with a different key, do this instead of
+        /// asking
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+    },
+    /// Replace the encryption key pair with one derived from the signing key pair, so one
+    /// Ed25519 key both signs and receives encrypted messages
+    Unify {
+        /// Key directory, default the one with sign_secret.key
+        #[arg(long, short = 'k')]
+        key: Option<PathBuf>,
+        /// Protect the new encryption secret key with a passphrase
+        #[arg(long)]
+        passphrase: bool,
+        /// Replace an existing, unrelated encryption key pair
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the X25519 encryption key belonging to an Ed25519 signing key: the public key
+    /// for a signing public key, the secret key for a signing secret key
+    X25519 {
+        key: String,  // Signing key in hex, bech32, did:key or path
+    },
+    /// Set, change or remove the passphrase of a secret key file; the key itself is unchanged
+    Passwd {
+        file: PathBuf,
+        /// Store the key unencrypted again
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Rewrite a key in any format sodix reads (raw 32/64-byte binary from libsodium
+    /// programs, PEM, JWK line, base64, bech32, ...) as a sodix hex key file
+    Convert {
+        file: PathBuf,
+        /// Output key file, default stdout
+        #[arg(long, short = 'o')]
+        out: Option<PathBuf>,
+    },
+    /// Write your encryption public key, signed by your signing key, for the web server of
+    /// your address's domain to serve at https://<domain>/.well-known/sodix/<hash>
+    Publish {
+        /// Your email address, e.g. alice@example.com
+        address: String,
+        /// Web root; the key is written below .well-known/sodix/ in it
+        #[arg(long, short = 'o')]
+        out: PathBuf,
+        /// Publish the named identity's keys instead of the default key files
+        #[arg(long)]
+        id: Option<String>,
+    },
+    /// Download the published key of an address and save it as a contact under that
+    /// address, after confirming its fingerprint
+    Fetch {
+        address: String,
+        /// Accept the key only with this fingerprint, without asking
+        #[arg(long)]
+        fingerprint: Option<String>,
+        /// Fetch from this URL instead of the address's well-known one
+        #[arg(long)]
+        url: Option<String>,
+        /// If the address is a contact with a different key, do this instead of asking
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+    },
+}
+/// Per-user key directory: $XDG_DATA_HOME/sodix on Linux and the platform's data
+/// directory elsewhere, or the executable's directory when there is no home directory.
+fn get_default_key_dir() -> PathBuf {
+    ProjectDirs::from("", "", "sodix")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(get_legacy_key_dir)
+}
+
+/// Where keys used to live: next to the executable. Still read for files the default
+/// directory does not have; `key migrate` copies them over.
+fn get_legacy_key_dir() -> PathBuf {
     std::env::current_exe()
         .unwrap()
         .parent()
         .unwrap()
-        .join(format!("{}.key", key_type))
+        .to_path_buf()
 }
 
-fn load_key(path: &Path, expected_size: usize) -> Result<Vec<u8>, String> {
-    let key_hex = fs::read_to_string(path)
-        .map_err(|e| format!("Failed to read key from {}: {}", path.display(), e))?;
-    let key_bytes = hex::decode(key_hex.trim())
-        .map_err(|e| format!("Invalid hex in key file {}: {}", path.display(), e))?;
+/// `name` in the default key directory, or in the legacy one if only that has it.
+fn default_key_file(name: impl AsRef<Path>) -> PathBuf {
+    let path = get_default_key_dir().join(&name);
+    let legacy_path = get_legacy_key_dir().join(&name);
+    if !path.exists() && legacy_path.exists() { legacy_path } else { path }
+}
+
+fn get_default_key_path(key_type: &str) -> PathBuf {
+    match identity::active() {
+        Some(file) => identity::key_path(file, key_type),
+        None => default_key_file(format!("{}.key", key_type)),
+    }
+}
+
+/// The default signing public key, if there is one: the sender's key for containers
+/// with a signed ephemeral sender key when no --signer is given, read only then.
+fn default_signing_public_key() -> Result<Option<[u8; 32]>, String> {
+    let path = get_default_key_path("sign_public");
+    if !key_exists(&path) {
+        return Ok(None);
+    }
+    Ok(Some(load_key(&path, 32)?.as_slice().try_into().unwrap()))
+}
+
+/// Whether a key file, or the identity key a path from get_default_key_path names, exists.
+fn key_exists(path: &Path) -> bool {
+    path.exists() || identity::split_path(path).is_some()
+}
+
+/// Directory holding the key files of a named identity (see `key add`).
+fn identity_dir(name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.') || name.starts_with('.') {
+        return Err(format!("Invalid identity name '{}': use letters, digits, '-', '_' and '.'", name));
+    }
+    Ok(default_key_file(Path::new("identities").join(name)))
+}
+
+/// Default key file of `key_type` for identity `id`, or the unnamed default keys.
+fn identity_key_path(id: Option<&str>, key_type: &str) -> Result<PathBuf, String> {
+    let Some(name) = id else {
+        return Ok(get_default_key_path(key_type));
+    };
+    let dir = identity_dir(name)?;
+    if !dir.is_dir() {
+        return Err(format!("Unknown identity '{}'; create it with `sodix key add --name {}`", name, name));
+    }
+    Ok(dir.join(format!("{}.key", key_type)))
+}
+
+fn load_key(path: &Path, expected_size: usize) -> Result<Zeroizing<Vec<u8>>, String> {
+    let key_bytes = if let Some((file, key_type)) = identity::split_path(path) {
+        identity::load_key(&file, key_type)?
+    } else {
+        load_key_file(path)?
+    };
     if key_bytes.len() != expected_size {
         return Err(format!(
             "Key size mismatch for {}: expected {} bytes, got {}",
@@ -97,327 +1345,4206 @@ fn load_key(path: &Path, expected_size: usize) -> Result<Vec<u8>, String> {
             key_bytes.len()
         ));
     }
+    memlock::lock(&key_bytes);
     Ok(key_bytes)
 }
 
-fn load_or_generate_signing_key(key_input: &str, is_secret: bool, verbose: bool) -> Result<Vec<u8>, String> {
-    // Check if key_input is a valid hex string of the expected length
-    let expected_size = if is_secret { 64 } else { 32 };
-    if let Ok(key_bytes) = hex::decode(key_input.trim()) {
-        if key_bytes.len() == expected_size {
-            return Ok(key_bytes);  // Use the hex string directly
-        }
+/// `load_key` for a secret key, refusing a key file that others can read.
+fn load_secret_key(path: &Path, expected_size: usize) -> Result<Zeroizing<Vec<u8>>, String> {
+    if identity::split_path(path).is_none() {
+        keyperms::check(path)?;
+    }
+    load_key(path, expected_size)
+}
+
+fn load_key_file(path: &Path) -> Result<Zeroizing<Vec<u8>>, String> {
+    let data = Zeroizing::new(fs::read(path).map_err(|e| format!("Failed to read key from {}: {}", path.display(), e))?);
+    memlock::lock(&data);
+    if pkcs8::is_pkcs8(&data) {
+        return pkcs8::parse(&data).map(|(_, _, key)| Zeroizing::new(key)).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    if is_raw_key(&data) {
+        return Ok(data);
+    }
+    let key_hex = std::str::from_utf8(&data)
+        .map_err(|e| format!("Failed to read key from {}: {}", path.display(), e))?;
+    #[cfg(feature = "full")]
+    if sshsig::is_secret_key(key_hex) {
+        return sshsig::parse_secret_key(key_hex, path).map(|sk| Zeroizing::new(sk.to_vec()));
+    }
+    if keyfile::is_encrypted(key_hex) {
+        return keyfile::unlock(key_hex, path);
+    }
+    let key_bytes = if armor::is_armored(key_hex) {
+        armor::dearmor_key(key_hex).map_err(|e| format!("{}: {}", path.display(), e))?
+    } else if is_text_key(key_hex) {
+        decode_text_key(key_hex, None).map_err(|e| format!("{}: {}", path.display(), e))?.to_vec()
+    } else if sshsig::is_public_key(key_hex) {
+        sshsig::parse_public_keys(key_hex).map_err(|e| format!("{}: {}", path.display(), e))?[0].to_vec()
+    } else if let Some(key) = parse_base64_key(key_hex) {
+        key.to_vec()
+    } else if age::is_identity(key_hex) {
+        age::decode_identity(key_hex).map_err(|e| format!("{}: {}", path.display(), e))?.to_vec()
+    } else {
+        hex::decode(key_hex.trim())
+            .map_err(|e| format!("Invalid hex in key file {}: {}", path.display(), e))?
+    };
+    Ok(Zeroizing::new(key_bytes))
+}
+
+/// A 32- or 64-byte file that is not text: a key as libsodium programs write it, the raw
+/// bytes with no encoding.
+fn is_raw_key(data: &[u8]) -> bool {
+    matches!(data.len(), 32 | 64) && data.iter().any(|&b| !(b.is_ascii_graphic() || b.is_ascii_whitespace()))
+}
+
+#[cfg(feature = "full")]
+fn load_or_generate_signing_key(key_input: &str, is_secret: bool, verbose: bool) -> Result<Zeroizing<Vec<u8>>, String> {
+    let path = Path::new(key_input);
+    if signing_key_literal(key_input, is_secret).is_none() && !identity::is_identity_file(path) && !key_exists(path) {
+        return generate_signing_key_files(path, is_secret, verbose);
     }
+    load_signing_key(key_input, is_secret)
+}
 
+/// A signing key given as hex or read from a key or identity file. Unlike
+/// `load_or_generate_signing_key`, a missing file is an error, never a new key pair.
+fn load_signing_key(key_input: &str, is_secret: bool) -> Result<Zeroizing<Vec<u8>>, String> {
+    if let Some(key) = signing_key_literal(key_input, is_secret) {
+        return key;
+    }
     // Treat key_input as a file path
+    let expected_size = if is_secret { 64 } else { 32 };
     let path = Path::new(key_input);
-    if path.exists() {
-        load_key(path, expected_size)
+    if identity::is_identity_file(path) {
+        load_key(&identity::key_path(path, if is_secret { "sign_secret" } else { "sign_public" }), expected_size)
+    } else if !key_exists(path) {
+        Err(format!("Key file {} not found", path.display()))
+    } else if is_secret {
+        load_secret_key(path, expected_size)
     } else {
-        let keypair: SigningKeyPair<StackByteArray<32>, StackByteArray<64>> = SigningKeyPair::gen();
-        let dir = path.parent().unwrap_or_else(|| Path::new("."));
-        let public_key_path = dir.join("sign_public.key");
-        let secret_key_path = dir.join("sign_secret.key");
-        fs::write(&public_key_path, hex::encode(&keypair.public_key))
-            .map_err(|e| format!("Failed to write signing public key to {}: {}", public_key_path.display(), e))?;
-        fs::write(&secret_key_path, hex::encode(&keypair.secret_key))
-            .map_err(|e| format!("Failed to write signing secret key to {}: {}", secret_key_path.display(), e))?;
-        if verbose {
-            println!(
-                "Generated signing keys at: {} and {}",
-                public_key_path.display(),
-                secret_key_path.display()
-            );
-        }
-        Ok(if is_secret {
-            keypair.secret_key.to_vec()
-        } else {
-            keypair.public_key.to_vec()
-        })
+        load_key(path, expected_size)
+    }
+}
+
+/// The signing public key to verify with, strictly loaded.
+fn load_signing_public_key(key_input: &str) -> Result<Zeroizing<Vec<u8>>, String> {
+    load_signing_key(key_input, false)
+}
+
+/// A signing key written out in the argument: hex of the expected length, or for a
+/// public key any text form.
+fn signing_key_literal(key_input: &str, is_secret: bool) -> Option<Result<Zeroizing<Vec<u8>>, String>> {
+    if !is_secret && is_text_key(key_input) {
+        return Some(decode_text_key(key_input, Some(KeyKind::Signing)).map(|pk| Zeroizing::new(pk.to_vec())));
+    }
+    let key_bytes = hex::decode(key_input.trim()).map(Zeroizing::new).ok()?;
+    (key_bytes.len() == if is_secret { 64 } else { 32 }).then_some(Ok(key_bytes))
+}
+
+/// `path` was not found, so a key pair is about to be generated beside it; refuse if
+/// that would replace key files already there.
+#[cfg(feature = "full")]
+fn refuse_key_clobber(path: &Path, key_paths: &[&Path]) -> Result<(), String> {
+    match key_paths.iter().find(|key_path| fs::symlink_metadata(key_path).is_ok()) {
+        Some(existing) => Err(format!(
+            "Key file {} not found, and generating a key pair for it would overwrite {}",
+            path.display(),
+            existing.display()
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(feature = "full")]
+fn generate_signing_key_files(path: &Path, is_secret: bool, verbose: bool) -> Result<Zeroizing<Vec<u8>>, String> {
+    let keypair: SigningKeyPair<StackByteArray<32>, StackByteArray<64>> = SigningKeyPair::gen();
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+    let public_key_path = dir.join("sign_public.key");
+    let secret_key_path = dir.join("sign_secret.key");
+    refuse_key_clobber(path, &[&public_key_path, &secret_key_path])?;
+    fs::write(&public_key_path, hex::encode(&keypair.public_key))
+        .map_err(|e| format!("Failed to write signing public key to {}: {}", public_key_path.display(), e))?;
+    keyperms::write_secret(&secret_key_path, Zeroizing::new(hex::encode(&keypair.secret_key)).as_bytes())
+        .map_err(|e| format!("Failed to write signing secret key to {}: {}", secret_key_path.display(), e))?;
+    if verbose {
+        println!(
+            "Generated signing keys at: {} and {}",
+            public_key_path.display(),
+            secret_key_path.display()
+        );
     }
+    Ok(Zeroizing::new(if is_secret {
+        keypair.secret_key.to_vec()
+    } else {
+        keypair.public_key.to_vec()
+    }))
 }
 
-fn load_or_generate_encryption_key(path: &Path, is_secret: bool, verbose: bool) -> Result<Vec<u8>, String> {
-    if path.exists() {
+fn load_or_generate_encryption_key(path: &Path, is_secret: bool, verbose: bool) -> Result<Zeroizing<Vec<u8>>, String> {
+    if identity::is_identity_file(path) {
+        load_key(&identity::key_path(path, if is_secret { "enc_secret" } else { "enc_public" }), 32)
+    } else if key_exists(path) && is_secret {
+        load_secret_key(path, 32)
+    } else if key_exists(path) {
         load_key(path, 32)
     } else {
-        let keypair = StackKeyPair::gen();
-        let dir = path.parent().unwrap();
-        let public_key_path = dir.join("enc_public.key");
-        let secret_key_path = dir.join("enc_secret.key");
-        fs::write(&public_key_path, hex::encode(&keypair.public_key))
-            .map_err(|e| format!("Failed to write encryption public key to {}: {}", public_key_path.display(), e))?;
-        fs::write(&secret_key_path, hex::encode(&keypair.secret_key))
-            .map_err(|e| format!("Failed to write encryption secret key to {}: {}", secret_key_path.display(), e))?;
-        if verbose {
-            println!(
-                "Generated encryption keys at: {} and {}",
-                public_key_path.display(),
-                secret_key_path.display()
-            );
-        }
-        Ok(if is_secret {
-            keypair.secret_key.to_vec()
-        } else {
-            keypair.public_key.to_vec()
-        })
+        generate_encryption_key_files(path, is_secret, verbose)
     }
 }
 
-fn generate_keys(dir: &Path, verbose: bool) -> Result<(), String> {
-    // Create directory if it doesn't exist
+#[cfg(feature = "full")]
+fn generate_encryption_key_files(path: &Path, is_secret: bool, verbose: bool) -> Result<Zeroizing<Vec<u8>>, String> {
+    let keypair = StackKeyPair::gen();
+    let dir = path.parent().unwrap();
     fs::create_dir_all(dir)
         .map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+    let public_key_path = dir.join("enc_public.key");
+    let secret_key_path = dir.join("enc_secret.key");
+    refuse_key_clobber(path, &[&public_key_path, &secret_key_path])?;
+    fs::write(&public_key_path, hex::encode(&keypair.public_key))
+        .map_err(|e| format!("Failed to write encryption public key to {}: {}", public_key_path.display(), e))?;
+    keyperms::write_secret(&secret_key_path, Zeroizing::new(hex::encode(&keypair.secret_key)).as_bytes())
+        .map_err(|e| format!("Failed to write encryption secret key to {}: {}", secret_key_path.display(), e))?;
+    if verbose {
+        println!(
+            "Generated encryption keys at: {} and {}",
+            public_key_path.display(),
+            secret_key_path.display()
+        );
+    }
+    Ok(Zeroizing::new(if is_secret {
+        keypair.secret_key.to_vec()
+    } else {
+        keypair.public_key.to_vec()
+    }))
+}
+
+#[cfg(not(feature = "full"))]
+fn generate_encryption_key_files(path: &Path, _is_secret: bool, _verbose: bool) -> Result<Zeroizing<Vec<u8>>, String> {
+    Err(format!("Key file {} not found (verifier build cannot generate keys)", path.display()))
+}
 
+#[cfg(feature = "full")]
+fn generate_keys(dir: &Path, key_type: KeyType, passphrase: Option<&str>, verbose: bool) -> Result<(), String> {
     let sign_keypair: SigningKeyPair<StackByteArray<32>, StackByteArray<64>> = SigningKeyPair::gen();
-    let sign_public_key_path = dir.join("sign_public.key");
-    let sign_secret_key_path = dir.join("sign_secret.key");
-    fs::write(&sign_public_key_path, hex::encode(&sign_keypair.public_key))
-        .map_err(|e| format!("Failed to write signing public key to {}: {}", sign_public_key_path.display(), e))?;
-    fs::write(&sign_secret_key_path, hex::encode(&sign_keypair.secret_key))
-        .map_err(|e| format!("Failed to write signing secret key to {}: {}", sign_secret_key_path.display(), e))?;
+    let enc_keypair = StackKeyPair::gen();
+    write_keys(
+        dir,
+        key_type,
+        (&sign_keypair.public_key[..], &sign_keypair.secret_key[..]),
+        (&enc_keypair.public_key[..], &enc_keypair.secret_key[..]),
+        passphrase,
+        verbose,
+    )
+}
 
+/// `generate --vanity`: like generate_keys, with a signing key whose public key starts
+/// with `prefix`.
+#[cfg(feature = "full")]
+fn generate_vanity_keys(dir: &Path, key_type: KeyType, prefix: &str, passphrase: Option<&str>, verbose: bool) -> Result<(), String> {
+    let (sign_public, sign_secret) = vanity::grind(prefix)?;
     let enc_keypair = StackKeyPair::gen();
+    write_keys(
+        dir,
+        key_type,
+        (&sign_public, &sign_secret),
+        (&enc_keypair.public_key[..], &enc_keypair.secret_key[..]),
+        passphrase,
+        verbose,
+    )
+}
+
+/// `generate --unified`: a signing key pair (with a `vanity` prefix if given) and the
+/// encryption key pair converted from it.
+#[cfg(feature = "full")]
+fn generate_unified_keys(dir: &Path, vanity: Option<&str>, passphrase: Option<&str>, verbose: bool) -> Result<(), String> {
+    let (sign_public, sign_secret) = match vanity {
+        Some(prefix) => vanity::grind(prefix)?,
+        None => {
+            let keypair: SigningKeyPair<StackByteArray<32>, StackByteArray<64>> = SigningKeyPair::gen();
+            (keypair.public_key[..].try_into().unwrap(), keypair.secret_key[..].try_into().unwrap())
+        }
+    };
+    let (enc_public, enc_secret) = unified_encryption_keys(&sign_public, &sign_secret)?;
+    write_keys(dir, KeyType::All, (&sign_public, &sign_secret), (&enc_public, &enc_secret), passphrase, verbose)
+}
+
+/// The X25519 key pair that belongs to an Ed25519 signing key pair.
+#[cfg(feature = "full")]
+fn unified_encryption_keys(sign_public: &[u8; 32], sign_secret: &[u8; 64]) -> Result<([u8; 32], [u8; 32]), String> {
+    let mut enc_secret = [0u8; 32];
+    crypto_sign_ed25519_sk_to_curve25519(&mut enc_secret, sign_secret);
+    Ok((x25519_public_key(sign_public)?, enc_secret))
+}
+
+#[cfg(feature = "full")]
+fn x25519_public_key(sign_public: &[u8; 32]) -> Result<[u8; 32], String> {
+    let mut enc_public = [0u8; 32];
+    crypto_sign_ed25519_pk_to_curve25519(&mut enc_public, sign_public)
+        .map_err(|e| format!("Cannot convert signing public key to an encryption key: {}", e))?;
+    Ok(enc_public)
+}
+
+/// `key unify`: replace the encryption key pair in `dir` with the one converted from its
+/// signing key pair.
+#[cfg(feature = "full")]
+fn unify_keys(dir: &Path, passphrase: bool, force: bool, verbose: bool) -> Result<(), String> {
+    let sign_public: [u8; 32] = load_key(&dir.join("sign_public.key"), 32)?.as_slice().try_into().unwrap();
+    let sign_secret: Zeroizing<[u8; 64]> = Zeroizing::new(load_secret_key(&dir.join("sign_secret.key"), 64)?.as_slice().try_into().unwrap());
+    if sign_secret[32..] != sign_public {
+        return Err(format!("The signing key files in {} do not belong together", dir.display()));
+    }
+    let (enc_public, enc_secret) = unified_encryption_keys(&sign_public, &sign_secret)?;
     let enc_public_key_path = dir.join("enc_public.key");
     let enc_secret_key_path = dir.join("enc_secret.key");
-    fs::write(&enc_public_key_path, hex::encode(&enc_keypair.public_key))
+    if enc_public_key_path.exists() && !force {
+        let current = load_key(&enc_public_key_path, 32)?;
+        if current[..] == enc_public {
+            println!("The encryption keys in {} are already derived from the signing keys", dir.display());
+            return Ok(());
+        }
+        return Err(format!(
+            "{} exists; --force replaces it, after which messages encrypted to the old key cannot be decrypted with this directory",
+            enc_public_key_path.display()
+        ));
+    }
+    let passphrase = if passphrase { Some(keyfile::new_passphrase()?) } else { None };
+    fs::write(&enc_public_key_path, hex::encode(enc_public))
         .map_err(|e| format!("Failed to write encryption public key to {}: {}", enc_public_key_path.display(), e))?;
-    fs::write(&enc_secret_key_path, hex::encode(&enc_keypair.secret_key))
+    keyperms::write_secret(&enc_secret_key_path, secret_key_file(enc_secret, passphrase.as_deref().map(String::as_str))?)
         .map_err(|e| format!("Failed to write encryption secret key to {}: {}", enc_secret_key_path.display(), e))?;
+    if verbose {
+        println!("Encryption public key: {}", hex::encode(enc_public));
+    }
+    println!("Encryption keys in {} now derived from the signing keys", dir.display());
+    Ok(())
+}
 
+/// `generate --from-passphrase`: derive both key pairs from a passphrase and record the
+/// parameters in derivation.params.
+#[cfg(feature = "full")]
+fn derive_keys(dir: &Path, key_type: KeyType, salt: Option<String>, params_path: Option<PathBuf>, verbose: bool) -> Result<(), String> {
+    let params = match (salt, params_path) {
+        (_, Some(path)) => {
+            let text = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read derivation parameters from {}: {}", path.display(), e))?;
+            derive::Params::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        (Some(salt), None) => derive::Params::new(&salt)?,
+        (None, None) => return Err("--from-passphrase needs a --salt (or --params from an earlier derivation)".to_string()),
+    };
+    let passphrase = keyfile::new_passphrase()?;
+    if passphrase.chars().count() < 16 {
+        eprintln!("Warning: anyone who guesses this passphrase can recreate your keys; use a long one");
+    }
+    let keys = derive::derive(&passphrase, &params)?;
+    write_keys(dir, key_type, (&keys.sign_public, &keys.sign_secret), (&keys.enc_public, &keys.enc_secret), None, verbose)?;
+    let params_path = dir.join(derive::PARAMS_FILE);
+    fs::write(&params_path, params.to_text())
+        .map_err(|e| format!("Failed to write derivation parameters to {}: {}", params_path.display(), e))?;
     if verbose {
-        println!(
-            "Generated keys at: {}, {}, {}, and {}",
-            sign_public_key_path.display(),
-            sign_secret_key_path.display(),
-            enc_public_key_path.display(),
-            enc_secret_key_path.display()
-        );
+        println!("Derivation parameters saved to: {}", params_path.display());
+    }
+    Ok(())
+}
+
+/// Write the key pairs selected by `key_type`, each as (public, secret), to their key
+/// files in `dir`.
+#[cfg(feature = "full")]
+fn write_keys(dir: &Path, key_type: KeyType, sign: (&[u8], &[u8]), enc: (&[u8], &[u8]), passphrase: Option<&str>, verbose: bool) -> Result<(), String> {
+    // Create directory if it doesn't exist
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+
+    let mut written = Vec::new();
+    if key_type.sign() {
+        let sign_public_key_path = dir.join("sign_public.key");
+        let sign_secret_key_path = dir.join("sign_secret.key");
+        fs::write(&sign_public_key_path, hex::encode(sign.0))
+            .map_err(|e| format!("Failed to write signing public key to {}: {}", sign_public_key_path.display(), e))?;
+        keyperms::write_secret(&sign_secret_key_path, secret_key_file(sign.1, passphrase)?)
+            .map_err(|e| format!("Failed to write signing secret key to {}: {}", sign_secret_key_path.display(), e))?;
+        written.extend([sign_public_key_path, sign_secret_key_path]);
+    }
+    if key_type.enc() {
+        let enc_public_key_path = dir.join("enc_public.key");
+        let enc_secret_key_path = dir.join("enc_secret.key");
+        fs::write(&enc_public_key_path, hex::encode(enc.0))
+            .map_err(|e| format!("Failed to write encryption public key to {}: {}", enc_public_key_path.display(), e))?;
+        keyperms::write_secret(&enc_secret_key_path, secret_key_file(enc.1, passphrase)?)
+            .map_err(|e| format!("Failed to write encryption secret key to {}: {}", enc_secret_key_path.display(), e))?;
+        written.extend([enc_public_key_path, enc_secret_key_path]);
+    }
+
+    if verbose {
+        let paths: Vec<String> = written.iter().map(|p| p.display().to_string()).collect();
+        let (last, rest) = paths.split_last().unwrap();
+        println!("Generated keys at: {} and {}", rest.join(", "), last);
     }
     Ok(())
 }
 
-fn print_keys(dir: &Path, verbose: bool) -> Result<(), String> {
+/// `generate --single-file`: write new key pairs to the identity file `file`. Returns the
+/// public keys (signing, encryption).
+#[cfg(feature = "full")]
+fn generate_identity_file(file: &Path, name: Option<&str>, passphrase: Option<&str>, force: bool, verbose: bool) -> Result<([u8; 32], [u8; 32]), String> {
+    check_overwrite(file, force)?;
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => file.file_stem().map_or_else(|| "sodix".to_string(), |s| s.to_string_lossy().into_owned()),
+    };
+    let sign_keypair: SigningKeyPair<StackByteArray<32>, StackByteArray<64>> = SigningKeyPair::gen();
+    let sign_public: [u8; 32] = sign_keypair.public_key[..].try_into().unwrap();
+    let enc_keypair = StackKeyPair::gen();
+    let enc_public: [u8; 32] = enc_keypair.public_key[..].try_into().unwrap();
+    let text = identity::create(
+        &name,
+        &keymeta::format_time(unix_now()),
+        (&sign_public, &secret_key_file(&sign_keypair.secret_key, passphrase)?),
+        (&enc_public, &secret_key_file(&enc_keypair.secret_key, passphrase)?),
+    )?;
+    if let Some(dir) = file.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+    }
+    keyperms::write_secret(file, text).map_err(|e| format!("Failed to write identity to {}: {}", file.display(), e))?;
+    if verbose {
+        println!("Generated identity '{}' at: {}", name, file.display());
+    }
+    Ok((sign_public, enc_public))
+}
+
+/// Secret key file contents: hex, or the encrypted format when a passphrase is given.
+#[cfg(feature = "full")]
+fn secret_key_file(secret_key: impl AsRef<[u8]>, passphrase: Option<&str>) -> Result<String, String> {
+    match passphrase {
+        Some(passphrase) => keyfile::encrypt(secret_key.as_ref(), passphrase),
+        None => Ok(hex::encode(secret_key)),
+    }
+}
+
+#[cfg(feature = "full")]
+fn print_keys(dir: &Path, bech32: bool, did: bool, armor: bool, verbose: bool) -> Result<(), String> {
     // Create directory if it doesn't exist
     fs::create_dir_all(dir)
         .map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
 
-    // Generate both key pairs if any key file is missing
+    // Generate both key pairs if there are no key files. A directory made with
+    // `generate --type` keeps its single pair and the other one prints as empty lines.
     let sign_public_key_path = dir.join("sign_public.key");
     let sign_secret_key_path = dir.join("sign_secret.key");
     let enc_public_key_path = dir.join("enc_public.key");
     let enc_secret_key_path = dir.join("enc_secret.key");
+    let has_sign = sign_public_key_path.exists() || sign_secret_key_path.exists();
+    let has_enc = enc_public_key_path.exists() || enc_secret_key_path.exists();
 
-    if !sign_public_key_path.exists() || !sign_secret_key_path.exists() 
-        || !enc_public_key_path.exists() || !enc_secret_key_path.exists() {
+    if !has_sign && !has_enc {
         if verbose {
-            println!("Some keys missing, generating new keypairs...");
+            println!("No keys found, generating new keypairs...");
         }
-        generate_keys(dir, verbose)?;
+        generate_keys(dir, KeyType::All, None, verbose)?;
     }
 
-    let sign_pk = load_key(&sign_public_key_path, 32).map(|k| hex::encode(k))?;
-    let sign_sk = load_key(&sign_secret_key_path, 64).map(|k| hex::encode(k))?;
-    let enc_pk = load_key(&enc_public_key_path, 32).map(|k| hex::encode(k))?;
-    let enc_sk = load_key(&enc_secret_key_path, 32).map(|k| hex::encode(k))?;
+    let load = |path: &Path, size, present: bool, secret: bool| match present {
+        false => Ok(None),
+        true if secret => load_secret_key(path, size).map(Some),
+        true => load_key(path, size).map(Some),
+    };
+    let (has_sign, has_enc) = (has_sign || !has_enc, has_enc || !has_sign);
+    let keys = [
+        load(&sign_public_key_path, 32, has_sign, false)?,
+        load(&sign_secret_key_path, 64, has_sign, true)?,
+        load(&enc_public_key_path, 32, has_enc, false)?,
+        load(&enc_secret_key_path, 32, has_enc, true)?,
+    ];
+    print_key_lines(keys, bech32, did, armor, verbose);
+    Ok(())
+}
 
+/// `print` for a single-file identity.
+#[cfg(feature = "full")]
+fn print_identity(file: &Path, bech32: bool, did: bool, armor: bool, verbose: bool) -> Result<(), String> {
+    let id = identity::Identity::load(file)?;
+    let sign_sk = load_key(&identity::key_path(file, "sign_secret"), 64)?;
+    let enc_sk = load_key(&identity::key_path(file, "enc_secret"), 32)?;
     if verbose {
-        println!("Signing Public Key (sign_public.key): {}", sign_pk);
-        println!("Signing Secret Key (sign_secret.key): {}", sign_sk);
-        println!("Encryption Public Key (enc_public.key): {}", enc_pk);
-        println!("Encryption Secret Key (enc_secret.key): {}", enc_sk);
-    } else {
-        println!("{}", sign_pk); // Line 1: Signing Public Key
-        println!("{}", sign_sk); // Line 2: Signing Secret Key
-        println!("{}", enc_pk);  // Line 3: Encryption Public Key
-        println!("{}", enc_sk);  // Line 4: Encryption Secret Key
+        println!("Identity: {} (created {})", id.name, id.created);
     }
+    print_key_lines([Some(Zeroizing::new(id.sign_public.to_vec())), Some(sign_sk), Some(Zeroizing::new(id.enc_public.to_vec())), Some(enc_sk)], bech32, did, armor, verbose);
     Ok(())
 }
 
-fn parse_hex_key(hex_key: &str) -> Result<[u8; 32], String> {
+/// Print the signing and encryption key pairs, in that order; missing keys print as
+/// empty lines, or are left out when armored.
+#[cfg(feature = "full")]
+fn print_key_lines(keys: [Option<Zeroizing<Vec<u8>>>; 4], bech32: bool, did: bool, armor: bool, verbose: bool) {
+    const LABELS: [(&str, &str); 4] = [
+        ("Signing Public Key", "sign_public.key"),
+        ("Signing Secret Key", "sign_secret.key"),
+        ("Encryption Public Key", "enc_public.key"),
+        ("Encryption Secret Key", "enc_secret.key"),
+    ];
+    const ARMOR_LABELS: [&str; 4] = [
+        armor::SIGNING_PUBLIC_KEY,
+        armor::SIGNING_SECRET_KEY,
+        armor::ENCRYPTION_PUBLIC_KEY,
+        armor::ENCRYPTION_SECRET_KEY,
+    ];
+    for (i, key) in keys.iter().enumerate() {
+        if armor {
+            if let Some(key) = key {
+                let headers = if i % 2 == 0 {
+                    vec![("Fingerprint", fingerprint(key, FingerprintAlg::default()))]
+                } else {
+                    Vec::new()
+                };
+                print!("{}", armor::armor(ARMOR_LABELS[i], &headers, key));
+            }
+            continue;
+        }
+        let text = match key {
+            Some(key) if bech32 && i == 0 => bech32key::encode(KeyKind::Signing, key),
+            Some(key) if bech32 && i == 2 => bech32key::encode(KeyKind::Encryption, key),
+            Some(key) if did && i == 0 => didkey::encode(KeyKind::Signing, key),
+            Some(key) if did && i == 2 => didkey::encode(KeyKind::Encryption, key),
+            Some(key) => hex::encode(key),
+            None => String::new(),
+        };
+        if verbose {
+            println!("{} ({}): {}", LABELS[i].0, LABELS[i].1, text);
+        } else {
+            println!("{}", text);
+        }
+    }
+}
+
+/// Whether `s` is a public key written as bech32, did:key or an age recipient rather
+/// than hex or a path.
+fn is_text_key(s: &str) -> bool {
+    bech32key::is_bech32(s) || didkey::is_did_key(s) || age::is_recipient(s)
+}
+
+/// Decode a bech32, did:key or age public key; `expected` rejects keys of the other kind.
+fn decode_text_key(s: &str, expected: Option<KeyKind>) -> Result<[u8; 32], String> {
+    if didkey::is_did_key(s) {
+        didkey::decode(s, expected)
+    } else if age::is_recipient(s) {
+        if expected == Some(KeyKind::Signing) {
+            return Err(format!("Expected {}, got {}", KeyKind::Signing.name(), KeyKind::Encryption.name()));
+        }
+        age::decode_recipient(s)
+    } else {
+        bech32key::decode(s, expected)
+    }
+}
+
+/// Refuse to replace an existing output unless --force was given.
+fn check_overwrite(path: &Path, force: bool) -> Result<(), String> {
+    if !force && fs::symlink_metadata(path).is_ok() {
+        return Err(format!("{} already exists; pass --force to overwrite it", path.display()));
+    }
+    Ok(())
+}
+
+/// `encrypt --shred`: overwrite and delete the plaintext once its ciphertext is on disk.
+#[cfg(feature = "full")]
+fn shred_input(input: &str, shred: bool, verbose: bool) -> Result<(), String> {
+    if !shred || cleanup::shred_on_commit(Path::new(input)) {
+        return Ok(());
+    }
+    cleanup::shred(Path::new(input)).map_err(|e| format!("Failed to shred {}: {}", input, e))?;
+    if verbose {
+        println!("Shredded {}", input);
+    }
+    Ok(())
+}
+
+/// `encrypt --format age`: <input>.age for files, armored age text on stdout otherwise.
+#[cfg(feature = "full")]
+fn encrypt_age(input: &str, file: bool, recipients: &[[u8; 32]], armor: bool, force: bool, verbose: bool) -> Result<(), String> {
+    if file {
+        let output_file = format!("{}.age", input);
+        check_overwrite(Path::new(&output_file), force)?;
+        age::encrypt_file(Path::new(input), Path::new(&output_file), recipients, armor)?;
+        if verbose {
+            println!("Encrypted file saved to: {}", output_file);
+        }
+    } else {
+        let mut data = Vec::new();
+        age::encrypt(input.as_bytes(), &mut data, recipients)?;
+        print!("{}", age::armor(&data));
+    }
+    Ok(())
+}
+
+/// `decrypt --format age`: <input>.age to <input> for files, armored age text given as
+/// the input to stdout otherwise.
+fn decrypt_age(input: &str, file: bool, secret_key: &[u8; 32], force: bool, verbose: bool) -> Result<(), String> {
+    if file {
+        let encrypted_file = if input.ends_with(".age") { input.to_string() } else { format!("{}.age", input) };
+        let output_file = &encrypted_file[..encrypted_file.len() - 4];
+        check_overwrite(Path::new(output_file), force)?;
+        age::decrypt_file(Path::new(&encrypted_file), Path::new(output_file), secret_key)?;
+        if verbose {
+            println!("Decrypted file saved to: {}", output_file);
+        }
+    } else {
+        let mut plaintext = Vec::new();
+        age::decrypt(&age::dearmor(input)?[..], &mut plaintext, secret_key)?;
+        io::stdout()
+            .write_all(&plaintext)
+            .map_err(|e| format!("Failed to write decrypted data: {}", e))?;
+    }
+    Ok(())
+}
+
+/// `encrypt --format saltpack`: an armored message, written to <input>.saltpack for files.
+#[cfg(feature = "full")]
+fn encrypt_saltpack(input: &str, file: bool, recipients: &[[u8; 32]], sender_key: &[u8; 32], force: bool, verbose: bool) -> Result<(), String> {
+    if !file {
+        print!("{}", saltpack::encrypt(input.as_bytes(), sender_key, recipients)?);
+        return Ok(());
+    }
+    let data = mmap::read(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?;
+    let output_file = format!("{}.saltpack", input);
+    check_overwrite(Path::new(&output_file), force)?;
+    cleanup::write_atomic(&output_file, saltpack::encrypt(&data, sender_key, recipients)?)
+        .map_err(|e| format!("Failed to write encrypted file {}: {}", output_file, e))?;
+    if verbose {
+        println!("Encrypted file saved to: {}", output_file);
+    }
+    Ok(())
+}
+
+/// `decrypt --format saltpack`: <input>.saltpack to <input> for files, the armored
+/// message given as the input to stdout otherwise. With `sender`, the message must
+/// come from that key.
+fn decrypt_saltpack(input: &str, file: bool, secret_key: &[u8; 32], sender: Option<[u8; 32]>, force: bool, verbose: bool) -> Result<(), String> {
+    let (armored, output_file) = if file {
+        let encrypted_file = if input.ends_with(".saltpack") { input.to_string() } else { format!("{}.saltpack", input) };
+        check_overwrite(Path::new(encrypted_file.trim_end_matches(".saltpack")), force)?;
+        let text = fs::read_to_string(&encrypted_file)
+            .map_err(|e| format!("Failed to read encrypted file {}: {}", encrypted_file, e))?;
+        (text, Some(encrypted_file.trim_end_matches(".saltpack").to_string()))
+    } else {
+        (input.to_string(), None)
+    };
+    let (plaintext, from) = saltpack::decrypt(&armored, secret_key)?;
+    match (sender, from) {
+        (Some(expected), Some(from)) if from != expected => {
+            return Err(format!("The message is from {}, not from the given sender key", hex::encode(from)))
+        }
+        (Some(_), None) => return Err("The message is from an anonymous sender".to_string()),
+        _ => {}
+    }
+    if verbose {
+        match from {
+            Some(from) => eprintln!("Sender: {}", hex::encode(from)),
+            None => eprintln!("Sender: anonymous"),
+        }
+    }
+    match output_file {
+        Some(output_file) => {
+            cleanup::write_atomic(&output_file, &plaintext)
+                .map_err(|e| format!("Failed to write decrypted file {}: {}", output_file, e))?;
+            if verbose {
+                println!("Decrypted file saved to: {}", output_file);
+            }
+        }
+        None => io::stdout()
+            .write_all(&plaintext)
+            .map_err(|e| format!("Failed to write decrypted data: {}", e))?,
+    }
+    Ok(())
+}
+
+/// A WireGuard-style key: 32 bytes of standard base64, 44 characters ending in '='.
+fn parse_base64_key(s: &str) -> Option<[u8; 32]> {
+    let s = s.trim();
+    if s.len() != 44 || !s.ends_with('=') {
+        return None;
+    }
+    STANDARD.decode(s).ok()?.try_into().ok()
+}
+
+fn parse_hex_key(hex_key: &str) -> Result<[u8; 32], String> {
+    if is_text_key(hex_key) {
+        return decode_text_key(hex_key, Some(KeyKind::Encryption));
+    }
+    if let Some(key) = parse_base64_key(hex_key) {
+        return Ok(key);
+    }
     let key_vec = hex::decode(hex_key)
+        .map(Zeroizing::new)
         .map_err(|e| format!("Invalid hex key: {}", e))?;
-    key_vec.try_into()
+    key_vec.as_slice().try_into()
         .map_err(|_| "Public key must be 32 bytes".to_string())
 }
 
+#[cfg(feature = "full")]
+/// Read a recipients file: one hex public key per line, blank lines and '#' comments ignored.
+fn load_recipients(path: &Path) -> Result<Vec<[u8; 32]>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read recipients file {}: {}", path.display(), e))?;
+    let mut keys = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        keys.push(parse_hex_key(line).map_err(|e| format!("{}:{}: {}", path.display(), i + 1, e))?);
+    }
+    Ok(keys)
+}
+
+/// Decrypt the container read from `reader` chunk by chunk, authenticating each chunk
+/// before it is written: to `output_file`, which only appears once all of it checks
+/// out, or else to stdout.
+fn decrypt_container_to<R: Read>(reader: R, output_file: Option<&str>, keys: &container::OpenKeys, verbose: bool) -> Result<(), String> {
+    let Some(output_file) = output_file else {
+        let mut stdout = BufWriter::new(io::stdout().lock());
+        container::decrypt_stream(reader, &mut stdout, keys)?;
+        return stdout.flush().map_err(|e| format!("Failed to write decrypted data: {}", e));
+    };
+    let mut writer = cleanup::AtomicFile::create(output_file)
+        .map_err(|e| format!("Failed to write decrypted file {}: {}", output_file, e))?;
+    container::decrypt_stream(reader, &mut writer, keys)?;
+    writer.commit().map_err(|e| format!("Failed to write decrypted file {}: {}", output_file, e))?;
+    if verbose {
+        println!("Decrypted file saved to: {}", output_file);
+    }
+    Ok(())
+}
+
+/// crypto_box_open_easy on nonce || MAC || ciphertext, decrypting in place: returns
+/// the part of `combined` holding the plaintext.
+fn crypto_box_open_in_place<'a>(combined: &'a mut [u8], pk: &[u8; 32], sk: &[u8; 32]) -> Result<&'a [u8], String> {
+    if combined.len() < sharedkey::NONCE_LEN + sharedkey::MAC_LEN {
+        return Err("Input too short; must contain nonce and ciphertext".to_string());
+    }
+    let (nonce, boxed) = combined.split_at_mut(sharedkey::NONCE_LEN);
+    container::shared_key(pk, sk)
+        .open_in_place(boxed, (&*nonce).try_into().unwrap())
+        .map_err(|_| "Error decrypting data: decryption failed".to_string())
+}
+
+/// Parse `OFFSET..LEN` (or `OFFSET..` for "to the end") as used by `decrypt --range`.
+fn parse_range(range: &str) -> Result<(u64, Option<u64>), String> {
+    let (offset, len) = range
+        .split_once("..")
+        .ok_or_else(|| format!("Invalid range '{}', expected OFFSET..LEN", range))?;
+    let offset = offset.trim().parse().map_err(|e| format!("Invalid range offset '{}': {}", offset, e))?;
+    let len = match len.trim() {
+        "" => None,
+        len => Some(len.parse().map_err(|e| format!("Invalid range length '{}': {}", len, e))?),
+    };
+    Ok((offset, len))
+}
+
+#[cfg(feature = "full")]
+/// crypto_stream_xchacha20_xor over stdin/stdout. The output is not authenticated:
+/// flipped ciphertext bits silently flip plaintext bits.
+fn stream_xor(key_hex: &str, nonce_hex: &str) -> Result<(), String> {
+    let key: Zeroizing<[u8; 32]> = hex::decode(key_hex.trim())
+        .map(Zeroizing::new)
+        .map_err(|e| format!("Invalid hex key: {}", e))?
+        .as_slice()
+        .try_into()
+        .map(Zeroizing::new)
+        .map_err(|_| "Stream key must be 32 bytes")?;
+    let nonce: [u8; 24] = hex::decode(nonce_hex.trim())
+        .map_err(|e| format!("Invalid hex nonce: {}", e))?
+        .try_into()
+        .map_err(|_| "Stream nonce must be 24 bytes")?;
+
+    let mut cipher = XChaCha20::new(&(*key).into(), &nonce.into());
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = stdin.read(&mut buf).map_err(|e| format!("Failed to read input: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        cipher.apply_keystream(&mut buf[..n]);
+        stdout.write_all(&buf[..n]).map_err(|e| format!("Failed to write output: {}", e))?;
+    }
+    stdout.flush().map_err(|e| format!("Failed to flush output: {}", e))
+}
+
+#[cfg(feature = "full")]
+fn scalarmult(secret_hex: &str, public_hex: Option<&str>) -> Result<(), String> {
+    let n = parse_hex_key(secret_hex.trim())?;
+    let mut q = [0u8; 32];
+    match public_hex {
+        Some(public_hex) => {
+            let p = parse_hex_key(public_hex.trim())?;
+            crypto_scalarmult(&mut q, &n, &p);
+            if q == [0u8; 32] {
+                return Err("Shared secret is all zeros (low-order public key)".to_string());
+            }
+        }
+        None => crypto_scalarmult_base(&mut q, &n),
+    }
+    println!("{}", hex::encode(q));
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(feature = "full")]
+fn delegate_key(root_key_input: &str, ttl: &str, dir: &Path, force: bool, verbose: bool) -> Result<(), String> {
+    let ttl = humantime::parse_duration(ttl).map_err(|e| format!("Invalid ttl '{}': {}", ttl, e))?;
+    // A delegated key in use must not be replaced by re-running the command
+    let public_key_path = dir.join("delegate_public.key");
+    let secret_key_path = dir.join("delegate_secret.key");
+    let cert_path = dir.join("delegate.cert");
+    for path in [&public_key_path, &secret_key_path, &cert_path] {
+        check_overwrite(path, force)?;
+    }
+
+    let root_sk = load_signing_key(root_key_input, true).map_err(|e| format!("Failed to load the root key: {}", e))?;
+    let root_sk: Zeroizing<[u8; 64]> = Zeroizing::new(root_sk.as_slice().try_into().map_err(|_| "Secret key must be 64 bytes")?);
+
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+    let subkey: SigningKeyPair<StackByteArray<32>, StackByteArray<64>> = SigningKeyPair::gen();
+    let not_before = unix_now();
+    let not_after = not_before + ttl.as_secs();
+    let cert = DelegationCert::issue(&root_sk, *subkey.public_key, not_before, not_after)?;
+
+    fs::write(&public_key_path, hex::encode(&subkey.public_key))
+        .map_err(|e| format!("Failed to write delegated public key to {}: {}", public_key_path.display(), e))?;
+    keyperms::write_secret(&secret_key_path, Zeroizing::new(hex::encode(&subkey.secret_key)).as_bytes())
+        .map_err(|e| format!("Failed to write delegated secret key to {}: {}", secret_key_path.display(), e))?;
+    fs::write(&cert_path, hex::encode(cert.to_bytes()))
+        .map_err(|e| format!("Failed to write delegation certificate to {}: {}", cert_path.display(), e))?;
+
+    if verbose {
+        println!(
+            "Generated delegated signing keys at: {} and {}, certificate at: {} (valid until {})",
+            public_key_path.display(),
+            secret_key_path.display(),
+            cert_path.display(),
+            humantime::format_rfc3339_seconds(UNIX_EPOCH + std::time::Duration::from_secs(not_after))
+        );
+    } else {
+        println!("{}", cert_path.display());
+    }
+    Ok(())
+}
+
+/// A public key of either kind given as hex, bech32, did:key or a key file.
+#[cfg(feature = "full")]
+fn parse_any_public_key(input: &str) -> Result<[u8; 32], String> {
+    if is_text_key(input) {
+        return decode_text_key(input, None);
+    }
+    match hex::decode(input.trim()) {
+        Ok(bytes) if bytes.len() == 32 => Ok(bytes.try_into().unwrap()),
+        _ => Ok(load_key(Path::new(input), 32)?.as_slice().try_into().unwrap()),
+    }
+}
+
+/// Names of the identities created with `key add`, sorted.
+#[cfg(feature = "full")]
+fn identity_names() -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+    for dir in [get_default_key_dir(), get_legacy_key_dir()] {
+        let dir = dir.join("identities");
+        if !dir.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&dir).map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))? {
+            let entry = entry.map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+            if entry.path().is_dir() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+#[cfg(feature = "full")]
+fn list_identities() -> Result<(), String> {
+    for name in identity_names()? {
+        let dir = identity_dir(&name)?;
+        let path = if dir.join("sign_public.key").exists() { dir.join("sign_public.key") } else { dir.join("enc_public.key") };
+        let pk = load_key(&path, 32)?;
+        println!("{}\t{}", name, fingerprint(&pk, FingerprintAlg::default()));
+    }
+    Ok(())
+}
+
+/// `sodix report`: the keys of the default identity and each named one, with the
+/// expiry keys.meta records for them, flagging keys expired or expiring within
+/// `within`. Reads nothing but the key directory.
+#[cfg(feature = "full")]
+fn report(within: &str, json: bool) -> Result<(), String> {
+    let window = humantime::parse_duration(within).map_err(|e| format!("Invalid duration '{}': {}", within, e))?.as_secs();
+    let now = unix_now();
+    let mut identities = vec![(None, default_key_file("sign_public.key"), default_key_file("enc_public.key"))];
+    for name in identity_names()? {
+        let dir = identity_dir(&name)?;
+        identities.push((Some(name), dir.join("sign_public.key"), dir.join("enc_public.key")));
+    }
+    let (mut count, mut expired, mut expiring, mut unlimited) = (0, 0, 0, 0);
+    let mut entries = Vec::new();
+    for (name, sign_path, enc_path) in identities {
+        let mut keys = Vec::new();
+        for (key_type, path) in [("sign", sign_path), ("enc", enc_path)] {
+            if !path.exists() {
+                continue;
+            }
+            let pk = load_key(&path, 32)?;
+            let expires = keymeta::KeyMeta::load(&key_meta_dirs(&path.to_string_lossy()))?.expires(&pk);
+            let status = match expires {
+                None => "no expiry",
+                Some(expires) if expires <= now => "expired",
+                Some(expires) if expires - now <= window => "expiring",
+                Some(_) => "ok",
+            };
+            match status {
+                "expired" => expired += 1,
+                "expiring" => expiring += 1,
+                "no expiry" => unlimited += 1,
+                _ => {}
+            }
+            count += 1;
+            keys.push((key_type, fingerprint(&pk, FingerprintAlg::default()), expires, status));
+        }
+        if !keys.is_empty() {
+            entries.push((name, keys));
+        }
+    }
+    if json {
+        let identities: Vec<serde_json::Value> = entries
+            .iter()
+            .map(|(name, keys)| {
+                let keys: Vec<serde_json::Value> = keys
+                    .iter()
+                    .map(|(key_type, fpr, expires, status)| {
+                        serde_json::json!({
+                            "type": key_type,
+                            "fingerprint": fpr,
+                            "expires": expires.map(keymeta::format_time),
+                            "status": status,
+                        })
+                    })
+                    .collect();
+                serde_json::json!({ "identity": name, "keys": keys })
+            })
+            .collect();
+        let report = serde_json::json!({
+            "generated": keymeta::format_time(now),
+            "expiring_within": within,
+            "identities": identities,
+            "summary": { "keys": count, "expired": expired, "expiring": expiring, "no_expiry": unlimited },
+        });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        return Ok(());
+    }
+    for (name, keys) in &entries {
+        for (key_type, fpr, expires, status) in keys {
+            let expires = expires.map_or("-".to_string(), keymeta::format_time);
+            println!("{}\t{}\t{}\t{}\t{}", name.as_deref().unwrap_or("(default)"), key_type, fpr, expires, status);
+        }
+    }
+    println!(
+        "{} keys in {} identities: {} expired, {} expiring within {}, {} with no expiry",
+        count,
+        entries.len(),
+        expired,
+        expiring,
+        within,
+        unlimited
+    );
+    Ok(())
+}
+
+// Everything sodix keeps in the default key directory
+#[cfg(feature = "full")]
+const KEY_DIR_ENTRIES: &[&str] = &[
+    "sign_public.key", "sign_secret.key", "enc_public.key", "enc_secret.key",
+    "minisign.pub", "minisign.key", "signify.pub", "signify.sec",
+    "channel.state", "contacts.toml", "keylog", "keys.meta", "identities", "git_signers",
+    "certifications", "introducers", "known_signers", "derivation.params",
+    "identity.sodix",
+];
+
+#[cfg(feature = "full")]
+fn migrate_keys(remove: bool, verbose: bool) -> Result<(), String> {
+    let from = get_legacy_key_dir();
+    let to = get_default_key_dir();
+    if from == to {
+        return Err(format!("Keys are already in the default directory {}", to.display()));
+    }
+    fs::create_dir_all(&to)
+        .map_err(|e| format!("Failed to create directory {}: {}", to.display(), e))?;
+    let mut moved = 0;
+    for name in KEY_DIR_ENTRIES {
+        let (src, dst) = (from.join(name), to.join(name));
+        if !src.exists() {
+            continue;
+        }
+        if dst.exists() {
+            eprintln!("Skipping {}: {} already exists", src.display(), dst.display());
+            continue;
+        }
+        copy_recursive(&src, &dst)?;
+        if remove {
+            let removed = if src.is_dir() { fs::remove_dir_all(&src) } else { fs::remove_file(&src) };
+            removed.map_err(|e| format!("Copied {}, but failed to remove it: {}", src.display(), e))?;
+        }
+        if verbose {
+            println!("{} -> {}", src.display(), dst.display());
+        }
+        moved += 1;
+    }
+    println!("Migrated {} key file(s) to {}", moved, to.display());
+    Ok(())
+}
+
+/// `key export`: everything in KEY_DIR_ENTRIES, or only the identities in `ids`.
+#[cfg(feature = "full")]
+fn export_keys(all: bool, ids: &[String], out: &Path, force: bool, verbose: bool) -> Result<(), String> {
+    check_overwrite(out, force)?;
+    let mut entries = Vec::new();
+    if all {
+        // Files still in the legacy directory are exported from there
+        for name in KEY_DIR_ENTRIES {
+            let path = default_key_file(name);
+            entries.extend(bundle::collect(path.parent().unwrap(), &[name])?);
+        }
+    } else {
+        for name in ids {
+            let dir = identity_dir(name)?;
+            if !dir.is_dir() {
+                return Err(format!("Unknown identity '{}'", name));
+            }
+            entries.extend(bundle::collect(dir.parent().unwrap(), &[name.as_str()])?);
+        }
+        for entry in &mut entries {
+            entry.path = format!("identities/{}", entry.path);
+        }
+    }
+    if entries.is_empty() {
+        return Err("No keys to export".to_string());
+    }
+    let passphrase = keyfile::new_passphrase()?;
+    fs::write(out, bundle::seal(&entries, &passphrase)?)
+        .map_err(|e| format!("Failed to write key bundle to {}: {}", out.display(), e))?;
+    if verbose {
+        for entry in &entries {
+            println!("{}", entry.path);
+        }
+    }
+    println!("Exported {} file(s) to {}", entries.len(), out.display());
+    Ok(())
+}
+
+/// `key import`: unpack a bundle into the default key directory. Nothing is written if a
+/// file would be replaced without --force, or if a name conflict stops the import.
+/// Contacts and known signers are merged name by name, each name saved with a
+/// different key settled by `on_conflict` or by asking.
+#[cfg(feature = "full")]
+fn import_keys(path: &Path, force: bool, on_conflict: Option<ConflictPolicy>, verbose: bool) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read key bundle from {}: {}", path.display(), e))?;
+    if pkcs8::is_pkcs8(&data) {
+        let key = pkcs8::parse(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+        return import_key_files(&[key], force, verbose);
+    }
+    if jwk::is_jwk(&data) {
+        let keys = jwk::parse(&String::from_utf8_lossy(&data)).map_err(|e| format!("{}: {}", path.display(), e))?;
+        return import_key_files(&keys, force, verbose);
+    }
+    if !bundle::is_bundle(&data) {
+        return Err(format!("{} is not a sodix key bundle, PKCS#8 key or JWK", path.display()));
+    }
+    let entries = bundle::open(&data, &keyfile::ask_passphrase(path)?)?;
+    let dir = get_default_key_dir();
+    let mut contacts = contacts::Contacts::load(&dir.join(contacts::FILE_NAME))?;
+    let mut signers = trust::TrustStore::load(&dir.join(trust::FILE_NAME))?;
+    let (mut contacts_changed, mut signers_changed) = (false, false);
+    let mut writes = Vec::new();
+    for entry in &entries {
+        let dst = bundle::destination(&dir, entry)?;
+        let text = || String::from_utf8(entry.data.clone()).map_err(|_| format!("{} in the key bundle is not text", entry.path));
+        if entry.path == contacts::FILE_NAME {
+            let imported = contacts::Contacts::parse(Path::new(&entry.path), &text()?)?;
+            for (name, _) in imported.iter() {
+                contacts_changed |= import_contact(&mut contacts, name, &imported.key(name)?, on_conflict)?;
+            }
+            continue;
+        }
+        if entry.path == trust::FILE_NAME {
+            for signer in trust::TrustStore::parse(Path::new(&entry.path), &text()?)?.iter() {
+                let take = match signers.get(&signer.name) {
+                    Some(current) if current.key == signer.key => false,
+                    Some(current) => take_conflicting_key("Known signer", &signer.name, &current.key, &signer.key, on_conflict)?,
+                    None => true,
+                };
+                if take {
+                    signers.insert(&signer.name, signer.key, signer.first_seen)?;
+                    signers_changed = true;
+                }
+            }
+            continue;
+        }
+        match fs::read(&dst) {
+            Ok(existing) if existing == entry.data => continue,
+            Ok(_) if !force => {
+                return Err(format!("{} already exists with different contents; use --force to replace it", dst.display()))
+            }
+            _ => writes.push((dst, entry)),
+        }
+    }
+    for (dst, entry) in &writes {
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory {}: {}", parent.display(), e))?;
+        }
+        keyperms::write_secret(dst, &entry.data).map_err(|e| format!("Failed to write {}: {}", dst.display(), e))?;
+        if verbose {
+            println!("{}", dst.display());
+        }
+    }
+    if contacts_changed {
+        contacts.save()?;
+    }
+    if signers_changed {
+        signers.save()?;
+    }
+    let merged = entries.iter().filter(|e| e.path == contacts::FILE_NAME || e.path == trust::FILE_NAME).count();
+    println!("Imported {} file(s) into {} ({} already present, {} merged)", writes.len(), dir.display(), entries.len() - writes.len() - merged, merged);
+    Ok(())
+}
+
+/// `key export --format pkcs8|jwk`: the default keys, or those of identity `id`, as one
+/// file per key in `out`. Missing key pairs are skipped.
+#[cfg(feature = "full")]
+fn export_key_files(id: Option<&str>, out: &Path, format: ExportFormat, der: bool, force: bool, verbose: bool) -> Result<(), String> {
+    const KEYS: [(&str, pkcs8::Algorithm, bool, usize); 4] = [
+        ("sign_secret", pkcs8::Algorithm::Ed25519, true, 64),
+        ("sign_public", pkcs8::Algorithm::Ed25519, false, 32),
+        ("enc_secret", pkcs8::Algorithm::X25519, true, 32),
+        ("enc_public", pkcs8::Algorithm::X25519, false, 32),
+    ];
+    let mut files = Vec::new();
+    for (key_type, algorithm, secret, size) in KEYS {
+        // WireGuard and age keys are X25519 only
+        if matches!(format, ExportFormat::Wireguard | ExportFormat::Age) && algorithm != pkcs8::Algorithm::X25519 {
+            continue;
+        }
+        let path = identity_key_path(id, key_type)?;
+        if !key_exists(&path) {
+            continue;
+        }
+        let key = if secret { load_secret_key(&path, size)? } else { load_key(&path, size)? };
+        let (extension, contents) = match format {
+            ExportFormat::Jwk => ("jwk", jwk::encode(algorithm, secret, &key).into_bytes()),
+            ExportFormat::Wireguard => ("wg", format!("{}\n", STANDARD.encode(&key)).into_bytes()),
+            ExportFormat::Age if secret => ("txt", age::encode_identity(key[..].try_into().unwrap()).into_bytes()),
+            ExportFormat::Age => ("txt", format!("{}\n", age::encode_recipient(key[..].try_into().unwrap())).into_bytes()),
+            _ if der => ("der", pkcs8::encode(algorithm, secret, &key, false)),
+            _ => ("pem", pkcs8::encode(algorithm, secret, &key, true)),
+        };
+        let dst = out.join(format!("{}.{}", key_type, extension));
+        check_overwrite(&dst, force)?;
+        files.push((dst, secret, contents));
+    }
+    if files.is_empty() {
+        return Err("No keys to export".to_string());
+    }
+    // Written only once none would replace a file without --force
+    fs::create_dir_all(out).map_err(|e| format!("Failed to create directory {}: {}", out.display(), e))?;
+    for (dst, secret, contents) in &files {
+        let written = if *secret { keyperms::write_secret(dst, contents) } else { fs::write(dst, contents) };
+        written.map_err(|e| format!("Failed to write {}: {}", dst.display(), e))?;
+        if verbose {
+            println!("{}", dst.display());
+        }
+    }
+    println!("Exported {} key(s) to {}; the secret keys are not encrypted", files.len(), out.display());
+    Ok(())
+}
+
+/// `key fetch`, and `encrypt --to` for an address that is not a contact yet: download the
+/// published key of `address`, have the user confirm its fingerprint (or match it against
+/// `expected`) and save it as a contact named after the address.
+#[cfg(feature = "full")]
+fn add_published_contact(contacts: &mut contacts::Contacts, address: &str, expected: Option<String>, url: Option<&str>, on_conflict: Option<ConflictPolicy>, verbose: bool) -> Result<[u8; 32], String> {
+    if verbose {
+        eprintln!("Fetching the key of {} from {}", address, url.map_or_else(|| wellknown::url(address), |url| Ok(url.to_string()))?);
+    }
+    let published = wellknown::fetch(address, url)?;
+    let fpr = fingerprint(&published.key, FingerprintAlg::default());
+    if verbose {
+        eprintln!("Signed by {}", hex::encode(published.signer));
+    }
+    match expected {
+        Some(pinned) if !fingerprint::matches(&pinned, &published.key)? => {
+            return Err(format!("Key fingerprint is {}, not {}", fpr, pinned));
+        }
+        Some(_) => {}
+        None => {
+            eprintln!("Published key of {}: {}", address, fpr);
+            eprint!("Does the fingerprint match the one its owner gave you? Add the contact? [y/N] ");
+            io::stderr().flush().map_err(|e| format!("Failed to write prompt: {}", e))?;
+            let mut line = String::new();
+            io::stdin().read_line(&mut line).map_err(|e| format!("Failed to read answer: {}", e))?;
+            if !matches!(line.trim(), "y" | "Y" | "yes") {
+                return Err(format!("The key of {} was not added", address));
+            }
+        }
+    }
+    if import_contact(contacts, address, &published.key, on_conflict)? {
+        contacts.save()?;
+    }
+    contacts.key(address)
+}
+
+/// Save `key` as contact `name`. A name saved with a different key is settled by
+/// `policy` (see `take_conflicting_key`). Returns whether the address book changed.
+#[cfg(feature = "full")]
+fn import_contact(contacts: &mut contacts::Contacts, name: &str, key: &[u8; 32], policy: Option<ConflictPolicy>) -> Result<bool, String> {
+    if contacts.contains(name) {
+        let current = contacts.key(name)?;
+        if current == *key || !take_conflicting_key("Contact", name, &current, key, policy)? {
+            return Ok(false);
+        }
+        contacts.remove(name)?;
+    }
+    contacts.add(name, key)?;
+    Ok(true)
+}
+
+/// `name` is saved with `current` and an import brings `imported`: show both keys with
+/// their certification status, then keep, replace or stop as `policy` says, or as the
+/// user answers when there is no policy. Returns whether to take the imported key.
+#[cfg(feature = "full")]
+fn take_conflicting_key(what: &str, name: &str, current: &[u8; 32], imported: &[u8; 32], policy: Option<ConflictPolicy>) -> Result<bool, String> {
+    let conflict = format!("{} '{}' is already saved with a different key", what, name);
+    eprintln!("{}:", conflict);
+    eprintln!("  current   {}  {}", fingerprint(current, FingerprintAlg::default()), certification_status(current)?);
+    eprintln!("  imported  {}  {}", fingerprint(imported, FingerprintAlg::default()), certification_status(imported)?);
+    match policy {
+        Some(ConflictPolicy::Keep) => Ok(false),
+        Some(ConflictPolicy::Replace) => Ok(true),
+        Some(ConflictPolicy::Fail) => Err(conflict),
+        None if !io::stdin().is_terminal() => Err(format!("{}; pass --on-conflict keep, replace or fail", conflict)),
+        None => {
+            eprint!("Replace it with the imported key, keep the current one, or stop? [r/k/S] ");
+            io::stderr().flush().map_err(|e| format!("Failed to write prompt: {}", e))?;
+            let mut line = String::new();
+            io::stdin().read_line(&mut line).map_err(|e| format!("Failed to read answer: {}", e))?;
+            match line.trim() {
+                "r" | "R" | "replace" => Ok(true),
+                "k" | "K" | "keep" => Ok(false),
+                _ => Err(conflict),
+            }
+        }
+    }
+}
+
+/// Who vouches for `key`: a certification in certifications/ by you or an introducer.
+#[cfg(feature = "full")]
+fn certification_status(key: &[u8; 32]) -> Result<String, String> {
+    let Vouchers { own, introducers } = load_vouchers(false)?;
+    if own.contains(key) {
+        return Ok("your own key".to_string());
+    }
+    Ok(match certification::find(&default_key_file("certifications"), key, &introducers)? {
+        Some(cert) => format!(
+            "certified as \"{}\" by {} ({})",
+            cert.name,
+            fingerprint(&cert.certifier, FingerprintAlg::default()),
+            keymeta::format_time(cert.certified)
+        ),
+        None => "not certified".to_string(),
+    })
+}
+
+/// One key log entry per line: sequence, time, then the action, contact and key
+/// fingerprint, or "checkpoint" and the signer's fingerprint.
+#[cfg(feature = "full")]
+fn print_keylog_entry(entry: &keylog::Entry) {
+    let what = match &entry.kind {
+        keylog::Kind::Change { action, name, key } => {
+            format!("{}\t{}\t{}", action.as_str(), name, fingerprint(key, FingerprintAlg::default()))
+        }
+        keylog::Kind::Checkpoint { signer, .. } => format!("checkpoint\t{}", fingerprint(signer, FingerprintAlg::default())),
+    };
+    println!("{}\t{}\t{}", entry.seq, keymeta::format_time(entry.time), what);
+}
+
+/// `keylog verify`: loading checks the chain and the checkpoint signatures; then the
+/// signers, the head seen before, and the contacts against the replayed log.
+#[cfg(feature = "full")]
+fn verify_keylog(log_path: &Path, contacts_path: &Path, signers: &[[u8; 32]], since: Option<&str>, verbose: bool) -> Result<(), String> {
+    let log = keylog::KeyLog::load(log_path)?;
+    let entries = log.entries();
+
+    let checkpoints: Vec<(u64, [u8; 32])> = entries
+        .iter()
+        .filter_map(|e| match &e.kind {
+            keylog::Kind::Checkpoint { signer, .. } => Some((e.seq, *signer)),
+            keylog::Kind::Change { .. } => None,
+        })
+        .collect();
+    for (seq, signer) in &checkpoints {
+        if !signers.is_empty() && !signers.contains(signer) {
+            return Err(format!(
+                "Checkpoint {} is signed by {}, which is not among the given signers",
+                seq,
+                fingerprint(signer, FingerprintAlg::default())
+            ));
+        }
+    }
+    if !signers.is_empty() && checkpoints.is_empty() {
+        return Err(format!("The key log {} has no checkpoint", log_path.display()));
+    }
+    let signed = checkpoints.last().map_or(0, |(seq, _)| *seq);
+    if (signed as usize) < entries.len() {
+        eprintln!("Warning: {} change(s) after the last checkpoint are not signed", entries.len() - signed as usize);
+    }
+
+    if let Some(since) = since {
+        let head: [u8; 32] = hex::decode(since).ok().and_then(|h| h.try_into().ok()).ok_or("--since takes a 64-digit hex head hash")?;
+        let start = match entries.iter().position(|e| e.hash == head) {
+            Some(i) => i + 1,
+            None if head == [0; 32] => 0,
+            None => return Err(format!("The key log does not extend {}: entries seen before were changed or removed", since)),
+        };
+        for entry in &entries[start..] {
+            print_keylog_entry(entry);
+        }
+    }
+
+    let contacts = contacts::Contacts::load(contacts_path)?.keys()?;
+    let logged = log.state();
+    let mut mismatches = Vec::new();
+    for (name, key) in &contacts {
+        match logged.get(name) {
+            Some(logged_key) if logged_key == key => {}
+            Some(logged_key) => mismatches.push(format!(
+                "'{}' has key {}, but {} in the key log",
+                name,
+                fingerprint(key, FingerprintAlg::default()),
+                fingerprint(logged_key, FingerprintAlg::default())
+            )),
+            None => mismatches.push(format!("'{}' is not in the key log", name)),
+        }
+    }
+    for name in logged.keys().filter(|name| !contacts.contains_key(*name)) {
+        mismatches.push(format!("'{}' was removed without a key log entry", name));
+    }
+    if !mismatches.is_empty() {
+        return Err(format!("The contacts in {} do not match the key log:\n  {}", contacts_path.display(), mismatches.join("\n  ")));
+    }
+
+    if verbose {
+        eprintln!("{} entries, {} checkpoint(s); {} contact(s) match the log", entries.len(), checkpoints.len(), contacts.len());
+    }
+    println!("{}", hex::encode(log.head()));
+    Ok(())
+}
+
+/// `key export --format openpgp`: a certificate of the signing key of the default keys
+/// or identity `id`, self-certified for `user_id`, as `out`/sign_public.asc.
+#[cfg(all(feature = "full", feature = "openpgp"))]
+fn export_openpgp(id: Option<&str>, out: &Path, user_id: Option<String>, force: bool, verbose: bool) -> Result<(), String> {
+    let path = identity_key_path(id, "sign_secret")?;
+    if !key_exists(&path) {
+        return Err("No signing key to export".to_string());
+    }
+    let sk: Zeroizing<[u8; 64]> = Zeroizing::new(load_secret_key(&path, 64)?.as_slice().try_into().unwrap());
+    let user_id = user_id.unwrap_or_else(|| id.unwrap_or("sodix").to_string());
+    let certificate = openpgp::certificate(&sk, &user_id)?;
+    let dst = out.join("sign_public.asc");
+    check_overwrite(&dst, force)?;
+    fs::create_dir_all(out).map_err(|e| format!("Failed to create directory {}: {}", out.display(), e))?;
+    fs::write(&dst, certificate).map_err(|e| format!("Failed to write {}: {}", dst.display(), e))?;
+    if verbose {
+        eprintln!("OpenPGP fingerprint: {}", openpgp::fingerprint(sk[32..].try_into().unwrap())?);
+    }
+    println!("Exported the OpenPGP certificate for '{}' to {}", user_id, dst.display());
+    Ok(())
+}
+
+/// `key import` of PKCS#8 or JWK keys, each as (algorithm, secret, key): written as hex
+/// key files to the default key directory, with the public key of a secret key alongside.
+#[cfg(feature = "full")]
+fn import_key_files(keys: &[(pkcs8::Algorithm, bool, Vec<u8>)], force: bool, verbose: bool) -> Result<(), String> {
+    let mut files: Vec<(String, String)> = Vec::new();
+    for (algorithm, secret, key) in keys {
+        let prefix = match algorithm {
+            pkcs8::Algorithm::Ed25519 => "sign",
+            pkcs8::Algorithm::X25519 => "enc",
+        };
+        let mut add = |name: String, contents: String| {
+            match files.iter().find(|(n, _)| *n == name) {
+                Some((_, c)) if *c != contents => Err(format!("More than one key would be imported as {}", name)),
+                Some(_) => Ok(()),
+                None => {
+                    files.push((name, contents));
+                    Ok(())
+                }
+            }
+        };
+        if *secret {
+            let public_key = match algorithm {
+                pkcs8::Algorithm::Ed25519 => key[32..].to_vec(),
+                pkcs8::Algorithm::X25519 => {
+                    let mut public_key = [0u8; 32];
+                    crypto_scalarmult_base(&mut public_key, key.as_slice().try_into().unwrap());
+                    public_key.to_vec()
+                }
+            };
+            add(format!("{}_secret.key", prefix), hex::encode(key))?;
+            add(format!("{}_public.key", prefix), hex::encode(public_key))?;
+        } else {
+            add(format!("{}_public.key", prefix), hex::encode(key))?;
+        }
+    }
+    if files.is_empty() {
+        return Err("No keys to import".to_string());
+    }
+    let dir = get_default_key_dir();
+    for (name, contents) in &files {
+        let dst = dir.join(name);
+        match fs::read_to_string(&dst) {
+            Ok(existing) if existing.trim() == contents => {}
+            Ok(_) if !force => {
+                return Err(format!("{} already exists with a different key; use --force to replace it", dst.display()))
+            }
+            _ => {}
+        }
+    }
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+    for (name, contents) in &files {
+        let dst = dir.join(name);
+        let written = if name.ends_with("_secret.key") { keyperms::write_secret(&dst, contents) } else { fs::write(&dst, contents) };
+        written.map_err(|e| format!("Failed to write {}: {}", dst.display(), e))?;
+        if verbose {
+            println!("{}", dst.display());
+        }
+    }
+    println!("Imported {} into {}", files.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(", "), dir.display());
+    Ok(())
+}
+
+#[cfg(feature = "full")]
+fn copy_recursive(src: &Path, dst: &Path) -> Result<(), String> {
+    if !src.is_dir() {
+        fs::copy(src, dst).map_err(|e| format!("Failed to copy {} to {}: {}", src.display(), dst.display(), e))?;
+        return Ok(());
+    }
+    fs::create_dir_all(dst).map_err(|e| format!("Failed to create directory {}: {}", dst.display(), e))?;
+    for entry in fs::read_dir(src).map_err(|e| format!("Failed to read directory {}: {}", src.display(), e))? {
+        let entry = entry.map_err(|e| format!("Failed to read directory {}: {}", src.display(), e))?;
+        copy_recursive(&entry.path(), &dst.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "full")]
+fn change_passphrase(path: &Path, remove: bool, verbose: bool) -> Result<(), String> {
+    let text = fs::read_to_string(path)
+        .map(Zeroizing::new)
+        .map_err(|e| format!("Failed to read key from {}: {}", path.display(), e))?;
+    let encrypted = keyfile::is_encrypted(&text);
+    let secret_key = if encrypted {
+        keyfile::unlock(&text, path)?
+    } else {
+        Zeroizing::new(hex::decode(text.trim()).map_err(|e| format!("Invalid hex in key file {}: {}", path.display(), e))?)
+    };
+    if secret_key.len() != 32 && secret_key.len() != 64 {
+        return Err(format!("{} is not a sodix secret key", path.display()));
+    }
+    if remove && !encrypted {
+        return Err(format!("{} is not passphrase-protected", path.display()));
+    }
+    let passphrase = if remove { None } else { Some(keyfile::new_passphrase()?) };
+
+    // Replace the file only once the new contents are fully written
+    let tmp_path = path.with_extension("key.tmp");
+    keyperms::write_secret(&tmp_path, secret_key_file(&secret_key, passphrase.as_deref().map(String::as_str))?)
+        .map_err(|e| format!("Failed to write key to {}: {}", tmp_path.display(), e))?;
+    fs::rename(&tmp_path, path)
+        .map_err(|e| format!("Failed to replace key {}: {}", path.display(), e))?;
+    if verbose {
+        println!(
+            "{} {}",
+            if remove { "Removed the passphrase from" } else { "Set a new passphrase on" },
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "full")]
+fn load_cert(path: &Path) -> Result<DelegationCert, String> {
+    let cert_hex = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read certificate from {}: {}", path.display(), e))?;
+    let cert_bytes = hex::decode(cert_hex.trim())
+        .map_err(|e| format!("Invalid hex in certificate file {}: {}", path.display(), e))?;
+    DelegationCert::from_bytes(&cert_bytes)
+}
+
+/// Verify a bare signature or an envelope against `pk`. For envelopes carrying a
+/// delegation certificate, `pk` is the trusted root and the certificate's subkey signs.
+fn verify_signature(sig: &[u8], data: &[u8], pk: &[u8; 32]) -> Result<(), String> {
+    if Envelope::is_envelope(sig) {
+        let env = Envelope::decode(sig)?;
+        let signer = envelope_signer(&env, pk)?;
+        env.verify_attributes(&signer)?;
+        if env.prehashed {
+            let mut state = Ed25519ph::new();
+            state.update(data);
+            return state.verify(&env.signature, &signer);
+        }
+        crypto_sign_verify_detached(&env.signature, data, &signer).map_err(|e| e.to_string())
+    } else {
+        let sig: &[u8; 64] = sig.try_into().map_err(|_| "Signature must be 64 bytes")?;
+        crypto_sign_verify_detached(sig, data, pk).map_err(|e| e.to_string())
+    }
+}
+
+/// [`verify_signature`] for the contents of `path`. Prehashed signatures are checked
+/// while streaming the file; others need the whole file in memory.
+fn verify_file_signature(sig: &[u8], path: &Path, pk: &[u8; 32]) -> Result<(), String> {
+    if Envelope::is_envelope(sig) {
+        let env = Envelope::decode(sig)?;
+        if env.prehashed {
+            let signer = envelope_signer(&env, pk)?;
+            env.verify_attributes(&signer)?;
+            let mut state = Ed25519ph::new();
+            read_chunks(path, |chunk| state.update(chunk))?;
+            return state.verify(&env.signature, &signer);
+        }
+    }
+    let data = mmap::read(path).map_err(|e| format!("Failed to read input file {}: {}", path.display(), e))?;
+    verify_signature(sig, &data, pk)
+}
+
+/// The key that made an envelope's signature: `pk` itself, or the subkey its
+/// delegation certificate (checked against `pk`) vouches for.
+fn envelope_signer(env: &Envelope, pk: &[u8; 32]) -> Result<[u8; 32], String> {
+    match &env.cert {
+        Some(cert) => {
+            cert.verify(pk, unix_now())?;
+            Ok(cert.subkey_public_key)
+        }
+        None => Ok(*pk),
+    }
+}
+
+/// Enforce `check --max-age` and `--purpose` against a verified signature's signed
+/// metadata. Signatures from the future are allowed a few minutes of clock skew.
+fn check_signed_metadata(sig: &[u8], max_age: Option<Duration>, purpose: Option<&str>) -> Result<(), String> {
+    if max_age.is_none() && purpose.is_none() {
+        return Ok(());
+    }
+    let env = if Envelope::is_envelope(sig) { Some(Envelope::decode(sig)?) } else { None };
+    if let Some(max_age) = max_age {
+        let timestamp = env.as_ref().and_then(|env| env.timestamp).ok_or("Signature has no signed timestamp")?;
+        let now = unix_now();
+        if timestamp > now + 300 {
+            return Err("Signature timestamp is in the future".to_string());
+        }
+        if now.saturating_sub(timestamp) > max_age.as_secs() {
+            return Err(format!(
+                "Signature is older than {} (signed {})",
+                humantime::format_duration(max_age),
+                humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(timestamp))
+            ));
+        }
+    }
+    if let Some(purpose) = purpose {
+        match env.as_ref().and_then(|env| env.purpose.as_deref()) {
+            Some(signed) if signed == purpose => {}
+            Some(signed) => return Err(format!("Signature is for purpose \"{}\", not \"{}\"", signed, purpose)),
+            None => return Err("Signature has no signed purpose".to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Reject a signature by a key known to have expired, unless its signed timestamp shows
+/// it was made before the expiry.
+fn check_key_expiry(timestamp: Option<u64>, expires: Option<u64>) -> Result<(), String> {
+    match expires {
+        Some(expires) if expires <= unix_now() && timestamp.is_none_or(|t| t >= expires) => Err(format!(
+            "Signing key expired on {}; pass --ignore-expiry to accept its signatures",
+            keymeta::format_time(expires)
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn signed_timestamp(sig: &[u8]) -> Option<u64> {
+    if !Envelope::is_envelope(sig) {
+        return None;
+    }
+    Envelope::decode(sig).ok().and_then(|env| env.timestamp)
+}
+
+/// Keys that need no certification, and those whose certifications are accepted.
+struct Vouchers {
+    /// Your own signing and encryption public keys
+    own: Vec<[u8; 32]>,
+    /// Your signing key and the keys in introducers/
+    introducers: Vec<[u8; 32]>,
+}
+
+fn load_vouchers(verbose: bool) -> Result<Vouchers, String> {
+    let mut own: Vec<[u8; 32]> = Vec::new();
+    let mut introducers = Vec::new();
+    let sign_public_path = get_default_key_path("sign_public");
+    if key_exists(&sign_public_path) {
+        let pk = load_key(&sign_public_path, 32)?.as_slice().try_into().unwrap();
+        own.push(pk);
+        introducers.push(pk);
+    }
+    let enc_public_path = get_default_key_path("enc_public");
+    if key_exists(&enc_public_path) {
+        own.push(load_key(&enc_public_path, 32)?.as_slice().try_into().unwrap());
+    }
+    let introducers_dir = default_key_file("introducers");
+    if introducers_dir.is_dir() {
+        introducers.extend(load_public_keys(&introducers_dir.to_string_lossy(), verbose)?.into_iter().map(|(pk, _)| pk));
+    }
+    Ok(Vouchers { own, introducers })
+}
+
+/// Fail unless every key is your own or has a certification in certifications/ by you
+/// or one of the keys in introducers/.
+fn check_certified(keys: &[[u8; 32]], verbose: bool) -> Result<(), String> {
+    let Vouchers { own, introducers } = load_vouchers(verbose)?;
+    let certifications_dir = default_key_file("certifications");
+    for key in keys.iter().filter(|k| !own.contains(k)) {
+        match certification::find(&certifications_dir, key, &introducers)? {
+            Some(cert) if verbose => eprintln!(
+                "{} is certified as \"{}\" by {} ({})",
+                fingerprint(key, FingerprintAlg::default()),
+                cert.name,
+                fingerprint(&cert.certifier, FingerprintAlg::default()),
+                keymeta::format_time(cert.certified)
+            ),
+            Some(_) => {}
+            None => {
+                return Err(format!(
+                    "{} is not certified by you or an introducer; certify it with `sodix key certify`",
+                    fingerprint(key, FingerprintAlg::default())
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check `key` against the key remembered for `name`, remembering it if there is none.
+fn trust_on_first_use(name: &str, key: &[u8; 32]) -> Result<(), String> {
+    let mut store = trust::TrustStore::load(&default_key_file(trust::FILE_NAME))?;
+    match store.get(name) {
+        Some(known) if known.key == *key => Ok(()),
+        Some(known) => {
+            eprintln!("WARNING: THE SIGNING KEY FOR \"{}\" HAS CHANGED", name);
+            eprintln!("  known since {}: {}", keymeta::format_time(known.first_seen), fingerprint(&known.key, FingerprintAlg::default()));
+            eprintln!("  signed by now:  {}", fingerprint(key, FingerprintAlg::default()));
+            Err(format!(
+                "Signature for \"{}\" was made by a different key than before; if the key was rotated on purpose, run `sodix trust remove {}`",
+                name, name
+            ))
+        }
+        None => {
+            store.insert(name, *key, unix_now())?;
+            store.save()?;
+            eprintln!("Trusting {} for \"{}\" on first use", fingerprint(key, FingerprintAlg::default()), name);
+            Ok(())
+        }
+    }
+}
+
+/// Directories whose keys.meta describes a key given as `key_input`: the default key
+/// directory, and the key's own directory when it is a file or directory.
+fn key_meta_dirs(key_input: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![default_key_file(keymeta::FILE_NAME).parent().unwrap().to_path_buf()];
+    let path = Path::new(key_input);
+    if path.is_dir() {
+        dirs.push(path.to_path_buf());
+    } else if let Some(parent) = path.parent().filter(|_| path.is_file()) {
+        dirs.push(parent.to_path_buf());
+    }
+    dirs
+}
+
+/// Signing public keys named by a `check --key` value: a hex key or key file, or every
+/// 32-byte key file in a directory (other files, like secret keys, are skipped).
+/// Each key comes with the name to report it by.
+fn load_public_keys(key_input: &str, verbose: bool) -> Result<Vec<([u8; 32], String)>, String> {
+    let dir = Path::new(key_input);
+    if !dir.is_dir() {
+        let pk = load_signing_public_key(key_input)?;
+        return Ok(vec![(pk.as_slice().try_into().unwrap(), key_input.to_string())]);
+    }
+    let mut paths = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read key directory {}: {}", dir.display(), e))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to read key directory {}: {}", dir.display(), e))?;
+    paths.sort();
+    let mut keys = Vec::new();
+    for path in paths.into_iter().filter(|p| p.is_file()) {
+        match load_key(&path, 32) {
+            Ok(pk) => keys.push((pk.as_slice().try_into().unwrap(), path.display().to_string())),
+            Err(e) if verbose => eprintln!("Skipping non-key file ({})", e),
+            Err(_) => {}
+        }
+    }
+    Ok(keys)
+}
+
+/// Pair signatures with the keys that made them, using each key and signature at most
+/// once, so duplicates can't inflate a `--require` count. Returns (key, signature)
+/// index pairs and the last verification error.
+fn match_signatures(
+    sigs: &[Vec<u8>],
+    input: &str,
+    file: bool,
+    keys: &[[u8; 32]],
+    expiries: &[Option<u64>],
+    max_age: Option<Duration>,
+    purpose: Option<&str>,
+) -> (Vec<(usize, usize)>, Option<String>) {
+    let mut used = vec![false; sigs.len()];
+    let mut matches = Vec::new();
+    let mut error = None;
+    for (key_index, pk) in keys.iter().enumerate() {
+        for (sig_index, sig) in sigs.iter().enumerate() {
+            if used[sig_index] {
+                continue;
+            }
+            let result = if file {
+                verify_file_signature(sig, Path::new(input), pk)
+            } else {
+                verify_signature(sig, input.as_bytes(), pk)
+            };
+            let result = result
+                .and_then(|_| check_key_expiry(signed_timestamp(sig), expiries[key_index]))
+                .and_then(|_| check_signed_metadata(sig, max_age, purpose));
+            match result {
+                Ok(()) => {
+                    used[sig_index] = true;
+                    matches.push((key_index, sig_index));
+                    break;
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+    }
+    (matches, error)
+}
+
+/// Print the signed metadata and comments of a verified envelope (`check -v`).
+fn print_envelope_details(sig: &[u8]) -> Result<(), String> {
+    if !Envelope::is_envelope(sig) {
+        return Ok(());
+    }
+    let env = Envelope::decode(sig)?;
+    if let Some(timestamp) = env.timestamp {
+        println!("Signed at: {}", humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(timestamp)));
+    }
+    if let Some(signer) = env.signer {
+        println!("Signer: {}", signer);
+    }
+    if let Some(purpose) = env.purpose {
+        println!("Purpose: {}", purpose);
+    }
+    if let Some(trusted_comment) = env.trusted_comment {
+        println!("Trusted comment: {}", trusted_comment);
+    }
+    if let Some(comment) = env.comment {
+        println!("Comment (unverified): {}", comment);
+    }
+    Ok(())
+}
+
+/// Feed the contents of `path` to `f`.
+fn read_chunks(path: &Path, f: impl FnMut(&[u8])) -> Result<(), String> {
+    mmap::read_chunks(path, f).map_err(|e| format!("Failed to read input file {}: {}", path.display(), e))
+}
+
+// Files from this size on (or half the --max-memory budget) stay on the streaming
+// paths: sign hashes them (--prehash) and encrypt only writes them to a chunked
+// container, instead of holding a message that may be larger than memory, or than a
+// 32-bit address space, in one piece.
+#[cfg(feature = "full")]
+const LARGE_FILE: u64 = 4 << 30;
+
+/// Whether the input file `path` is too large to handle in memory.
+#[cfg(feature = "full")]
+fn is_large_file(path: &str) -> Result<bool, String> {
+    let metadata = fs::metadata(path).map_err(|e| format!("Failed to read input file {}: {}", path, e))?;
+    Ok(metadata.len() >= memory::large_file(LARGE_FILE))
+}
+
+/// Where `sign` gets its Ed25519 signatures: a secret key, or ssh-agent (--use-agent).
+#[cfg(feature = "full")]
+enum Signer {
+    /// The 64-byte secret key as loaded, kept in its locked, wiped-on-drop buffer
+    Key(Zeroizing<Vec<u8>>),
+    Agent(agent::Agent, [u8; 32]),
+}
+
+#[cfg(feature = "full")]
+impl Signer {
+    fn public_key(&self) -> [u8; 32] {
+        match self {
+            Signer::Key(sk) => sk[32..].try_into().unwrap(),
+            Signer::Agent(_, public_key) => *public_key,
+        }
+    }
+
+    fn sign(&mut self, data: &[u8]) -> Result<[u8; 64], String> {
+        match self {
+            Signer::Key(sk) => {
+                let mut signature = [0u8; 64];
+                crypto_sign_detached(&mut signature, data, sk.as_slice().try_into().unwrap()).map_err(|e| format!("Error signing data: {}", e))?;
+                Ok(signature)
+            }
+            Signer::Agent(agent, public_key) => agent.sign(public_key, data),
+        }
+    }
+}
+
+/// The ssh-agent key to sign with: the one whose public key `key` (hex, bech32, did:key
+/// or key file) gives, or else the agent's only Ed25519 key.
+#[cfg(feature = "full")]
+fn agent_signing_key(agent: &mut agent::Agent, key: Option<&str>, verbose: bool) -> Result<[u8; 32], String> {
+    let keys = agent.keys()?;
+    let (public_key, comment) = match key {
+        Some(key) => {
+            let wanted = if Path::new(key).is_file() {
+                load_key(Path::new(key), 32)?.to_vec()
+            } else if is_text_key(key) {
+                decode_text_key(key, Some(KeyKind::Signing))?.to_vec()
+            } else {
+                hex::decode(key.trim()).map_err(|_| format!("With --use-agent, --key is a public key (hex or file), not '{}'", key))?
+            };
+            keys.into_iter()
+                .find(|(pk, _)| pk[..] == wanted[..])
+                .ok_or_else(|| format!("ssh-agent does not hold the key {}; add it with ssh-add", hex::encode(&wanted)))?
+        }
+        None => match <[_; 1]>::try_from(keys) {
+            Ok([only]) => only,
+            Err(keys) if keys.is_empty() => return Err("ssh-agent holds no Ed25519 keys; add one with ssh-add".to_string()),
+            Err(keys) => {
+                let listed: Vec<String> = keys.iter().map(|(pk, comment)| format!("{} ({})", hex::encode(pk), comment)).collect();
+                return Err(format!("ssh-agent holds several Ed25519 keys; pick one with -k: {}", listed.join(", ")));
+            }
+        },
+    };
+    if verbose {
+        eprintln!("Signing with ssh-agent key {} ({})", sshsig::fingerprint(&public_key), comment);
+    }
+    Ok(public_key)
+}
+
+#[cfg(feature = "full")]
+/// Sign with crypto_sign: the output is the 64-byte signature followed by the message.
+fn sign_attached(input: &str, file: bool, signer: &mut Signer, force: bool, verbose: bool) -> Result<(), String> {
+    let data = if file {
+        mmap::read(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?
+    } else {
+        input.as_bytes().to_vec().into()
+    };
+    let mut signed = signer.sign(&data)?.to_vec();
+    signed.extend_from_slice(&data);
+    if file {
+        let output_file = format!("{}.signed", input);
+        check_overwrite(Path::new(&output_file), force)?;
+        cleanup::write_atomic(&output_file, &signed)
+            .map_err(|e| format!("Failed to write signed file {}: {}", output_file, e))?;
+        if verbose {
+            println!("Signed file saved to: {}", output_file);
+        }
+    } else {
+        println!("{}", hex::encode(signed));
+    }
+    Ok(())
+}
+
+/// Verify a crypto_sign message (hex, or the file `<input>.signed` with -f) and write
+/// the original message to stdout. Nothing is written unless the signature is valid.
+fn check_attached(input: &str, file: bool, pk: &[u8; 32]) -> Result<(), String> {
+    let signed = if file {
+        let signed_file = if input.ends_with(".signed") { input.to_string() } else { format!("{}.signed", input) };
+        fs::read(&signed_file).map_err(|e| format!("Failed to read signed file {}: {}", signed_file, e))?
+    } else {
+        hex::decode(input.trim()).map_err(|e| format!("Invalid hex signed message: {}", e))?
+    };
+    if signed.len() < 64 {
+        return Err("Signed message is shorter than a signature".to_string());
+    }
+    let mut message = vec![0u8; signed.len() - 64];
+    crypto_sign_open(&mut message, &signed, pk).map_err(|e| format!("Signature verification failed: {}", e))?;
+    let mut stdout = io::stdout();
+    stdout.write_all(&message).map_err(|e| format!("Failed to write output: {}", e))?;
+    if !file {
+        writeln!(stdout).map_err(|e| format!("Failed to write output: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "full")]
+#[allow(clippy::too_many_arguments)]
+fn sign_minisign(input: &str, file: bool, key: Option<String>, sig_path: Option<PathBuf>, attached: bool, comment: Option<String>, trusted_comment: Option<String>, force: bool, verbose: bool) -> Result<(), String> {
+    if attached {
+        return Err("minisign has no attached signatures".to_string());
+    }
+    let key_path = key.map(PathBuf::from).unwrap_or_else(|| get_default_key_path("minisign"));
+    let key_text = fs::read_to_string(&key_path)
+        .map_err(|e| format!("Failed to read minisign secret key {}: {}", key_path.display(), e))?;
+    let sk = minisign::parse_secret_key(&key_text)?;
+    let comment = comment.unwrap_or_else(|| "signature from sodix secret key".to_string());
+    let mut trusted = trusted_comment.unwrap_or_else(|| format!("timestamp:{}", unix_now()));
+    let minisig = if file {
+        if !trusted.contains('\t') {
+            trusted.push_str(&format!("\tfile:{}\thashed", Path::new(input).file_name().unwrap_or_default().to_string_lossy()));
+        }
+        let reader = File::open(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?;
+        minisign::sign(io::BufReader::new(reader), &sk, &comment, &trusted)?
+    } else {
+        minisign::sign(input.as_bytes(), &sk, &comment, &trusted)?
+    };
+    write_signature_file(sig_path.or_else(|| file.then(|| PathBuf::from(format!("{}.minisig", input)))), minisig.as_bytes(), force, verbose)
+}
+
+#[cfg(feature = "full")]
+#[allow(clippy::too_many_arguments)]
+fn sign_signify(input: &str, file: bool, key: Option<String>, sig_path: Option<PathBuf>, attached: bool, comment: Option<String>, trusted_comment: Option<String>, force: bool, verbose: bool) -> Result<(), String> {
+    if trusted_comment.is_some() {
+        return Err("signify signatures have no trusted comment".to_string());
+    }
+    let key_path = key.map(PathBuf::from).unwrap_or_else(|| default_key_file("signify.sec"));
+    let key_text = fs::read_to_string(&key_path)
+        .map_err(|e| format!("Failed to read signify secret key {}: {}", key_path.display(), e))?;
+    let sk = signify::parse_secret_key(&key_text)?;
+    // Same default as signify: name the public key to check with
+    let comment = comment.unwrap_or_else(|| {
+        format!("verify with {}", key_path.with_extension("pub").file_name().unwrap_or_default().to_string_lossy())
+    });
+    let data = if file {
+        mmap::read(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?
+    } else {
+        input.as_bytes().to_vec().into()
+    };
+    let signature = if attached {
+        signify::sign_embedded(&data, &sk, &comment)?
+    } else {
+        signify::sign(&data, &sk, &comment)?.into_bytes()
+    };
+    write_signature_file(sig_path.or_else(|| file.then(|| PathBuf::from(format!("{}.sig", input)))), &signature, force, verbose)
+}
+
+#[cfg(feature = "full")]
+fn sign_sshsig(input: &str, file: bool, key: Option<String>, sig_path: Option<PathBuf>, namespace: &str, force: bool, verbose: bool) -> Result<(), String> {
+    let key_path = match key {
+        Some(key) => PathBuf::from(key),
+        None => std::env::var_os("HOME")
+            .map(|home| Path::new(&home).join(".ssh").join("id_ed25519"))
+            .ok_or("No --key given and HOME is not set")?,
+    };
+    let key_text = fs::read_to_string(&key_path)
+        .map_err(|e| format!("Failed to read SSH private key {}: {}", key_path.display(), e))?;
+    let sk = sshsig::parse_secret_key(&key_text, &key_path)?;
+    let signature = if file {
+        let reader = File::open(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?;
+        sshsig::sign(io::BufReader::new(reader), &sk, namespace)?
+    } else {
+        sshsig::sign(input.as_bytes(), &sk, namespace)?
+    };
+    write_signature_file(sig_path.or_else(|| file.then(|| PathBuf::from(format!("{}.sig", input)))), signature.as_bytes(), force, verbose)
+}
+
+/// `run --key`: read the program once, verify those bytes, then execute a private copy
+/// of exactly them, so the file can't be swapped between the check and the exec.
+/// Exits with the program's status.
+#[cfg(feature = "full")]
+fn run_verified(program: &Path, signature: Option<String>, key: &str, args: &[String], verbose: bool) -> Result<(), String> {
+    let pk = load_signing_public_key(key)?;
+    let data = fs::read(program).map_err(|e| format!("Failed to read {}: {}", program.display(), e))?;
+    let signature = signature.unwrap_or_else(|| format!("{}.sig", program.display()));
+    let sig_hex = if Path::new(&signature).is_file() {
+        fs::read_to_string(&signature).map_err(|e| format!("Failed to read signature from {}: {}", signature, e))?
+    } else {
+        signature
+    };
+    let sig = if armor::is_armored(&sig_hex) {
+        armor::dearmor_one(&sig_hex, armor::SIGNATURE)?
+    } else {
+        hex::decode(sig_hex.trim()).map_err(|e| format!("Invalid hex signature: {}", e))?
+    };
+    verify_signature(&sig, &data, pk.as_slice().try_into().unwrap())
+        .map_err(|e| format!("Refusing to run {}: signature verification failed: {}", program.display(), e))?;
+
+    let name = program.file_name().unwrap_or_default().to_string_lossy();
+    let copy_path = std::env::temp_dir().join(format!("sodix-run-{}-{}", hex::encode(rand::rng().random::<[u8; 8]>()), name));
+    let _partial = cleanup::PartialFile::new(&copy_path);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o700);
+    let written = options.open(&copy_path).and_then(|mut f| f.write_all(&data));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&copy_path);
+        return Err(format!("Failed to write {}: {}", copy_path.display(), e));
+    }
+    if verbose {
+        eprintln!("Signature valid, running {}", program.display());
+    }
+    let status = std::process::Command::new(&copy_path).args(args).status();
+    let _ = fs::remove_file(&copy_path);
+    let status = status.map_err(|e| format!("Failed to run {}: {}", program.display(), e))?;
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        std::process::exit(128 + signal);
+    }
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+#[cfg(feature = "full")]
+fn sign_cose(input: &str, file: bool, key: Option<String>, sig_path: Option<PathBuf>, attached: bool, force: bool, verbose: bool) -> Result<(), String> {
+    let secret_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+    let sk = load_signing_key(&secret_key_input, true)?;
+    let data = if file {
+        mmap::read(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?
+    } else {
+        input.as_bytes().to_vec().into()
+    };
+    let signature = cose::sign(&data, sk.as_slice().try_into().unwrap(), attached)?;
+    match sig_path.or_else(|| file.then(|| PathBuf::from(format!("{}.cose", input)))) {
+        Some(sig_path) => write_signature_file(Some(sig_path), &signature, force, verbose),
+        None => {
+            println!("{}", hex::encode(signature));
+            Ok(())
+        }
+    }
+}
+
+/// `sign --format saltpack`: an armored detached signature, or with `attached` a signed
+/// message; files get <input>.saltpack.sig or <input>.saltpack.
+#[cfg(feature = "full")]
+fn sign_saltpack(input: &str, file: bool, key: Option<String>, sig_path: Option<PathBuf>, attached: bool, force: bool, verbose: bool) -> Result<(), String> {
+    let secret_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+    let sk = load_signing_key(&secret_key_input, true)?;
+    let data = if file {
+        mmap::read(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?
+    } else {
+        input.as_bytes().to_vec().into()
+    };
+    let signature = saltpack::sign(&data, sk.as_slice().try_into().unwrap(), attached)?;
+    let extension = if attached { "saltpack" } else { "saltpack.sig" };
+    match sig_path.or_else(|| file.then(|| PathBuf::from(format!("{}.{}", input, extension)))) {
+        Some(sig_path) => write_signature_file(Some(sig_path), signature.as_bytes(), force, verbose),
+        None => {
+            print!("{}", signature);
+            Ok(())
+        }
+    }
+}
+
+/// `sign --format openpgp`: an armored detached signature, <input>.asc for files.
+#[cfg(all(feature = "full", feature = "openpgp"))]
+fn sign_openpgp(input: &str, file: bool, key: Option<String>, sig_path: Option<PathBuf>, force: bool, verbose: bool) -> Result<(), String> {
+    let secret_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+    let sk = load_signing_key(&secret_key_input, true)?;
+    let data = if file {
+        mmap::read(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?
+    } else {
+        input.as_bytes().to_vec().into()
+    };
+    let signature = openpgp::sign(&data, sk.as_slice().try_into().unwrap())?;
+    match sig_path.or_else(|| file.then(|| PathBuf::from(format!("{}.asc", input)))) {
+        Some(sig_path) => write_signature_file(Some(sig_path), signature.as_bytes(), force, verbose),
+        None => {
+            print!("{}", signature);
+            Ok(())
+        }
+    }
+}
+
+/// Write a signature to `sig_path`, or to stdout without one.
+#[cfg(feature = "full")]
+fn write_signature_file(sig_path: Option<PathBuf>, signature: &[u8], force: bool, verbose: bool) -> Result<(), String> {
+    match sig_path {
+        Some(sig_path) => {
+            check_overwrite(&sig_path, force)?;
+            cleanup::write_atomic(&sig_path, signature)
+                .map_err(|e| format!("Failed to write signature to {}: {}", sig_path.display(), e))?;
+            if verbose {
+                println!("Signature saved to: {}", sig_path.display());
+            }
+            Ok(())
+        }
+        None => io::stdout().write_all(signature).map_err(|e| format!("Failed to write output: {}", e)),
+    }
+}
+
+#[cfg(feature = "full")]
+fn generate_minisign_keys(dir: &Path, force: bool, verbose: bool) -> Result<(), String> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+    let public_key_path = dir.join("minisign.pub");
+    let secret_key_path = dir.join("minisign.key");
+    check_overwrite(&secret_key_path, force)?;
+    let (public_key, secret_key) = minisign::generate();
+    fs::write(&public_key_path, public_key)
+        .map_err(|e| format!("Failed to write minisign public key to {}: {}", public_key_path.display(), e))?;
+    keyperms::write_secret(&secret_key_path, secret_key)
+        .map_err(|e| format!("Failed to write minisign secret key to {}: {}", secret_key_path.display(), e))?;
+    if verbose {
+        println!("Generated minisign keys at: {} and {}", public_key_path.display(), secret_key_path.display());
+    } else {
+        println!("Keys generated successfully");
+    }
+    Ok(())
+}
+
+#[cfg(feature = "full")]
+fn generate_signify_keys(dir: &Path, force: bool, verbose: bool) -> Result<(), String> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+    let public_key_path = dir.join("signify.pub");
+    let secret_key_path = dir.join("signify.sec");
+    check_overwrite(&secret_key_path, force)?;
+    let (public_key, secret_key) = signify::generate("signify");
+    fs::write(&public_key_path, public_key)
+        .map_err(|e| format!("Failed to write signify public key to {}: {}", public_key_path.display(), e))?;
+    keyperms::write_secret(&secret_key_path, secret_key)
+        .map_err(|e| format!("Failed to write signify secret key to {}: {}", secret_key_path.display(), e))?;
+    if verbose {
+        println!("Generated signify keys at: {} and {}", public_key_path.display(), secret_key_path.display());
+    } else {
+        println!("Keys generated successfully");
+    }
+    Ok(())
+}
+
+/// Act as git's gpg.program (see gitsign.rs). Signatures are timestamped envelopes;
+/// verification accepts sign_public.key and every key in git_signers/ in the key directory.
+fn git_sign(args: &[String], verbose: bool) -> Result<(), String> {
+    let gpg = gitsign::parse_args(args)?;
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data).map_err(|e| format!("Failed to read input: {}", e))?;
+    let mut status = String::new();
+    let result = match gpg.mode {
+        #[cfg(feature = "full")]
+        gitsign::Mode::Sign { key } => {
+            // Without user.signingkey git passes the committer ident, which is not a key
+            let is_key = |k: &String| Path::new(k).is_file() || hex::decode(k.trim()).is_ok_and(|b| b.len() == 64);
+            let secret_key_input = key
+                .filter(is_key)
+                .unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+            let sk = load_or_generate_signing_key(&secret_key_input, true, verbose)?;
+            let mut signature = [0u8; 64];
+            crypto_sign_detached(&mut signature, &data, sk.as_slice().try_into().unwrap())
+                .map_err(|e| format!("Error signing data: {}", e))?;
+            let now = unix_now();
+            let mut env = Envelope::new(signature);
+            env.timestamp = Some(now);
+            env.signer = Some(fingerprint(&sk[32..], FingerprintAlg::default()));
+            env.sign_attributes(sk.as_slice().try_into().unwrap())?;
+            print!("{}", gitsign::armor(&env.encode()));
+            status.push_str(&format!("[GNUPG:] SIG_CREATED D 22 10 00 {} {}\n", now, gitsign::key_ids(&sk[32..]).1));
+            Ok(())
+        }
+        #[cfg(not(feature = "full"))]
+        gitsign::Mode::Sign { key } => Err(format!(
+            "Cannot sign with {}: this sodix was built without signing support",
+            key.unwrap_or_else(|| "the default key".to_string())
+        )),
+        gitsign::Mode::Verify { signature_file } => {
+            let text = fs::read_to_string(&signature_file)
+                .map_err(|e| format!("Failed to read signature from {}: {}", signature_file, e))?;
+            let sig = gitsign::dearmor(&text)?;
+            let mut keys = Vec::new();
+            let public_key_path = get_default_key_path("sign_public");
+            if key_exists(&public_key_path) {
+                keys.push((load_key(&public_key_path, 32)?.as_slice().try_into().unwrap(), public_key_path.display().to_string()));
+            }
+            let signers_dir = default_key_file("git_signers");
+            if signers_dir.is_dir() {
+                keys.extend(load_public_keys(&signers_dir.to_string_lossy(), verbose)?);
+            }
+            let env = if Envelope::is_envelope(&sig) { Some(Envelope::decode(&sig)?) } else { None };
+            let timestamp = env.as_ref().and_then(|env| env.timestamp).unwrap_or(0);
+            status.push_str("[GNUPG:] NEWSIG\n");
+            match keys.iter().find(|(pk, _)| verify_signature(&sig, &data, pk).is_ok()) {
+                Some((pk, name)) => {
+                    let (key_id, fpr) = gitsign::key_ids(pk);
+                    let date = humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(timestamp)).to_string();
+                    status.push_str(&format!("[GNUPG:] GOODSIG {} {}\n", key_id, name));
+                    status.push_str(&format!("[GNUPG:] VALIDSIG {} {} {} 0 4 0 22 10 00 {}\n", fpr, &date[..10], timestamp, fpr));
+                    status.push_str("[GNUPG:] TRUST_FULLY 0 pgp\n");
+                    eprintln!("sodix: Good signature from {} ({})", name, fingerprint(pk, FingerprintAlg::default()));
+                    Ok(())
+                }
+                None => {
+                    // A known signer with a failing signature is BAD; otherwise the key is unknown
+                    let claimed = env.as_ref().and_then(|env| env.signer.as_deref());
+                    let known = keys
+                        .iter()
+                        .find(|(pk, _)| claimed.is_some_and(|fp| fingerprint::matches(fp, pk).unwrap_or(false)));
+                    match known {
+                        Some((pk, name)) => status.push_str(&format!("[GNUPG:] BADSIG {} {}\n", gitsign::key_ids(pk).0, name)),
+                        None => {
+                            let key_id = claimed
+                                .and_then(|fp| fp.strip_prefix("blake2b:"))
+                                .and_then(|hex| hex.get(hex.len().saturating_sub(16)..))
+                                .map(str::to_ascii_uppercase)
+                                .unwrap_or_else(|| "0000000000000000".to_string());
+                            status.push_str(&format!("[GNUPG:] ERRSIG {} 22 10 00 {} 9 -\n", key_id, timestamp));
+                            status.push_str(&format!("[GNUPG:] NO_PUBKEY {}\n", key_id));
+                        }
+                    }
+                    eprintln!("sodix: BAD signature or unknown key");
+                    Err("Signature verification failed".to_string())
+                }
+            }
+        }
+    };
+    match gpg.status_fd {
+        Some(1) => print!("{}", status),
+        Some(2) => eprint!("{}", status),
+        Some(fd) => return Err(format!("Unsupported status fd {}; use 1 or 2", fd)),
+        None => {}
+    }
+    result
+}
+
+/// `check --format sshsig`: the key is a .pub, authorized_keys or allowed_signers file,
+/// or an `ssh-ed25519 AAAA...` line; any key in it may have made the signature.
+fn check_sshsig(input: &str, signature: Option<String>, key: Option<String>, file: bool, sig_file: Option<PathBuf>, namespace: &str, verbose: bool) -> Result<(), String> {
+    let key_text = match key {
+        Some(k) if !Path::new(&k).exists() => k,
+        Some(k) => fs::read_to_string(&k).map_err(|e| format!("Failed to read SSH public key {}: {}", k, e))?,
+        None => return Err("sshsig needs --key with the signer's public key (.pub, authorized_keys or allowed_signers file)".to_string()),
+    };
+    let keys = sshsig::parse_public_keys(&key_text)?;
+    let sig_path = match (signature, sig_file) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, Some(path)) => path,
+        (None, None) if file => PathBuf::from(format!("{}.sig", input)),
+        (None, None) => return Err("A .sig signature file is required".to_string()),
+    };
+    let armored = fs::read_to_string(&sig_path)
+        .map_err(|e| format!("Failed to read signature from {}: {}", sig_path.display(), e))?;
+    let result = if file {
+        let reader = File::open(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?;
+        sshsig::verify(io::BufReader::new(reader), &armored, &keys, namespace)
+    } else {
+        sshsig::verify(input.as_bytes(), &armored, &keys, namespace)
+    };
+    match result {
+        Ok(signer) => {
+            print_check_result(true);
+            if verbose {
+                println!("Signer: ED25519 key {}", sshsig::fingerprint(&signer));
+            }
+        }
+        Err(e) => {
+            if verbose {
+                eprintln!("Signature verification failed: {}", e);
+            }
+            print_check_result(false);
+        }
+    }
+    Ok(())
+}
+
+/// `check --format cose`: the key is a sodix signing public key. With `attached`, INPUT
+/// is a COSE_Sign1 with embedded payload (a file with -f, else hex), printed if valid.
+fn check_cose(input: &str, signature: Option<String>, key: Option<String>, file: bool, sig_file: Option<PathBuf>, attached: bool, verbose: bool) -> Result<(), String> {
+    let public_key_input = key.unwrap_or_else(|| get_default_key_path("sign_public").to_string_lossy().into_owned());
+    let pk = load_signing_public_key(&public_key_input)?;
+    let read_cose = |value: &str, from_file: bool| -> Result<Vec<u8>, String> {
+        if from_file {
+            fs::read(value).map_err(|e| format!("Failed to read signature from {}: {}", value, e))
+        } else {
+            hex::decode(value.trim()).map_err(|e| format!("Invalid hex signature: {}", e))
+        }
+    };
+    if attached {
+        let data = read_cose(input, file)?;
+        let sign1 = cose::parse(&data)?;
+        sign1.verify(None, pk.as_slice().try_into().unwrap()).map_err(|e| format!("Signature verification failed: {}", e))?;
+        return io::stdout().write_all(sign1.payload.unwrap_or_default()).map_err(|e| format!("Failed to write output: {}", e));
+    }
+    let data = match (signature, sig_file) {
+        (Some(sig), _) => {
+            let is_file = Path::new(&sig).is_file();
+            read_cose(&sig, is_file)?
+        }
+        (None, Some(path)) => read_cose(&path.to_string_lossy(), true)?,
+        (None, None) if file => read_cose(&format!("{}.cose", input), true)?,
+        (None, None) => return Err("A COSE signature is required".to_string()),
+    };
+    let message = if file {
+        mmap::read(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?
+    } else {
+        input.as_bytes().to_vec().into()
+    };
+    match cose::parse(&data).and_then(|sign1| sign1.verify(Some(&message), pk.as_slice().try_into().unwrap())) {
+        Ok(()) => print_check_result(true),
+        Err(e) => {
+            if verbose {
+                eprintln!("Signature verification failed: {}", e);
+            }
+            print_check_result(false);
+        }
+    }
+    Ok(())
+}
+
+/// `check --format saltpack`: the key is a sodix signing public key. SIGNATURE is a file
+/// or the armored text; with `attached`, INPUT is a signed message (a file with -f)
+/// whose message is printed if valid.
+fn check_saltpack(input: &str, signature: Option<String>, key: Option<String>, file: bool, sig_file: Option<PathBuf>, attached: bool, verbose: bool) -> Result<(), String> {
+    let public_key_input = key.unwrap_or_else(|| get_default_key_path("sign_public").to_string_lossy().into_owned());
+    let pk: [u8; 32] = load_signing_public_key(&public_key_input)?.as_slice().try_into().unwrap();
+    let read_armored = |path: &str| fs::read_to_string(path).map_err(|e| format!("Failed to read signature from {}: {}", path, e));
+    if attached {
+        let armored = if file { read_armored(input)? } else { input.to_string() };
+        let message = saltpack::verify_attached(&armored, &pk).map_err(|e| format!("Signature verification failed: {}", e))?;
+        return io::stdout().write_all(&message).map_err(|e| format!("Failed to write output: {}", e));
+    }
+    let armored = match (signature, sig_file) {
+        (Some(sig), _) if Path::new(&sig).is_file() => read_armored(&sig)?,
+        (Some(sig), _) => sig,
+        (None, Some(path)) => read_armored(&path.to_string_lossy())?,
+        (None, None) if file => read_armored(&format!("{}.saltpack.sig", input))?,
+        (None, None) => return Err("A saltpack signature is required".to_string()),
+    };
+    let message = if file {
+        mmap::read(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?
+    } else {
+        input.as_bytes().to_vec().into()
+    };
+    match saltpack::verify_detached(&armored, &message, &pk) {
+        Ok(()) => print_check_result(true),
+        Err(e) => {
+            if verbose {
+                eprintln!("Signature verification failed: {}", e);
+            }
+            print_check_result(false);
+        }
+    }
+    Ok(())
+}
+
+/// `check --format openpgp`: the key is an OpenPGP certificate (file or armored text) or
+/// a sodix signing public key. SIGNATURE is a file or the armored text, default
+/// <input>.asc or gpg's binary <input>.sig.
+#[cfg(feature = "openpgp")]
+fn check_openpgp(input: &str, signature: Option<String>, key: Option<String>, file: bool, sig_file: Option<PathBuf>, verbose: bool) -> Result<(), String> {
+    let public_key_input = key.unwrap_or_else(|| get_default_key_path("sign_public").to_string_lossy().into_owned());
+    let certificate = if public_key_input.trim_start().starts_with("-----BEGIN PGP") {
+        Some(public_key_input.as_bytes().to_vec())
+    } else {
+        // Sodix key files are hex text; binary OpenPGP packets start with the high bit set
+        fs::read(&public_key_input)
+            .ok()
+            .filter(|data| data.starts_with(b"-----BEGIN PGP") || data.first().is_some_and(|b| b & 0x80 != 0))
+    };
+    let signer = match certificate {
+        Some(data) => openpgp::Signer::Certificates(openpgp::read_certificates(&data)?),
+        None => openpgp::Signer::Key(load_signing_public_key(&public_key_input)?.as_slice().try_into().unwrap()),
+    };
+    let read_signature = |path: &str| fs::read(path).map_err(|e| format!("Failed to read signature from {}: {}", path, e));
+    let signature = match (signature, sig_file) {
+        (Some(sig), _) if Path::new(&sig).is_file() => read_signature(&sig)?,
+        (Some(sig), _) => sig.into_bytes(),
+        (None, Some(path)) => read_signature(&path.to_string_lossy())?,
+        (None, None) if file && !Path::new(&format!("{}.asc", input)).exists() && Path::new(&format!("{}.sig", input)).exists() => {
+            read_signature(&format!("{}.sig", input))?
+        }
+        (None, None) if file => read_signature(&format!("{}.asc", input))?,
+        (None, None) => return Err("An OpenPGP signature is required".to_string()),
+    };
+    let message = if file {
+        mmap::read(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?
+    } else {
+        input.as_bytes().to_vec().into()
+    };
+    match openpgp::verify(&signature, &message, &signer) {
+        Ok(fpr) => {
+            if verbose {
+                eprintln!("Signed by OpenPGP key {}", fpr);
+            }
+            print_check_result(true);
+        }
+        Err(e) => {
+            if verbose {
+                eprintln!("Signature verification failed: {}", e);
+            }
+            print_check_result(false);
+        }
+    }
+    Ok(())
+}
+
+/// `check --format signify`: the key is a .pub file or its base64 line. With
+/// `attached`, INPUT is an embedded signature file whose message is printed if valid.
+fn check_signify(input: &str, signature: Option<String>, key: Option<String>, file: bool, sig_file: Option<PathBuf>, attached: bool, verbose: bool) -> Result<(), String> {
+    let key_text = match key {
+        Some(k) if !Path::new(&k).exists() => k,
+        k => {
+            let key_path = k.map(PathBuf::from).unwrap_or_else(|| default_key_file("signify.pub"));
+            fs::read_to_string(&key_path)
+                .map_err(|e| format!("Failed to read signify public key {}: {}", key_path.display(), e))?
+        }
+    };
+    let pk = signify::parse_public_key(&key_text)?;
+    if attached {
+        let signed = fs::read(input).map_err(|e| format!("Failed to read signature file {}: {}", input, e))?;
+        let message = signify::verify_embedded(&signed, &pk).map_err(|e| format!("Signature verification failed: {}", e))?;
+        return io::stdout().write_all(message).map_err(|e| format!("Failed to write output: {}", e));
+    }
+    let sig_path = match (signature, sig_file) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, Some(path)) => path,
+        (None, None) if file => PathBuf::from(format!("{}.sig", input)),
+        (None, None) => return Err("A .sig signature file is required".to_string()),
+    };
+    let sig = fs::read(&sig_path).map_err(|e| format!("Failed to read signature from {}: {}", sig_path.display(), e))?;
+    let data = if file {
+        mmap::read(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?
+    } else {
+        input.as_bytes().to_vec().into()
+    };
+    match signify::verify(&data, &sig, &pk) {
+        Ok(_) => print_check_result(true),
+        Err(e) => {
+            if verbose {
+                eprintln!("Signature verification failed: {}", e);
+            }
+            print_check_result(false);
+        }
+    }
+    Ok(())
+}
+
+/// `check --format minisign`: the key is a minisign.pub file or its `RW...` line,
+/// the signature a .minisig file.
+fn check_minisign(input: &str, signature: Option<String>, key: Option<String>, file: bool, sig_file: Option<PathBuf>, verbose: bool) -> Result<(), String> {
+    let key_text = match key {
+        Some(k) if !Path::new(&k).exists() => k,
+        k => {
+            let key_path = k.map(PathBuf::from).unwrap_or_else(|| default_key_file("minisign.pub"));
+            fs::read_to_string(&key_path)
+                .map_err(|e| format!("Failed to read minisign public key {}: {}", key_path.display(), e))?
+        }
+    };
+    let pk = minisign::parse_public_key(&key_text)?;
+    let sig_path = match (signature, sig_file) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, Some(path)) => path,
+        (None, None) if file => PathBuf::from(format!("{}.minisig", input)),
+        (None, None) => return Err("A .minisig signature file is required".to_string()),
+    };
+    let minisig = fs::read_to_string(&sig_path)
+        .map_err(|e| format!("Failed to read signature from {}: {}", sig_path.display(), e))?;
+    let result = if file {
+        let reader = File::open(input).map_err(|e| format!("Failed to read input file {}: {}", input, e))?;
+        minisign::verify(io::BufReader::new(reader), &minisig, &pk)
+    } else {
+        minisign::verify(input.as_bytes(), &minisig, &pk)
+    };
+    match result {
+        Ok(trusted_comment) => {
+            print_check_result(true);
+            if verbose {
+                println!("Trusted comment: {}", trusted_comment);
+            }
+        }
+        Err(e) => {
+            if verbose {
+                eprintln!("Signature verification failed: {}", e);
+            }
+            print_check_result(false);
+        }
+    }
+    Ok(())
+}
+
+/// Set when a check prints "invalid", so a check over several files can fail at the end.
+static CHECK_FAILED: AtomicBool = AtomicBool::new(false);
+/// The file being checked when check runs over several files.
+static CHECK_LABEL: Mutex<Option<String>> = Mutex::new(None);
+
+/// Print a check's verdict, followed by the file name when checking several files
+/// (the same lines check --batch prints).
+fn print_check_result(valid: bool) {
+    if !valid {
+        CHECK_FAILED.store(true, Ordering::Relaxed);
+    }
+    let verdict = if valid { "valid" } else { "invalid" };
+    match CHECK_LABEL.lock().ok().and_then(|label| label.clone()) {
+        Some(file) => println!("{} {}", verdict, file),
+        None => println!("{}", verdict),
+    }
+}
+
+fn input_mut(command: &mut Commands) -> Option<&mut String> {
+    match command {
+        #[cfg(feature = "full")]
+        Commands::Sign { input, .. } | Commands::Encrypt { input, .. } => Some(input),
+        Commands::Check { input, .. } | Commands::Decrypt { input, .. } => Some(input),
+        #[cfg(feature = "full")]
+        Commands::Recipients {
+            command: RecipientsCommands::Add { file, .. } | RecipientsCommands::Remove { file, .. } | RecipientsCommands::Rotate { file, .. },
+        } => Some(file),
+        _ => None,
+    }
+}
+
+/// With -f, sign, check, encrypt and decrypt take several paths and glob patterns,
+/// or a list of files from --files-from or -0; recipients takes several paths and
+/// patterns. Returns the files to run the command on one at a time, or None for a
+/// single file.
+fn batch_inputs(command: &mut Commands) -> Result<Option<Vec<String>>, String> {
+    let (more, files_from, null, exclude) = match command {
+        #[cfg(feature = "full")]
+        Commands::Sign { more, files_from, null, exclude, file: true, .. } | Commands::Encrypt { more, files_from, null, exclude, file: true, .. } => {
+            (std::mem::take(more), files_from.take(), *null, Some(std::mem::take(exclude)))
+        }
+        Commands::Decrypt { more, files_from, null, file: true, .. } => (std::mem::take(more), files_from.take(), *null, None::<Vec<String>>),
+        // With --sig-file, SIGNATURE is just another file to check
+        Commands::Check { signature, more, files_from, null, file: true, sig_file: Some(_), .. } => {
+            (signature.take().into_iter().chain(std::mem::take(more)).collect(), files_from.take(), *null, None)
+        }
+        #[cfg(feature = "full")]
+        Commands::Recipients {
+            command: RecipientsCommands::Add { more, .. } | RecipientsCommands::Remove { more, .. } | RecipientsCommands::Rotate { more, .. },
+        } => (std::mem::take(more), None, false, None),
+        _ => return Ok(None),
+    };
+    let listed = files_from.is_some() || null;
+    let input = input_mut(command).unwrap();
+    if more.is_empty() && !listed && !inputs::is_pattern(input) {
+        return Ok(None);
+    }
+    // INPUT is optional with a file list
+    let paths: Vec<String> = Some(input.clone()).filter(|i| !i.is_empty()).into_iter().chain(more).collect();
+    // Sign and encrypt skip ignored pattern matches, say build output under 'src/**'
+    let ignore = match exclude {
+        Some(exclude) => ignore::Ignore::load(Path::new("."), &exclude)?,
+        None => ignore::Ignore::default(),
+    };
+    let mut files = inputs::expand(&paths, &ignore)?;
+    if listed {
+        files.extend(inputs::read_list(files_from.as_deref(), null)?);
+    }
+    let files = inputs::unique(files);
+    if files.is_empty() {
+        return Err("The file list is empty".to_string());
+    }
+    let single_path = "names a single file; pass it without a path for one per input file";
+    match command {
+        #[cfg(feature = "full")]
+        Commands::Sign { sig_file, attached, .. } => match sig_file {
+            Some(Some(_)) => return Err(format!("--sig-file PATH {}", single_path)),
+            // Each signature goes next to its file rather than all of them to stdout
+            None if !*attached => *sig_file = Some(None),
+            _ => {}
+        },
+        Commands::Check { sig_file: Some(Some(_)), .. } => return Err(format!("--sig-file PATH {}", single_path)),
+        #[cfg(feature = "full")]
+        Commands::Encrypt { detached_header: Some(Some(_)), .. } => return Err(format!("--detached-header PATH {}", single_path)),
+        Commands::Decrypt { detached_header: Some(Some(_)), .. } => return Err(format!("--detached-header PATH {}", single_path)),
+        Commands::Decrypt { range, extract, .. } if range.is_some() || extract.is_some() => {
+            return Err("--range and --extract take a single file".to_string());
+        }
+        _ => {}
+    }
+    Ok(Some(files))
+}
+
+/// Run `command` on each file in turn, reporting failures as they happen. Unless
+/// `keep_partial`, what the files write is held back until all of them succeeded and
+/// the run stops at the first failure, leaving everything as it was; otherwise the
+/// other files go on and the run fails at the end if any file failed.
+fn run_batch(command: Commands, files: Vec<String>, keep_partial: bool, verbose: bool) -> Result<(), String> {
+    let check = matches!(command, Commands::Check { .. });
+    let transaction = !check && !keep_partial;
+    if transaction {
+        cleanup::begin();
+    }
+    let mut failed = 0;
+    for file in &files {
+        let mut each = command.clone();
+        *input_mut(&mut each).unwrap() = file.clone();
+        if check {
+            *CHECK_LABEL.lock().unwrap() = Some(file.clone());
+        }
+        CHECK_FAILED.store(false, Ordering::Relaxed);
+        match run(each, verbose) {
+            Ok(()) if CHECK_FAILED.load(Ordering::Relaxed) => failed += 1,
+            Ok(()) => {}
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                if check {
+                    print_check_result(false);
+                }
+                failed += 1;
+                if transaction {
+                    cleanup::roll_back();
+                    return Err(format!("{} failed, so no file was changed; pass --keep-partial to keep the results of the others", file));
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} files failed", failed, files.len()));
+    }
+    cleanup::commit()
+}
+
 fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().collect();
+    if gitsign::is_gpg_invocation(&args) {
+        return git_sign(&args[1..], false);
+    }
     let cli = Cli::parse();
+    cleanup::install();
     let verbose = cli.verbose;
+    if cli.no_mmap {
+        mmap::disable();
+    }
+    if let Some(size) = cli.max_memory {
+        memory::set_limit(split::parse_size(&size)?)?;
+    }
+    if cli.no_mlock {
+        memlock::disable();
+    }
+    if cli.allow_coredumps {
+        memlock::allow_core_dumps();
+    }
+    if cli.insecure_key_perms {
+        keyperms::allow_insecure();
+    }
+    if cli.trust_recipients {
+        container::trust_recipients();
+    }
+    memlock::set_verbose(verbose);
+    if cli.quiet {
+        progress::set_mode(progress::Mode::Quiet);
+    } else if cli.progress_json {
+        progress::set_mode(progress::Mode::Json);
+    }
+    if let Some(path) = cli.identity {
+        let id = identity::set_active(path.clone())?;
+        if verbose {
+            eprintln!("Using identity '{}' (created {}) from {}", id.name, id.created, path.display());
+        }
+    }
 
-    match cli.command {
-        Commands::Sign { input, key, file } => {
-            let secret_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
-            let sk = load_or_generate_signing_key(&secret_key_input, true, verbose)?;
+    let mut command = cli.command;
+    if let Some(files) = batch_inputs(&mut command)? {
+        return run_batch(command, files, cli.keep_partial, verbose);
+    }
+    run(command, verbose)
+}
+
+fn run(command: Commands, verbose: bool) -> Result<(), String> {
+    match command {
+        #[cfg(feature = "full")]
+        Commands::Sign { input, more: _, files_from: _, null: _, exclude: _, key, file, cert, sig_file, attached, prehash, format, namespace, comment, trusted_comment, timestamp, purpose, append, force, id, ignore_expiry, armor, use_agent } => {
+            if format != SignatureFormat::Sodix {
+                if cert.is_some() || prehash || timestamp || purpose.is_some() || append || id.is_some() || armor || use_agent {
+                    return Err("--cert, --prehash, --timestamp, --purpose, --append, --id, --armor and --use-agent only apply to sodix signatures".to_string());
+                }
+                let sig_path = sig_file.flatten();
+                return match format {
+                    SignatureFormat::Minisign => sign_minisign(&input, file, key, sig_path, attached, comment, trusted_comment, force, verbose),
+                    SignatureFormat::Signify => sign_signify(&input, file, key, sig_path, attached, comment, trusted_comment, force, verbose),
+                    SignatureFormat::Cose if comment.is_some() || trusted_comment.is_some() => {
+                        Err("COSE signatures have no comments".to_string())
+                    }
+                    SignatureFormat::Cose => sign_cose(&input, file, key, sig_path, attached, force, verbose),
+                    SignatureFormat::Saltpack if comment.is_some() || trusted_comment.is_some() => {
+                        Err("saltpack signatures have no comments".to_string())
+                    }
+                    SignatureFormat::Saltpack => sign_saltpack(&input, file, key, sig_path, attached, force, verbose),
+                    #[cfg(feature = "openpgp")]
+                    SignatureFormat::Openpgp if attached || comment.is_some() || trusted_comment.is_some() => {
+                        Err("OpenPGP signatures are detached only and have no comments".to_string())
+                    }
+                    #[cfg(feature = "openpgp")]
+                    SignatureFormat::Openpgp => sign_openpgp(&input, file, key, sig_path, force, verbose),
+                    _ if attached || comment.is_some() || trusted_comment.is_some() => {
+                        Err("SSH signatures have no attached mode or comments".to_string())
+                    }
+                    _ => sign_sshsig(&input, file, key, sig_path, &namespace, force, verbose),
+                };
+            }
+            let mut signer = if use_agent {
+                let mut ssh_agent = agent::Agent::connect()?;
+                let public_key = agent_signing_key(&mut ssh_agent, key.as_deref(), verbose)?;
+                Signer::Agent(ssh_agent, public_key)
+            } else {
+                let secret_key_input = match &key {
+                    Some(key) => key.clone(),
+                    None => identity_key_path(id.as_deref(), "sign_secret")?.to_string_lossy().into_owned(),
+                };
+                let sk = load_or_generate_signing_key(&secret_key_input, true, verbose)?;
+                Signer::Key(sk)
+            };
+            let public_key = signer.public_key();
+            let meta_input = key.unwrap_or_else(|| get_default_key_path("sign_public").to_string_lossy().into_owned());
+            let meta = keymeta::KeyMeta::load(&key_meta_dirs(&meta_input))?;
+            if let Some(expires) = meta.expires(&public_key).filter(|&expires| expires <= unix_now()) {
+                if !ignore_expiry {
+                    return Err(format!("Signing key expired on {}; pass --ignore-expiry to sign anyway", keymeta::format_time(expires)));
+                }
+                eprintln!("Warning: signing with a key that expired on {}", keymeta::format_time(expires));
+            }
+            let timestamp = timestamp || purpose.is_some();
+            if attached {
+                if comment.is_some() || trusted_comment.is_some() || timestamp {
+                    return Err("Comments and signed metadata need a detached signature; drop --attached".to_string());
+                }
+                return sign_attached(&input, file, &mut signer, force, verbose);
+            }
+            if comment.iter().chain(&trusted_comment).chain(&purpose).any(|c| c.len() > u16::MAX as usize) {
+                return Err("Comments and purpose must be shorter than 64 KiB".to_string());
+            }
+            let sig_path = sig_file.map(|path| path.unwrap_or_else(|| PathBuf::from(format!("{}.sig", input))));
+            let mut prehash = prehash;
+            if !prehash && file && matches!(signer, Signer::Key(_)) && is_large_file(&input)? {
+                if verbose {
+                    eprintln!("{} is too large to sign in memory; signing its SHA-512 (--prehash)", input);
+                }
+                prehash = true;
+            }
+            let signature = match &signer {
+                Signer::Key(sk) if prehash => {
+                    let mut state = Ed25519ph::new();
+                    if file {
+                        read_chunks(Path::new(&input), |chunk| state.update(chunk))?;
+                    } else {
+                        state.update(input.as_bytes());
+                    }
+                    state.sign(sk.as_slice().try_into().unwrap())?
+                }
+                _ => {
+                    let data = if file {
+                        mmap::read(&input).map_err(|e| format!("Failed to read input file {}: {}", input, e))
+                    } else {
+                        Ok(input.into_bytes().into())
+                    }?;
+                    signer.sign(&data)?
+                }
+            };
+            let cert = match cert {
+                Some(cert_path) => {
+                    let cert = load_cert(&cert_path)?;
+                    if cert.subkey_public_key != public_key {
+                        return Err(format!("Certificate {} was not issued for this signing key", cert_path.display()));
+                    }
+                    Some(cert)
+                }
+                None => None,
+            };
+            // A bare signature stays the default output; the envelope is only needed
+            // to carry a certificate, the prehash mode, comments or signed metadata
+            let signature_bytes = if cert.is_some() || prehash || comment.is_some() || trusted_comment.is_some() || timestamp {
+                let mut env = Envelope::new(signature);
+                env.cert = cert;
+                env.prehashed = prehash;
+                env.comment = comment;
+                env.trusted_comment = trusted_comment;
+                if timestamp {
+                    env.timestamp = Some(unix_now());
+                    env.signer = Some(fingerprint(&public_key, FingerprintAlg::default()));
+                    env.purpose = purpose;
+                }
+                env.sign_attributes_with(|message| signer.sign(message))?;
+                env.encode()
+            } else {
+                signature.to_vec()
+            };
+            let signature_text = if armor {
+                let headers = [("Key-Fingerprint", fingerprint(&public_key, FingerprintAlg::default()))];
+                armor::armor(armor::SIGNATURE, &headers, &signature_bytes)
+            } else {
+                format!("{}\n", hex::encode(signature_bytes))
+            };
+            match sig_path {
+                Some(sig_path) => {
+                    let written = if append {
+                        OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(&sig_path)
+                            .and_then(|mut f| f.write_all(signature_text.as_bytes()))
+                    } else {
+                        check_overwrite(&sig_path, force)?;
+                        cleanup::write_atomic(&sig_path, &signature_text)
+                    };
+                    written.map_err(|e| format!("Failed to write signature to {}: {}", sig_path.display(), e))?;
+                    if verbose {
+                        println!("Signature saved to: {}", sig_path.display());
+                    }
+                }
+                None => print!("{}", signature_text),
+            }
+        }
+
+        Commands::Check { input, signature, more: _, files_from: _, null: _, key, file, sig_file, attached, batch, format, namespace, max_age, purpose, require, ignore_expiry, require_certified, tofu } => {
+            if require == 0 {
+                return Err("--require must be at least 1".to_string());
+            }
+            if key.len() > 1 && (attached || batch || format != SignatureFormat::Sodix) {
+                return Err("Several --key are only supported for sodix detached signatures".to_string());
+            }
+            if format != SignatureFormat::Sodix && (max_age.is_some() || purpose.is_some() || tofu.is_some()) {
+                return Err("--max-age, --purpose and --tofu only apply to sodix signatures".to_string());
+            }
+            if attached && (max_age.is_some() || purpose.is_some()) {
+                return Err("Attached signatures carry no timestamp or purpose".to_string());
+            }
+            let max_age = max_age
+                .map(|age| humantime::parse_duration(&age).map_err(|e| format!("Invalid max age '{}': {}", age, e)))
+                .transpose()?;
+            match format {
+                SignatureFormat::Minisign if attached => return Err("minisign has no attached signatures".to_string()),
+                SignatureFormat::Sshsig if attached => return Err("SSH signatures have no attached mode".to_string()),
+                SignatureFormat::Minisign => return check_minisign(&input, signature, key.into_iter().next(), file, sig_file.flatten(), verbose),
+                SignatureFormat::Signify => {
+                    return check_signify(&input, signature, key.into_iter().next(), file, sig_file.flatten(), attached, verbose)
+                }
+                SignatureFormat::Sshsig => {
+                    return check_sshsig(&input, signature, key.into_iter().next(), file, sig_file.flatten(), &namespace, verbose)
+                }
+                SignatureFormat::Cose => {
+                    return check_cose(&input, signature, key.into_iter().next(), file, sig_file.flatten(), attached, verbose)
+                }
+                SignatureFormat::Saltpack => {
+                    return check_saltpack(&input, signature, key.into_iter().next(), file, sig_file.flatten(), attached, verbose)
+                }
+                #[cfg(feature = "openpgp")]
+                SignatureFormat::Openpgp if attached => return Err("OpenPGP signatures are detached only".to_string()),
+                #[cfg(feature = "openpgp")]
+                SignatureFormat::Openpgp => {
+                    return check_openpgp(&input, signature, key.into_iter().next(), file, sig_file.flatten(), verbose)
+                }
+                SignatureFormat::Sodix => {}
+            }
+            if batch {
+                // Per-line keys are allowed, so the default key is only loaded if asked for or present
+                let default_key = match key.into_iter().next() {
+                    Some(key_input) => Some(load_signing_public_key(&key_input)?),
+                    None => {
+                        let public_key_path = get_default_key_path("sign_public");
+                        if key_exists(&public_key_path) { Some(load_key(&public_key_path, 32)?) } else { None }
+                    }
+                };
+                let items = batch::read_manifest(Path::new(&input), default_key.map(|k| k.as_slice().try_into().unwrap()))?;
+                let results = batch::verify_all(&items);
+                let mut failed = 0;
+                for (item, result) in items.iter().zip(&results) {
+                    match result {
+                        Ok(_) => println!("valid {}", item.file.display()),
+                        Err(e) => {
+                            if verbose {
+                                eprintln!("{}: {}", item.file.display(), e);
+                            }
+                            println!("invalid {}", item.file.display());
+                            failed += 1;
+                        }
+                    }
+                }
+                if failed > 0 {
+                    return Err(format!("{} of {} signatures are invalid", failed, items.len()));
+                }
+                return Ok(());
+            }
+            let key_inputs = if key.is_empty() {
+                vec![get_default_key_path("sign_public").to_string_lossy().into_owned()]
+            } else {
+                key
+            };
+            let mut keys: Vec<[u8; 32]> = Vec::new();
+            let mut key_names = Vec::new();
+            for key_input in &key_inputs {
+                for (pk, name) in load_public_keys(key_input, verbose)? {
+                    if !keys.contains(&pk) {
+                        keys.push(pk);
+                        key_names.push(name);
+                    }
+                }
+            }
+            if keys.is_empty() {
+                return Err("No public keys found".to_string());
+            }
+            if require > keys.len() {
+                return Err(format!("--require {} needs at least {} distinct keys", require, require));
+            }
+            if require_certified {
+                check_certified(&keys, verbose)?;
+            }
+            let expiries: Vec<Option<u64>> = if ignore_expiry {
+                vec![None; keys.len()]
+            } else {
+                let dirs: Vec<PathBuf> = key_inputs.iter().flat_map(|k| key_meta_dirs(k)).collect();
+                let meta = keymeta::KeyMeta::load(&dirs)?;
+                keys.iter().map(|pk| meta.expires(pk)).collect()
+            };
+            if attached {
+                // Attached signatures carry no timestamp to show they predate the expiry
+                check_key_expiry(None, expiries[0])?;
+                return check_attached(&input, file, &keys[0]);
+            }
+            let signature = match (signature, sig_file) {
+                (Some(signature), _) => signature,
+                (None, None) => return Err("A signature (or --sig-file) is required".to_string()),
+                (None, path) => {
+                    let sig_path = path.flatten().unwrap_or_else(|| PathBuf::from(format!("{}.sig", input)));
+                    fs::read_to_string(&sig_path)
+                        .map_err(|e| format!("Failed to read signature from {}: {}", sig_path.display(), e))?
+                }
+            };
+            let sigs = if armor::is_armored(&signature) {
+                armor::dearmor(&signature, armor::SIGNATURE)?
+            } else {
+                signature
+                    .split_whitespace()
+                    .map(|sig| hex::decode(sig).map_err(|e| format!("Invalid hex signature: {}", e)))
+                    .collect::<Result<Vec<_>, _>>()?
+            };
+            if sigs.is_empty() {
+                return Err("No signature found".to_string());
+            }
+            let (matches, error) = match_signatures(&sigs, &input, file, &keys, &expiries, max_age, purpose.as_deref());
+            if matches.len() >= require {
+                if let Some(name) = &tofu {
+                    if let Err(e) = trust_on_first_use(name, &keys[matches[0].0]) {
+                        print_check_result(false);
+                        return Err(e);
+                    }
+                }
+                print_check_result(true);
+                for &(key_index, sig_index) in &matches {
+                    // With several candidate keys, say which one signed (e.g. during a rotation)
+                    if keys.len() > 1 {
+                        println!("Signed by: {} ({})", key_names[key_index], fingerprint(&keys[key_index], FingerprintAlg::default()));
+                    }
+                    if verbose {
+                        print_envelope_details(&sigs[sig_index])?;
+                    }
+                }
+            } else {
+                if verbose {
+                    if keys.len() == 1 && sigs.len() == 1 {
+                        eprintln!("Signature verification failed: {}", error.unwrap_or_default());
+                    } else {
+                        eprintln!("{} of {} required signatures are valid", matches.len(), require);
+                    }
+                }
+                print_check_result(false);
+            }
+        }
+
+        #[cfg(feature = "full")]
+        Commands::Encrypt { input, more: _, files_from: _, null: _, exclude: _, pubkey, recipients, to_self, seckey, ephemeral, sign_key, convergent, convergence_key, file, aead, aad, mime, fingerprint_alg, detached_header, id, to, require_certified, armor, compress, no_preserve, hide_name, force, pipe, split_size, shred, format } => {
+            let mut recipient_keys = Vec::new();
+            for hex_key in &pubkey {
+                recipient_keys.push(parse_hex_key(hex_key)?);
+            }
+            if !to.is_empty() {
+                let mut contacts = contacts::Contacts::load(&default_key_file(contacts::FILE_NAME))?;
+                for name in &to {
+                    // An address that is not a contact yet is looked up on its domain
+                    if !contacts.contains(name) && name.contains('@') {
+                        add_published_contact(&mut contacts, name, None, None, None, verbose)?;
+                    }
+                    recipient_keys.push(contacts.key(name)?);
+                }
+            }
+            if let Some(path) = &recipients {
+                recipient_keys.extend(load_recipients(path)?);
+            }
+            if to_self || recipient_keys.is_empty() {
+                let public_key_path = identity_key_path(id.as_deref(), "enc_public")?;
+                let pk_vec = load_or_generate_encryption_key(&public_key_path, false, verbose)?;
+                recipient_keys.push(pk_vec.as_slice().try_into().map_err(|_| "Public key must be 32 bytes")?);
+            }
+            // One key slot per distinct recipient
+            let mut unique: Vec<[u8; 32]> = Vec::new();
+            for pk in recipient_keys {
+                if !unique.contains(&pk) {
+                    unique.push(pk);
+                }
+            }
+            let recipient_keys = unique;
+            if require_certified {
+                check_certified(&recipient_keys, verbose)?;
+            }
+            if format == MessageFormat::Age {
+                if seckey.is_some() || compress.is_some() || ephemeral || convergent || aead.is_some() || mime || detached_header.is_some() || split_size.is_some() || pipe {
+                    return Err("--format age takes only recipients, -f and --armor".to_string());
+                }
+                encrypt_age(&input, file, &recipient_keys, armor, force, verbose)?;
+                return shred_input(&input, shred, verbose);
+            }
+            let pk = recipient_keys[0];
+            
+            let sk = match seckey {
+                _ if ephemeral => None,
+                Some(hex_key) => Some(parse_hex_key(&hex_key)?),
+                None => {
+                    let secret_key_path = identity_key_path(id.as_deref(), "enc_secret")?;
+                    let sk_vec = load_or_generate_encryption_key(&secret_key_path, true, verbose)?;
+                    Some(sk_vec.as_slice().try_into().map_err(|_| "Secret key must be 32 bytes")?)
+                }
+            };
+            if format == MessageFormat::Saltpack {
+                if ephemeral || compress.is_some() || convergent || aead.is_some() || mime || detached_header.is_some() || split_size.is_some() || pipe {
+                    return Err("--format saltpack takes only recipients, -s and -f".to_string());
+                }
+                let sk = sk.ok_or("--format saltpack needs a sender secret key")?;
+                encrypt_saltpack(&input, file, &recipient_keys, &sk, force, verbose)?;
+                return shred_input(&input, shred, verbose);
+            }
+            let convergence_secret = match (convergent, convergence_key, sk) {
+                (false, _, _) => None,
+                (true, Some(k), _) => Some(match hex::decode(k.trim()).map(Zeroizing::new) {
+                    Ok(bytes) if bytes.len() == 32 => bytes.as_slice().try_into().unwrap(),
+                    _ => load_secret_key(Path::new(&k), 32)?.as_slice().try_into().unwrap(),
+                }),
+                (true, None, Some(sk)) => Some(container::derive_convergence_secret(&sk)),
+                (true, None, None) => return Err("--convergent needs --convergence-key or a static sender key".to_string()),
+            };
+            let sender = match sk {
+                Some(sk) => container::Sender::Static(sk),
+                None => {
+                    let sign_key_input = match sign_key {
+                        Some(key) => key,
+                        None => identity_key_path(id.as_deref(), "sign_secret")?.to_string_lossy().into_owned(),
+                    };
+                    let sign_sk = load_or_generate_signing_key(&sign_key_input, true, verbose)?;
+                    container::Sender::Ephemeral(sign_sk.as_slice().try_into().map_err(|_| "Secret key must be 64 bytes")?)
+                }
+            };
+
+            if pipe {
+                let stdout = io::stdout();
+                if stdout.is_terminal() {
+                    return Err("Refusing to write encrypted data to a terminal; redirect or pipe stdout".to_string());
+                }
+                let mut writer = BufWriter::new(stdout.lock());
+                container::encrypt_pipe(io::stdin().lock(), &mut writer, &recipient_keys, &sender, compress)?;
+                return writer.flush().map_err(|e| format!("Failed to write output: {}", e));
+            }
+
+            // Files are streamed through the chunked container with constant memory
+            if file && aead.is_none() && !mime && !armor {
+                let name = match hide_name {
+                    true => Some(Path::new(&input)
+                        .file_name()
+                        .ok_or_else(|| format!("'{}' does not name a file", input))?
+                        .to_string_lossy()
+                        .into_owned()),
+                    false => None,
+                };
+                let output_file = match name {
+                    Some(_) => {
+                        let opaque = format!("{}.x", hex::encode(rand::rng().random::<[u8; 16]>()));
+                        Path::new(&input).with_file_name(opaque).to_string_lossy().into_owned()
+                    }
+                    None => format!("{}.x", input),
+                };
+                let header_file = detached_header.map(|path| path.unwrap_or_else(|| PathBuf::from(format!("{}.hdr", output_file))));
+                let split_size = split_size.map(|size| split::parse_size(&size)).transpose()?;
+                check_overwrite(Path::new(&output_file), force)?;
+                if split_size.is_some() {
+                    check_overwrite(&split::index_path(Path::new(&output_file)), force)?;
+                }
+                if let Some(header_file) = &header_file {
+                    check_overwrite(header_file, force)?;
+                }
+                container::encrypt_file(Path::new(&input), Path::new(&output_file), header_file.as_deref(), split_size, &recipient_keys, &sender, convergence_secret.as_ref(), compress, !no_preserve, name.as_deref())?;
+                if hide_name && !verbose {
+                    println!("{}", output_file);
+                }
+                if verbose {
+                    match split_size {
+                        Some(_) => println!("Encrypted file saved in parts, index: {}", split::index_path(Path::new(&output_file)).display()),
+                        None => println!("Encrypted file saved to: {}", output_file),
+                    }
+                    if let Some(header_file) = &header_file {
+                        println!("Header saved to: {}", header_file.display());
+                    }
+                }
+                return shred_input(&input, shred, verbose);
+            }
+
+            if file {
+                if is_large_file(&input)? {
+                    return Err(format!("{} is too large for --aead, --armor and --mime, which encrypt in memory; drop them to stream it into a container", input));
+                }
+                check_overwrite(Path::new(&format!("{}.x", input)), force)?;
+            }
             let data = if file {
-                fs::read(&input).map_err(|e| format!("Failed to read input file {}: {}", input, e))
+                mmap::read(&input).map_err(|e| format!("Failed to read input file {}: {}", input, e))
             } else {
-                Ok(input.into_bytes())
+                Ok(input.clone().into_bytes().into())
             }?;
-            let mut signature = [0u8; 64];
-            crypto_sign_detached(&mut signature, &data, sk.as_slice().try_into().unwrap())
-                .map_err(|e| format!("Error signing data: {}", e))?;
-            println!("{}", hex::encode(&signature));
+
+            let combined = match (sk, aead) {
+                (Some(sk), Some(alg)) if recipient_keys.len() == 1 => {
+                    let key = container::shared_key(&pk, &sk);
+                    aead::seal(alg, key.as_bytes(), &data, aad.unwrap_or_default().as_bytes())?
+                }
+                (Some(sk), None) if recipient_keys.len() == 1 && !convergent => {
+                    let mut nonce = [0u8; 24];
+                    rand::rng().fill(&mut nonce);
+
+                    let ciphertext = container::shared_key(&pk, &sk)
+                        .seal(&data, &nonce)
+                        .map_err(|e| format!("Error encrypting data: {}", e))?;
+
+                    let mut combined = Vec::new();
+                    combined.extend_from_slice(&nonce);
+                    combined.extend_from_slice(&ciphertext);
+                    combined
+                }
+                // Several recipients, an ephemeral sender or convergent: the same container as -f
+                (_, None) => container::encrypt_bytes(&data, &recipient_keys, &sender, convergence_secret.as_ref())?,
+                (_, Some(_)) => return Err("--aead supports a single recipient only".to_string()),
+            };
+            if mime {
+                let filename = if file {
+                    let name = Path::new(&input).file_name().map(|n| n.to_string_lossy().into_owned());
+                    format!("{}.x", name.unwrap_or_else(|| "message".to_string()))
+                } else {
+                    "message.x".to_string()
+                };
+                let alg = fingerprint_alg.unwrap_or_default();
+                let mut headers = Vec::new();
+                for recipient_pk in &recipient_keys {
+                    headers.push(("X-Sodix-Recipient", hex::encode(recipient_pk)));
+                    headers.push(("X-Sodix-Recipient-Fingerprint", fingerprint(recipient_pk, alg)));
+                }
+                if let Some(sk) = sk {
+                    let mut sender_pk = [0u8; 32];
+                    crypto_scalarmult_base(&mut sender_pk, &sk);
+                    headers.push(("X-Sodix-Sender-Fingerprint", fingerprint(&sender_pk, alg)));
+                }
+                if let Some(alg) = aead {
+                    headers.push(("X-Sodix-Aead", alg.to_possible_value().unwrap().get_name().to_string()));
+                }
+                print!("{}", mime::wrap(&combined, &filename, &headers));
+                return Ok(());
+            }
+
+            let armored = armor.then(|| {
+                let headers: Vec<_> = recipient_keys
+                    .iter()
+                    .map(|pk| ("Recipient-Fingerprint", fingerprint(pk, FingerprintAlg::default())))
+                    .collect();
+                armor::armor(armor::MESSAGE, &headers, &combined)
+            });
+
+            if file {
+                let output_file = format!("{}.x", input);
+                let written = match &armored {
+                    Some(text) => cleanup::write_atomic(&output_file, text.trim_end()),
+                    None => cleanup::AtomicFile::create(&output_file).and_then(|mut writer| {
+                        hexstream::write_hex(&mut writer, &combined)?;
+                        writer.commit()
+                    }),
+                };
+                written.map_err(|e| format!("Failed to write encrypted file {}: {}", output_file, e))?;
+                if verbose {
+                    println!("Encrypted file saved to: {}", output_file);
+                }
+                shred_input(&input, shred, verbose)?;
+            } else if let Some(text) = armored {
+                print!("{}", text);
+            } else {
+                let mut stdout = io::stdout().lock();
+                hexstream::write_hex(&mut stdout, &combined)
+                    .and_then(|()| writeln!(stdout))
+                    .map_err(|e| format!("Failed to write output: {}", e))?;
+            }
+        }
+
+        Commands::Decrypt { input, more: _, files_from: _, null: _, pubkey, seckey, signer, file, aead, aad, mime, range, detached_header, extract, no_preserve, force, pipe, id, format } => {
+            let sk = match seckey {
+                Some(key) if age::is_identity(&key) => age::decode_identity(&key)?,
+                Some(hex_key) => parse_hex_key(&hex_key)?,
+                None => {
+                    let secret_key_path = identity_key_path(id.as_deref(), "enc_secret")?;
+                    let sk_vec = load_or_generate_encryption_key(&secret_key_path, true, verbose)?;
+                    sk_vec.as_slice().try_into().map_err(|_| "Secret key must be 32 bytes")?
+                }
+            };
+            if format != MessageFormat::Sodix && (signer.is_some() || aead.is_some() || mime || range.is_some() || detached_header.is_some() || extract.is_some() || pipe) {
+                return Err("--format age and saltpack take only -k (saltpack), -s, --id and -f".to_string());
+            }
+            match format {
+                MessageFormat::Age if pubkey.is_some() => return Err("age files do not authenticate the sender; drop -k".to_string()),
+                MessageFormat::Age => return decrypt_age(&input, file, &sk, force, verbose),
+                MessageFormat::Saltpack => {
+                    let sender = pubkey.map(|key| parse_hex_key(&key)).transpose()?;
+                    return decrypt_saltpack(&input, file, &sk, sender, force, verbose);
+                }
+                MessageFormat::Sodix => {}
+            }
+            let pk = match pubkey {
+                Some(hex_key) => parse_hex_key(&hex_key)?,
+                None => {
+                    let public_key_path = identity_key_path(id.as_deref(), "enc_public")?;
+                    let pk_vec = load_or_generate_encryption_key(&public_key_path, false, verbose)?;
+                    pk_vec.as_slice().try_into().map_err(|_| "Public key must be 32 bytes")?
+                }
+            };
+
+            // Only needed for --ephemeral messages; the default key is read if one turns up
+            let signer_pk = signer.map(|key_input| load_signing_public_key(&key_input)).transpose()?;
+            let keys = container::OpenKeys {
+                recipient_sk: sk,
+                sender_pk: Some(pk),
+                signer_pk: signer_pk.map(|k| k.as_slice().try_into().unwrap()),
+            };
+            if pipe {
+                let stdout = io::stdout();
+                let mut writer = BufWriter::new(stdout.lock());
+                container::decrypt_stream(io::stdin().lock(), &mut writer, &keys)?;
+                return writer.flush().map_err(|e| format!("Failed to write output: {}", e));
+            }
+            
+            let (mut combined, output_path, aead) = if mime {
+                let message = fs::read_to_string(&input)
+                    .map_err(|e| format!("Failed to read MIME message {}: {}", input, e))?;
+                let container = mime::unwrap(&message)?;
+                if let Some(fp) = container.header("X-Sodix-Sender-Fingerprint") {
+                    if !fingerprint::matches(fp, &pk)? {
+                        return Err("The message's sender fingerprint does not match the sender public key".to_string());
+                    }
+                }
+                let aead = match aead {
+                    Some(alg) => Some(alg),
+                    None => container.header("X-Sodix-Aead").map(|v| AeadAlg::from_str(v, true)).transpose()?,
+                };
+                // Only the attachment's base name is used, never a path from the email
+                let output_file = match &container.filename {
+                    Some(name) if file => {
+                        let name = Path::new(name).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                        let name = name.strip_suffix(".x").unwrap_or(&name).to_string();
+                        if name.is_empty() {
+                            return Err("MIME attachment has no usable filename".to_string());
+                        }
+                        Some(name)
+                    }
+                    _ => None,
+                };
+                (container.data, output_file, aead)
+            } else if file {
+                // The index of a split file stands for the file itself
+                let input = input.strip_suffix(split::INDEX_EXTENSION).unwrap_or(&input);
+                let encrypted_file = if input.ends_with(".x") { input.to_string() } else { format!("{}.x", input) };
+                let output_file = if encrypted_file.ends_with(".x") {
+                    encrypted_file[..encrypted_file.len()-2].to_string()
+                } else {
+                    encrypted_file.clone()
+                };
+                let header_file = detached_header.map(|path| path.unwrap_or_else(|| PathBuf::from(format!("{}.hdr", encrypted_file))));
+                if header_file.is_some() || container::is_container_file(Path::new(&encrypted_file)) || split::index_path(Path::new(&encrypted_file)).is_file() {
+                    if aead.is_some() {
+                        return Err("--aead does not apply to streaming encrypted files".to_string());
+                    }
+                    if let Some(range) = range {
+                        let (offset, len) = parse_range(&range)?;
+                        let stdout = io::stdout();
+                        container::decrypt_range(Path::new(&encrypted_file), header_file.as_deref(), offset, len, stdout.lock(), &keys)?;
+                        return Ok(());
+                    }
+                    if let Some(member) = extract {
+                        // Only the base name is used, never a path from the archive
+                        let output_file = Path::new(&member)
+                            .file_name()
+                            .ok_or_else(|| format!("'{}' does not name a file", member))?
+                            .to_os_string();
+                        check_overwrite(Path::new(&output_file), force)?;
+                        let reader = container::PlaintextReader::open(Path::new(&encrypted_file), header_file.as_deref(), &keys)?;
+                        let size = archive::extract(reader, &member, Path::new(&output_file), !no_preserve)?;
+                        if verbose {
+                            println!("Extracted {} ({} bytes) to {}", member, size, Path::new(&output_file).display());
+                        }
+                        return Ok(());
+                    }
+                    // A hidden name comes back next to the opaque file; only its base name is used
+                    let output_file = match container::stored_name(Path::new(&encrypted_file), header_file.as_deref(), &keys)? {
+                        Some(name) => {
+                            let name = Path::new(&name).file_name().ok_or("Invalid file name in encrypted file header")?;
+                            Path::new(&encrypted_file).with_file_name(name).to_string_lossy().into_owned()
+                        }
+                        None => output_file,
+                    };
+                    check_overwrite(Path::new(&output_file), force)?;
+                    let metadata = container::decrypt_file(Path::new(&encrypted_file), header_file.as_deref(), Path::new(&output_file), &keys)?;
+                    if let Some(metadata) = metadata.filter(|_| !no_preserve) {
+                        // In a batch the output is still under its temporary name
+                        metadata.restore(&cleanup::staged_path(Path::new(&output_file)))?;
+                    }
+                    if verbose {
+                        println!("Decrypted file saved to: {}", output_file);
+                    }
+                    return Ok(());
+                }
+                if Path::new(&format!("{}.hdr", encrypted_file)).exists() {
+                    return Err(format!("{} has a detached header ({}.hdr); pass --detached-header", encrypted_file, encrypted_file));
+                }
+                if range.is_some() || extract.is_some() {
+                    return Err(format!("{} is not a chunked container; --range and --extract are unavailable", encrypted_file));
+                }
+                let read_error = |e: io::Error| format!("Failed to read encrypted file {}: {}", encrypted_file, e);
+                let file = File::open(&encrypted_file).map_err(read_error)?;
+                let text_len = file.metadata().map_err(read_error)?.len();
+                let mut reader = io::BufReader::new(file);
+                let combined = if armor::is_armored(&String::from_utf8_lossy(reader.fill_buf().map_err(read_error)?)) {
+                    let mut text = String::new();
+                    reader.read_to_string(&mut text).map_err(read_error)?;
+                    armor::dearmor_one(&text, armor::MESSAGE).map_err(|e| format!("{}: {}", encrypted_file, e))?
+                } else {
+                    let hex_error = |e: io::Error| match e.kind() {
+                        io::ErrorKind::InvalidData => format!("Invalid hex in file {}: {}", encrypted_file, e),
+                        _ => read_error(e),
+                    };
+                    let mut reader = hexstream::HexReader::new(reader);
+                    let mut combined = Vec::new();
+                    (&mut reader).take(container::MAGIC.len() as u64).read_to_end(&mut combined).map_err(hex_error)?;
+                    // A hex-wrapped container is decrypted as it is decoded, never whole
+                    if aead.is_none() && combined == container::MAGIC {
+                        check_overwrite(Path::new(&output_file), force)?;
+                        return decrypt_container_to(io::Cursor::new(combined).chain(reader), Some(&output_file), &keys, verbose);
+                    }
+                    // Other messages are decrypted in this one buffer
+                    combined.reserve_exact((text_len / 2) as usize);
+                    reader.read_to_end(&mut combined).map_err(hex_error)?;
+                    combined
+                };
+                (combined, Some(output_file), aead)
+            } else if armor::is_armored(&input) {
+                (armor::dearmor_one(&input, armor::MESSAGE)?, None, aead)
+            } else {
+                (hex::decode(&input).map_err(|e| format!("Invalid hex input: {}", e))?, None, aead)
+            };
+            if let Some(output_file) = &output_path {
+                check_overwrite(Path::new(output_file), force)?;
+            }
+
+            // Plaintext goes straight to the output: containers a chunk at a time, other
+            // messages once decrypted in the buffer they were read into
+            let plaintext = match aead {
+                None if combined.starts_with(container::MAGIC) => {
+                    return decrypt_container_to(&combined[..], output_path.as_deref(), &keys, verbose);
+                }
+                Some(alg) => {
+                    let key = container::shared_key(&pk, &sk);
+                    aead::open(alg, key.as_bytes(), &mut combined, aad.unwrap_or_default().as_bytes())?
+                }
+                None => crypto_box_open_in_place(&mut combined, &pk, &sk)?,
+            };
+
+            if let Some(output_file) = output_path {
+                cleanup::write_atomic(&output_file, plaintext)
+                    .map_err(|e| format!("Failed to write decrypted file {}: {}", output_file, e))?;
+                if verbose {
+                    println!("Decrypted file saved to: {}", output_file);
+                }
+            } else {
+                io::stdout()
+                    .write_all(plaintext)
+                    .map_err(|e| format!("Failed to write decrypted data: {}", e))?;
+                io::stdout().flush().map_err(|e| format!("Failed to flush output: {}", e))?;
+            }
         }
 
-        Commands::Check { input, signature, key, file } => {
-            let public_key_input = key.unwrap_or_else(|| get_default_key_path("sign_public").to_string_lossy().into_owned());
-            let pk = load_or_generate_signing_key(&public_key_input, false, verbose)?;
+        #[cfg(feature = "full")]
+        Commands::Signcrypt { input, pubkey, sign_key, file, force } => {
+            let output_file = format!("{}.sx", input);
+            if file {
+                check_overwrite(Path::new(&output_file), force)?;
+            }
+            let mut recipient_keys = Vec::new();
+            for hex_key in &pubkey {
+                recipient_keys.push(parse_hex_key(hex_key)?);
+            }
+            if recipient_keys.is_empty() {
+                let public_key_path = get_default_key_path("enc_public");
+                let pk_vec = load_or_generate_encryption_key(&public_key_path, false, verbose)?;
+                recipient_keys.push(pk_vec.as_slice().try_into().map_err(|_| "Public key must be 32 bytes")?);
+            }
+            let sign_key_input = sign_key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+            let sign_sk = load_signing_key(&sign_key_input, true)?;
             let data = if file {
-                fs::read(&input).map_err(|e| format!("Failed to read input file {}: {}", input, e))
+                mmap::read(&input).map_err(|e| format!("Failed to read input file {}: {}", input, e))
             } else {
-                Ok(input.into_bytes())
+                Ok(input.clone().into_bytes().into())
             }?;
-            let sig = hex::decode(&signature).map_err(|e| format!("Invalid hex signature: {}", e))?;
-            let result = crypto_sign_verify_detached(
-                sig.as_slice().try_into().map_err(|_| "Signature must be 64 bytes")?,
-                &data,
-                pk.as_slice().try_into().unwrap(),
-            );
-            match result {
-                Ok(_) => println!("valid"),
-                Err(e) => {
-                    if verbose {
-                        eprintln!("Signature verification failed: {}", e);
-                    }
-                    println!("invalid");
+            let sealed = signcrypt::seal(&data, &recipient_keys, sign_sk.as_slice().try_into().unwrap())?;
+            if file {
+                cleanup::write_atomic(&output_file, &sealed)
+                    .map_err(|e| format!("Failed to write encrypted file {}: {}", output_file, e))?;
+                if verbose {
+                    println!("Signed and encrypted file saved to: {}", output_file);
                 }
+            } else {
+                println!("{}", hex::encode(&sealed));
             }
         }
 
-        Commands::Encrypt { input, pubkey, seckey, file } => {
-            let pk = match pubkey {
-                Some(hex_key) => parse_hex_key(&hex_key)?,
-                None => {
-                    let public_key_path = get_default_key_path("enc_public");
-                    let pk_vec = load_or_generate_encryption_key(&public_key_path, false, verbose)?;
-                    pk_vec.try_into().map_err(|_| "Public key must be 32 bytes")?
-                }
-            };
-            
+        Commands::Unsigncrypt { input, seckey, signer, file, force } => {
+            let sealed_file = if input.ends_with(".sx") { input.clone() } else { format!("{}.sx", input) };
+            let output_file = sealed_file[..sealed_file.len() - 3].to_string();
+            if file {
+                check_overwrite(Path::new(&output_file), force)?;
+            }
             let sk = match seckey {
                 Some(hex_key) => parse_hex_key(&hex_key)?,
                 None => {
                     let secret_key_path = get_default_key_path("enc_secret");
                     let sk_vec = load_or_generate_encryption_key(&secret_key_path, true, verbose)?;
-                    sk_vec.try_into().map_err(|_| "Secret key must be 32 bytes")?
+                    sk_vec.as_slice().try_into().map_err(|_| "Secret key must be 32 bytes")?
                 }
             };
+            let signer_input = signer.unwrap_or_else(|| get_default_key_path("sign_public").to_string_lossy().into_owned());
+            let signer_pk = load_signing_public_key(&signer_input)?;
+            let (sealed, output_path) = if file {
+                let sealed = fs::read(&sealed_file)
+                    .map_err(|e| format!("Failed to read encrypted file {}: {}", sealed_file, e))?;
+                (sealed, Some(output_file))
+            } else {
+                (hex::decode(input.trim()).map_err(|e| format!("Invalid hex input: {}", e))?, None)
+            };
+            let message = signcrypt::open(&sealed, &sk, signer_pk.as_slice().try_into().unwrap())?;
+            if let Some(output_file) = output_path {
+                cleanup::write_atomic(&output_file, &message)
+                    .map_err(|e| format!("Failed to write decrypted file {}: {}", output_file, e))?;
+                if verbose {
+                    println!("Verified and decrypted file saved to: {}", output_file);
+                }
+            } else {
+                io::stdout()
+                    .write_all(&message)
+                    .map_err(|e| format!("Failed to write decrypted data: {}", e))?;
+                io::stdout().flush().map_err(|e| format!("Failed to flush output: {}", e))?;
+            }
+        }
 
-            let data = if file {
-                fs::read(&input).map_err(|e| format!("Failed to read input file {}: {}", input, e))
+        #[cfg(feature = "full")]
+        Commands::Generate { key, format, key_type, passphrase, expires, from_passphrase, salt, params, vanity, single_file, name, unified, force } => {
+            let dir = key.unwrap_or_else(get_default_key_dir);
+            match format {
+                SignatureFormat::Cose | SignatureFormat::Saltpack => {
+                    return Err("COSE and saltpack signatures use the sodix signing keys; run generate without --format".to_string())
+                }
+                #[cfg(feature = "openpgp")]
+                SignatureFormat::Openpgp => {
+                    return Err("OpenPGP signatures use the sodix signing keys; run generate without --format, then key export --format openpgp".to_string())
+                }
+                SignatureFormat::Minisign | SignatureFormat::Signify | SignatureFormat::Sshsig
+                    if passphrase || expires.is_some() || from_passphrase || vanity.is_some() || key_type != KeyType::All || single_file || unified =>
+                {
+                    return Err("--type, --passphrase, --expires, --from-passphrase, --vanity, --single-file and --unified only apply to sodix keys".to_string())
+                }
+                SignatureFormat::Minisign => return generate_minisign_keys(&dir, force, verbose),
+                SignatureFormat::Signify => return generate_signify_keys(&dir, force, verbose),
+                SignatureFormat::Sshsig => return Err("Create SSH keys with `ssh-keygen -t ed25519 -N \"\"`".to_string()),
+                SignatureFormat::Sodix => {}
+            }
+            let expires = expires.map(|when| keymeta::parse_expiry(&when, unix_now())).transpose()?;
+            if vanity.is_some() && !key_type.sign() {
+                return Err("--vanity needs a signing key pair; use --type sign or all".to_string());
+            }
+            if single_file {
+                // -k names the file here; without it the identity goes into the key directory
+                let file = if dir.is_dir() || dir == get_default_key_dir() { dir.join("identity.sodix") } else { dir };
+                let passphrase = if passphrase { Some(keyfile::new_passphrase()?) } else { None };
+                let (sign_pk, enc_pk) = generate_identity_file(&file, name.as_deref(), passphrase.as_deref().map(String::as_str), force, verbose)?;
+                if let Some(expires) = expires {
+                    let meta_dir = file.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+                    keymeta::record_expiry(meta_dir, &[&sign_pk, &enc_pk], expires)?;
+                    if verbose {
+                        println!("Keys expire on {}", keymeta::format_time(expires));
+                    }
+                }
+                if !verbose {
+                    println!("Keys generated successfully");
+                }
+                return Ok(());
+            }
+            let mut key_files = Vec::new();
+            if key_type.sign() {
+                key_files.extend(["sign_public.key", "sign_secret.key"]);
+            }
+            if key_type.enc() {
+                key_files.extend(["enc_public.key", "enc_secret.key"]);
+            }
+            for name in key_files {
+                check_overwrite(&dir.join(name), force)?;
+            }
+            if from_passphrase {
+                derive_keys(&dir, key_type, salt, params, verbose)?;
             } else {
-                Ok(input.clone().into_bytes())
-            }?;
+                let passphrase = if passphrase { Some(keyfile::new_passphrase()?) } else { None };
+                match vanity {
+                    _ if unified => generate_unified_keys(&dir, vanity.as_deref(), passphrase.as_deref().map(String::as_str), verbose)?,
+                    Some(prefix) => generate_vanity_keys(&dir, key_type, &prefix, passphrase.as_deref().map(String::as_str), verbose)?,
+                    None => generate_keys(&dir, key_type, passphrase.as_deref().map(String::as_str), verbose)?,
+                }
+            }
+            if let Some(expires) = expires {
+                let mut public_keys = Vec::new();
+                if key_type.sign() {
+                    public_keys.push(load_key(&dir.join("sign_public.key"), 32)?);
+                }
+                if key_type.enc() {
+                    public_keys.push(load_key(&dir.join("enc_public.key"), 32)?);
+                }
+                let public_keys: Vec<&[u8]> = public_keys.iter().map(|key| key.as_slice()).collect();
+                keymeta::record_expiry(&dir, &public_keys, expires)?;
+                if verbose {
+                    println!("Keys expire on {}", keymeta::format_time(expires));
+                }
+            }
+            if !verbose {
+                println!("Keys generated successfully");
+            }
+        }
+
+        #[cfg(feature = "full")]
+        Commands::Print { key, bech32, armor, did } => {
+            match key.as_deref().or(identity::active()) {
+                Some(file) if identity::is_identity_file(file) => print_identity(file, bech32, did, armor, verbose)?,
+                _ => {
+                    // Keys that are still in the legacy directory are printed from there
+                    let dir = key.unwrap_or_else(|| get_default_key_path("sign_secret").parent().unwrap().to_path_buf());
+                    print_keys(&dir, bech32, did, armor, verbose)?;
+                }
+            }
+        }
 
-            let mut nonce = [0u8; 24];
-            let mut rng = StdRng::seed_from_u64(42); // Seed with a u64 value
-            rng.fill(&mut nonce);
+        #[cfg(feature = "full")]
+        Commands::Stream { key, nonce } => {
+            stream_xor(&key, &nonce)?;
+        }
 
-            let mut ciphertext = vec![0u8; data.len() + 16];
-            crypto_box_easy(&mut ciphertext, &data, &nonce, &pk, &sk)
-                .map_err(|e| format!("Error encrypting data: {}", e))?;
+        #[cfg(feature = "full")]
+        Commands::Scalarmult { secret, public } => {
+            scalarmult(&secret, public.as_deref())?;
+        }
 
-            let mut combined = Vec::new();
-            combined.extend_from_slice(&nonce);
-            combined.extend_from_slice(&ciphertext);
-            let combined_hex = hex::encode(&combined);
+        #[cfg(feature = "full")]
+        Commands::Run { workflow, signature, key, vars, args } => match key {
+            Some(key) => run_verified(&workflow, signature, &key, &args, verbose)?,
+            None => workflow::run_workflow(&workflow, &vars, verbose)?,
+        },
 
-            if file {
-                let output_file = format!("{}.x", input);
-                fs::write(&output_file, &combined_hex)
-                    .map_err(|e| format!("Failed to write encrypted file {}: {}", output_file, e))?;
+        #[cfg(feature = "full")]
+        Commands::Key { command } => match command {
+            KeyCommands::Delegate { key, ttl, out, force } => {
+                let root_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+                let dir = out.unwrap_or_else(|| PathBuf::from("."));
+                delegate_key(&root_key_input, &ttl, &dir, force, verbose)?;
+            }
+            KeyCommands::Add { name, passphrase } => {
+                let dir = identity_dir(&name)?;
+                if dir.exists() {
+                    return Err(format!("Identity '{}' already exists at {}", name, dir.display()));
+                }
+                let passphrase = if passphrase { Some(keyfile::new_passphrase()?) } else { None };
+                generate_keys(&dir, KeyType::All, passphrase.as_deref().map(String::as_str), verbose)?;
+                if !verbose {
+                    println!("{}", dir.display());
+                }
+            }
+            KeyCommands::List => {
+                list_identities()?;
+            }
+            KeyCommands::Certify { pubkey, name, key, out, force } => {
+                let pk = parse_any_public_key(&pubkey)?;
+                let out = match out {
+                    Some(out) => out,
+                    None => {
+                        let dir = default_key_file("certifications");
+                        fs::create_dir_all(&dir)
+                            .map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+                        let fpr = fingerprint(&pk, FingerprintAlg::Blake2b);
+                        dir.join(format!("{}-{}.cert", name.replace(['/', '\\'], "_"), &fpr["blake2b:".len()..][..16]))
+                    }
+                };
+                check_overwrite(&out, force)?;
+                let secret_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+                let sk = load_signing_key(&secret_key_input, true)?;
+                let text = certification::create(&pk, &name, unix_now(), sk.as_slice().try_into().unwrap())?;
+                fs::write(&out, text)
+                    .map_err(|e| format!("Failed to write certification to {}: {}", out.display(), e))?;
+                println!("{}", out.display());
+            }
+            KeyCommands::Migrate { remove } => {
+                migrate_keys(remove, verbose)?;
+            }
+            KeyCommands::Export { all, id, out, format, der, user_id, force } => {
+                if der && format != ExportFormat::Pkcs8 {
+                    return Err("--der only applies to --format pkcs8".to_string());
+                }
+                #[cfg(feature = "openpgp")]
+                let is_openpgp = format == ExportFormat::Openpgp;
+                #[cfg(not(feature = "openpgp"))]
+                let is_openpgp = false;
+                if user_id.is_some() && !is_openpgp {
+                    return Err("--user-id only applies to --format openpgp".to_string());
+                }
+                if format == ExportFormat::Bundle {
+                    if !all && id.is_empty() {
+                        return Err("Choose what to export with --all or --id".to_string());
+                    }
+                    export_keys(all, &id, &out, force, verbose)?;
+                } else {
+                    if all || id.len() > 1 {
+                        return Err("--format pkcs8, jwk, wireguard, age and openpgp export the default keys or one --id".to_string());
+                    }
+                    #[cfg(feature = "openpgp")]
+                    if is_openpgp {
+                        return export_openpgp(id.first().map(String::as_str), &out, user_id, force, verbose);
+                    }
+                    export_key_files(id.first().map(String::as_str), &out, format, der, force, verbose)?;
+                }
+            }
+            KeyCommands::Import { file, force, on_conflict } => {
+                import_keys(&file, force, on_conflict, verbose)?;
+            }
+            KeyCommands::Unify { key, passphrase, force } => {
+                let dir = key.unwrap_or_else(|| get_default_key_path("sign_secret").parent().unwrap().to_path_buf());
+                unify_keys(&dir, passphrase, force, verbose)?;
+            }
+            KeyCommands::X25519 { key } => {
+                let bytes = if is_text_key(&key) {
+                    Zeroizing::new(decode_text_key(&key, Some(KeyKind::Signing))?.to_vec())
+                } else if let Ok(bytes) = hex::decode(key.trim()) {
+                    Zeroizing::new(bytes)
+                } else {
+                    load_key_file(Path::new(&key))?
+                };
+                match bytes.len() {
+                    32 => println!("{}", hex::encode(x25519_public_key(bytes[..].try_into().unwrap())?)),
+                    64 => {
+                        let mut enc_secret = [0u8; 32];
+                        crypto_sign_ed25519_sk_to_curve25519(&mut enc_secret, bytes[..].try_into().unwrap());
+                        println!("{}", hex::encode(enc_secret));
+                    }
+                    n => return Err(format!("Expected an Ed25519 signing key (32 or 64 bytes), got {} bytes", n)),
+                }
+            }
+            KeyCommands::Passwd { file, remove } => {
+                change_passphrase(&file, remove, verbose)?;
+            }
+            KeyCommands::Convert { file, out } => {
+                let key = load_key_file(&file)?;
+                if !matches!(key.len(), 32 | 64) {
+                    return Err(format!("{} holds a {}-byte key; sodix keys are 32 or 64 bytes", file.display(), key.len()));
+                }
+                match out {
+                    Some(out) => {
+                        keyperms::write_secret(&out, Zeroizing::new(hex::encode(&key)).as_bytes()).map_err(|e| format!("Failed to write key to {}: {}", out.display(), e))?;
+                        if verbose {
+                            println!("Wrote the {}-byte key to {}", key.len(), out.display());
+                        }
+                    }
+                    None => println!("{}", hex::encode(&key)),
+                }
+            }
+            KeyCommands::Publish { address, out, id } => {
+                let sk_path = identity_key_path(id.as_deref(), "sign_secret")?;
+                let sk = load_or_generate_signing_key(&sk_path.to_string_lossy(), true, verbose)?;
+                let pk_path = identity_key_path(id.as_deref(), "enc_public")?;
+                let pk: [u8; 32] = load_or_generate_encryption_key(&pk_path, false, verbose)?
+                    .as_slice().try_into()
+                    .map_err(|_| "Public key must be 32 bytes")?;
+                let text = wellknown::create(&address, &pk, unix_now(), sk.as_slice().try_into().unwrap())?;
+                let (path, _) = wellknown::location(&address)?;
+                let dst = out.join(path);
+                if let Some(dir) = dst.parent() {
+                    fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+                }
+                fs::write(&dst, text).map_err(|e| format!("Failed to write {}: {}", dst.display(), e))?;
+                println!("{}", dst.display());
                 if verbose {
-                    println!("Encrypted file saved to: {}", output_file);
+                    println!("Serve it at {}", wellknown::url(&address)?);
+                    println!("Fingerprint: {}", fingerprint(&pk, FingerprintAlg::default()));
                 }
-            } else {
-                println!("{}", combined_hex);
             }
-        }
+            KeyCommands::Fetch { address, fingerprint: expected, url, on_conflict } => {
+                let mut contacts = contacts::Contacts::load(&default_key_file(contacts::FILE_NAME))?;
+                let pk = add_published_contact(&mut contacts, &address, expected, url.as_deref(), on_conflict, verbose)?;
+                println!("{}", fingerprint(&pk, FingerprintAlg::default()));
+            }
+        },
 
-        Commands::Decrypt { input, pubkey, seckey, file } => {
-            let pk = match pubkey {
-                Some(hex_key) => parse_hex_key(&hex_key)?,
-                None => {
-                    let public_key_path = get_default_key_path("enc_public");
-                    let pk_vec = load_or_generate_encryption_key(&public_key_path, false, verbose)?;
-                    pk_vec.try_into().map_err(|_| "Public key must be 32 bytes")?
+        Commands::Trust { command } => {
+            let mut store = trust::TrustStore::load(&default_key_file(trust::FILE_NAME))?;
+            match command {
+                TrustCommands::List => {
+                    for signer in store.iter() {
+                        println!(
+                            "{}\t{}\t{}",
+                            signer.name,
+                            fingerprint(&signer.key, FingerprintAlg::default()),
+                            keymeta::format_time(signer.first_seen)
+                        );
+                    }
+                }
+                TrustCommands::Remove { name } => {
+                    store.remove(&name)?;
+                    store.save()?;
+                    if verbose {
+                        println!("Removed known signer '{}'", name);
+                    }
                 }
+            }
+        }
+
+        Commands::Fingerprint { key, alg, expect } => {
+            let pk = match key {
+                Some(k) if is_text_key(&k) => decode_text_key(&k, None)?.to_vec(),
+                Some(k) => match hex::decode(k.trim()) {
+                    Ok(bytes) if bytes.len() == 32 => bytes,
+                    _ => load_key(Path::new(&k), 32)?.to_vec(),
+                },
+                None => load_key(&get_default_key_path("enc_public"), 32)?.to_vec(),
             };
+            match expect {
+                Some(pinned) => println!("{}", if fingerprint::matches(&pinned, &pk)? { "valid" } else { "invalid" }),
+                None => println!("{}", fingerprint(&pk, alg.unwrap_or_default())),
+            }
+        }
 
-            let sk = match seckey {
-                Some(hex_key) => parse_hex_key(&hex_key)?,
+        #[cfg(feature = "full")]
+        Commands::Contact { command } => {
+            let mut contacts = contacts::Contacts::load(&default_key_file(contacts::FILE_NAME))?;
+            match command {
+                ContactCommands::Add { name, key, fingerprint: expected, on_conflict } => {
+                    let pk = match parse_hex_key(&key) {
+                        Ok(pk) => pk,
+                        Err(_) => load_key(Path::new(&key), 32)?.as_slice().try_into().unwrap(),
+                    };
+                    if let Some(pinned) = expected {
+                        if !fingerprint::matches(&pinned, &pk)? {
+                            return Err(format!("Key fingerprint is {}, not {}", fingerprint(&pk, FingerprintAlg::default()), pinned));
+                        }
+                    }
+                    if import_contact(&mut contacts, &name, &pk, on_conflict)? {
+                        contacts.save()?;
+                    }
+                    println!("{}", fingerprint(&contacts.key(&name)?, FingerprintAlg::default()));
+                }
+                ContactCommands::List => {
+                    for (name, contact) in contacts.iter() {
+                        println!("{}\t{}", name, contact.fingerprint);
+                    }
+                }
+                ContactCommands::Show { name } => {
+                    let pk = contacts.key(&name)?;
+                    println!("{}", hex::encode(pk));
+                    println!("{}", bech32key::encode(KeyKind::Encryption, &pk));
+                    println!("{}", fingerprint(&pk, FingerprintAlg::default()));
+                }
+                ContactCommands::Remove { name } => {
+                    contacts.remove(&name)?;
+                    contacts.save()?;
+                    if verbose {
+                        println!("Removed contact '{}'", name);
+                    }
+                }
+            }
+        }
+
+        #[cfg(feature = "full")]
+        Commands::Keylog { command } => {
+            let default_log = || default_key_file(keylog::FILE_NAME);
+            match command {
+                KeylogCommands::Init => {
+                    let contacts = contacts::Contacts::load(&default_key_file(contacts::FILE_NAME))?;
+                    let mut log = keylog::KeyLog::create(&default_log(), &contacts.keys()?)?;
+                    log.save()?;
+                    println!("Started the key log {} with {} contact(s)", default_log().display(), log.entries().len());
+                }
+                KeylogCommands::List { log } => {
+                    for entry in keylog::KeyLog::load(&log.unwrap_or_else(default_log))?.entries() {
+                        print_keylog_entry(entry);
+                    }
+                }
+                KeylogCommands::Checkpoint { key } => {
+                    let secret_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+                    let sk = load_signing_key(&secret_key_input, true)?;
+                    let mut log = keylog::KeyLog::load(&default_log())?;
+                    log.checkpoint(sk.as_slice().try_into().unwrap())?;
+                    log.save()?;
+                    println!("{}", hex::encode(log.head()));
+                }
+                KeylogCommands::Verify { log, contacts, signer, since } => {
+                    let log_path = log.unwrap_or_else(default_log);
+                    let contacts_path = contacts.unwrap_or_else(|| log_path.with_file_name(contacts::FILE_NAME));
+                    let signers = signer
+                        .iter()
+                        .map(|k| load_signing_public_key(k).map(|pk| <[u8; 32]>::try_from(pk.as_slice()).unwrap()))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    verify_keylog(&log_path, &contacts_path, &signers, since.as_deref(), verbose)?;
+                }
+            }
+        }
+
+        #[cfg(feature = "full")]
+        Commands::Recipients { command } => {
+            let (file, seckey) = match &command {
+                RecipientsCommands::Add { file, seckey, .. } | RecipientsCommands::Remove { file, seckey, .. } | RecipientsCommands::Rotate { file, seckey, .. } => (file, seckey),
+            };
+            // A detached header is rewrapped on its own, without touching the payload
+            let encrypted_file = if file.ends_with(".x") || file.ends_with(".hdr") { file.clone() } else { format!("{}.x", file) };
+            let parse_keys = |keys: &[String]| keys.iter().map(|hex_key| parse_hex_key(hex_key)).collect::<Result<Vec<_>, _>>();
+            let sk: [u8; 32] = match seckey {
+                Some(hex_key) => parse_hex_key(hex_key)?,
                 None => {
                     let secret_key_path = get_default_key_path("enc_secret");
                     let sk_vec = load_or_generate_encryption_key(&secret_key_path, true, verbose)?;
-                    sk_vec.try_into().map_err(|_| "Secret key must be 32 bytes")?
+                    sk_vec.as_slice().try_into().map_err(|_| "Secret key must be 32 bytes")?
                 }
             };
-            
-            let (combined, output_path) = if file {
-                let encrypted_file = if input.ends_with(".x") { input.clone() } else { format!("{}.x", input) };
-                let output_file = if encrypted_file.ends_with(".x") {
-                    encrypted_file[..encrypted_file.len()-2].to_string()
-                } else {
-                    encrypted_file.clone()
+            // Adding a slot needs the file key, so the file must open with our key
+            let open_keys = |sender: Option<String>, signer: Option<String>| -> Result<container::OpenKeys, String> {
+                let sender_pk = match sender {
+                    Some(hex_key) => parse_hex_key(&hex_key)?,
+                    None => {
+                        let mut own_pk = [0u8; 32];
+                        crypto_scalarmult_base(&mut own_pk, &sk);
+                        own_pk
+                    }
                 };
-                (
-                    hex::decode(fs::read_to_string(&encrypted_file)
-                        .map_err(|e| format!("Failed to read encrypted file {}: {}", encrypted_file, e))?)
-                        .map_err(|e| format!("Invalid hex in file {}: {}", encrypted_file, e))?,
-                    Some(output_file)
-                )
-            } else {
-                (hex::decode(&input).map_err(|e| format!("Invalid hex input: {}", e))?, None)
+                let signer_pk = signer.map(|key_input| load_signing_public_key(&key_input)).transpose()?;
+                Ok(container::OpenKeys {
+                    recipient_sk: sk,
+                    sender_pk: Some(sender_pk),
+                    signer_pk: signer_pk.map(|k| k.as_slice().try_into().unwrap()),
+                })
             };
 
-            if combined.len() < 24 + 16 {
-                return Err("Input too short; must contain nonce and ciphertext".to_string());
+            match command {
+                RecipientsCommands::Add { pubkey, sender, signer, .. } => {
+                    let keys = open_keys(sender, signer)?;
+                    let added = container::add_recipients(Path::new(&encrypted_file), &keys, &sk, &parse_keys(&pubkey)?)?;
+                    if verbose {
+                        println!("Added {} recipient(s) to {}; they decrypt with your public key as the sender key", added, encrypted_file);
+                    }
+                }
+                RecipientsCommands::Remove { pubkey, .. } => {
+                    let removed = container::remove_recipients(Path::new(&encrypted_file), &sk, &parse_keys(&pubkey)?)?;
+                    if removed == 0 {
+                        return Err(format!("{} has no key slot wrapped by your key for these recipients", encrypted_file));
+                    }
+                    if verbose {
+                        println!("Removed {} key slot(s) from {}", removed, encrypted_file);
+                    }
+                }
+                RecipientsCommands::Rotate { old, new, sender, signer, .. } => {
+                    let keys = open_keys(sender, signer)?;
+                    let (added, removed) = container::rotate_recipients(Path::new(&encrypted_file), &keys, &sk, &parse_keys(&old)?, &parse_keys(&new)?)?;
+                    if verbose {
+                        println!("Replaced {} key slot(s) in {} by {} new one(s)", removed, encrypted_file, added);
+                    }
+                }
             }
-            let nonce: [u8; 24] = combined[..24].try_into().unwrap();
-            let ciphertext = &combined[24..];
-            let mut plaintext = vec![0u8; ciphertext.len() - 16];
-            crypto_box_open_easy(&mut plaintext, ciphertext, &nonce, &pk, &sk)
-                .map_err(|e| format!("Error decrypting data: {}", e))?;
+        }
 
-            if let Some(output_file) = output_path {
-                fs::write(&output_file, &plaintext)
-                    .map_err(|e| format!("Failed to write decrypted file {}: {}", output_file, e))?;
+        Commands::Archive { command } => match command {
+            ArchiveCommands::List { file, pubkey, seckey, signer, detached_header, id } => {
+                let sk = match seckey {
+                    Some(hex_key) => parse_hex_key(&hex_key)?,
+                    None => {
+                        let secret_key_path = identity_key_path(id.as_deref(), "enc_secret")?;
+                        let sk_vec = load_or_generate_encryption_key(&secret_key_path, true, verbose)?;
+                        sk_vec.as_slice().try_into().map_err(|_| "Secret key must be 32 bytes")?
+                    }
+                };
+                let pk = match pubkey {
+                    Some(hex_key) => parse_hex_key(&hex_key)?,
+                    None => {
+                        let public_key_path = identity_key_path(id.as_deref(), "enc_public")?;
+                        let pk_vec = load_or_generate_encryption_key(&public_key_path, false, verbose)?;
+                        pk_vec.as_slice().try_into().map_err(|_| "Public key must be 32 bytes")?
+                    }
+                };
+                let signer_pk = signer.map(|key_input| load_signing_public_key(&key_input)).transpose()?;
+                let keys = container::OpenKeys {
+                    recipient_sk: sk,
+                    sender_pk: Some(pk),
+                    signer_pk: signer_pk.map(|k| k.as_slice().try_into().unwrap()),
+                };
+                let encrypted_file = if file.ends_with(".x") { file.clone() } else { format!("{}.x", file) };
+                let header_file = detached_header.map(|path| path.unwrap_or_else(|| PathBuf::from(format!("{}.hdr", encrypted_file))));
                 if verbose {
-                    println!("Decrypted file saved to: {}", output_file);
+                    if let Some(name) = container::stored_name(Path::new(&encrypted_file), header_file.as_deref(), &keys)? {
+                        println!("Archive: {}", name);
+                    }
                 }
-            } else {
-                io::stdout()
-                    .write_all(&plaintext)
-                    .map_err(|e| format!("Failed to write decrypted data: {}", e))?;
-                io::stdout().flush().map_err(|e| format!("Failed to flush output: {}", e))?;
+                let reader = container::PlaintextReader::open(Path::new(&encrypted_file), header_file.as_deref(), &keys)?;
+                let entries = archive::list(reader)?;
+                for entry in &entries {
+                    println!("{}", entry);
+                }
+                if verbose {
+                    let total: u64 = entries.iter().map(|e| e.size).sum();
+                    println!("{} entries, {} bytes", entries.len(), total);
+                }
+            }
+        },
+
+        Commands::Channel { command } => match command {
+            #[cfg(feature = "full")]
+            ChannelCommands::Publish { dir, key, expires } => {
+                let secret_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+                let sk = load_signing_key(&secret_key_input, true)?;
+                let validity = humantime::parse_duration(&expires)
+                    .map_err(|e| format!("Invalid expiry '{}': {}", expires, e))?;
+                let version = channel::publish(&dir, sk.as_slice().try_into().unwrap(), validity.as_secs(), unix_now())?;
+                if verbose {
+                    println!("Published channel version {} for {}", version, dir.display());
+                } else {
+                    println!("{}", version);
+                }
+            }
+            ChannelCommands::Verify { dir, key, state } => {
+                let public_key_input = key.unwrap_or_else(|| get_default_key_path("sign_public").to_string_lossy().into_owned());
+                let pk = load_signing_public_key(&public_key_input)?;
+                let state = state.unwrap_or_else(|| default_key_file("channel.state"));
+                let version = channel::verify(&dir, pk.as_slice().try_into().unwrap(), &state, unix_now())?;
+                if verbose {
+                    println!("Channel {} is valid at version {}", dir.display(), version);
+                } else {
+                    println!("valid");
+                }
+            }
+        },
+
+        Commands::Hash { files, alg, create, check, force } => {
+            if let Some(sums_path) = check {
+                let alg = alg.unwrap_or_else(|| HashAlg::for_sums_file(&sums_path));
+                let text = fs::read_to_string(&sums_path)
+                    .map_err(|e| format!("Failed to read checksum file {}: {}", sums_path.display(), e))?;
+                let (mut checked, mut failed) = (0, 0);
+                for line in text.lines().filter(|l| !l.trim().is_empty() && !l.starts_with('#')) {
+                    let entry = checksum::parse_line(line, alg)?;
+                    checked += 1;
+                    match checksum::hash_file(Path::new(&entry.name), entry.alg, entry.digest.len()) {
+                        Ok(digest) if digest == entry.digest => {
+                            if verbose {
+                                println!("{}: OK", entry.name);
+                            }
+                        }
+                        Ok(_) => {
+                            println!("{}: FAILED", entry.name);
+                            failed += 1;
+                        }
+                        Err(e) => {
+                            if verbose {
+                                eprintln!("{}", e);
+                            }
+                            println!("{}: FAILED open or read", entry.name);
+                            failed += 1;
+                        }
+                    }
+                }
+                if checked == 0 {
+                    return Err(format!("No checksum lines found in {}", sums_path.display()));
+                }
+                if failed > 0 {
+                    return Err(format!("{} of {} checksums did not match", failed, checked));
+                }
+                println!("valid");
+                return Ok(());
+            }
+            if let Some(sums_path) = &create {
+                check_overwrite(sums_path, force)?;
+            }
+            let alg = alg.unwrap_or_else(|| create.as_deref().map(HashAlg::for_sums_file).unwrap_or(HashAlg::Blake2b));
+            let len = if alg == HashAlg::Blake2b { 64 } else { 32 };
+            let files = if files.is_empty() { vec![PathBuf::from("-")] } else { files };
+            let mut lines = String::new();
+            for path in files {
+                let mut paths = if path.is_dir() { channel::list_files(&path)? } else { vec![path] };
+                paths.sort();
+                for path in paths {
+                    let name = path.to_string_lossy().into_owned();
+                    lines.push_str(&checksum::format_line(&name, &checksum::hash_file(&path, alg, len)?));
+                    lines.push('\n');
+                }
+            }
+            match create {
+                Some(sums_path) => {
+                    fs::write(&sums_path, lines)
+                        .map_err(|e| format!("Failed to write checksum file {}: {}", sums_path.display(), e))?;
+                    if verbose {
+                        println!("Checksums saved to: {}", sums_path.display());
+                    }
+                }
+                None => print!("{}", lines),
             }
         }
 
-        Commands::Generate { key } => {
-            let dir = key.unwrap_or_else(|| {
-                std::env::current_exe()
-                    .unwrap()
-                    .parent()
-                    .unwrap()
-                    .to_path_buf()
-            });
-            generate_keys(&dir, verbose)?;
-            if !verbose {
-                println!("Keys generated successfully");
+        Commands::GitSign { args } => git_sign(&args, verbose)?,
+
+        Commands::Manifest { command } => match command {
+            #[cfg(feature = "full")]
+            ManifestCommands::Create { dir, out, key, exclude, force } => {
+                let out = out.unwrap_or_else(|| PathBuf::from(manifest::DEFAULT_NAME));
+                check_overwrite(&out, force)?;
+                let secret_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+                let sk = load_or_generate_signing_key(&secret_key_input, true, verbose)?;
+                let ignore = ignore::Ignore::load(&dir, &exclude)?;
+                let text = manifest::create(&dir, &out, sk.as_slice().try_into().unwrap(), &ignore)?;
+                fs::write(&out, text).map_err(|e| format!("Failed to write manifest {}: {}", out.display(), e))?;
+                if verbose {
+                    println!("Manifest of {} saved to: {}", dir.display(), out.display());
+                }
+            }
+            ManifestCommands::Verify { dir, manifest, key, exclude } => {
+                let public_key_input = key.unwrap_or_else(|| get_default_key_path("sign_public").to_string_lossy().into_owned());
+                let pk = load_signing_public_key(&public_key_input)?;
+                let ignore = ignore::Ignore::load(&dir, &exclude)?;
+                let changes = manifest::verify(&dir, &manifest, pk.as_slice().try_into().unwrap(), &ignore)?;
+                for change in &changes {
+                    println!("{}", change);
+                }
+                if !changes.is_empty() {
+                    return Err(format!("{} files differ from the manifest", changes.len()));
+                }
+                println!("valid");
+            }
+        },
+
+        Commands::License { command } => match command {
+            #[cfg(feature = "full")]
+            LicenseCommands::Issue { customer, feature, expires, key } => {
+                let secret_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+                let sk = load_signing_key(&secret_key_input, true)?;
+                let expires = match expires {
+                    Some(duration) => {
+                        let validity = humantime::parse_duration(&duration)
+                            .map_err(|e| format!("Invalid expiry '{}': {}", duration, e))?;
+                        Some(unix_now() + validity.as_secs())
+                    }
+                    None => None,
+                };
+                let lic = license::License { customer, features: feature, expires };
+                println!("{}", license::issue(&lic, sk.as_slice().try_into().unwrap())?);
+            }
+            LicenseCommands::Verify { token, key, feature } => {
+                let public_key_input = key.unwrap_or_else(|| get_default_key_path("sign_public").to_string_lossy().into_owned());
+                let pk = load_signing_public_key(&public_key_input)?;
+                let token = if Path::new(&token).is_file() {
+                    fs::read_to_string(&token).map_err(|e| format!("Failed to read license {}: {}", token, e))?
+                } else {
+                    token
+                };
+                let lic = license::verify(&token, pk.as_slice().try_into().unwrap(), unix_now())?;
+                if let Some(feature) = feature {
+                    if !lic.has_feature(&feature) {
+                        return Err(format!("License for '{}' does not include feature '{}'", lic.customer, feature));
+                    }
+                }
+                if verbose {
+                    println!("Customer: {}", lic.customer);
+                    println!("Features: {}", lic.features.join(", "));
+                    match lic.expires {
+                        Some(expires) => println!("Expires: {} (unix time)", expires),
+                        None => println!("Expires: never"),
+                    }
+                } else {
+                    println!("valid");
+                }
+            }
+        },
+
+        Commands::Jwt { command } => match command {
+            #[cfg(feature = "full")]
+            JwtCommands::Sign { claims, expires, key } => {
+                let secret_key_input = key.unwrap_or_else(|| get_default_key_path("sign_secret").to_string_lossy().into_owned());
+                let sk = load_or_generate_signing_key(&secret_key_input, true, verbose)?;
+                let text = if claims == Path::new("-") {
+                    let mut text = String::new();
+                    io::stdin().read_to_string(&mut text).map_err(|e| format!("Failed to read claims: {}", e))?;
+                    text
+                } else {
+                    fs::read_to_string(&claims).map_err(|e| format!("Failed to read claims from {}: {}", claims.display(), e))?
+                };
+                let mut claims: serde_json::Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON claims: {}", e))?;
+                if let Some(expires) = expires {
+                    let validity = humantime::parse_duration(&expires).map_err(|e| format!("Invalid expiry '{}': {}", expires, e))?;
+                    let now = unix_now();
+                    if let Some(claims) = claims.as_object_mut() {
+                        claims.insert("iat".to_string(), now.into());
+                        claims.insert("exp".to_string(), (now + validity.as_secs()).into());
+                    }
+                }
+                let token = jwt::sign(&claims, sk.as_slice().try_into().unwrap(), &jwk::key_id(sk[32..].try_into().unwrap()))?;
+                println!("{}", token);
+            }
+            JwtCommands::Verify { token, key, aud, leeway } => {
+                let public_key_input = key.unwrap_or_else(|| get_default_key_path("sign_public").to_string_lossy().into_owned());
+                let pk = load_signing_public_key(&public_key_input)?;
+                let token = if Path::new(&token).is_file() {
+                    fs::read_to_string(&token).map_err(|e| format!("Failed to read token {}: {}", token, e))?
+                } else {
+                    token
+                };
+                let leeway = humantime::parse_duration(&leeway).map_err(|e| format!("Invalid leeway '{}': {}", leeway, e))?;
+                let (header, claims) = jwt::verify(&token, pk.as_slice().try_into().unwrap(), unix_now(), aud.as_deref(), leeway.as_secs())?;
+                if verbose {
+                    println!("Header: {}", header);
+                }
+                println!("{}", serde_json::to_string_pretty(&claims).unwrap());
             }
+        },
+
+        #[cfg(feature = "full")]
+        Commands::Watch { dir, on_change, out, key, exclude, id } => {
+            let action = match on_change {
+                WatchAction::Sign => {
+                    if key.len() > 1 {
+                        return Err("watch --on-change sign takes one --key".to_string());
+                    }
+                    let secret_key_input = match key.into_iter().next() {
+                        Some(key) => key,
+                        None => identity_key_path(id.as_deref(), "sign_secret")?.to_string_lossy().into_owned(),
+                    };
+                    let sk = load_or_generate_signing_key(&secret_key_input, true, verbose)?;
+                    watch::Action::Sign(sk.as_slice().try_into().map_err(|_| "Secret key must be 64 bytes")?)
+                }
+                WatchAction::Encrypt => {
+                    let mut recipients: Vec<[u8; 32]> = Vec::new();
+                    for hex_key in &key {
+                        let pk = parse_hex_key(hex_key)?;
+                        if !recipients.contains(&pk) {
+                            recipients.push(pk);
+                        }
+                    }
+                    if recipients.is_empty() {
+                        let public_key_path = identity_key_path(id.as_deref(), "enc_public")?;
+                        let pk_vec = load_or_generate_encryption_key(&public_key_path, false, verbose)?;
+                        recipients.push(pk_vec.as_slice().try_into().map_err(|_| "Public key must be 32 bytes")?);
+                    }
+                    let secret_key_path = identity_key_path(id.as_deref(), "enc_secret")?;
+                    let sk_vec = load_or_generate_encryption_key(&secret_key_path, true, verbose)?;
+                    let sender = container::Sender::Static(sk_vec.as_slice().try_into().map_err(|_| "Secret key must be 32 bytes")?);
+                    watch::Action::Encrypt { recipients, sender }
+                }
+            };
+            let ignore = ignore::Ignore::load(&dir, &exclude)?;
+            watch::Watch::new(&dir, out.as_deref(), action, ignore, verbose)?.run()?;
         }
 
-        Commands::Print { key } => {
-            let dir = key.unwrap_or_else(|| {
-                std::env::current_exe()
-                    .unwrap()
-                    .parent()
-                    .unwrap()
-                    .to_path_buf()
-            });
-            print_keys(&dir, verbose)?;
+        #[cfg(feature = "full")]
+        Commands::Pair { listen, connect, code, out, force } => {
+            // Refuse before pairing, so a code is not spent on keys that can't be saved
+            let out_paths = out.map(|dir| (dir.join("sign_public.key"), dir.join("enc_public.key"), dir));
+            if let Some((sign_path, enc_path, _)) = &out_paths {
+                check_overwrite(sign_path, force)?;
+                check_overwrite(enc_path, force)?;
+            }
+            let sign_pk = load_or_generate_signing_key(&get_default_key_path("sign_public").to_string_lossy(), false, verbose)?;
+            let enc_pk = load_or_generate_encryption_key(&get_default_key_path("enc_public"), false, verbose)?;
+            let own = pair::PeerKeys {
+                sign_pk: sign_pk.as_slice().try_into().unwrap(),
+                enc_pk: enc_pk.as_slice().try_into().unwrap(),
+            };
+            let peer = match (listen, connect) {
+                (Some(addr), _) => {
+                    let code = code.unwrap_or_else(pair::generate_code);
+                    eprintln!("Pairing code: {}", code);
+                    eprintln!("Waiting on {} for the other device...", addr);
+                    pair::listen(&addr, &code, &own)?
+                }
+                (None, Some(addr)) => {
+                    let code = match code {
+                        Some(code) => code,
+                        None => {
+                            eprint!("Pairing code: ");
+                            io::stderr().flush().map_err(|e| format!("Failed to write prompt: {}", e))?;
+                            let mut line = String::new();
+                            io::stdin().read_line(&mut line).map_err(|e| format!("Failed to read pairing code: {}", e))?;
+                            line.trim().to_string()
+                        }
+                    };
+                    pair::connect(&addr, &code, &own)?
+                }
+                (None, None) => unreachable!("clap requires --listen or --connect"),
+            };
+
+            if verbose {
+                println!("Peer signing public key: {} ({})", hex::encode(peer.sign_pk), fingerprint(&peer.sign_pk, FingerprintAlg::default()));
+                println!("Peer encryption public key: {} ({})", hex::encode(peer.enc_pk), fingerprint(&peer.enc_pk, FingerprintAlg::default()));
+            } else {
+                println!("{}", hex::encode(peer.sign_pk));
+                println!("{}", hex::encode(peer.enc_pk));
+            }
+            if let Some((sign_path, enc_path, dir)) = out_paths {
+                fs::create_dir_all(&dir)
+                    .map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+                cleanup::write_atomic(&sign_path, hex::encode(peer.sign_pk))
+                    .map_err(|e| format!("Failed to write signing public key to {}: {}", sign_path.display(), e))?;
+                cleanup::write_atomic(&enc_path, hex::encode(peer.enc_pk))
+                    .map_err(|e| format!("Failed to write encryption public key to {}: {}", enc_path.display(), e))?;
+            }
+        }
+
+        #[cfg(feature = "full")]
+        Commands::Report { within, json } => {
+            report(&within, json)?;
+        }
+
+        Commands::Version { features } => {
+            println!("sodix {}", env!("CARGO_PKG_VERSION"));
+            if features {
+                let build: Vec<&str> = [("full", cfg!(feature = "full")), ("openpgp", cfg!(feature = "openpgp"))]
+                    .into_iter()
+                    .filter_map(|(name, on)| on.then_some(name))
+                    .collect();
+                println!("Build features: {}", if build.is_empty() { "none (verify/decrypt only)".to_string() } else { build.join(", ") });
+                let cpu = cpu::features();
+                if cpu.is_empty() {
+                    println!("CPU features ({}): no accelerated code paths, portable code only", std::env::consts::ARCH);
+                } else {
+                    println!("CPU features ({}):", std::env::consts::ARCH);
+                    for feature in cpu {
+                        println!("  {:<10} {:<9}  {}", feature.name, feature.status(), feature.used_for);
+                    }
+                }
+            }
         }
     }
     Ok(())
@@ -0,0 +1,116 @@
+use crate::channel::{hash_file, list_files_except, relative_name};
+use crate::ignore::Ignore;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Signed directory manifest:
+//
+//   sodix-manifest 1
+//   file <BLAKE2b-256> <size> <path relative to the directory>
+//   signature <hex signature over every line above>
+//
+// Files are sorted by path and paths use '/', so the same tree always gives the same
+// manifest. A manifest kept inside the directory does not list itself.
+#[cfg(feature = "full")]
+pub const DEFAULT_NAME: &str = "manifest.sdx";
+const HEADER: &str = "sodix-manifest 1";
+const SIGNATURE_PREFIX: &str = "signature ";
+
+/// How the directory differs from a verified manifest.
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Modified(String),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added(name) => write!(f, "added {}", name),
+            Change::Removed(name) => write!(f, "removed {}", name),
+            Change::Modified(name) => write!(f, "modified {}", name),
+        }
+    }
+}
+
+/// Build and sign the manifest of `dir`, leaving out `manifest_path` if it is inside
+/// and whatever `ignore` matches.
+#[cfg(feature = "full")]
+pub fn create(dir: &Path, manifest_path: &Path, sk: &[u8; 64], ignore: &Ignore) -> Result<String, String> {
+    let mut text = format!("{}\n", HEADER);
+    for (name, path) in files(dir, manifest_path, ignore)? {
+        let (size, hash) = hash_file(&path)?;
+        text.push_str(&format!("file {} {} {}\n", hex::encode(hash), size, name));
+    }
+    let mut signature = [0u8; 64];
+    crypto_sign_detached(&mut signature, text.as_bytes(), sk)
+        .map_err(|e| format!("Error signing data: {}", e))?;
+    text.push_str(&format!("{}{}\n", SIGNATURE_PREFIX, hex::encode(signature)));
+    Ok(text)
+}
+
+/// Check the manifest's signature against `pk`, then compare it with `dir`. Returns
+/// the differences, sorted by path; empty means the tree matches. Listed files are
+/// always compared; `ignore` only decides which unlisted files count as added.
+pub fn verify(dir: &Path, manifest_path: &Path, pk: &[u8; 32], ignore: &Ignore) -> Result<Vec<Change>, String> {
+    let text = std::fs::read_to_string(manifest_path)
+        .map_err(|e| format!("Failed to read manifest {}: {}", manifest_path.display(), e))?;
+    let body_len = text
+        .rfind(&format!("\n{}", SIGNATURE_PREFIX))
+        .map(|i| i + 1)
+        .ok_or("Manifest is not signed")?;
+    let (body, signature_line) = text.split_at(body_len);
+    let sig = hex::decode(signature_line[SIGNATURE_PREFIX.len()..].trim())
+        .map_err(|e| format!("Invalid hex signature in manifest: {}", e))?;
+    crate::verify_signature(&sig, body.as_bytes(), pk)
+        .map_err(|e| format!("Manifest signature is invalid: {}", e))?;
+
+    let mut lines = body.lines();
+    if lines.next() != Some(HEADER) {
+        return Err("Not a sodix manifest".to_string());
+    }
+    let mut on_disk = files(dir, manifest_path, ignore)?;
+    let mut changes = Vec::new();
+    for line in lines {
+        let mut parts = line.splitn(4, ' ');
+        let (hash, size, name) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some("file"), Some(hash), Some(size), Some(name)) => (hash, size, name),
+            _ => return Err(format!("Invalid manifest line '{}'", line)),
+        };
+        let ignored = || Some(dir.join(name)).filter(|path| ignore.is_ignored(name) && path.is_file());
+        match on_disk.remove(name).or_else(ignored) {
+            Some(path) => {
+                let (actual_size, actual_hash) = hash_file(&path)?;
+                if actual_size.to_string() != size || hex::encode(actual_hash) != hash {
+                    changes.push(Change::Modified(name.to_string()));
+                }
+            }
+            None => changes.push(Change::Removed(name.to_string())),
+        }
+    }
+    changes.extend(on_disk.into_keys().map(Change::Added));
+    changes.sort_by(|a, b| change_name(a).cmp(change_name(b)));
+    Ok(changes)
+}
+
+fn change_name(change: &Change) -> &str {
+    match change {
+        Change::Added(name) | Change::Removed(name) | Change::Modified(name) => name,
+    }
+}
+
+/// Files under `dir` by listed name, without the manifest itself or ignored files.
+fn files(dir: &Path, manifest_path: &Path, ignore: &Ignore) -> Result<BTreeMap<String, PathBuf>, String> {
+    let manifest = manifest_path.canonicalize().ok();
+    let mut files = BTreeMap::new();
+    for path in list_files_except(dir, ignore)? {
+        if manifest.is_some() && path.canonicalize().ok() == manifest {
+            continue;
+        }
+        files.insert(relative_name(dir, &path)?, path);
+    }
+    Ok(files)
+}
@@ -0,0 +1,97 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+// Secret keys, seeds and passphrases are locked into RAM (mlock, VirtualLock on
+// Windows) as they are read or derived, so the kernel never writes them out to swap
+// or a hibernation file. They are wiped on drop (zeroize); the pages stay locked until
+// exit, since the secrets are few and small and the allocator may have put other
+// locked secrets on the same page.
+//
+// Locking needs a large enough RLIMIT_MEMLOCK (`ulimit -l`; 8 MiB on most Linux
+// systems) or, on Windows, room in the working set. When it is refused the secret is
+// used unlocked, with a note under -v. --no-mlock turns locking off for the run.
+//
+// With the first secret, core dumps are turned off on Unix (RLIMIT_CORE soft limit 0,
+// and on Linux the dumpable flag, which also keeps other processes of the user from
+// reading our memory through ptrace or /proc), so a crash cannot write key material
+// to a core file. --allow-coredumps keeps them for debugging.
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+static NOTED: AtomicBool = AtomicBool::new(false);
+static ALLOW_CORE_DUMPS: AtomicBool = AtomicBool::new(false);
+static NO_CORE_DUMPS: Once = Once::new();
+
+/// Leave secrets in swappable memory, for the rest of the run (--no-mlock).
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Let a crash dump core even with secrets loaded (--allow-coredumps).
+pub fn allow_core_dumps() {
+    ALLOW_CORE_DUMPS.store(true, Ordering::Relaxed);
+}
+
+/// Note on stderr when a secret could not be locked (-v).
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+/// Keep the pages holding `secret` out of swap, where the system permits, and any
+/// secret out of a core file.
+pub fn lock(secret: &[u8]) {
+    if !ALLOW_CORE_DUMPS.load(Ordering::Relaxed) {
+        NO_CORE_DUMPS.call_once(disable_core_dumps);
+    }
+    if secret.is_empty() || DISABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = lock_pages(secret) {
+        if VERBOSE.load(Ordering::Relaxed) && !NOTED.swap(true, Ordering::Relaxed) {
+            eprintln!("Note: secret keys are not locked in memory and may be swapped to disk ({}); raise `ulimit -l` or pass --no-mlock", e);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn disable_core_dumps() {
+    // SAFETY: both calls only change limits and flags of this process. The hard limit
+    // is left alone, so commands run from hooks may still raise theirs.
+    unsafe {
+        let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) == 0 {
+            limit.rlim_cur = 0;
+            libc::setrlimit(libc::RLIMIT_CORE, &limit);
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        libc::prctl(libc::PR_SET_DUMPABLE, 0);
+    }
+}
+
+#[cfg(not(unix))]
+fn disable_core_dumps() {}
+
+#[cfg(unix)]
+fn lock_pages(secret: &[u8]) -> io::Result<()> {
+    // SAFETY: mlock only changes how the pages of a live allocation are paged; the
+    // range is a valid slice, and the kernel rounds it out to whole pages.
+    match unsafe { libc::mlock(secret.as_ptr().cast(), secret.len()) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(windows)]
+fn lock_pages(secret: &[u8]) -> io::Result<()> {
+    // SAFETY: as for mlock; VirtualLock rounds the range out to whole pages.
+    match unsafe { windows_sys::Win32::System::Memory::VirtualLock(secret.as_ptr().cast(), secret.len()) } {
+        0 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn lock_pages(_secret: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "memory locking is not supported on this platform"))
+}
@@ -0,0 +1,83 @@
+use std::sync::OnceLock;
+
+// Memory budget for the run (--max-memory), so sodix fits in a small container or
+// cgroup instead of being OOM-killed halfway through a file. Without one the defaults
+// hold: chunks sealed on every core, zstd with the window of the level asked for, and
+// messages of up to 4 GiB handled in memory.
+//
+// Of a budget, RESERVED covers the program itself; of the rest, a quarter may go to
+// chunks in flight and half to zstd, whose window and match tables are cut to fit (high
+// levels then find fewer matches). Decompression refuses frames whose window does not
+// fit rather than allocating it. Files larger than half the rest are signed prehashed
+// and only encrypted to a container. Memory-mapped input is page cache, which the
+// kernel reclaims under the limit, so it is not counted.
+const RESERVED: u64 = 16 << 20;
+const MIN_LIMIT: u64 = 32 << 20;
+#[cfg(feature = "full")]
+/// zstd compression memory per byte of window, with hash and chain tables of twice
+/// the window's entries at 4 bytes each
+const ZSTD_COMPRESS_COST: u64 = 18;
+#[cfg(feature = "full")]
+/// zstd window, chain and hash logs of each compression level (0 for the negative
+/// ones) on large inputs, from zstd's clevels.h
+const ZSTD_LEVEL_LOGS: [(u32, u32, u32); 23] = [
+    (19, 12, 13), (19, 13, 14), (20, 15, 16), (21, 16, 17), (21, 18, 18), (21, 18, 19),
+    (21, 18, 19), (21, 19, 20), (21, 19, 20), (22, 20, 21), (22, 21, 22), (22, 21, 22),
+    (22, 22, 23), (22, 22, 22), (22, 22, 23), (22, 23, 23), (22, 22, 22), (23, 23, 22),
+    (23, 23, 22), (23, 24, 22), (25, 25, 23), (26, 26, 24), (27, 27, 25),
+];
+const ZSTD_WINDOW_LOG_MIN: u32 = 10;
+
+static LIMIT: OnceLock<u64> = OnceLock::new();
+
+/// Keep the rest of the run within `bytes` (--max-memory).
+pub fn set_limit(bytes: u64) -> Result<(), String> {
+    if bytes < MIN_LIMIT {
+        return Err(format!("--max-memory must be at least {} MiB", MIN_LIMIT >> 20));
+    }
+    let _ = LIMIT.set(bytes);
+    Ok(())
+}
+
+/// The budget beyond what the program itself needs, if there is one.
+fn available() -> Option<u64> {
+    LIMIT.get().map(|limit| limit - RESERVED)
+}
+
+/// How many buffers of `size` bytes may be in flight at once, at most `wanted`.
+#[cfg(feature = "full")]
+pub fn buffers(size: usize, wanted: usize) -> usize {
+    match available() {
+        Some(available) => (available / 4 / size as u64).clamp(1, wanted as u64) as usize,
+        None => wanted,
+    }
+}
+
+/// The zstd window, chain and hash logs to compress at `level` with, when the
+/// level's own would not fit the budget.
+#[cfg(feature = "full")]
+pub fn zstd_logs(level: i32) -> Option<(u32, u32, u32)> {
+    let budget = available()? / 2;
+    let (window, chain, hash) = ZSTD_LEVEL_LOGS[level.clamp(0, 22) as usize];
+    if (1u64 << window) + (4u64 << chain) + (4u64 << hash) <= budget {
+        return None;
+    }
+    let fits = largest_log(budget / ZSTD_COMPRESS_COST);
+    Some((window.min(fits), chain.min(fits + 1), hash.min(fits + 1)))
+}
+
+/// The largest zstd window log a decompressor may allocate, if there is a budget.
+pub fn zstd_window_log_max() -> Option<u32> {
+    available().map(|available| largest_log(available / 2))
+}
+
+/// Size from which a file is too large to hold in memory whole.
+#[cfg(feature = "full")]
+pub fn large_file(default: u64) -> u64 {
+    available().map_or(default, |available| default.min(available / 2))
+}
+
+/// log2 of the largest power of two up to `bytes`, as a zstd window log.
+fn largest_log(bytes: u64) -> u32 {
+    bytes.checked_ilog2().unwrap_or(0).max(ZSTD_WINDOW_LOG_MIN)
+}
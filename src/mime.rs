@@ -0,0 +1,130 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+#[cfg(feature = "full")]
+use rand::Rng;
+
+/// Ciphertext recovered from a saved email, plus the attachment name and top-level headers.
+pub struct MimeContainer {
+    pub data: Vec<u8>,
+    pub filename: Option<String>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl MimeContainer {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Build a multipart/mixed message with `data` as a base64 attachment. `headers` are
+/// added to the top-level header block (e.g. X-Sodix-Recipient-Fingerprint).
+#[cfg(feature = "full")]
+pub fn wrap(data: &[u8], filename: &str, headers: &[(&str, String)]) -> String {
+    let mut rnd = [0u8; 12];
+    rand::rng().fill(&mut rnd);
+    let boundary = format!("sodix-{}", hex::encode(rnd));
+
+    let mut out = String::new();
+    out.push_str("MIME-Version: 1.0\r\n");
+    for (name, value) in headers {
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    out.push_str(&format!("Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n", boundary));
+    out.push_str("This is a multi-part message in MIME format.\r\n\r\n");
+
+    out.push_str(&format!("--{}\r\n", boundary));
+    out.push_str("Content-Type: text/plain; charset=us-ascii\r\n\r\n");
+    out.push_str(&format!(
+        "The attachment {} is encrypted with sodix.\r\nSave this email and run: sodix decrypt --mime <saved.eml>\r\n\r\n",
+        filename
+    ));
+
+    out.push_str(&format!("--{}\r\n", boundary));
+    out.push_str(&format!("Content-Type: application/octet-stream; name=\"{}\"\r\n", filename));
+    out.push_str(&format!("Content-Disposition: attachment; filename=\"{}\"\r\n", filename));
+    out.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+    let encoded = STANDARD.encode(data);
+    for line in encoded.as_bytes().chunks(76) {
+        out.push_str(std::str::from_utf8(line).unwrap());
+        out.push_str("\r\n");
+    }
+    out.push_str(&format!("--{}--\r\n", boundary));
+    out
+}
+
+/// Extract the first base64 attachment from a message produced by [`wrap`]
+/// (or re-serialized by a mail client).
+pub fn unwrap(message: &str) -> Result<MimeContainer, String> {
+    let message = message.replace("\r\n", "\n");
+    let (head, body) = split_headers(&message);
+    let headers = parse_headers(head);
+    let content_type = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Content-Type"))
+        .map(|(_, v)| v.clone())
+        .ok_or("Not a MIME message: missing Content-Type header")?;
+    let boundary = header_param(&content_type, "boundary")
+        .ok_or("Not a multipart MIME message: missing boundary")?;
+
+    let delimiter = format!("--{}", boundary);
+    for part in body.split(delimiter.as_str()).skip(1) {
+        if part.starts_with("--") {
+            break;
+        }
+        let (part_head, part_body) = split_headers(part.trim_start_matches('\n'));
+        let part_headers = parse_headers(part_head);
+        let is_base64 = part_headers.iter().any(|(k, v)| {
+            k.eq_ignore_ascii_case("Content-Transfer-Encoding") && v.trim().eq_ignore_ascii_case("base64")
+        });
+        if !is_base64 {
+            continue;
+        }
+        let filename = part_headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("Content-Disposition"))
+            .and_then(|(_, v)| header_param(v, "filename"));
+        let encoded: String = part_body.chars().filter(|c| !c.is_whitespace()).collect();
+        let data = STANDARD
+            .decode(encoded)
+            .map_err(|e| format!("Invalid base64 in MIME attachment: {}", e))?;
+        return Ok(MimeContainer { data, filename, headers });
+    }
+    Err("No base64 attachment found in MIME message".to_string())
+}
+
+fn split_headers(text: &str) -> (&str, &str) {
+    match text.find("\n\n") {
+        Some(i) => (&text[..i], &text[i + 2..]),
+        None => (text, ""),
+    }
+}
+
+fn parse_headers(head: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in head.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            // Folded continuation of the previous header
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    headers
+}
+
+fn header_param(value: &str, param: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|p| {
+        let (k, v) = p.trim().split_once('=')?;
+        if k.trim().eq_ignore_ascii_case(param) {
+            Some(v.trim().trim_matches('"').to_string())
+        } else {
+            None
+        }
+    })
+}
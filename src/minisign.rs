@@ -0,0 +1,252 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use dryoc::classic::crypto_generichash::{crypto_generichash_final, crypto_generichash_init, crypto_generichash_update};
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_generichash::crypto_generichash;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::{crypto_sign_detached, crypto_sign_keypair};
+#[cfg(feature = "full")]
+use rand::Rng;
+use std::io::Read;
+
+// minisign / rsign2 formats (https://jedisct1.github.io/minisign/):
+//
+//   public key:  base64("Ed" | key id (8) | Ed25519 public key (32))
+//   secret key:  base64("Ed" | kdf alg (2) | "B2" | kdf salt (32) | opslimit u64 LE | memlimit u64 LE
+//                       | key id (8) | Ed25519 secret key (64) | BLAKE2b-256("Ed" | key id | secret key))
+//   signature:   untrusted comment: <text>
+//                base64("ED" | key id (8) | Ed25519 signature of BLAKE2b-512(message))
+//                trusted comment: <text>
+//                base64(Ed25519 signature of the signature bytes | trusted comment)
+//
+// Each file is preceded by an "untrusted comment:" line. Legacy "Ed" signatures sign
+// the message itself rather than its hash. Only unencrypted secret keys (kdf alg 0,
+// `minisign -G -W`) can be used; encrypted ones need scrypt and a password.
+const SIG_ALG: &[u8; 2] = b"Ed";
+const SIG_ALG_HASHED: &[u8; 2] = b"ED";
+#[cfg(feature = "full")]
+const KDF_NONE: &[u8; 2] = &[0, 0];
+#[cfg(feature = "full")]
+const CHECKSUM_ALG: &[u8; 2] = b"B2";
+#[cfg(feature = "full")]
+const SECRET_KEY_LEN: usize = 2 + 2 + 2 + 32 + 8 + 8 + 8 + 64 + 32;
+const UNTRUSTED: &str = "untrusted comment: ";
+const TRUSTED: &str = "trusted comment: ";
+
+pub struct PublicKey {
+    pub key_id: [u8; 8],
+    pub public_key: [u8; 32],
+}
+
+#[cfg(feature = "full")]
+pub struct SecretKey {
+    pub key_id: [u8; 8],
+    pub secret_key: [u8; 64],
+}
+
+/// Key id as minisign prints it: the little-endian u64 in upper-case hex.
+pub fn key_id_hex(key_id: &[u8; 8]) -> String {
+    format!("{:016X}", u64::from_le_bytes(*key_id))
+}
+
+/// Parse a public key file, or the bare base64 line (`RW...`) projects publish.
+pub fn parse_public_key(text: &str) -> Result<PublicKey, String> {
+    let bytes = decode_payload(text, "public key")?;
+    if bytes.len() != 42 || &bytes[..2] != SIG_ALG {
+        return Err("Not a minisign public key".to_string());
+    }
+    Ok(PublicKey {
+        key_id: bytes[2..10].try_into().unwrap(),
+        public_key: bytes[10..].try_into().unwrap(),
+    })
+}
+
+#[cfg(feature = "full")]
+pub fn parse_secret_key(text: &str) -> Result<SecretKey, String> {
+    let bytes = decode_payload(text, "secret key")?;
+    if bytes.len() != SECRET_KEY_LEN || &bytes[..2] != SIG_ALG || &bytes[4..6] != CHECKSUM_ALG {
+        return Err("Not a minisign secret key".to_string());
+    }
+    if &bytes[2..4] != KDF_NONE {
+        return Err("Encrypted minisign secret keys are not supported; create an unencrypted one with `minisign -G -W`".to_string());
+    }
+    let key_id: [u8; 8] = bytes[54..62].try_into().unwrap();
+    let secret_key: [u8; 64] = bytes[62..126].try_into().unwrap();
+    if bytes[126..] != checksum(&key_id, &secret_key) {
+        return Err("minisign secret key checksum mismatch".to_string());
+    }
+    Ok(SecretKey { key_id, secret_key })
+}
+
+/// A fresh key pair as (public key file, secret key file) contents.
+#[cfg(feature = "full")]
+pub fn generate() -> (String, String) {
+    let (public_key, secret_key) = crypto_sign_keypair();
+    let mut key_id = [0u8; 8];
+    rand::rng().fill(&mut key_id);
+
+    let public = [&SIG_ALG[..], &key_id, &public_key].concat();
+    let mut secret = Vec::with_capacity(SECRET_KEY_LEN);
+    secret.extend_from_slice(SIG_ALG);
+    secret.extend_from_slice(KDF_NONE);
+    secret.extend_from_slice(CHECKSUM_ALG);
+    secret.extend_from_slice(&[0u8; 32 + 8 + 8]);
+    secret.extend_from_slice(&key_id);
+    secret.extend_from_slice(&secret_key);
+    secret.extend_from_slice(&checksum(&key_id, &secret_key));
+    (
+        format!("{}minisign public key {}\n{}\n", UNTRUSTED, key_id_hex(&key_id), STANDARD.encode(public)),
+        format!("{}minisign secret key (unencrypted)\n{}\n", UNTRUSTED, STANDARD.encode(secret)),
+    )
+}
+
+/// Sign `message` (hashed while streaming) and return the .minisig file contents.
+#[cfg(feature = "full")]
+pub fn sign<R: Read>(message: R, key: &SecretKey, untrusted_comment: &str, trusted_comment: &str) -> Result<String, String> {
+    if untrusted_comment.contains('\n') || trusted_comment.contains('\n') {
+        return Err("Comments must be a single line".to_string());
+    }
+    let mut signature = [0u8; 64];
+    crypto_sign_detached(&mut signature, &hash_message(message)?, &key.secret_key)
+        .map_err(|e| format!("Error signing data: {}", e))?;
+    let mut global_signature = [0u8; 64];
+    crypto_sign_detached(&mut global_signature, &[&signature[..], trusted_comment.as_bytes()].concat(), &key.secret_key)
+        .map_err(|e| format!("Error signing data: {}", e))?;
+    Ok(format!(
+        "{}{}\n{}\n{}{}\n{}\n",
+        UNTRUSTED,
+        untrusted_comment,
+        STANDARD.encode([&SIG_ALG_HASHED[..], &key.key_id, &signature].concat()),
+        TRUSTED,
+        trusted_comment,
+        STANDARD.encode(global_signature)
+    ))
+}
+
+/// Verify a .minisig signature of `message`; returns the verified trusted comment.
+pub fn verify<R: Read>(mut message: R, signature_file: &str, key: &PublicKey) -> Result<String, String> {
+    let mut lines = signature_file.lines().map(str::trim_end);
+    let invalid = || "Not a minisign signature".to_string();
+    lines.next().filter(|l| l.starts_with(UNTRUSTED)).ok_or_else(invalid)?;
+    let sig_bytes = STANDARD.decode(lines.next().ok_or_else(invalid)?).map_err(|e| format!("Invalid minisign signature: {}", e))?;
+    let trusted_comment = lines.next().and_then(|l| l.strip_prefix(TRUSTED)).ok_or_else(invalid)?;
+    let global_signature = STANDARD.decode(lines.next().ok_or_else(invalid)?).map_err(|e| format!("Invalid minisign signature: {}", e))?;
+    if sig_bytes.len() != 74 || global_signature.len() != 64 {
+        return Err(invalid());
+    }
+
+    let key_id: [u8; 8] = sig_bytes[2..10].try_into().unwrap();
+    if key_id != key.key_id {
+        return Err(format!(
+            "Signature was made with key {}, not {}",
+            key_id_hex(&key_id),
+            key_id_hex(&key.key_id)
+        ));
+    }
+    let signature: [u8; 64] = sig_bytes[10..].try_into().unwrap();
+    let signed = match &sig_bytes[..2] {
+        alg if alg == SIG_ALG_HASHED => hash_message(message)?.to_vec(),
+        alg if alg == SIG_ALG => {
+            let mut data = Vec::new();
+            message.read_to_end(&mut data).map_err(|e| format!("Failed to read input: {}", e))?;
+            data
+        }
+        _ => return Err("Unsupported minisign signature algorithm".to_string()),
+    };
+    crypto_sign_verify_detached(&signature, &signed, &key.public_key).map_err(|e| e.to_string())?;
+    crypto_sign_verify_detached(
+        global_signature.as_slice().try_into().unwrap(),
+        &[&signature[..], trusted_comment.as_bytes()].concat(),
+        &key.public_key,
+    )
+    .map_err(|_| "Trusted comment signature is invalid".to_string())?;
+    Ok(trusted_comment.to_string())
+}
+
+/// The base64 payload of a key file, skipping its untrusted comment line if present.
+fn decode_payload(text: &str, what: &str) -> Result<Vec<u8>, String> {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with(UNTRUSTED))
+        .ok_or_else(|| format!("Empty minisign {}", what))?;
+    STANDARD.decode(line).map_err(|e| format!("Invalid minisign {}: {}", what, e))
+}
+
+fn hash_message<R: Read>(mut message: R) -> Result<[u8; 64], String> {
+    let mut state = crypto_generichash_init(None, 64).map_err(|e| e.to_string())?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = message.read(&mut buf).map_err(|e| format!("Failed to read input: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        crypto_generichash_update(&mut state, &buf[..n]);
+    }
+    let mut digest = [0u8; 64];
+    crypto_generichash_final(state, &mut digest).map_err(|e| e.to_string())?;
+    Ok(digest)
+}
+
+#[cfg(feature = "full")]
+fn checksum(key_id: &[u8; 8], secret_key: &[u8; 64]) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    crypto_generichash(&mut digest, &[&SIG_ALG[..], key_id, secret_key].concat(), None).unwrap();
+    digest
+}
+
+#[cfg(all(test, feature = "full"))]
+mod tests {
+    use super::*;
+
+    // The key and signatures of "test" from the tests of minisign-verify, by minisign's
+    // author: a legacy (Ed) signature and a prehashed (ED) one.
+    const PUBLIC_KEY: &str = "untrusted comment: minisign public key E7620F1842B4E81F
+RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3
+";
+    const LEGACY_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==
+";
+    const PREHASHED_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==
+";
+
+    #[test]
+    fn public_key_known_answer() {
+        let key = parse_public_key(PUBLIC_KEY).unwrap();
+        assert_eq!(key_id_hex(&key.key_id), "E7620F1842B4E81F");
+    }
+
+    #[test]
+    fn reference_signatures_verify() {
+        let key = parse_public_key(PUBLIC_KEY).unwrap();
+        assert_eq!(verify(&b"test"[..], LEGACY_SIGNATURE, &key).unwrap(), "timestamp:1555779966\tfile:test");
+        assert_eq!(verify(&b"test"[..], PREHASHED_SIGNATURE, &key).unwrap(), "timestamp:1556193335\tfile:test");
+    }
+
+    #[test]
+    fn other_message_is_refused() {
+        let key = parse_public_key(PUBLIC_KEY).unwrap();
+        assert!(verify(&b"Test"[..], LEGACY_SIGNATURE, &key).is_err());
+        assert!(verify(&b"Test"[..], PREHASHED_SIGNATURE, &key).is_err());
+    }
+
+    #[test]
+    fn tampered_trusted_comment_is_refused() {
+        let key = parse_public_key(PUBLIC_KEY).unwrap();
+        let tampered = PREHASHED_SIGNATURE.replace("file:test", "file:evil");
+        assert_eq!(verify(&b"test"[..], &tampered, &key).unwrap_err(), "Trusted comment signature is invalid");
+    }
+
+    #[test]
+    fn other_key_is_refused() {
+        let (public_key, _) = generate();
+        let error = verify(&b"test"[..], PREHASHED_SIGNATURE, &parse_public_key(&public_key).unwrap()).unwrap_err();
+        assert!(error.starts_with("Signature was made with key E7620F1842B4E81F"), "{}", error);
+    }
+}
@@ -0,0 +1,100 @@
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Whole-file reads for hashing, signing and in-memory encryption. Regular files of
+// at least MIN_SIZE are memory-mapped instead of copied into a buffer with read(),
+// so a multi-gigabyte input costs page cache rather than a heap copy of itself.
+//
+// A mapped file that shrinks while it is read faults the process (SIGBUS), and on
+// NFS or SMB a network error does the same instead of failing the read. --no-mmap
+// turns mapping off for the run; everything then goes through read(). So does a file
+// that cannot be mapped, such as one larger than the address space of a 32-bit build.
+const MIN_SIZE: u64 = 1 << 20;
+const MAP_PIECE: usize = 1 << 20;
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Read files with read() only, for the rest of the run (--no-mmap).
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// The contents of a file, mapped or read into memory.
+pub enum Contents {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Contents::Mapped(map) => map,
+            Contents::Read(data) => data,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Contents {
+    fn from(data: Vec<u8>) -> Self {
+        Contents::Read(data)
+    }
+}
+
+/// The whole file at `path`, as `fs::read` would return it.
+pub fn read(path: impl AsRef<Path>) -> io::Result<Contents> {
+    let mut file = File::open(path)?;
+    if let Some(map) = map(&file)? {
+        return Ok(Contents::Mapped(map));
+    }
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(Contents::Read(data))
+}
+
+/// Feed the contents of `path` to `f`: in 1 MiB pieces of the mapping, or 64 KiB
+/// reads, so unmapped files are never held in memory whole.
+pub fn read_chunks(path: &Path, mut f: impl FnMut(&[u8])) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    if let Some(map) = map(&file)? {
+        // Still in pieces, so progress can be reported
+        for piece in map.chunks(MAP_PIECE) {
+            f(piece);
+        }
+        return Ok(());
+    }
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        f(&buf[..n]);
+    }
+}
+
+/// A read-only mapping of `file`, if it is a regular file large enough to be worth
+/// one and mapping is enabled.
+fn map(file: &File) -> io::Result<Option<Mmap>> {
+    if DISABLED.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.len() < MIN_SIZE {
+        return Ok(None);
+    }
+    // SAFETY: the mapping is only ever read. Another process changing the file while
+    // it is mapped changes what is read, as it would between read() calls; shrinking
+    // it faults, which is what --no-mmap is for.
+    let Ok(map) = (unsafe { Mmap::map(file) }) else {
+        return Ok(None);
+    };
+    #[cfg(unix)]
+    let _ = map.advise(memmap2::Advice::Sequential);
+    Ok(Some(map))
+}
@@ -0,0 +1,115 @@
+//! Hashing and Ed25519ph signatures over data fed in pieces as it arrives (from a
+//! socket, a pipe, a file read in chunks) instead of held in memory whole. Both
+//! implement [`std::io::Write`], so `io::copy` can feed them.
+//!
+//! ```ignore
+//! let mut hasher = sodix::multipart::Hasher::blake2b(64)?;
+//! io::copy(&mut socket, &mut hasher)?;
+//! let digest = hasher.finalize();
+//!
+//! let mut signer = sodix::multipart::Ed25519ph::new();
+//! signer.update(header);
+//! signer.update(body);
+//! signer.verify(&signature, &public_key)?;
+//! ```
+
+use dryoc::classic::crypto_generichash::{crypto_generichash_final, crypto_generichash_init, crypto_generichash_update, GenericHashState};
+use dryoc::classic::crypto_sign::{crypto_sign_final_verify, crypto_sign_init, crypto_sign_update, SignerState};
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_final_create;
+use sha2::{Digest, Sha256};
+use std::io::{self, Write};
+
+/// A BLAKE2b (crypto_generichash, as b2sum) or SHA-256 digest in progress.
+pub struct Hasher(HashState);
+
+enum HashState {
+    Blake2b { state: GenericHashState, len: usize },
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    /// BLAKE2b with a digest of `len` bytes, 16 to 64.
+    pub fn blake2b(len: usize) -> Result<Self, String> {
+        let state = crypto_generichash_init(None, len).map_err(|e| format!("Invalid BLAKE2b length: {}", e))?;
+        Ok(Hasher(HashState::Blake2b { state, len }))
+    }
+
+    pub fn sha256() -> Self {
+        Hasher(HashState::Sha256(Sha256::new()))
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.0 {
+            HashState::Blake2b { state, .. } => crypto_generichash_update(state, data),
+            HashState::Sha256(sha256) => sha256.update(data),
+        }
+    }
+
+    /// The digest of everything passed to [`update`](Self::update).
+    pub fn finalize(self) -> Vec<u8> {
+        match self.0 {
+            HashState::Blake2b { state, len } => {
+                let mut digest = vec![0u8; len];
+                crypto_generichash_final(state, &mut digest).expect("digest length checked in blake2b()");
+                digest
+            }
+            HashState::Sha256(sha256) => sha256.finalize().to_vec(),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An Ed25519ph (crypto_sign_init/update/final) signature over a message in pieces;
+/// the same signatures as `sodix sign --prehash`.
+pub struct Ed25519ph(SignerState);
+
+impl Ed25519ph {
+    pub fn new() -> Self {
+        Ed25519ph(crypto_sign_init())
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        crypto_sign_update(&mut self.0, data);
+    }
+
+    /// Sign everything passed to [`update`](Self::update) with an Ed25519 secret key.
+    #[cfg(feature = "full")]
+    pub fn sign(self, secret_key: &[u8; 64]) -> Result<[u8; 64], String> {
+        let mut signature = [0u8; 64];
+        crypto_sign_final_create(self.0, &mut signature, secret_key).map_err(|e| format!("Error signing data: {}", e))?;
+        Ok(signature)
+    }
+
+    /// Check `signature` over everything passed to [`update`](Self::update).
+    pub fn verify(self, signature: &[u8; 64], public_key: &[u8; 32]) -> Result<(), String> {
+        crypto_sign_final_verify(self.0, signature, public_key).map_err(|e| e.to_string())
+    }
+}
+
+impl Default for Ed25519ph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for Ed25519ph {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
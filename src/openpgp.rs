@@ -0,0 +1,190 @@
+use sequoia_openpgp as pgp;
+use pgp::cert::{Cert, CertParser};
+use pgp::packet::key::{Key4, PublicParts, UnspecifiedRole};
+use pgp::packet::Key;
+use pgp::parse::stream::{DetachedVerifierBuilder, MessageLayer, MessageStructure, VerificationHelper};
+use pgp::parse::Parse;
+use pgp::policy::{HashAlgoSecurity, Policy, StandardPolicy};
+use pgp::types::SignatureType;
+use pgp::{KeyHandle, Packet, PacketPile};
+use std::time::UNIX_EPOCH;
+#[cfg(feature = "full")]
+use pgp::crypto::KeyPair;
+#[cfg(feature = "full")]
+use pgp::packet::key::{PrimaryRole, SecretParts};
+#[cfg(feature = "full")]
+use pgp::packet::signature::SignatureBuilder;
+#[cfg(feature = "full")]
+use pgp::packet::UserID;
+#[cfg(feature = "full")]
+use pgp::serialize::{Serialize, SerializeInto};
+#[cfg(feature = "full")]
+use pgp::types::KeyFlags;
+
+// OpenPGP (RFC 4880, RFC 9580) detached signatures with the sodix Ed25519 signing key,
+// through sequoia-openpgp, for ecosystems that want .asc files.
+//
+// A sodix signing key is used as a v4 EdDSA key created at time 0, so its certificate
+// and fingerprint stay the same every time it is exported. Against a certificate,
+// signatures are checked under sequoia's standard policy: binding signatures, key
+// flags, expiry, revocations and hash algorithms all count. Against a bare sodix
+// public key there is nothing to check but the signature itself.
+
+/// The sodix signing public key `key` as an OpenPGP key.
+fn public_key(key: &[u8; 32]) -> Result<Key<PublicParts, UnspecifiedRole>, String> {
+    Key4::import_public_ed25519(key, UNIX_EPOCH).map(Key::from).map_err(|e| format!("Invalid Ed25519 key: {}", e))
+}
+
+/// The OpenPGP fingerprint of the sodix signing public key `key`.
+#[cfg(feature = "full")]
+pub fn fingerprint(key: &[u8; 32]) -> Result<String, String> {
+    Ok(public_key(key)?.fingerprint().to_hex())
+}
+
+#[cfg(feature = "full")]
+fn key_pair(secret_key: &[u8; 64]) -> Result<(Key<SecretParts, PrimaryRole>, KeyPair), String> {
+    let key: Key<SecretParts, PrimaryRole> = Key4::import_secret_ed25519(&secret_key[..32], UNIX_EPOCH)
+        .map_err(|e| format!("Invalid signing key: {}", e))?
+        .into();
+    let signer = key.clone().into_keypair().map_err(|e| format!("Invalid signing key: {}", e))?;
+    Ok((key, signer))
+}
+
+/// An armored detached signature over `message` by the sodix key `secret_key`.
+#[cfg(feature = "full")]
+pub fn sign(message: &[u8], secret_key: &[u8; 64]) -> Result<String, String> {
+    let (_, mut signer) = key_pair(secret_key)?;
+    let signature = SignatureBuilder::new(SignatureType::Binary)
+        .sign_message(&mut signer, message)
+        .map_err(|e| format!("Error signing data: {}", e))?;
+    let mut writer = pgp::armor::Writer::new(Vec::new(), pgp::armor::Kind::Signature).map_err(|e| e.to_string())?;
+    Packet::from(signature).serialize(&mut writer).map_err(|e| format!("Error writing the signature: {}", e))?;
+    let armored = writer.finalize().map_err(|e| format!("Error writing the signature: {}", e))?;
+    Ok(String::from_utf8(armored).expect("armor is ASCII"))
+}
+
+/// An armored certificate (transferable public key) for the sodix key `secret_key`
+/// with one self-certified `user_id`, e.g. "Alice <alice@example.com>", for
+/// `gpg --import`.
+#[cfg(feature = "full")]
+pub fn certificate(secret_key: &[u8; 64], user_id: &str) -> Result<String, String> {
+    let error = |e: pgp::anyhow::Error| format!("Error creating the OpenPGP certificate: {}", e);
+    let (key, mut signer) = key_pair(secret_key)?;
+    let cert = Cert::try_from(vec![Packet::from(key)]).map_err(error)?;
+    let user_id = UserID::from(user_id);
+    let binding = SignatureBuilder::new(SignatureType::PositiveCertification)
+        .set_key_flags(KeyFlags::empty().set_certification().set_signing())
+        .and_then(|builder| builder.set_preferred_hash_algorithms(vec![pgp::types::HashAlgorithm::SHA512, pgp::types::HashAlgorithm::SHA256]))
+        .and_then(|builder| user_id.bind(&mut signer, &cert, builder))
+        .map_err(error)?;
+    let (cert, _) = cert.insert_packets(vec![Packet::from(user_id), binding.into()]).map_err(error)?;
+    // Cert serializes the public parts only
+    let armored = cert.armored().to_vec().map_err(error)?;
+    Ok(String::from_utf8(armored).expect("armor is ASCII"))
+}
+
+/// The certificates in `data`, binary or armored.
+pub fn read_certificates(data: &[u8]) -> Result<Vec<Cert>, String> {
+    let certs = CertParser::from_bytes(data)
+        .and_then(|parser| parser.collect::<pgp::Result<Vec<Cert>>>())
+        .map_err(|e| format!("Invalid OpenPGP certificate: {}", e))?;
+    if certs.is_empty() {
+        return Err("No OpenPGP certificate in the key".to_string());
+    }
+    Ok(certs)
+}
+
+/// Who a signature is checked against.
+pub enum Signer {
+    Certificates(Vec<Cert>),
+    /// A sodix signing public key
+    Key([u8; 32]),
+}
+
+/// Verify a detached signature (binary or armored) over `message` by `signer`; the
+/// fingerprint of the key that made the first good signature.
+pub fn verify(signature: &[u8], message: &[u8], signer: &Signer) -> Result<String, String> {
+    match signer {
+        Signer::Certificates(certs) => verify_with_certificates(signature, message, certs),
+        Signer::Key(key) => verify_with_key(signature, message, key),
+    }
+}
+
+/// A signing key of one of `certs`, valid under the standard policy.
+fn verify_with_certificates(signature: &[u8], message: &[u8], certs: &[Cert]) -> Result<String, String> {
+    let policy = StandardPolicy::new();
+    let helper = Helper { certs, signer: None };
+    let mut verifier = DetachedVerifierBuilder::from_bytes(signature)
+        .and_then(|builder| builder.with_policy(&policy, None, helper))
+        .map_err(|e| format!("Invalid OpenPGP signature: {}", e))?;
+    verifier.verify_bytes(message).map_err(|e| e.to_string())?;
+    Ok(verifier.into_helper().signer.expect("checked by the helper"))
+}
+
+/// The bare key `key`, with nothing to check but the signature.
+fn verify_with_key(signature: &[u8], message: &[u8], key: &[u8; 32]) -> Result<String, String> {
+    let key = public_key(key)?;
+    let policy = StandardPolicy::new();
+    let packets = PacketPile::from_bytes(signature).map_err(|e| format!("Invalid OpenPGP signature: {}", e))?;
+    let mut error = "No signature packets".to_string();
+    for packet in packets.into_children() {
+        let Packet::Signature(signature) = packet else {
+            continue;
+        };
+        let checked = policy
+            .signature(&signature, HashAlgoSecurity::CollisionResistance)
+            .and_then(|()| signature.signature_alive(None, None))
+            .and_then(|()| match signature.typ() {
+                SignatureType::Text => signature.verify_message(&key, canonical_text(message)),
+                _ => signature.verify_message(&key, message),
+            });
+        match checked {
+            Ok(()) => return Ok(key.fingerprint().to_hex()),
+            Err(e) => error = e.to_string(),
+        }
+    }
+    Err(error)
+}
+
+/// Text signatures are over the data with CRLF line endings.
+fn canonical_text(message: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(message.len());
+    for (i, &b) in message.iter().enumerate() {
+        if b == b'\n' && (i == 0 || message[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+        out.push(b);
+    }
+    out
+}
+
+/// Gives the verifier the certificates and keeps the signer of the first good signature.
+struct Helper<'a> {
+    certs: &'a [Cert],
+    signer: Option<String>,
+}
+
+impl VerificationHelper for Helper<'_> {
+    fn get_certs(&mut self, _ids: &[KeyHandle]) -> pgp::Result<Vec<Cert>> {
+        Ok(self.certs.to_vec())
+    }
+
+    fn check(&mut self, structure: MessageStructure) -> pgp::Result<()> {
+        let mut error = "No signature packets".to_string();
+        for layer in structure {
+            let MessageLayer::SignatureGroup { results } = layer else {
+                continue;
+            };
+            for result in results {
+                match result {
+                    Ok(good) => {
+                        self.signer = Some(good.ka.key().fingerprint().to_hex());
+                        return Ok(());
+                    }
+                    Err(e) => error = e.to_string(),
+                }
+            }
+        }
+        Err(pgp::anyhow::Error::msg(error))
+    }
+}
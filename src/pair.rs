@@ -0,0 +1,158 @@
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::XChaCha20Poly1305;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use dryoc::classic::crypto_generichash::crypto_generichash;
+use rand::Rng;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+// CPace-style pairing over Ristretto255. Both sides derive the generator from the
+// shared code and a session id, exchange y*G, and confirm the resulting key by
+// sending their public keys under it. A wrong code only fails that one attempt;
+// nothing on the wire lets an eavesdropper test guesses offline.
+//
+//   listener  -> sid (16) | Y_L (32)
+//   connector -> Y_C (32) | XChaCha20-Poly1305(k_C, connector's keys)
+//   listener  -> XChaCha20-Poly1305(k_L, listener's keys)
+const CONTEXT: &[u8] = b"sodix pair v1";
+const KEYS_LEN: usize = 32 + 32;
+const SEALED_LEN: usize = KEYS_LEN + 16;
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A device's public signing and encryption keys.
+pub struct PeerKeys {
+    pub sign_pk: [u8; 32],
+    pub enc_pk: [u8; 32],
+}
+
+impl PeerKeys {
+    fn to_bytes(&self) -> [u8; KEYS_LEN] {
+        let mut out = [0u8; KEYS_LEN];
+        out[..32].copy_from_slice(&self.sign_pk);
+        out[32..].copy_from_slice(&self.enc_pk);
+        out
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        PeerKeys {
+            sign_pk: bytes[..32].try_into().unwrap(),
+            enc_pk: bytes[32..].try_into().unwrap(),
+        }
+    }
+}
+
+/// A fresh code of 8 digits, grouped for reading aloud.
+pub fn generate_code() -> String {
+    let n: u32 = rand::rng().random_range(0..100_000_000);
+    format!("{:04}-{:04}", n / 10_000, n % 10_000)
+}
+
+/// Accept one connection on `addr` and exchange keys with it.
+pub fn listen(addr: &str, code: &str, own: &PeerKeys) -> Result<PeerKeys, String> {
+    let listener = TcpListener::bind(addr).map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+    let (mut stream, _) = listener.accept().map_err(|e| format!("Failed to accept connection: {}", e))?;
+    set_timeouts(&stream)?;
+
+    let mut sid = [0u8; 16];
+    rand::rng().fill(&mut sid);
+    let (y, own_share) = share(code, &sid);
+    send(&mut stream, &[&sid[..], &own_share].concat())?;
+
+    let mut msg = [0u8; 32 + SEALED_LEN];
+    recv(&mut stream, &mut msg)?;
+    let (k_listener, k_connector) = session_keys(code, &sid, &y, &own_share, &msg[..32], true)?;
+    let peer = open(&k_connector, &msg[32..])?;
+    send(&mut stream, &seal(&k_listener, own)?)?;
+    Ok(peer)
+}
+
+/// Connect to a listening device at `addr` and exchange keys with it.
+pub fn connect(addr: &str, code: &str, own: &PeerKeys) -> Result<PeerKeys, String> {
+    let mut stream = TcpStream::connect(addr).map_err(|e| format!("Failed to connect to {}: {}", addr, e))?;
+    set_timeouts(&stream)?;
+
+    let mut msg = [0u8; 16 + 32];
+    recv(&mut stream, &mut msg)?;
+    let sid: [u8; 16] = msg[..16].try_into().unwrap();
+    let (y, own_share) = share(code, &sid);
+    let (k_listener, k_connector) = session_keys(code, &sid, &y, &msg[16..], &own_share, false)?;
+    send(&mut stream, &[&own_share[..], &seal(&k_connector, own)?].concat())?;
+
+    // The listener hangs up instead of replying when our keys don't decrypt
+    let mut reply = [0u8; SEALED_LEN];
+    match stream.read_exact(&mut reply) {
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            Err("Pairing failed: the other device rejected the code".to_string())
+        }
+        Err(e) => Err(format!("Pairing failed: {}", e)),
+        Ok(()) => open(&k_listener, &reply),
+    }
+}
+
+/// Our secret scalar and public share y*G, with G hashed from the code and session id.
+fn share(code: &str, sid: &[u8; 16]) -> (Scalar, [u8; 32]) {
+    let code = normalize(code);
+    let mut uniform = [0u8; 64];
+    let input = [CONTEXT, sid, &(code.len() as u64).to_be_bytes(), code.as_bytes()].concat();
+    crypto_generichash(&mut uniform, &input, None).unwrap();
+    let generator = RistrettoPoint::from_uniform_bytes(&uniform);
+
+    let mut wide = [0u8; 64];
+    rand::rng().fill(&mut wide);
+    let y = Scalar::from_bytes_mod_order_wide(&wide);
+    (y, (generator * y).compress().to_bytes())
+}
+
+/// Directional keys from the shared point and the transcript (listener's share first).
+fn session_keys(code: &str, sid: &[u8; 16], y: &Scalar, listener_share: &[u8], connector_share: &[u8], we_listen: bool) -> Result<([u8; 32], [u8; 32]), String> {
+    let peer_share = if we_listen { connector_share } else { listener_share };
+    let peer_point = CompressedRistretto::from_slice(peer_share)
+        .ok()
+        .and_then(|p| p.decompress())
+        .ok_or("Pairing failed: invalid share from the other device")?;
+    let shared = peer_point * y;
+    if shared == RistrettoPoint::default() {
+        return Err("Pairing failed: invalid share from the other device".to_string());
+    }
+    let code = normalize(code);
+    let transcript = [CONTEXT, sid, code.as_bytes(), listener_share, connector_share, shared.compress().as_bytes()].concat();
+    let mut okm = [0u8; 64];
+    crypto_generichash(&mut okm, &transcript, None).unwrap();
+    Ok((okm[..32].try_into().unwrap(), okm[32..].try_into().unwrap()))
+}
+
+fn seal(key: &[u8; 32], keys: &PeerKeys) -> Result<Vec<u8>, String> {
+    // Each key encrypts exactly one message, so a zero nonce is safe
+    XChaCha20Poly1305::new(key.into())
+        .encrypt(&[0u8; 24].into(), &keys.to_bytes()[..])
+        .map_err(|_| "Pairing failed: could not encrypt keys".to_string())
+}
+
+fn open(key: &[u8; 32], sealed: &[u8]) -> Result<PeerKeys, String> {
+    let keys = XChaCha20Poly1305::new(key.into())
+        .decrypt(&[0u8; 24].into(), sealed)
+        .map_err(|_| "Pairing failed: the codes do not match (or someone interfered)".to_string())?;
+    Ok(PeerKeys::from_bytes(&keys))
+}
+
+/// Codes are compared without separators or surrounding space, so 1234-5678 == 12345678.
+fn normalize(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase()
+}
+
+fn set_timeouts(stream: &TcpStream) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)))
+        .map_err(|e| format!("Failed to configure connection: {}", e))
+}
+
+fn send(stream: &mut TcpStream, data: &[u8]) -> Result<(), String> {
+    stream.write_all(data).map_err(|e| format!("Pairing failed: {}", e))
+}
+
+fn recv(stream: &mut TcpStream, buf: &mut [u8]) -> Result<(), String> {
+    stream.read_exact(buf).map_err(|e| format!("Pairing failed: {}", e))
+}
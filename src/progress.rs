@@ -0,0 +1,129 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::cell::Cell;
+use std::io::{self, IsTerminal, Read};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+// Progress of long file operations, on stderr: a bar with rate and ETA when stderr is
+// a terminal, nothing with --quiet or when it is redirected, and with --progress-json
+// one JSON object per line for other programs to follow:
+//
+//   {"bytes":1048576,"file":"disk.img","task":"encrypt","total":107374182400}
+//   {"bytes":107374182400,"done":true,"file":"disk.img","task":"encrypt","total":107374182400}
+//
+// JSON lines come at most every JSON_INTERVAL, and a task that completes ends with a
+// "done" line; one that fails just stops. Bars are only drawn for files of at least
+// MIN_BAR_SIZE, which would otherwise flash by.
+const JSON_INTERVAL: Duration = Duration::from_millis(500);
+const MIN_BAR_SIZE: u64 = 8 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Bar,
+    Quiet,
+    Json,
+}
+
+static MODE: OnceLock<Mode> = OnceLock::new();
+
+/// How progress is reported for the rest of the run (--quiet, --progress-json).
+pub fn set_mode(mode: Mode) {
+    let _ = MODE.set(mode);
+}
+
+/// One operation on one file.
+pub struct Progress {
+    task: &'static str,
+    file: String,
+    total: Option<u64>,
+    bytes: Cell<u64>,
+    last_report: Cell<Instant>,
+    bar: Option<ProgressBar>,
+    json: bool,
+}
+
+impl Progress {
+    /// Start reporting `task` ("encrypt", "decrypt", "hash", ...) on `path`, which is
+    /// `total` bytes long if that is known.
+    pub fn start(task: &'static str, path: &Path, total: Option<u64>) -> Self {
+        let mode = MODE.get().copied().unwrap_or(Mode::Bar);
+        let file = path.display().to_string();
+        let bar = (mode == Mode::Bar && io::stderr().is_terminal() && total.is_none_or(|total| total >= MIN_BAR_SIZE)).then(|| {
+            let (bar, template) = match total {
+                Some(total) => (ProgressBar::new(total), "{msg} [{bar:30}] {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}"),
+                None => (ProgressBar::no_length(), "{msg} {spinner} {bytes} {bytes_per_sec}"),
+            };
+            bar.set_draw_target(ProgressDrawTarget::stderr());
+            bar.set_style(ProgressStyle::with_template(template).unwrap().progress_chars("=> "));
+            bar.set_message(format!("{} {}", task, file));
+            bar
+        });
+        Progress {
+            task,
+            file,
+            total,
+            bytes: Cell::new(0),
+            last_report: Cell::new(Instant::now()),
+            bar,
+            json: mode == Mode::Json,
+        }
+    }
+
+    pub fn inc(&self, n: u64) {
+        self.bytes.set(self.bytes.get() + n);
+        if let Some(bar) = &self.bar {
+            bar.inc(n);
+        }
+        if self.json && self.last_report.get().elapsed() >= JSON_INTERVAL {
+            self.last_report.set(Instant::now());
+            self.report(false);
+        }
+    }
+
+    /// `reader`, counting what is read from it as done.
+    pub fn reader<R: Read>(&self, reader: R) -> ProgressReader<'_, R> {
+        ProgressReader { inner: reader, progress: self }
+    }
+
+    /// The task completed; dropping a Progress without this only clears the bar.
+    pub fn finish(self) {
+        if self.json {
+            self.report(true);
+        }
+    }
+
+    fn report(&self, done: bool) {
+        let mut line = serde_json::json!({
+            "task": self.task,
+            "file": self.file,
+            "bytes": self.bytes.get(),
+            "total": self.total,
+        });
+        if done {
+            line["done"] = true.into();
+        }
+        eprintln!("{}", line);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.progress.inc(n as u64);
+        Ok(n)
+    }
+}
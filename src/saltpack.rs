@@ -0,0 +1,689 @@
+use dryoc::classic::crypto_box::{crypto_box_easy, crypto_box_open_easy};
+use dryoc::classic::crypto_core::crypto_scalarmult_base;
+use dryoc::classic::crypto_secretbox::crypto_secretbox_open_easy;
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha512};
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_secretbox::crypto_secretbox_easy;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+#[cfg(feature = "full")]
+use rand::Rng;
+
+// Saltpack version 2 messages (https://saltpack.org), as read and written by Keybase:
+// MessagePack packets, armored with base62 in 15-character words between
+// "BEGIN SALTPACK ENCRYPTED MESSAGE." and ". END SALTPACK ENCRYPTED MESSAGE." (or
+// SIGNED MESSAGE, DETACHED SIGNATURE). Every message starts with
+//
+//   bin(header), header hash = SHA-512(header)
+//
+// Encryption, header ["saltpack", [2, 0], 0, ephemeral public key,
+// secretbox(sender public key, SENDER_KEY_NONCE, payload key),
+// [[recipient public key, box(payload key, "saltpack_recipsb" || u64 index)]...]],
+// then [[authenticator per recipient], secretbox(chunk, "saltpack_ploadsb" || u64 n),
+// final flag] per 1 MiB chunk. An authenticator is the first 32 bytes of
+// HMAC-SHA-512(MAC key, SHA-512(header hash || nonce || final flag || secretbox)), where
+// the recipient's MAC key is the first 32 bytes of the SHA-512 of the last 32 bytes of
+// box(32 zero bytes) from the sender's and from the ephemeral key, under the first 16
+// bytes of the header hash || u64 index with the low bit of byte 15 cleared and set.
+//
+// Signing, header ["saltpack", [2, 0], 1 (attached) or 2 (detached), signer public key,
+// 32-byte random nonce], then for attached messages [signature, chunk, final flag] per
+// chunk, signing ATTACHED_CONTEXT || SHA-512(header hash || u64 n || final flag || chunk),
+// and for detached ones bin(signature of DETACHED_CONTEXT || SHA-512(header hash ||
+// message)). The sodix X25519 and Ed25519 keys are used as they are.
+const FORMAT_NAME: &str = "saltpack";
+const VERSION: u64 = 2;
+const MODE_ENCRYPTION: u64 = 0;
+const MODE_ATTACHED: u64 = 1;
+const MODE_DETACHED: u64 = 2;
+const SENDER_KEY_NONCE: &[u8; 24] = b"saltpack_sender_key_sbox";
+const RECIPIENT_NONCE: &[u8; 16] = b"saltpack_recipsb";
+const PAYLOAD_NONCE: &[u8; 16] = b"saltpack_ploadsb";
+const ATTACHED_CONTEXT: &[u8] = b"saltpack attached signature\0";
+const DETACHED_CONTEXT: &[u8] = b"saltpack detached signature\0";
+#[cfg(feature = "full")]
+const CHUNK_SIZE: usize = 1 << 20;
+
+pub const ENCRYPTED_MESSAGE: &str = "ENCRYPTED MESSAGE";
+pub const SIGNED_MESSAGE: &str = "SIGNED MESSAGE";
+pub const DETACHED_SIGNATURE: &str = "DETACHED SIGNATURE";
+
+const ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BLOCK_SIZE: usize = 32;
+#[cfg(feature = "full")]
+const WORD_SIZE: usize = 15;
+#[cfg(feature = "full")]
+const WORDS_PER_LINE: usize = 200;
+
+/// `message` encrypted from `sender_key` to `recipients`, armored.
+#[cfg(feature = "full")]
+pub fn encrypt(message: &[u8], sender_key: &[u8; 32], recipients: &[[u8; 32]]) -> Result<String, String> {
+    let mut rng = rand::rng();
+    let mut ephemeral_key = [0u8; 32];
+    rng.fill(&mut ephemeral_key);
+    let mut payload_key = [0u8; 32];
+    rng.fill(&mut payload_key);
+    let ephemeral_public = public_key(&ephemeral_key);
+
+    let mut header = Vec::new();
+    put_array(&mut header, 6);
+    put_preamble(&mut header, MODE_ENCRYPTION);
+    put_bin(&mut header, &ephemeral_public);
+    put_bin(&mut header, &secretbox(&public_key(sender_key), SENDER_KEY_NONCE, &payload_key)?);
+    put_array(&mut header, recipients.len());
+    for (index, recipient) in recipients.iter().enumerate() {
+        let mut payload_key_box = vec![0u8; 32 + 16];
+        crypto_box_easy(&mut payload_key_box, &payload_key, &indexed_nonce(RECIPIENT_NONCE, index as u64), recipient, &ephemeral_key)
+            .map_err(|e| format!("Error encrypting data: {}", e))?;
+        put_array(&mut header, 2);
+        put_bin(&mut header, recipient);
+        put_bin(&mut header, &payload_key_box);
+    }
+    let header_hash: [u8; 64] = Sha512::digest(&header).into();
+    let mac_keys = recipients
+        .iter()
+        .enumerate()
+        .map(|(index, recipient)| mac_key(&header_hash, index as u64, (recipient, sender_key), (recipient, &ephemeral_key)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut out = Vec::new();
+    put_bin(&mut out, &header);
+    let chunks = chunks(message);
+    for (n, chunk) in chunks.iter().enumerate() {
+        let last = n + 1 == chunks.len();
+        let nonce = indexed_nonce(PAYLOAD_NONCE, n as u64);
+        let payload = secretbox(chunk, &nonce, &payload_key)?;
+        let hash = authenticator_hash(&header_hash, &nonce, last, &payload);
+        put_array(&mut out, 3);
+        put_array(&mut out, mac_keys.len());
+        for key in &mac_keys {
+            put_bin(&mut out, &authenticator(key, &hash).finalize().into_bytes()[..32]);
+        }
+        put_bin(&mut out, &payload);
+        put_bool(&mut out, last);
+    }
+    Ok(armor(ENCRYPTED_MESSAGE, &out))
+}
+
+/// The plaintext of an armored encrypted message for `secret_key`, and the sender's
+/// public key unless the sender is anonymous.
+pub fn decrypt(armored: &str, secret_key: &[u8; 32]) -> Result<(Vec<u8>, Option<[u8; 32]>), String> {
+    let data = dearmor(armored, ENCRYPTED_MESSAGE)?;
+    let mut packets = MsgPack(&data);
+    let header = packets.bin()?;
+    let header_hash: [u8; 64] = Sha512::digest(header).into();
+    let mut header = MsgPack(header);
+    read_preamble(&mut header, 6, MODE_ENCRYPTION)?;
+    let ephemeral_public = key_32(header.bin()?)?;
+    let sender_box = header.bin()?;
+    let own_public = public_key(secret_key);
+    let mut opened = None;
+    for index in 0..header.array()? as u64 {
+        if header.array()? != 2 {
+            return Err("Malformed saltpack recipient".to_string());
+        }
+        let recipient = header.bin_or_nil()?;
+        let payload_key_box = header.bin()?;
+        // Hidden recipients (nil) could be anyone, so each of those boxes is tried
+        if opened.is_some() || recipient.is_some_and(|pk| pk != own_public) || payload_key_box.len() != 32 + 16 {
+            continue;
+        }
+        let mut payload_key = [0u8; 32];
+        let nonce = indexed_nonce(RECIPIENT_NONCE, index);
+        if crypto_box_open_easy(&mut payload_key, payload_key_box, &nonce, &ephemeral_public, secret_key).is_ok() {
+            opened = Some((index, payload_key));
+        }
+    }
+    let (index, payload_key) = opened.ok_or("This saltpack message is not encrypted to your key")?;
+    if sender_box.len() != 32 + 16 {
+        return Err("Malformed saltpack sender box".to_string());
+    }
+    let mut sender = [0u8; 32];
+    crypto_secretbox_open_easy(&mut sender, sender_box, SENDER_KEY_NONCE, &payload_key)
+        .map_err(|_| "saltpack sender box is invalid".to_string())?;
+    let mac_key = mac_key(&header_hash, index, (&sender, secret_key), (&ephemeral_public, secret_key))?;
+
+    let mut plaintext = Vec::new();
+    for n in 0u64.. {
+        if packets.0.is_empty() {
+            return Err("saltpack message is truncated".to_string());
+        }
+        if packets.array()? != 3 {
+            return Err("Malformed saltpack payload packet".to_string());
+        }
+        let mut authenticators = Vec::new();
+        for _ in 0..packets.array()? {
+            authenticators.push(packets.bin()?);
+        }
+        let payload = packets.bin()?;
+        let last = packets.bool()?;
+        let nonce = indexed_nonce(PAYLOAD_NONCE, n);
+        let valid = authenticators.get(index as usize).is_some_and(|tag| {
+            authenticator(&mac_key, &authenticator_hash(&header_hash, &nonce, last, payload))
+                .verify_truncated_left(tag)
+                .is_ok()
+        });
+        if !valid || payload.len() < 16 {
+            return Err("saltpack message is corrupted or was modified".to_string());
+        }
+        let mut chunk = vec![0u8; payload.len() - 16];
+        crypto_secretbox_open_easy(&mut chunk, payload, &nonce, &payload_key)
+            .map_err(|_| "saltpack message is corrupted or was modified".to_string())?;
+        plaintext.extend_from_slice(&chunk);
+        if last {
+            break;
+        }
+    }
+    if !packets.0.is_empty() {
+        return Err("Trailing data after the final saltpack packet".to_string());
+    }
+    Ok((plaintext, (sender != ephemeral_public).then_some(sender)))
+}
+
+/// An armored attached signed message, or detached signature, of `message`.
+#[cfg(feature = "full")]
+pub fn sign(message: &[u8], secret_key: &[u8; 64], attached: bool) -> Result<String, String> {
+    let mut nonce = [0u8; 32];
+    rand::rng().fill(&mut nonce);
+    let mut header = Vec::new();
+    put_array(&mut header, 5);
+    put_preamble(&mut header, if attached { MODE_ATTACHED } else { MODE_DETACHED });
+    put_bin(&mut header, &secret_key[32..]);
+    put_bin(&mut header, &nonce);
+    let header_hash: [u8; 64] = Sha512::digest(&header).into();
+    let sign = |context: &[u8], digest: &[u8]| -> Result<[u8; 64], String> {
+        let mut signature = [0u8; 64];
+        crypto_sign_detached(&mut signature, &[context, digest].concat(), secret_key)
+            .map_err(|e| format!("Error signing data: {}", e))?;
+        Ok(signature)
+    };
+
+    let mut out = Vec::new();
+    put_bin(&mut out, &header);
+    if !attached {
+        let digest = Sha512::new().chain_update(header_hash).chain_update(message).finalize();
+        put_bin(&mut out, &sign(DETACHED_CONTEXT, &digest)?);
+        return Ok(armor(DETACHED_SIGNATURE, &out));
+    }
+    let chunks = chunks(message);
+    for (n, chunk) in chunks.iter().enumerate() {
+        let last = n + 1 == chunks.len();
+        put_array(&mut out, 3);
+        put_bin(&mut out, &sign(ATTACHED_CONTEXT, &chunk_digest(&header_hash, n as u64, last, chunk))?);
+        put_bin(&mut out, chunk);
+        put_bool(&mut out, last);
+    }
+    Ok(armor(SIGNED_MESSAGE, &out))
+}
+
+/// The message of an armored attached signed message if `public_key` signed it.
+pub fn verify_attached(armored: &str, public_key: &[u8; 32]) -> Result<Vec<u8>, String> {
+    let data = dearmor(armored, SIGNED_MESSAGE)?;
+    let mut packets = MsgPack(&data);
+    let header_hash = read_signature_header(&mut packets, MODE_ATTACHED, public_key)?;
+    let mut message = Vec::new();
+    for n in 0u64.. {
+        if packets.0.is_empty() {
+            return Err("saltpack message is truncated".to_string());
+        }
+        if packets.array()? != 3 {
+            return Err("Malformed saltpack payload packet".to_string());
+        }
+        let signature = signature_64(packets.bin()?)?;
+        let chunk = packets.bin()?;
+        let last = packets.bool()?;
+        let digest = chunk_digest(&header_hash, n, last, chunk);
+        crypto_sign_verify_detached(&signature, &[ATTACHED_CONTEXT, &digest].concat(), public_key)
+            .map_err(|_| "saltpack signature is invalid".to_string())?;
+        message.extend_from_slice(chunk);
+        if last {
+            break;
+        }
+    }
+    if !packets.0.is_empty() {
+        return Err("Trailing data after the final saltpack packet".to_string());
+    }
+    Ok(message)
+}
+
+/// Check an armored detached signature of `message` by `public_key`.
+pub fn verify_detached(armored: &str, message: &[u8], public_key: &[u8; 32]) -> Result<(), String> {
+    let data = dearmor(armored, DETACHED_SIGNATURE)?;
+    let mut packets = MsgPack(&data);
+    let header_hash = read_signature_header(&mut packets, MODE_DETACHED, public_key)?;
+    let signature = signature_64(packets.bin()?)?;
+    if !packets.0.is_empty() {
+        return Err("Trailing data after the saltpack signature".to_string());
+    }
+    let digest = Sha512::new().chain_update(header_hash).chain_update(message).finalize();
+    crypto_sign_verify_detached(&signature, &[DETACHED_CONTEXT, &digest].concat(), public_key)
+        .map_err(|_| "saltpack signature is invalid".to_string())
+}
+
+fn read_signature_header(packets: &mut MsgPack, mode: u64, public_key: &[u8; 32]) -> Result<[u8; 64], String> {
+    let header = packets.bin()?;
+    let header_hash = Sha512::digest(header).into();
+    let mut header = MsgPack(header);
+    read_preamble(&mut header, 5, mode)?;
+    let signer = key_32(header.bin()?)?;
+    if signer != *public_key {
+        return Err(format!("Signed by {}, not by the given key", hex::encode(signer)));
+    }
+    Ok(header_hash)
+}
+
+#[cfg(feature = "full")]
+fn chunks(message: &[u8]) -> Vec<&[u8]> {
+    // An empty message is one empty final chunk
+    if message.is_empty() {
+        vec![message]
+    } else {
+        message.chunks(CHUNK_SIZE).collect()
+    }
+}
+
+fn chunk_digest(header_hash: &[u8; 64], n: u64, last: bool, chunk: &[u8]) -> [u8; 64] {
+    Sha512::new()
+        .chain_update(header_hash)
+        .chain_update(n.to_be_bytes())
+        .chain_update([last as u8])
+        .chain_update(chunk)
+        .finalize()
+        .into()
+}
+
+fn authenticator_hash(header_hash: &[u8; 64], nonce: &[u8; 24], last: bool, payload: &[u8]) -> [u8; 64] {
+    Sha512::new()
+        .chain_update(header_hash)
+        .chain_update(nonce)
+        .chain_update([last as u8])
+        .chain_update(payload)
+        .finalize()
+        .into()
+}
+
+fn authenticator(mac_key: &[u8; 32], hash: &[u8; 64]) -> Hmac<Sha512> {
+    let mut mac = <Hmac<Sha512> as Mac>::new_from_slice(mac_key).expect("HMAC takes any key length");
+    mac.update(hash);
+    mac
+}
+
+/// A recipient's MAC key from two (public key, secret key) pairs whose boxes match the
+/// ones the sender computes with the sender and the ephemeral key.
+fn mac_key(header_hash: &[u8; 64], index: u64, long_term: (&[u8; 32], &[u8; 32]), ephemeral: (&[u8; 32], &[u8; 32])) -> Result<[u8; 32], String> {
+    let mut nonce = [0u8; 24];
+    nonce[..16].copy_from_slice(&header_hash[..16]);
+    nonce[16..].copy_from_slice(&index.to_be_bytes());
+    let mut hash = Sha512::new();
+    for (bit, (public_key, secret_key)) in [(0, long_term), (1, ephemeral)] {
+        nonce[15] = nonce[15] & 0xfe | bit;
+        let mut zeros_box = [0u8; 32 + 16];
+        crypto_box_easy(&mut zeros_box, &[0u8; 32], &nonce, public_key, secret_key)
+            .map_err(|e| format!("Error computing saltpack MAC key: {}", e))?;
+        hash.update(&zeros_box[16..]);
+    }
+    Ok(hash.finalize()[..32].try_into().unwrap())
+}
+
+#[cfg(feature = "full")]
+fn secretbox(message: &[u8], nonce: &[u8; 24], key: &[u8; 32]) -> Result<Vec<u8>, String> {
+    let mut ciphertext = vec![0u8; message.len() + 16];
+    crypto_secretbox_easy(&mut ciphertext, message, nonce, key).map_err(|e| format!("Error encrypting data: {}", e))?;
+    Ok(ciphertext)
+}
+
+fn indexed_nonce(prefix: &[u8; 16], index: u64) -> [u8; 24] {
+    let mut nonce = [0u8; 24];
+    nonce[..16].copy_from_slice(prefix);
+    nonce[16..].copy_from_slice(&index.to_be_bytes());
+    nonce
+}
+
+fn public_key(secret_key: &[u8; 32]) -> [u8; 32] {
+    let mut public_key = [0u8; 32];
+    crypto_scalarmult_base(&mut public_key, secret_key);
+    public_key
+}
+
+fn key_32(bytes: &[u8]) -> Result<[u8; 32], String> {
+    bytes.try_into().map_err(|_| "saltpack keys must be 32 bytes".to_string())
+}
+
+fn signature_64(bytes: &[u8]) -> Result<[u8; 64], String> {
+    bytes.try_into().map_err(|_| "saltpack signatures must be 64 bytes".to_string())
+}
+
+#[cfg(feature = "full")]
+fn put_preamble(out: &mut Vec<u8>, mode: u64) {
+    out.push(0xa0 | FORMAT_NAME.len() as u8);
+    out.extend_from_slice(FORMAT_NAME.as_bytes());
+    put_array(out, 2);
+    put_uint(out, VERSION);
+    put_uint(out, 0);
+    put_uint(out, mode);
+}
+
+fn read_preamble(header: &mut MsgPack, fields: usize, mode: u64) -> Result<(), String> {
+    if header.array()? != fields || header.str()? != FORMAT_NAME {
+        return Err("Not a saltpack header".to_string());
+    }
+    if header.array()? != 2 {
+        return Err("Malformed saltpack version".to_string());
+    }
+    let (major, _minor) = (header.uint()?, header.uint()?);
+    if major != VERSION {
+        return Err(format!("Only saltpack version {} is supported, not {}", VERSION, major));
+    }
+    if header.uint()? != mode {
+        return Err("Unexpected saltpack message mode".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "full")]
+fn put_array(out: &mut Vec<u8>, len: usize) {
+    match len {
+        0..=15 => out.push(0x90 | len as u8),
+        16..=0xffff => {
+            out.push(0xdc);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(0xdd);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+fn put_uint(out: &mut Vec<u8>, n: u64) {
+    // Only small numbers occur in saltpack headers
+    assert!(n < 0x80);
+    out.push(n as u8);
+}
+
+#[cfg(feature = "full")]
+fn put_bin(out: &mut Vec<u8>, bytes: &[u8]) {
+    match bytes.len() {
+        0..=0xff => out.extend_from_slice(&[0xc4, bytes.len() as u8]),
+        0x100..=0xffff => {
+            out.push(0xc5);
+            out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(0xc6);
+            out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(bytes);
+}
+
+#[cfg(feature = "full")]
+fn put_bool(out: &mut Vec<u8>, value: bool) {
+    out.push(if value { 0xc3 } else { 0xc2 });
+}
+
+/// Reader for the MessagePack subset saltpack uses.
+struct MsgPack<'a>(&'a [u8]);
+
+impl<'a> MsgPack<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let (value, rest) = self.0.split_at_checked(n).ok_or("Truncated saltpack message")?;
+        self.0 = rest;
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn length(&mut self, size: usize) -> Result<usize, String> {
+        Ok(self.take(size)?.iter().fold(0, |n, b| n << 8 | *b as usize))
+    }
+
+    fn array(&mut self) -> Result<usize, String> {
+        match self.byte()? {
+            b @ 0x90..=0x9f => Ok((b & 0x0f) as usize),
+            0xdc => self.length(2),
+            0xdd => self.length(4),
+            _ => Err("Expected a MessagePack array".to_string()),
+        }
+    }
+
+    fn uint(&mut self) -> Result<u64, String> {
+        match self.byte()? {
+            b @ 0x00..=0x7f => Ok(b as u64),
+            0xcc => Ok(self.length(1)? as u64),
+            0xcd => Ok(self.length(2)? as u64),
+            0xce => Ok(self.length(4)? as u64),
+            0xcf => Ok(self.take(8)?.iter().fold(0, |n, b| n << 8 | *b as u64)),
+            _ => Err("Expected a MessagePack integer".to_string()),
+        }
+    }
+
+    fn str(&mut self) -> Result<&'a str, String> {
+        let len = match self.byte()? {
+            b @ 0xa0..=0xbf => (b & 0x1f) as usize,
+            0xd9 => self.length(1)?,
+            0xda => self.length(2)?,
+            0xdb => self.length(4)?,
+            _ => return Err("Expected a MessagePack string".to_string()),
+        };
+        std::str::from_utf8(self.take(len)?).map_err(|_| "Invalid UTF-8 in saltpack message".to_string())
+    }
+
+    fn bin(&mut self) -> Result<&'a [u8], String> {
+        self.bin_or_nil()?.ok_or_else(|| "Expected MessagePack bytes".to_string())
+    }
+
+    fn bin_or_nil(&mut self) -> Result<Option<&'a [u8]>, String> {
+        let len = match self.byte()? {
+            0xc0 => return Ok(None),
+            0xc4 => self.length(1)?,
+            0xc5 => self.length(2)?,
+            0xc6 => self.length(4)?,
+            _ => return Err("Expected MessagePack bytes".to_string()),
+        };
+        self.take(len).map(Some)
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.byte()? {
+            0xc2 => Ok(false),
+            0xc3 => Ok(true),
+            _ => Err("Expected a MessagePack boolean".to_string()),
+        }
+    }
+}
+
+/// Saltpack's BaseX armor: base62 in 32-byte blocks, each the fewest characters that
+/// hold it, with the spare bits of the last block at the right.
+#[cfg(feature = "full")]
+pub fn armor(kind: &str, data: &[u8]) -> String {
+    let encoded: String = data.chunks(BLOCK_SIZE).map(encode_block).collect();
+    let words: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(WORD_SIZE)
+        .map(|word| std::str::from_utf8(word).unwrap())
+        .collect();
+    let lines: Vec<String> = words.chunks(WORDS_PER_LINE).map(|line| line.join(" ")).collect();
+    format!("BEGIN SALTPACK {}. {}. END SALTPACK {}.\n", kind, lines.join("\n"), kind)
+}
+
+/// The bytes of an armored message of `kind`. Whitespace and '>' (from quoting) are
+/// ignored, and the BEGIN line may name an application ("BEGIN KEYBASE SALTPACK ...").
+pub fn dearmor(text: &str, kind: &str) -> Result<Vec<u8>, String> {
+    let mut parts = text.trim().trim_end_matches('.').split('.');
+    let (Some(begin), Some(body), Some(end), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+        return Err(format!("Not an armored saltpack {}", kind.to_lowercase()));
+    };
+    let expected: Vec<&str> = ["SALTPACK"].into_iter().chain(kind.split(' ')).collect();
+    let frame = |line: &str, word: &str| {
+        let words: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == '>').filter(|w| !w.is_empty()).collect();
+        words.first() == Some(&word) && (words[1..] == expected[..] || words.get(2..) == Some(&expected[..]))
+    };
+    if !frame(begin, "BEGIN") || !frame(end, "END") {
+        return Err(format!("Not an armored saltpack {}", kind.to_lowercase()));
+    }
+    let body: Vec<u8> = body.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'>').collect();
+    let chars_per_block = encoded_len(BLOCK_SIZE);
+    let mut data = Vec::new();
+    for block in body.chunks(chars_per_block) {
+        data.extend_from_slice(&decode_block(block)?);
+    }
+    Ok(data)
+}
+
+/// Characters needed for `n` bytes, computed like the reference implementation.
+fn encoded_len(n: usize) -> usize {
+    (8.0 * n as f64 / 62f64.log2()).ceil() as usize
+}
+
+fn extra_bits(n: usize, chars: usize) -> u32 {
+    (62f64.log2() * chars as f64).floor() as u32 - 8 * n as u32
+}
+
+#[cfg(feature = "full")]
+fn encode_block(block: &[u8]) -> String {
+    let chars = encoded_len(block.len());
+    // The block as a big-endian number, with a spare byte for the shift
+    let mut number = [&[0u8][..], block].concat();
+    let shift = extra_bits(block.len(), chars);
+    let mut carry = 0u16;
+    for byte in number.iter_mut().rev() {
+        let shifted = (*byte as u16) << shift | carry;
+        *byte = shifted as u8;
+        carry = shifted >> 8;
+    }
+    let mut digits = vec![0u8; chars];
+    for digit in digits.iter_mut().rev() {
+        let mut remainder = 0u32;
+        for byte in number.iter_mut() {
+            let value = remainder << 8 | *byte as u32;
+            *byte = (value / 62) as u8;
+            remainder = value % 62;
+        }
+        *digit = ALPHABET[remainder as usize];
+    }
+    String::from_utf8(digits).unwrap()
+}
+
+fn decode_block(block: &[u8]) -> Result<Vec<u8>, String> {
+    let invalid = || "Invalid saltpack armor".to_string();
+    let n = (0..=BLOCK_SIZE).find(|&n| encoded_len(n) == block.len()).ok_or_else(invalid)?;
+    let mut number = vec![0u8; n + 1];
+    for c in block {
+        let mut carry = ALPHABET.iter().position(|a| a == c).ok_or_else(invalid)? as u32;
+        for byte in number.iter_mut().rev() {
+            let value = *byte as u32 * 62 + carry;
+            *byte = value as u8;
+            carry = value >> 8;
+        }
+        if carry != 0 {
+            return Err(invalid());
+        }
+    }
+    let shift = extra_bits(n, block.len());
+    if number[n] & ((1u8 << shift) - 1) != 0 {
+        return Err(invalid());
+    }
+    let mut carry = 0u8;
+    for byte in number.iter_mut() {
+        let shifted = *byte >> shift | carry;
+        carry = if shift == 0 { 0 } else { *byte << (8 - shift) };
+        *byte = shifted;
+    }
+    if number[0] != 0 {
+        return Err(invalid());
+    }
+    Ok(number.split_off(1))
+}
+
+#[cfg(all(test, feature = "full"))]
+mod tests {
+    use super::*;
+
+    // Messages built from the saltpack v2 specification by an implementation written
+    // apart from this one, carrying MESSAGE. The signatures use the RFC 8032 TEST 1 key
+    // and the header nonce 00 01 .. 1f. The encrypted message is from Alice to Bob
+    // (the RFC 7748 section 6.1 X25519 keys), with the ephemeral secret key 20 21 .. 3f
+    // and the payload key "saltpack test vector payload key".
+    const SIGNING_SECRET_KEY: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60\
+        d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const ALICE_PUBLIC_KEY: &str = "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a";
+    const BOB_SECRET_KEY: &str = "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb";
+    const MESSAGE: &[u8] = b"This is the content.";
+    const SIGNED: &str = "BEGIN SALTPACK SIGNED MESSAGE. kYM5h1pg6qz9UMn j6G9T0lmN10dLd7 epdIrqzWw1TbY1i \
+        FRM43qYG8mPP9SU HaBS9QJCNGv5kg4 a7rpQi8nv6x2rAq vm2hjyqHMt2W0dT SnpZlxYwqv7tXOo LFPS8W8lt3DX4Xu \
+        kOaKegIUkgHCoEz J5UbnAaPUAjq3dt my4k6UqLtycKyta cQnxA8aWH3lSewi AYMPG8Nj3C7tDQG C6XYZ7BPyKnsEE1 \
+        Jdm2F4LH4. END SALTPACK SIGNED MESSAGE.";
+    const DETACHED: &str = "BEGIN SALTPACK DETACHED SIGNATURE. kYM5h1pg6qz9UMn j6G9T0tZR3Dthib KbNSezmQ3HBDg1i \
+        FRM43qYG8mPP9SU HaBS9QJCNGv5kg4 a7rpQi8nv6x2rAq vm2hjyqHMt2W0dT SnpZlxYxAR7VLtt g5G0LRGtu5A1rKX \
+        t3cC6XXb5rg8uV4 3tR7j5yt4Rr6N4Q ecsYvwhBZxaCugu HA4OKALf3iMAaju L8rnN2m. END SALTPACK DETACHED SIGNATURE.";
+    const ENCRYPTED: &str = "BEGIN SALTPACK ENCRYPTED MESSAGE. keDIDMQWYvVR58B FTfTeD305cnrfmo hhMiXZZUP4EE57s \
+        OhCsee4BS6ypAHY j81KHbDvKzIwaxs K49smCRxEcTWSRO NsKoWNKnoMB8NQm h8IaylJMc9EpOcR ZxNPNS3Hulqby3I \
+        XvghSLcS5FDH5Z0 WmVnp2q02phCMo1 UvBiSNRfFxrseYI ze1YwzzwdhuX3FT h7O2dpuQczD04Pg VkqkualGAaq5zUF \
+        wSe1KITByVBm4nS 4F0LOPshqFolI1w 9VUHxR1YZImZ7bJ b7vYSabupc3Ojw3 U5r46KcdTmIiaUN w4eFWE448uAqabh \
+        tRTHWChvfjdQsLj ME9wE61TnHKvsTC 89QF2E. END SALTPACK ENCRYPTED MESSAGE.";
+
+    fn key<const N: usize>(hex_key: &str) -> [u8; N] {
+        hex::decode(hex_key).unwrap().try_into().unwrap()
+    }
+
+    fn signing_public_key() -> [u8; 32] {
+        key::<64>(SIGNING_SECRET_KEY)[32..].try_into().unwrap()
+    }
+
+    /// `armored` with one bit of byte `index` of its contents flipped.
+    fn flip(armored: &str, kind: &str, index: usize) -> String {
+        let mut data = dearmor(armored, kind).unwrap();
+        data[index] ^= 1;
+        armor(kind, &data)
+    }
+
+    #[test]
+    fn armor_round_trips() {
+        let data = dearmor(SIGNED, SIGNED_MESSAGE).unwrap();
+        assert_eq!(armor(SIGNED_MESSAGE, &data), format!("{}\n", SIGNED));
+        for len in 0..=70 {
+            let data: Vec<u8> = (0..len as u8).collect();
+            assert_eq!(dearmor(&armor(ENCRYPTED_MESSAGE, &data), ENCRYPTED_MESSAGE).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn known_signed_message_verifies() {
+        assert_eq!(verify_attached(SIGNED, &signing_public_key()).unwrap(), MESSAGE);
+        verify_detached(DETACHED, MESSAGE, &signing_public_key()).unwrap();
+    }
+
+    #[test]
+    fn known_encrypted_message_decrypts() {
+        let (plaintext, sender) = decrypt(ENCRYPTED, &key(BOB_SECRET_KEY)).unwrap();
+        assert_eq!(plaintext, MESSAGE);
+        assert_eq!(sender, Some(key(ALICE_PUBLIC_KEY)));
+    }
+
+    #[test]
+    fn tampered_signatures_are_refused() {
+        let public_key = signing_public_key();
+        let len = dearmor(SIGNED, SIGNED_MESSAGE).unwrap().len();
+        // The last byte of the chunk, just before the final flag
+        let tampered = flip(SIGNED, SIGNED_MESSAGE, len - 2);
+        assert_eq!(verify_attached(&tampered, &public_key).unwrap_err(), "saltpack signature is invalid");
+        let error = verify_detached(DETACHED, b"This is the content!", &public_key).unwrap_err();
+        assert_eq!(error, "saltpack signature is invalid");
+        assert!(verify_attached(SIGNED, &[1; 32]).unwrap_err().starts_with("Signed by"));
+    }
+
+    #[test]
+    fn tampered_ciphertext_is_refused() {
+        let len = dearmor(ENCRYPTED, ENCRYPTED_MESSAGE).unwrap().len();
+        // The last byte of the payload secretbox, just before the final flag
+        let error = decrypt(&flip(ENCRYPTED, ENCRYPTED_MESSAGE, len - 2), &key(BOB_SECRET_KEY)).unwrap_err();
+        assert_eq!(error, "saltpack message is corrupted or was modified");
+        assert_eq!(decrypt(ENCRYPTED, &[7; 32]).unwrap_err(), "This saltpack message is not encrypted to your key");
+    }
+}
@@ -0,0 +1,95 @@
+//! crypto_box with the shared key computed ahead: [`SharedKey::new`] does the X25519
+//! scalar multiplication (crypto_box_beforenm) once for a pair of key pairs, and each
+//! box sealed or opened with it afterwards (crypto_box_easy_afternm and
+//! crypto_box_open_easy_afternm) costs only XSalsa20-Poly1305. Boxes are the same as
+//! crypto_box_easy's, so either side can use either API.
+//!
+//! ```ignore
+//! let mut keys = sodix::sharedkey::SharedKeys::new();
+//! for (nonce, message) in messages {
+//!     let boxed = keys.get(&their_public_key, &our_secret_key).seal(&message, &nonce)?;
+//! }
+//! ```
+
+use crypto_secretbox::aead::{AeadInPlace, KeyInit};
+use crypto_secretbox::XSalsa20Poly1305;
+use dryoc::classic::crypto_box::crypto_box_beforenm;
+use dryoc::classic::crypto_generichash::crypto_generichash;
+use std::collections::BTreeMap;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+// A box is MAC (16) || ciphertext, under a 24-byte nonce the caller supplies and
+// must never repeat for the same pair of key pairs.
+pub const NONCE_LEN: usize = 24;
+pub const MAC_LEN: usize = 16;
+
+/// The crypto_box key shared by one of our secret keys and a peer's public key.
+/// Both directions between the two key pairs use the same key.
+/// Wiped from memory when dropped.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct SharedKey([u8; 32]);
+
+impl SharedKey {
+    pub fn new(their_public_key: &[u8; 32], our_secret_key: &[u8; 32]) -> Self {
+        SharedKey(crypto_box_beforenm(their_public_key, our_secret_key))
+    }
+
+    /// The raw key, for constructions keyed like crypto_box (sodix's `--aead`).
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// crypto_box_easy_afternm: MAC || ciphertext of `message`.
+    pub fn seal(&self, message: &[u8], nonce: &[u8; NONCE_LEN]) -> Result<Vec<u8>, String> {
+        let mut boxed = vec![0u8; MAC_LEN];
+        boxed.extend_from_slice(message);
+        let tag = XSalsa20Poly1305::new(&self.0.into())
+            .encrypt_in_place_detached(nonce.into(), b"", &mut boxed[MAC_LEN..])
+            .map_err(|_| "Message too long to encrypt".to_string())?;
+        boxed[..MAC_LEN].copy_from_slice(&tag);
+        Ok(boxed)
+    }
+
+    /// crypto_box_open_easy_afternm: the message in `boxed` (MAC || ciphertext).
+    pub fn open(&self, boxed: &[u8], nonce: &[u8; NONCE_LEN]) -> Result<Vec<u8>, String> {
+        let mut boxed = boxed.to_vec();
+        self.open_in_place(&mut boxed, nonce)?;
+        boxed.drain(..MAC_LEN);
+        Ok(boxed)
+    }
+
+    /// Like [`open`](Self::open), decrypting in place: returns the part of `boxed`
+    /// holding the message.
+    pub fn open_in_place<'a>(&self, boxed: &'a mut [u8], nonce: &[u8; NONCE_LEN]) -> Result<&'a [u8], String> {
+        if boxed.len() < MAC_LEN {
+            return Err("Ciphertext too short".to_string());
+        }
+        let (tag, ciphertext) = boxed.split_at_mut(MAC_LEN);
+        XSalsa20Poly1305::new(&self.0.into())
+            .decrypt_in_place_detached(nonce.into(), b"", ciphertext, (&*tag).into())
+            .map_err(|_| "Decryption failed".to_string())?;
+        Ok(ciphertext)
+    }
+}
+
+/// Shared keys computed so far, for a caller that meets the same peers again and
+/// again. They are found by a BLAKE2b of the peer's public key keyed with our secret
+/// key, so the cache holds no copy of the secret key itself.
+#[derive(Default)]
+pub struct SharedKeys(BTreeMap<[u8; 32], SharedKey>);
+
+impl SharedKeys {
+    pub const fn new() -> Self {
+        SharedKeys(BTreeMap::new())
+    }
+
+    /// The shared key of the pair, computed on first use.
+    pub fn get(&mut self, their_public_key: &[u8; 32], our_secret_key: &[u8; 32]) -> SharedKey {
+        let mut id = [0u8; 32];
+        crypto_generichash(&mut id, their_public_key, Some(our_secret_key)).expect("valid BLAKE2b lengths");
+        self.0
+            .entry(id)
+            .or_insert_with(|| SharedKey::new(their_public_key, our_secret_key))
+            .clone()
+    }
+}
@@ -0,0 +1,74 @@
+use crate::container::{self, OpenKeys};
+use dryoc::classic::crypto_core::crypto_scalarmult_base;
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+
+// Signed message, encrypted as a whole into a container with a signed ephemeral
+// sender key:
+//
+//   MAGIC | version | signer public key (32) | recipient count u16 | recipient keys (32 each)
+//   | signature (64) | message
+//
+// The signature covers CONTEXT, everything before it and the message. Naming the
+// recipients inside the signature stops a recipient from re-encrypting the signed
+// message to someone else and passing it off as sent to them.
+const MAGIC: &[u8; 4] = b"SXSC";
+const VERSION: u8 = 1;
+const CONTEXT: &[u8] = b"sodix signcrypt v1";
+
+/// Sign `data` with `sign_sk` and encrypt it to `recipients`; returns container bytes.
+#[cfg(feature = "full")]
+pub fn seal(data: &[u8], recipients: &[[u8; 32]], sign_sk: &[u8; 64]) -> Result<Vec<u8>, String> {
+    let mut frame = Vec::with_capacity(4 + 1 + 32 + 2 + recipients.len() * 32 + 64 + data.len());
+    frame.extend_from_slice(MAGIC);
+    frame.push(VERSION);
+    frame.extend_from_slice(&sign_sk[32..]);
+    frame.extend_from_slice(&(recipients.len() as u16).to_be_bytes());
+    for recipient_pk in recipients {
+        frame.extend_from_slice(recipient_pk);
+    }
+    let mut signature = [0u8; 64];
+    crypto_sign_detached(&mut signature, &signed_bytes(&frame, data), sign_sk)
+        .map_err(|e| format!("Error signing data: {}", e))?;
+    frame.extend_from_slice(&signature);
+    frame.extend_from_slice(data);
+    container::encrypt_bytes(&frame, recipients, &container::Sender::Ephemeral(*sign_sk), None)
+}
+
+/// Decrypt a [`seal`]ed message and verify it was signed by `signer_pk` for us.
+pub fn open(sealed: &[u8], recipient_sk: &[u8; 32], signer_pk: &[u8; 32]) -> Result<Vec<u8>, String> {
+    let keys = OpenKeys { recipient_sk: *recipient_sk, sender_pk: None, signer_pk: Some(*signer_pk) };
+    let mut frame = Vec::new();
+    container::decrypt_stream(sealed, &mut frame, &keys)?;
+
+    if frame.len() < 4 + 1 + 32 + 2 || &frame[..4] != MAGIC {
+        return Err("Not a sodix signcrypted message".to_string());
+    }
+    if frame[4] != VERSION {
+        return Err(format!("Unsupported signcrypt version {}", frame[4]));
+    }
+    let count = u16::from_be_bytes([frame[37], frame[38]]) as usize;
+    let head_len = 39 + count * 32;
+    if frame.len() < head_len + 64 {
+        return Err("Truncated signcrypted message".to_string());
+    }
+    let (head, rest) = frame.split_at(head_len);
+    let (signature, message) = rest.split_at(64);
+
+    if &head[5..37] != signer_pk {
+        return Err("Message is signed by a different key than the expected signer".to_string());
+    }
+    let mut own_pk = [0u8; 32];
+    crypto_scalarmult_base(&mut own_pk, recipient_sk);
+    if !head[39..].chunks(32).any(|pk| pk == own_pk) {
+        return Err("Message was not signed for this recipient (it may have been forwarded)".to_string());
+    }
+    crypto_sign_verify_detached(signature.try_into().unwrap(), &signed_bytes(head, message), signer_pk)
+        .map_err(|e| format!("Signature verification failed: {}", e))?;
+    Ok(message.to_vec())
+}
+
+fn signed_bytes(head: &[u8], message: &[u8]) -> Vec<u8> {
+    [CONTEXT, head, message].concat()
+}
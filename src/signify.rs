@@ -0,0 +1,215 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use dryoc::classic::crypto_sign::crypto_sign_verify_detached;
+#[cfg(feature = "full")]
+use dryoc::classic::crypto_sign::{crypto_sign_detached, crypto_sign_keypair};
+#[cfg(feature = "full")]
+use rand::Rng;
+#[cfg(feature = "full")]
+use sha2::{Digest, Sha512};
+
+// OpenBSD signify formats, each a comment line followed by one base64 line:
+//
+//   public key:  base64("Ed" | key number (8) | Ed25519 public key (32))
+//   secret key:  base64("Ed" | "BK" | kdf rounds u32 BE | salt (16) | SHA-512(secret key)[..8]
+//                       | key number (8) | Ed25519 secret key (64))
+//   signature:   base64("Ed" | key number (8) | Ed25519 signature of the message)
+//
+// An embedded signature (signify -e) is the signature file followed by the message.
+// Secret keys with kdf rounds > 0 are encrypted with bcrypt_pbkdf and a passphrase,
+// which sodix does not support; `signify -G -n` creates unencrypted ones.
+const PK_ALG: &[u8; 2] = b"Ed";
+#[cfg(feature = "full")]
+const KDF_ALG: &[u8; 2] = b"BK";
+#[cfg(feature = "full")]
+const SECRET_KEY_LEN: usize = 2 + 2 + 4 + 16 + 8 + 8 + 64;
+const COMMENT: &str = "untrusted comment: ";
+
+pub struct PublicKey {
+    pub key_number: [u8; 8],
+    pub public_key: [u8; 32],
+}
+
+#[cfg(feature = "full")]
+pub struct SecretKey {
+    pub key_number: [u8; 8],
+    pub secret_key: [u8; 64],
+}
+
+/// Parse a .pub file, or just its base64 line.
+pub fn parse_public_key(text: &str) -> Result<PublicKey, String> {
+    let bytes = decode_payload(text, "public key")?;
+    if bytes.len() != 42 || &bytes[..2] != PK_ALG {
+        return Err("Not a signify public key".to_string());
+    }
+    Ok(PublicKey {
+        key_number: bytes[2..10].try_into().unwrap(),
+        public_key: bytes[10..].try_into().unwrap(),
+    })
+}
+
+#[cfg(feature = "full")]
+pub fn parse_secret_key(text: &str) -> Result<SecretKey, String> {
+    let bytes = decode_payload(text, "secret key")?;
+    if bytes.len() != SECRET_KEY_LEN || &bytes[..2] != PK_ALG || &bytes[2..4] != KDF_ALG {
+        return Err("Not a signify secret key".to_string());
+    }
+    if bytes[4..8] != [0u8; 4] {
+        return Err("Passphrase-protected signify secret keys are not supported; create one with `signify -G -n`".to_string());
+    }
+    let secret_key: [u8; 64] = bytes[40..].try_into().unwrap();
+    if bytes[24..32] != Sha512::digest(secret_key)[..8] {
+        return Err("signify secret key checksum mismatch".to_string());
+    }
+    Ok(SecretKey { key_number: bytes[32..40].try_into().unwrap(), secret_key })
+}
+
+/// A fresh unencrypted key pair as (.pub file, .sec file) contents.
+#[cfg(feature = "full")]
+pub fn generate(comment: &str) -> (String, String) {
+    let (public_key, secret_key) = crypto_sign_keypair();
+    let mut key_number = [0u8; 8];
+    let mut salt = [0u8; 16];
+    rand::rng().fill(&mut key_number);
+    rand::rng().fill(&mut salt);
+
+    let public = [&PK_ALG[..], &key_number, &public_key].concat();
+    let mut secret = Vec::with_capacity(SECRET_KEY_LEN);
+    secret.extend_from_slice(PK_ALG);
+    secret.extend_from_slice(KDF_ALG);
+    secret.extend_from_slice(&0u32.to_be_bytes());
+    secret.extend_from_slice(&salt);
+    secret.extend_from_slice(&Sha512::digest(secret_key)[..8]);
+    secret.extend_from_slice(&key_number);
+    secret.extend_from_slice(&secret_key);
+    (
+        format!("{}{} public key\n{}\n", COMMENT, comment, STANDARD.encode(public)),
+        format!("{}{} secret key\n{}\n", COMMENT, comment, STANDARD.encode(secret)),
+    )
+}
+
+/// Detached signature file contents for `message`.
+#[cfg(feature = "full")]
+pub fn sign(message: &[u8], key: &SecretKey, comment: &str) -> Result<String, String> {
+    if comment.contains('\n') {
+        return Err("Comments must be a single line".to_string());
+    }
+    let mut signature = [0u8; 64];
+    crypto_sign_detached(&mut signature, message, &key.secret_key)
+        .map_err(|e| format!("Error signing data: {}", e))?;
+    Ok(format!(
+        "{}{}\n{}\n",
+        COMMENT,
+        comment,
+        STANDARD.encode([&PK_ALG[..], &key.key_number, &signature].concat())
+    ))
+}
+
+/// Embedded signature (signify -e): the signature file followed by the message.
+#[cfg(feature = "full")]
+pub fn sign_embedded(message: &[u8], key: &SecretKey, comment: &str) -> Result<Vec<u8>, String> {
+    let mut out = sign(message, key, comment)?.into_bytes();
+    out.extend_from_slice(message);
+    Ok(out)
+}
+
+/// Verify a detached signature file for `message`.
+pub fn verify(message: &[u8], signature_file: &[u8], key: &PublicKey) -> Result<(), String> {
+    let (signature, rest) = split_signature(signature_file)?;
+    if !rest.is_empty() {
+        return Err("Signature file has an embedded message; check it with --attached".to_string());
+    }
+    verify_with(message, &signature, key)
+}
+
+/// Verify an embedded signature and return the message that follows it.
+pub fn verify_embedded<'a>(signed: &'a [u8], key: &PublicKey) -> Result<&'a [u8], String> {
+    let (signature, message) = split_signature(signed)?;
+    verify_with(message, &signature, key)?;
+    Ok(message)
+}
+
+fn verify_with(message: &[u8], signature: &[u8], key: &PublicKey) -> Result<(), String> {
+    if signature.len() != 74 || &signature[..2] != PK_ALG {
+        return Err("Not a signify signature".to_string());
+    }
+    if signature[2..10] != key.key_number {
+        return Err("Signature was made with a different key (key number mismatch)".to_string());
+    }
+    crypto_sign_verify_detached(signature[10..].try_into().unwrap(), message, &key.public_key).map_err(|e| e.to_string())
+}
+
+/// Split a signature file into the decoded signature and whatever follows its line.
+fn split_signature(file: &[u8]) -> Result<(Vec<u8>, &[u8]), String> {
+    let invalid = || "Not a signify signature".to_string();
+    let comment_end = file.iter().position(|&b| b == b'\n').ok_or_else(invalid)?;
+    if !file.starts_with(COMMENT.as_bytes()) {
+        return Err(invalid());
+    }
+    let rest = &file[comment_end + 1..];
+    let line_end = rest.iter().position(|&b| b == b'\n').ok_or_else(invalid)?;
+    let line = std::str::from_utf8(&rest[..line_end]).map_err(|_| invalid())?;
+    let signature = STANDARD.decode(line.trim_end()).map_err(|e| format!("Invalid signify signature: {}", e))?;
+    Ok((signature, &rest[line_end + 1..]))
+}
+
+/// The base64 payload of a key file, skipping its comment line if present.
+fn decode_payload(text: &str, what: &str) -> Result<Vec<u8>, String> {
+    let line = text
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with(COMMENT))
+        .ok_or_else(|| format!("Empty signify {}", what))?;
+    STANDARD.decode(line).map_err(|e| format!("Invalid signify {}: {}", what, e))
+}
+
+#[cfg(all(test, feature = "full"))]
+mod tests {
+    use super::*;
+
+    // RFC 8032 section 7.1, TEST 2: a signify signature is "Ed", the key number and
+    // the plain Ed25519 signature of the message.
+    const SECRET_KEY: &str = "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb\
+        3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c";
+    const MESSAGE: &[u8] = &[0x72];
+    const SIGNATURE: &str = "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+        085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00";
+    const KEY_NUMBER: [u8; 8] = *b"sodixkat";
+
+    fn keys() -> (SecretKey, PublicKey) {
+        let secret_key: [u8; 64] = hex::decode(SECRET_KEY).unwrap().try_into().unwrap();
+        let public_key = secret_key[32..].try_into().unwrap();
+        (SecretKey { key_number: KEY_NUMBER, secret_key }, PublicKey { key_number: KEY_NUMBER, public_key })
+    }
+
+    #[test]
+    fn signature_known_answer() {
+        let (secret_key, _) = keys();
+        let file = sign(MESSAGE, &secret_key, "test").unwrap();
+        let expected = [&PK_ALG[..], &KEY_NUMBER, &hex::decode(SIGNATURE).unwrap()].concat();
+        assert_eq!(file, format!("untrusted comment: test\n{}\n", STANDARD.encode(expected)));
+    }
+
+    #[test]
+    fn known_signature_verifies() {
+        let (secret_key, public_key) = keys();
+        verify(MESSAGE, sign(MESSAGE, &secret_key, "test").unwrap().as_bytes(), &public_key).unwrap();
+        let embedded = sign_embedded(MESSAGE, &secret_key, "test").unwrap();
+        assert_eq!(verify_embedded(&embedded, &public_key).unwrap(), MESSAGE);
+    }
+
+    #[test]
+    fn tampering_is_refused() {
+        let (secret_key, public_key) = keys();
+        let file = sign(MESSAGE, &secret_key, "test").unwrap();
+        assert!(verify(&[0x73], file.as_bytes(), &public_key).is_err());
+        let mut embedded = sign_embedded(MESSAGE, &secret_key, "test").unwrap();
+        *embedded.last_mut().unwrap() ^= 1;
+        assert!(verify_embedded(&embedded, &public_key).is_err());
+        let other = PublicKey { key_number: *b"otherkey", public_key: public_key.public_key };
+        assert_eq!(
+            verify(MESSAGE, file.as_bytes(), &other).unwrap_err(),
+            "Signature was made with a different key (key number mismatch)"
+        );
+    }
+}
@@ -0,0 +1,316 @@
+#[cfg(feature = "full")]
+use crate::cleanup::{self, AtomicFile};
+use std::fs::{self, File};
+#[cfg(feature = "full")]
+use std::io::Write;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+// An encrypted file split into parts for targets with a per-file size limit:
+// backup.tar.x.001, backup.tar.x.002, ... and the index backup.tar.x.parts:
+//
+//   sodix-split 1
+//   <size> backup.tar.x.001
+//   <size> backup.tar.x.002
+//
+// Parts are plain byte ranges of the container, so its chunks keep authenticating
+// the contents; the index only says how to put them back together. Part sizes are
+// checked against it before anything is read, so a missing or truncated part is
+// reported by name.
+const HEADER: &str = "sodix-split 1";
+pub const INDEX_EXTENSION: &str = ".parts";
+
+/// The index of the parts of `path`.
+pub fn index_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(INDEX_EXTENSION);
+    PathBuf::from(name)
+}
+
+#[cfg(feature = "full")]
+fn part_path(path: &Path, number: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{:03}", number));
+    PathBuf::from(name)
+}
+
+/// A size such as 1G, 650M or 4096; K, M, G and T are powers of 1024.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let digits = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let shift = match size[digits.len()..].to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("Invalid size '{}', expected e.g. 4096, 650M or 1G", size)),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("Invalid size '{}', expected e.g. 4096, 650M or 1G", size))
+}
+
+/// Where an encrypted file is written: whole, or in parts of at most a given size.
+#[cfg(feature = "full")]
+pub enum Output {
+    Whole(AtomicFile),
+    Parts(SplitWriter),
+}
+
+#[cfg(feature = "full")]
+impl Output {
+    pub fn create(path: &Path, split_size: Option<u64>) -> io::Result<Self> {
+        match split_size {
+            Some(part_size) => Ok(Output::Parts(SplitWriter {
+                path: path.to_path_buf(),
+                part_size,
+                parts: Vec::new(),
+                current: None,
+                finished: false,
+            })),
+            None => AtomicFile::create(path).map(Output::Whole),
+        }
+    }
+
+    /// Move the complete file, or the parts and their index, into place.
+    pub fn commit(self) -> io::Result<()> {
+        match self {
+            Output::Whole(file) => file.commit(),
+            Output::Parts(writer) => writer.commit(),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Whole(file) => file.write(buf),
+            Output::Parts(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Whole(file) => file.flush(),
+            Output::Parts(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Writes parts of `part_size` bytes, each committed as it fills. Dropped before
+/// `commit`, it removes the parts written so far.
+#[cfg(feature = "full")]
+pub struct SplitWriter {
+    path: PathBuf,
+    part_size: u64,
+    parts: Vec<(PathBuf, u64)>,
+    current: Option<(AtomicFile, u64)>,
+    finished: bool,
+}
+
+#[cfg(feature = "full")]
+impl SplitWriter {
+    fn finish_part(&mut self) -> io::Result<()> {
+        if let Some((file, size)) = self.current.take() {
+            file.commit()?;
+            self.parts.push((part_path(&self.path, self.parts.len() + 1), size));
+        }
+        Ok(())
+    }
+
+    fn commit(mut self) -> io::Result<()> {
+        self.finish_part()?;
+        let mut index = format!("{}\n", HEADER);
+        for (path, size) in &self.parts {
+            index.push_str(&format!("{} {}\n", size, path.file_name().unwrap_or_default().to_string_lossy()));
+        }
+        cleanup::write_atomic(index_path(&self.path), index)?;
+        self.finished = true;
+        // A whole file of the same name would be read instead of the parts, and parts
+        // left over from an earlier, longer split would only confuse
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let mut number = self.parts.len() + 1;
+        while fs::remove_file(part_path(&self.path, number)).is_ok() {
+            number += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "full")]
+impl Write for SplitWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.current.as_ref().is_some_and(|(_, size)| *size == self.part_size) {
+            self.finish_part()?;
+        }
+        if self.current.is_none() {
+            let file = AtomicFile::create(part_path(&self.path, self.parts.len() + 1))?;
+            self.current = Some((file, 0));
+        }
+        let (file, size) = self.current.as_mut().unwrap();
+        let room = (self.part_size - *size).min(buf.len() as u64) as usize;
+        let n = file.write(&buf[..room])?;
+        *size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.current {
+            Some((file, _)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "full")]
+impl Drop for SplitWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            for (path, _) in &self.parts {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// An encrypted file opened for reading: `path` itself or, if there is no such
+/// file, the parts listed in its index.
+pub enum Input {
+    Whole(File),
+    Parts(SplitReader),
+}
+
+impl Input {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        match File::open(path) {
+            Ok(file) => Ok(Input::Whole(file)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && index_path(path).is_file() => {
+                SplitReader::open(&index_path(path)).map(Input::Parts)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Total size in bytes, of all the parts together for a split file.
+    pub fn size(&self) -> io::Result<u64> {
+        match self {
+            Input::Whole(file) => file.metadata().map(|m| m.len()),
+            Input::Parts(reader) => Ok(reader.len()),
+        }
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Whole(file) => file.read(buf),
+            Input::Parts(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for Input {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Input::Whole(file) => file.seek(pos),
+            Input::Parts(reader) => reader.seek(pos),
+        }
+    }
+}
+
+/// The parts of a split file read back to back as one stream.
+pub struct SplitReader {
+    parts: Vec<PathBuf>,
+    /// Offset of each part in the whole, plus the total length at the end
+    starts: Vec<u64>,
+    position: u64,
+    current: Option<(usize, File)>,
+}
+
+impl SplitReader {
+    fn open(index: &Path) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", index.display(), msg));
+        let text = fs::read_to_string(index)?;
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(invalid("not a sodix split index".to_string()));
+        }
+        let dir = index.parent().unwrap_or(Path::new(""));
+        let mut parts = Vec::new();
+        let mut starts = vec![0];
+        for line in lines {
+            let (size, name) = line
+                .split_once(' ')
+                .and_then(|(size, name)| Some((size.parse::<u64>().ok()?, name)))
+                .ok_or_else(|| invalid(format!("invalid line '{}'", line)))?;
+            // Parts always sit next to the index
+            if Path::new(name).file_name() != Some(name.as_ref()) {
+                return Err(invalid(format!("invalid part name '{}'", name)));
+            }
+            let path = dir.join(name);
+            let actual = fs::metadata(&path)
+                .map_err(|e| io::Error::new(e.kind(), format!("part {}: {}", path.display(), e)))?
+                .len();
+            if actual != size {
+                return Err(invalid(format!("part {} has {} bytes, expected {}", path.display(), actual, size)));
+            }
+            starts.push(starts.last().unwrap() + size);
+            parts.push(path);
+        }
+        if parts.is_empty() {
+            return Err(invalid("no parts listed".to_string()));
+        }
+        Ok(SplitReader { parts, starts, position: 0, current: None })
+    }
+
+    fn len(&self) -> u64 {
+        *self.starts.last().unwrap()
+    }
+}
+
+impl Read for SplitReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        // The last part whose start is at or before the position
+        let part = self.starts.partition_point(|&start| start <= self.position) - 1;
+        if !matches!(&self.current, Some((i, _)) if *i == part) {
+            let file = File::open(&self.parts[part])
+                .map_err(|e| io::Error::new(e.kind(), format!("part {}: {}", self.parts[part].display(), e)))?;
+            self.current = Some((part, file));
+        }
+        let file = &mut self.current.as_mut().unwrap().1;
+        file.seek(SeekFrom::Start(self.position - self.starts[part]))?;
+        let room = (self.starts[part + 1] - self.position).min(buf.len() as u64) as usize;
+        let n = file.read(&mut buf[..room])?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("part {} is truncated", self.parts[part].display())));
+        }
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for SplitReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+        self.position = position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of the file"))?;
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,91 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Keys remembered by `check --tofu <name>` (trust on first use), known_signers in the
+// key directory, one line per name:
+//
+//   <name> <hex public key> <first seen, unix seconds>
+//
+// The first key that verifies for a name is stored; later checks for that name fail
+// if a different key signed. `sodix trust remove <name>` forgets it after a rotation.
+pub const FILE_NAME: &str = "known_signers";
+
+pub struct KnownSigner {
+    pub name: String,
+    pub key: [u8; 32],
+    pub first_seen: u64,
+}
+
+pub struct TrustStore {
+    path: PathBuf,
+    signers: Vec<KnownSigner>,
+}
+
+impl TrustStore {
+    /// Read the store; a missing file is an empty one.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Failed to read known signers from {}: {}", path.display(), e)),
+        };
+        Self::parse(path, &text)
+    }
+
+    /// A store read from `text`, e.g. one in a key bundle, saved to `path`.
+    pub fn parse(path: &Path, text: &str) -> Result<Self, String> {
+        let mut signers = Vec::new();
+        for (i, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let invalid = || format!("{}:{}: expected '<name> <hex key> <first seen>'", path.display(), i + 1);
+            let mut fields = line.split_whitespace();
+            let (name, key, first_seen) = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(name), Some(key), Some(first_seen), None) => (name, key, first_seen),
+                _ => return Err(invalid()),
+            };
+            signers.push(KnownSigner {
+                name: name.to_string(),
+                key: hex::decode(key).ok().and_then(|k| k.try_into().ok()).ok_or_else(invalid)?,
+                first_seen: first_seen.parse().map_err(|_| invalid())?,
+            });
+        }
+        Ok(TrustStore { path: path.to_path_buf(), signers })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory {}: {}", dir.display(), e))?;
+        }
+        let text: String = self
+            .signers
+            .iter()
+            .map(|s| format!("{} {} {}\n", s.name, hex::encode(s.key), s.first_seen))
+            .collect();
+        fs::write(&self.path, text).map_err(|e| format!("Failed to write known signers to {}: {}", self.path.display(), e))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&KnownSigner> {
+        self.signers.iter().find(|s| s.name == name)
+    }
+
+    pub fn insert(&mut self, name: &str, key: [u8; 32], first_seen: u64) -> Result<(), String> {
+        if name.is_empty() || name.chars().any(char::is_whitespace) {
+            return Err(format!("Invalid signer name '{}'", name));
+        }
+        self.signers.retain(|s| s.name != name);
+        self.signers.push(KnownSigner { name: name.to_string(), key, first_seen });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), String> {
+        let before = self.signers.len();
+        self.signers.retain(|s| s.name != name);
+        if self.signers.len() == before {
+            return Err(format!("No known signer named '{}'", name));
+        }
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &KnownSigner> {
+        self.signers.iter()
+    }
+}
@@ -0,0 +1,117 @@
+use crate::bech32key::{self, KeyKind};
+use dryoc::classic::crypto_sign::crypto_sign_keypair;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Vanity signing keys (generate --vanity PREFIX): random key pairs are generated on
+// every core until the public key, as `sodix print` shows it, starts with PREFIX. A
+// prefix starting with "sodixsign1" is matched against the bech32 form, anything else
+// against hex. Each extra character multiplies the expected work by 16 (hex) or 32
+// (bech32), so prefixes needing more than MAX_ATTEMPTS tries are refused.
+const MAX_ATTEMPTS: u64 = 1 << 32;
+const BECH32_PREFIX: &str = "sodixsign1";
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+enum Pattern {
+    Hex(String),
+    Bech32(String),
+}
+
+impl Pattern {
+    fn parse(prefix: &str) -> Result<Self, String> {
+        let prefix = prefix.to_ascii_lowercase();
+        if let Some(data) = prefix.strip_prefix(BECH32_PREFIX) {
+            if let Some(c) = data.chars().find(|c| !BECH32_CHARSET.contains(*c)) {
+                return Err(format!("'{}' cannot appear in a bech32 key", c));
+            }
+            return Ok(Pattern::Bech32(prefix));
+        }
+        if let Some(c) = prefix.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(format!(
+                "'{}' cannot appear in a hex key; start the prefix with {} to match the bech32 form",
+                c, BECH32_PREFIX
+            ));
+        }
+        Ok(Pattern::Hex(prefix))
+    }
+
+    /// Average number of key pairs to try before one matches.
+    fn expected_attempts(&self) -> f64 {
+        match self {
+            Pattern::Hex(p) => 16f64.powi(p.len() as i32),
+            Pattern::Bech32(p) => 32f64.powi((p.len() - BECH32_PREFIX.len()) as i32),
+        }
+    }
+
+    fn matches(&self, public_key: &[u8; 32]) -> bool {
+        match self {
+            Pattern::Hex(p) => hex::encode(public_key).starts_with(p.as_str()),
+            Pattern::Bech32(p) => bech32key::encode(KeyKind::Signing, public_key).starts_with(p.as_str()),
+        }
+    }
+}
+
+/// Generate signing key pairs until the public key starts with `prefix`, reporting
+/// progress on stderr. Returns (public, secret).
+pub fn grind(prefix: &str) -> Result<([u8; 32], [u8; 64]), String> {
+    let pattern = Pattern::parse(prefix)?;
+    let expected = pattern.expected_attempts();
+    if expected > MAX_ATTEMPTS as f64 {
+        return Err(format!(
+            "Prefix '{}' needs about {:.0} attempts on average; the limit is {}, use a shorter one",
+            prefix, expected, MAX_ATTEMPTS
+        ));
+    }
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let attempts = AtomicU64::new(0);
+    let done = AtomicBool::new(false);
+    let found = Mutex::new(None);
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    let (public_key, secret_key) = crypto_sign_keypair();
+                    if attempts.fetch_add(1, Ordering::Relaxed) >= MAX_ATTEMPTS {
+                        done.store(true, Ordering::Relaxed);
+                    } else if pattern.matches(&public_key) {
+                        found.lock().unwrap().get_or_insert((public_key, secret_key));
+                        done.store(true, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+        let mut last_report = Instant::now();
+        let mut reported = false;
+        while !done.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(50));
+            if last_report.elapsed() >= PROGRESS_INTERVAL {
+                last_report = Instant::now();
+                let tried = attempts.load(Ordering::Relaxed);
+                let rate = tried as f64 / start.elapsed().as_secs_f64();
+                eprint!(
+                    "\r{} key pairs tried on {} threads ({:.0}/s, about {:.0}s expected in total)",
+                    tried,
+                    threads,
+                    rate,
+                    expected / rate
+                );
+                let _ = std::io::stderr().flush();
+                reported = true;
+            }
+        }
+        if reported {
+            eprintln!();
+        }
+    });
+    let tried = attempts.load(Ordering::Relaxed).min(MAX_ATTEMPTS);
+    eprintln!("{} key pairs tried in {:.1}s", tried, start.elapsed().as_secs_f64());
+    found
+        .into_inner()
+        .unwrap()
+        .ok_or_else(|| format!("No key starting with '{}' after {} attempts", prefix, MAX_ATTEMPTS))
+}
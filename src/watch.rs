@@ -0,0 +1,191 @@
+use crate::channel::{list_files_except, relative_name};
+use crate::container::{self, Sender};
+use crate::ignore::Ignore;
+use crate::mmap;
+use dryoc::classic::crypto_sign::crypto_sign_detached;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+// Keeps signatures or encrypted copies of a directory tree current. Every file gets
+// an output named after it (<file>.sig or <file>.x), next to it or at the same
+// relative path under a mirror directory. On start, outputs that are missing or
+// older than their file are brought up to date; after that each change re-signs or
+// re-encrypts the file, and deleting a file deletes its output.
+//
+// Editors and copies produce bursts of events for one save, so events are collected
+// until the tree has been quiet for DEBOUNCE and each touched file is handled once.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+pub enum Action {
+    /// A bare hex signature, as `sign -f --sig-file` writes
+    Sign([u8; 64]),
+    /// A chunked container, as `encrypt -f` writes
+    Encrypt { recipients: Vec<[u8; 32]>, sender: Sender },
+}
+
+impl Action {
+    fn extension(&self) -> &'static str {
+        match self {
+            Action::Sign(_) => ".sig",
+            Action::Encrypt { .. } => ".x",
+        }
+    }
+}
+
+pub struct Watch {
+    dir: PathBuf,
+    out: Option<PathBuf>,
+    action: Action,
+    ignore: Ignore,
+    verbose: bool,
+}
+
+impl Watch {
+    pub fn new(dir: &Path, out: Option<&Path>, action: Action, ignore: Ignore, verbose: bool) -> Result<Self, String> {
+        let dir = dir.canonicalize().map_err(|e| format!("Failed to open directory {}: {}", dir.display(), e))?;
+        if !dir.is_dir() {
+            return Err(format!("{} is not a directory", dir.display()));
+        }
+        let out = match out {
+            Some(out) => {
+                fs::create_dir_all(out).map_err(|e| format!("Failed to create directory {}: {}", out.display(), e))?;
+                Some(out.canonicalize().map_err(|e| format!("Failed to open directory {}: {}", out.display(), e))?)
+            }
+            None => None,
+        };
+        Ok(Watch { dir, out, action, ignore, verbose })
+    }
+
+    /// Bring every output up to date, then follow changes until interrupted.
+    pub fn run(&self) -> Result<(), String> {
+        // Start watching first so nothing changed during the initial pass is missed
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).map_err(|e| format!("Failed to watch {}: {}", self.dir.display(), e))?;
+        watcher
+            .watch(&self.dir, RecursiveMode::Recursive)
+            .map_err(|e| format!("Failed to watch {}: {}", self.dir.display(), e))?;
+        self.update_tree(&self.dir)?;
+        if self.verbose {
+            println!("Watching {}", self.dir.display());
+        }
+        loop {
+            let mut changed = BTreeSet::new();
+            let mut event = rx.recv().map_err(|_| "The file watcher stopped".to_string())?;
+            loop {
+                match event {
+                    Ok(event) if !matches!(event.kind, EventKind::Access(_)) => changed.extend(event.paths),
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Watch error: {}", e),
+                }
+                event = match rx.recv_timeout(DEBOUNCE) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return Err("The file watcher stopped".to_string()),
+                };
+            }
+            for path in changed {
+                if let Err(e) = self.changed(&path) {
+                    eprintln!("{}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    fn update_tree(&self, dir: &Path) -> Result<(), String> {
+        for path in list_files_except(dir, &self.ignore)? {
+            let rel = relative_name(&self.dir, &path)?;
+            if self.is_output(&path, &rel) || self.ignore.is_ignored(&rel) {
+                continue;
+            }
+            let output = self.output(&path, &rel);
+            if is_stale(&path, &output) {
+                if let Err(e) = self.update(&path, &rel, &output) {
+                    eprintln!("{}: {}", path.display(), e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn changed(&self, path: &Path) -> Result<(), String> {
+        let Ok(rel) = relative_name(&self.dir, path) else {
+            return Ok(());
+        };
+        if rel.is_empty() || self.is_output(path, &rel) || self.ignore.is_ignored(&rel) {
+            return Ok(());
+        }
+        // A directory moved in brings files that report no events of their own
+        if path.is_dir() {
+            return self.update_tree(path);
+        }
+        let output = self.output(path, &rel);
+        if path.is_file() {
+            // A later event for a file that was already handled in this burst
+            if !is_stale(path, &output) {
+                return Ok(());
+            }
+            return self.update(path, &rel, &output);
+        }
+        if !path.exists() && output.is_file() {
+            fs::remove_file(&output).map_err(|e| format!("Failed to remove {}: {}", output.display(), e))?;
+            println!("removed {}", rel);
+        }
+        Ok(())
+    }
+
+    fn update(&self, path: &Path, rel: &str, output: &Path) -> Result<(), String> {
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory {}: {}", parent.display(), e))?;
+        }
+        match &self.action {
+            Action::Sign(sk) => {
+                let data = mmap::read(path).map_err(|e| format!("Failed to read input file {}: {}", path.display(), e))?;
+                let mut signature = [0u8; 64];
+                crypto_sign_detached(&mut signature, &data, sk).map_err(|e| format!("Error signing data: {}", e))?;
+                fs::write(output, format!("{}\n", hex::encode(signature)))
+                    .map_err(|e| format!("Failed to write signature to {}: {}", output.display(), e))?;
+                println!("signed {}", rel);
+            }
+            Action::Encrypt { recipients, sender } => {
+                container::encrypt_file(path, output, None, None, recipients, sender, None, None, true, None)?;
+                println!("encrypted {}", rel);
+            }
+        }
+        if self.verbose {
+            println!("  -> {}", output.display());
+        }
+        Ok(())
+    }
+
+    fn output(&self, path: &Path, rel: &str) -> PathBuf {
+        match &self.out {
+            Some(out) => out.join(format!("{}{}", rel, self.action.extension())),
+            None => {
+                let mut name = path.file_name().unwrap_or_default().to_os_string();
+                name.push(self.action.extension());
+                path.with_file_name(name)
+            }
+        }
+    }
+
+    /// Whether `path` is one of the outputs rather than a file to watch.
+    fn is_output(&self, path: &Path, rel: &str) -> bool {
+        match &self.out {
+            Some(out) => path.starts_with(out),
+            None => rel.ends_with(self.action.extension()) || rel.ends_with(".x.hdr"),
+        }
+    }
+}
+
+/// The output is missing or older than the file.
+fn is_stale(path: &Path, output: &Path) -> bool {
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(path), modified(output)) {
+        (Some(source), Some(output)) => output < source,
+        _ => true,
+    }
+}
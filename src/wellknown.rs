@@ -0,0 +1,124 @@
+use dryoc::classic::crypto_sign::{crypto_sign_detached, crypto_sign_verify_detached};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+// Key discovery over HTTPS, in the manner of OpenPGP's Web Key Directory: the
+// encryption key of alice@example.com is published at
+//
+//   https://example.com/.well-known/sodix/<hex SHA-256 of "alice", lowercased>
+//
+// as a document signed by the owner's signing key:
+//
+//   sodix-published-key 1
+//   address alice@example.com
+//   key <hex X25519 public key>
+//   published <unix seconds>
+//   signer <hex signing public key>
+//   signature <hex Ed25519 signature over every line above>
+//
+// The signature only binds the key to the address; whoever controls the web server
+// can publish any key, so `key fetch` and `encrypt --to` show the fingerprint for
+// confirmation (or check --fingerprint) before the key becomes a contact.
+const HEADER: &str = "sodix-published-key 1";
+const SIGNATURE_PREFIX: &str = "signature ";
+const DIRECTORY: &str = ".well-known/sodix";
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct PublishedKey {
+    pub key: [u8; 32],
+    pub signer: [u8; 32],
+}
+
+/// The path below the web root and the domain for `address`.
+pub fn location(address: &str) -> Result<(String, String), String> {
+    let (local, domain) = address
+        .rsplit_once('@')
+        .filter(|(local, domain)| !local.is_empty() && !domain.is_empty())
+        .ok_or_else(|| format!("'{}' is not an email address", address))?;
+    if domain.contains(['/', ':', '?', '#']) || domain.chars().any(char::is_whitespace) {
+        return Err(format!("Invalid domain in '{}'", address));
+    }
+    let hash = Sha256::digest(local.to_lowercase().as_bytes());
+    Ok((format!("{}/{}", DIRECTORY, hex::encode(hash)), domain.to_lowercase()))
+}
+
+pub fn url(address: &str) -> Result<String, String> {
+    let (path, domain) = location(address)?;
+    Ok(format!("https://{}/{}", domain, path))
+}
+
+/// A document publishing `key` for `address`, signed with `sk`.
+pub fn create(address: &str, key: &[u8; 32], published: u64, sk: &[u8; 64]) -> Result<String, String> {
+    location(address)?;
+    let mut text = format!(
+        "{}\naddress {}\nkey {}\npublished {}\nsigner {}\n",
+        HEADER,
+        address,
+        hex::encode(key),
+        published,
+        hex::encode(&sk[32..])
+    );
+    let mut signature = [0u8; 64];
+    crypto_sign_detached(&mut signature, text.as_bytes(), sk).map_err(|e| format!("Error signing published key: {}", e))?;
+    text.push_str(&format!("{}{}\n", SIGNATURE_PREFIX, hex::encode(signature)));
+    Ok(text)
+}
+
+/// Parse a published key document for `address` and check its signature.
+pub fn verify(text: &str, address: &str) -> Result<PublishedKey, String> {
+    let body_len = text
+        .rfind(&format!("\n{}", SIGNATURE_PREFIX))
+        .map(|i| i + 1)
+        .ok_or("Published key is not signed")?;
+    let (body, signature_line) = text.split_at(body_len);
+    let mut lines = body.lines();
+    if lines.next() != Some(HEADER) {
+        return Err("Not a sodix published key".to_string());
+    }
+    let mut field = |name: &str| {
+        lines
+            .next()
+            .and_then(|l| l.strip_prefix(name))
+            .and_then(|l| l.strip_prefix(' '))
+            .ok_or_else(|| format!("Published key is missing '{}'", name))
+    };
+    let published_address = field("address")?;
+    if !published_address.eq_ignore_ascii_case(address) {
+        return Err(format!("The published key is for {}, not {}", published_address, address));
+    }
+    let key = decode_key(field("key")?)?;
+    field("published")?.parse::<u64>().map_err(|_| "Invalid publication time".to_string())?;
+    let signer = decode_key(field("signer")?)?;
+    let signature: [u8; 64] = hex::decode(signature_line[SIGNATURE_PREFIX.len()..].trim())
+        .ok()
+        .and_then(|s| s.try_into().ok())
+        .ok_or("Invalid published key signature")?;
+    crypto_sign_verify_detached(&signature, body.as_bytes(), &signer)
+        .map_err(|e| format!("Published key signature is invalid: {}", e))?;
+    Ok(PublishedKey { key, signer })
+}
+
+/// Download and verify the key of `address` from its well-known URL, or from `url`.
+pub fn fetch(address: &str, url: Option<&str>) -> Result<PublishedKey, String> {
+    let url = match url {
+        Some(url) => url.to_string(),
+        None => self::url(address)?,
+    };
+    let text = ureq::get(&url)
+        .timeout(TIMEOUT)
+        .call()
+        .map_err(|e| match e {
+            ureq::Error::Status(404, _) => format!("No sodix key is published for {} at {}", address, url),
+            e => format!("Failed to fetch the key of {}: {}", address, e),
+        })?
+        .into_string()
+        .map_err(|e| format!("Failed to read {}: {}", url, e))?;
+    verify(&text, address)
+}
+
+fn decode_key(s: &str) -> Result<[u8; 32], String> {
+    hex::decode(s)
+        .ok()
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| format!("Invalid key '{}' in published key", s))
+}
@@ -0,0 +1,150 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// A version-controllable sequence of sodix (and external) commands:
+///
+/// ```toml
+/// [vars]
+/// file = "release.tar.gz"
+///
+/// [[step]]
+/// name = "sign"
+/// run = ["sign", "-f", "${file}"]
+/// capture = "sig"
+///
+/// [[step]]
+/// name = "upload"
+/// exec = ["curl", "-fT", "${file}", "https://example.com/${file}?sig=${sig}"]
+/// on_failure = "continue"
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Workflow {
+    #[serde(default)]
+    vars: HashMap<String, String>,
+    #[serde(default, rename = "step")]
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    name: Option<String>,
+    /// Arguments to sodix itself, e.g. ["encrypt", "-f", "${file}"]
+    run: Option<Vec<String>>,
+    /// An external program and its arguments
+    exec: Option<Vec<String>>,
+    /// Store trimmed stdout in this variable instead of printing it
+    capture: Option<String>,
+    /// Fail the step unless trimmed stdout equals this (e.g. "valid" for check)
+    expect: Option<String>,
+    #[serde(default)]
+    on_failure: OnFailure,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum OnFailure {
+    #[default]
+    Abort,
+    Continue,
+}
+
+pub fn run_workflow(path: &Path, overrides: &[String], verbose: bool) -> Result<(), String> {
+    let source = path.display();
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read workflow {}: {}", source, e))?;
+    let workflow: Workflow = toml::from_str(&text)
+        .map_err(|e| format!("Invalid workflow {}: {}", source, e))?;
+
+    let mut vars = workflow.vars;
+    for kv in overrides {
+        let (k, v) = kv
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid --var '{}', expected NAME=VALUE", kv))?;
+        vars.insert(k.to_string(), v.to_string());
+    }
+
+    let exe = std::env::current_exe().map_err(|e| format!("Failed to locate sodix executable: {}", e))?;
+    let mut failed = Vec::new();
+    for (i, step) in workflow.steps.iter().enumerate() {
+        let name = step.name.clone().unwrap_or_else(|| format!("step {}", i + 1));
+        let (program, args) = match (&step.run, &step.exec) {
+            (Some(args), None) => (exe.to_string_lossy().into_owned(), args.clone()),
+            (None, Some(cmd)) if !cmd.is_empty() => (cmd[0].clone(), cmd[1..].to_vec()),
+            _ => return Err(format!("Workflow {}: {} must have exactly one of 'run' or 'exec'", source, name)),
+        };
+        let program = substitute(&program, &vars)?;
+        let args = args.iter().map(|a| substitute(a, &vars)).collect::<Result<Vec<_>, _>>()?;
+        if verbose {
+            eprintln!("[{}] {} {}", name, program, args.join(" "));
+        }
+
+        let needs_stdout = step.capture.is_some() || step.expect.is_some();
+        let mut command = Command::new(&program);
+        command.args(&args).stdin(Stdio::inherit()).stderr(Stdio::inherit());
+        let result = if needs_stdout {
+            command.output().map(|o| (o.status, Some(o.stdout)))
+        } else {
+            command.status().map(|s| (s, None))
+        };
+
+        let outcome = match result {
+            Err(e) => Err(format!("failed to start {}: {}", program, e)),
+            Ok((status, _)) if !status.success() => Err(format!("exited with {}", status)),
+            Ok((_, stdout)) => {
+                let out = stdout.map(|o| String::from_utf8_lossy(&o).trim().to_string());
+                match (&step.expect, &out) {
+                    (Some(expected), Some(got)) if expected != got => {
+                        Err(format!("expected output '{}', got '{}'", expected, got))
+                    }
+                    _ => Ok(out),
+                }
+            }
+        };
+
+        match outcome {
+            Ok(out) => {
+                if let (Some(var), Some(out)) = (&step.capture, out) {
+                    vars.insert(var.clone(), out);
+                }
+            }
+            Err(e) => {
+                eprintln!("Workflow step '{}' failed: {}", name, e);
+                if step.on_failure == OnFailure::Abort {
+                    return Err(format!("Workflow {} aborted at '{}'", source, name));
+                }
+                failed.push(name);
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Workflow {} finished with failed steps: {}", source, failed.join(", ")))
+    }
+}
+
+/// Expand `${name}` references; unknown names are an error rather than an empty string.
+fn substitute(s: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unterminated variable reference in '{}'", s))?;
+        let name = &rest[start + 2..start + end];
+        let value = vars
+            .get(name)
+            .ok_or_else(|| format!("Undefined workflow variable '{}'", name))?;
+        out.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
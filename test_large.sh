@@ -0,0 +1,55 @@
+#!/bin/bash
+
+# Files over 4 GiB, and larger than RAM, through the streaming paths: encrypt and
+# decrypt in a chunked container, a range beyond 4 GiB, and sign (prehashed
+# automatically) and check, also with --no-mmap. Needs about 3 x SIZE of free disk.
+
+# Exit on any error and enable debug output
+set -e
+set -x
+
+# Check if sodix is installed
+if ! command -v sodix &> /dev/null; then
+    echo "Error: sodix is not installed. Please install it first:"
+    echo "cargo install sodix"
+    exit 1
+fi
+
+SIZE=${SIZE:-6G}
+
+# Keys and files live in a scratch directory
+rm -rf large_test
+mkdir -p large_test
+export XDG_DATA_HOME="$PWD/large_test/data"
+cd large_test
+sodix g
+
+# A sparse file with data just past 4 GiB and at its end
+echo "Creating a $SIZE file..."
+truncate -s "$SIZE" big.bin
+echo "past 4 GiB" | dd of=big.bin bs=1 seek=$((4 * 1024 * 1024 * 1024 + 100)) conv=notrunc
+echo "the end" | dd of=big.bin bs=1 seek=$(($(stat -c %s big.bin) - 8)) conv=notrunc
+ORIGINAL=$(sodix hash big.bin | cut -d' ' -f1)
+
+# Encrypt and decrypt, with memory capped far below the file size
+(
+    ulimit -v $((1024 * 1024))
+    sodix encrypt -f big.bin -q
+    mv big.bin big.bin.orig
+    sodix decrypt -f big.bin -q
+)
+DECRYPTED=$(sodix hash big.bin | cut -d' ' -f1)
+[ "$ORIGINAL" = "$DECRYPTED" ]
+
+# Random access beyond 4 GiB
+RANGE=$(sodix decrypt -f big.bin --range $((4 * 1024 * 1024 * 1024 + 100))..10)
+[ "$RANGE" = "past 4 GiB" ]
+
+# Sign and check with and without memory mapping
+sodix sign -f big.bin --sig-file
+sodix check -f big.bin --sig-file
+sodix check -f big.bin --sig-file --no-mmap
+
+cd ..
+rm -rf large_test
+echo -e "\nSuccess: $SIZE file encrypted, decrypted, signed and checked"
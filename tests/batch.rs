@@ -0,0 +1,80 @@
+// Runs over several files are all-or-nothing unless --keep-partial is given.
+#![cfg(feature = "full")]
+
+mod common;
+
+use common::{key_pair, Scratch};
+use std::fs;
+
+#[test]
+fn failed_encrypt_batch_writes_nothing() {
+    let scratch = Scratch::new("batch-encrypt");
+    let (sender_sk, _) = key_pair(1);
+    let (_, recipient_pk) = key_pair(2);
+    for name in ["a.txt", "b.txt"] {
+        fs::write(scratch.0.join(name), name).unwrap();
+    }
+    let encrypt = |extra: &[&str]| {
+        scratch
+            .sodix()
+            .current_dir(&scratch.0)
+            .args(extra)
+            .args(["encrypt", "-f", "a.txt", "b.txt", "missing.txt", "--shred", "-k", &hex::encode(recipient_pk), "-s", &hex::encode(sender_sk)])
+            .output()
+            .unwrap()
+    };
+
+    assert!(!encrypt(&[]).status.success());
+    assert!(!scratch.0.join("a.txt.x").exists());
+    assert!(!scratch.0.join("b.txt.x").exists());
+    // Nor are the inputs shredded
+    assert_eq!(fs::read(scratch.0.join("a.txt")).unwrap(), b"a.txt");
+    let leftovers: Vec<_> = fs::read_dir(&scratch.0).unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(leftovers.len(), 2, "{:?}", leftovers);
+
+    assert!(!encrypt(&["--keep-partial"]).status.success());
+    assert!(scratch.0.join("a.txt.x").exists());
+    assert!(!scratch.0.join("a.txt").exists());
+}
+
+#[test]
+fn failed_rotate_restores_rewritten_headers() {
+    let scratch = Scratch::new("batch-rotate");
+    let (own_sk, own_pk) = key_pair(1);
+    let (_, old_pk) = key_pair(2);
+    let (_, new_pk) = key_pair(3);
+    let (other_sk, _) = key_pair(4);
+    let encrypt = |name: &str, sender_sk: [u8; 32]| {
+        fs::write(scratch.0.join(name), name).unwrap();
+        let status = scratch
+            .sodix()
+            .current_dir(&scratch.0)
+            .args(["encrypt", "-f", name, "-k", &hex::encode(own_pk), "-k", &hex::encode(old_pk), "-s", &hex::encode(sender_sk)])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        fs::read(scratch.0.join(format!("{}.x", name))).unwrap()
+    };
+    let a = encrypt("a", own_sk);
+    let b = encrypt("b", own_sk);
+    // Wrapped by someone else, so our key does not open it as our own file
+    encrypt("c", other_sk);
+
+    let rotate = |files: &[&str]| {
+        scratch
+            .sodix()
+            .current_dir(&scratch.0)
+            .args(["recipients", "rotate"])
+            .args(files)
+            .args(["--old", &hex::encode(old_pk), "--new", &hex::encode(new_pk), "-s", &hex::encode(own_sk)])
+            .output()
+            .unwrap()
+    };
+    assert!(!rotate(&["a.x", "b.x", "c.x"]).status.success());
+    assert_eq!(fs::read(scratch.0.join("a.x")).unwrap(), a);
+    assert_eq!(fs::read(scratch.0.join("b.x")).unwrap(), b);
+
+    let output = rotate(&["a.x", "b.x"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_ne!(fs::read(scratch.0.join("a.x")).unwrap(), a);
+}
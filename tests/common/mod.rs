@@ -0,0 +1,40 @@
+// Helpers shared by the integration tests; each test crate uses only some of them.
+#![allow(dead_code)]
+
+use dryoc::classic::crypto_core::crypto_scalarmult_base;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// A scratch directory under the system temp dir, removed on drop; also serves as
+/// HOME, so no test touches the user's key files.
+pub struct Scratch(pub PathBuf);
+
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("sodix-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Scratch(dir)
+    }
+
+    pub fn sodix(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_sodix"));
+        command.env("HOME", &self.0).env("XDG_DATA_HOME", &self.0).env("XDG_CONFIG_HOME", &self.0).arg("-q");
+        command
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A fixed X25519 key pair as (secret, public).
+pub fn key_pair(seed: u8) -> ([u8; 32], [u8; 32]) {
+    let sk = [seed; 32];
+    let mut pk = [0u8; 32];
+    crypto_scalarmult_base(&mut pk, &sk);
+    (sk, pk)
+}
@@ -0,0 +1,44 @@
+// A name saved with one key is never pointed at another without an explicit choice.
+#![cfg(feature = "full")]
+
+mod common;
+
+use common::{key_pair, Scratch};
+use std::process::Output;
+
+#[test]
+fn contact_with_a_different_key_needs_a_choice() {
+    let scratch = Scratch::new("contacts");
+    let (_, first) = key_pair(1);
+    let (_, second) = key_pair(2);
+    let add = |key: &[u8; 32], policy: Option<&str>| -> Output {
+        let mut command = scratch.sodix();
+        command.args(["contact", "add", "bob", &hex::encode(key)]);
+        if let Some(policy) = policy {
+            command.args(["--on-conflict", policy]);
+        }
+        command.output().unwrap()
+    };
+    let saved = |expected: &Output| {
+        let shown = scratch.sodix().args(["contact", "show", "bob"]).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&shown.stdout).lines().last(), String::from_utf8_lossy(&expected.stdout).lines().last());
+    };
+
+    let original = add(&first, None);
+    assert!(original.status.success());
+    // Adding the same key again changes nothing
+    assert!(add(&first, None).status.success());
+
+    // Without a terminal to ask on, a conflict needs --on-conflict
+    let refused = add(&second, None);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("--on-conflict"));
+    assert!(!add(&second, Some("fail")).status.success());
+    assert!(add(&second, Some("keep")).status.success());
+    saved(&original);
+
+    let replaced = add(&second, Some("replace"));
+    assert!(replaced.status.success());
+    assert_ne!(replaced.stdout, original.stdout);
+    saved(&replaced);
+}
@@ -0,0 +1,156 @@
+// End-to-end tests of the encrypted file container through the sodix binary: a
+// streamed round trip fed from a generator, and random access into a sparse container
+// larger than 4 GiB, built here so the test neither writes nor encrypts gigabytes.
+#![cfg(feature = "full")]
+
+mod common;
+
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::XChaCha20Poly1305;
+use common::{key_pair, Scratch};
+use sodix::sharedkey::SharedKey;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+const TAG_LEN: u64 = 16;
+
+/// `len` pseudo-random bytes (xorshift64), produced as they are read.
+struct Generator {
+    state: u64,
+    left: u64,
+}
+
+impl Generator {
+    fn new(len: u64) -> Self {
+        Generator { state: 0x9e37_79b9_7f4a_7c15, left: len }
+    }
+}
+
+impl Read for Generator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.left as usize);
+        for byte in &mut buf[..n] {
+            self.state ^= self.state << 13;
+            self.state ^= self.state >> 7;
+            self.state ^= self.state << 17;
+            *byte = self.state as u8;
+        }
+        self.left -= n as u64;
+        Ok(n)
+    }
+}
+
+fn generated(len: u64) -> Vec<u8> {
+    let mut data = Vec::new();
+    Generator::new(len).read_to_end(&mut data).unwrap();
+    data
+}
+
+/// Run `command` with `input` streamed to its stdin from another thread.
+fn run_with_input(mut command: Command, mut input: impl Read + Send + 'static) -> Output {
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let feeder = thread::spawn(move || {
+        // A refused input closes the pipe early; the exit status tells
+        let _ = io::copy(&mut input, &mut stdin);
+    });
+    let output = child.wait_with_output().unwrap();
+    feeder.join().unwrap();
+    output
+}
+
+#[test]
+fn streamed_round_trip_and_truncation() {
+    let scratch = Scratch::new("streamed");
+    let (sender_sk, sender_pk) = key_pair(1);
+    let (recipient_sk, recipient_pk) = key_pair(2);
+    let len = 3 * CHUNK_SIZE + 1234;
+
+    let mut encrypt = scratch.sodix();
+    encrypt.args(["encrypt", "--pipe", "-k", &hex::encode(recipient_pk), "-s", &hex::encode(sender_sk)]);
+    let encrypted = run_with_input(encrypt, Generator::new(len));
+    assert!(encrypted.status.success(), "{}", String::from_utf8_lossy(&encrypted.stderr));
+    let container = encrypted.stdout;
+
+    let decrypt = |input: Vec<u8>| {
+        let mut command = scratch.sodix();
+        command.args(["decrypt", "--pipe", "-k", &hex::encode(sender_pk), "-s", &hex::encode(recipient_sk)]);
+        run_with_input(command, io::Cursor::new(input))
+    };
+    let decrypted = decrypt(container.clone());
+    assert!(decrypted.status.success(), "{}", String::from_utf8_lossy(&decrypted.stderr));
+    assert!(decrypted.stdout == generated(len));
+
+    // Cut after the third full chunk: every chunk left authenticates, but none is final
+    let cut = container.len() - (1234 + TAG_LEN as usize);
+    let truncated = decrypt(container[..cut].to_vec());
+    assert!(!truncated.status.success());
+}
+
+#[test]
+fn range_beyond_4_gib_in_a_sparse_container() {
+    let scratch = Scratch::new("sparse");
+    let (sender_sk, sender_pk) = key_pair(3);
+    let (recipient_sk, recipient_pk) = key_pair(4);
+    let file_key = [7u8; 32];
+    let base_nonce = [9u8; 16];
+    // The last chunk starts at exactly 4 GiB and is partial
+    let plaintext_len: u64 = (4 << 30) + 1000;
+    let chunk_count = plaintext_len.div_ceil(CHUNK_SIZE);
+
+    // Header with one key slot and no records, as written before the header binding
+    let box_nonce = [5u8; 24];
+    let slot = SharedKey::new(&recipient_pk, &sender_sk).seal(&file_key, &box_nonce).unwrap();
+    let header_len = 43 + 72;
+    let mut header = b"SODIXENC\x02".to_vec();
+    header.extend_from_slice(&(header_len as u32).to_be_bytes());
+    header.extend_from_slice(&(CHUNK_SIZE as u32).to_be_bytes());
+    header.extend_from_slice(&plaintext_len.to_be_bytes());
+    header.extend_from_slice(&base_nonce);
+    header.extend_from_slice(&1u16.to_be_bytes());
+    header.extend_from_slice(&box_nonce);
+    header.extend_from_slice(&slot);
+    assert_eq!(header.len(), header_len);
+
+    // Everything but the header and the two chunks around the 4 GiB mark is a hole
+    let path = scratch.0.join("big.x");
+    let mut file = File::create(&path).unwrap();
+    file.write_all(&header).unwrap();
+    file.set_len(header_len as u64 + plaintext_len + chunk_count * TAG_LEN).unwrap();
+    let cipher = XChaCha20Poly1305::new(&file_key.into());
+    let plaintext = generated(CHUNK_SIZE + 1000);
+    for index in [chunk_count - 2, chunk_count - 1] {
+        let start = (index + 2 - chunk_count) * CHUNK_SIZE;
+        let mut chunk = plaintext[start as usize..(start + CHUNK_SIZE).min(plaintext.len() as u64) as usize].to_vec();
+        let mut nonce = [0u8; 24];
+        nonce[..16].copy_from_slice(&base_nonce);
+        nonce[16..].copy_from_slice(&index.to_be_bytes());
+        let mut aad = (CHUNK_SIZE as u32).to_be_bytes().to_vec();
+        aad.extend_from_slice(&plaintext_len.to_be_bytes());
+        aad.push((index + 1 == chunk_count) as u8);
+        cipher.encrypt_in_place(&nonce.into(), &aad, &mut chunk).unwrap();
+        file.seek(SeekFrom::Start(header_len as u64 + index * (CHUNK_SIZE + TAG_LEN))).unwrap();
+        file.write_all(&chunk).unwrap();
+    }
+    drop(file);
+
+    let range = |offset: u64, len: u64| {
+        scratch
+            .sodix()
+            .args(["decrypt", "-f", path.to_str().unwrap(), "--range", &format!("{}..{}", offset, len)])
+            .args(["-k", &hex::encode(sender_pk), "-s", &hex::encode(recipient_sk)])
+            .output()
+            .unwrap()
+    };
+    // Across the 4 GiB boundary, into the final chunk
+    let output = range((4 << 30) - 100, 1100);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stdout == plaintext[CHUNK_SIZE as usize - 100..]);
+    // A chunk in the hole does not authenticate
+    assert!(!range(0, 10).status.success());
+    // Nor does reading past the end
+    assert!(!range(plaintext_len - 10, 11).status.success());
+}
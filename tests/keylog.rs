@@ -0,0 +1,55 @@
+// The key log records every change to the contacts, and `keylog verify` catches a
+// log rewritten after a head was seen and contacts changed behind its back.
+#![cfg(feature = "full")]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+use std::process::Output;
+
+#[test]
+fn changes_are_logged_and_rewrites_detected() {
+    let scratch = Scratch::new("keylog");
+    let sodix = |args: &[&str]| -> Output { scratch.sodix().args(args).output().unwrap() };
+    let fails_with = |args: &[&str], error: &str| {
+        let output = sodix(args);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(error), "{}", String::from_utf8_lossy(&output.stderr));
+    };
+    let run = |args: &[&str]| {
+        let output = sodix(args);
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let key = |digit: char| digit.to_string().repeat(64);
+
+    run(&["generate"]);
+    run(&["contact", "add", "alice", &key('1')]);
+    run(&["keylog", "init"]);
+    run(&["contact", "add", "bob", &key('2')]);
+    let seen = run(&["keylog", "checkpoint"]).trim().to_string();
+    run(&["contact", "add", "bob", &key('3'), "--on-conflict", "replace"]);
+    run(&["contact", "remove", "alice"]);
+
+    let log = scratch.0.join("sodix").join("keylog");
+    let actions: Vec<String> = run(&["keylog", "list"]).lines().map(|l| l.split('\t').nth(2).unwrap().to_string()).collect();
+    assert_eq!(actions, ["add", "add", "checkpoint", "replace", "remove"]);
+    let public_key = fs::read_to_string(scratch.0.join("sodix").join("sign_public.key")).unwrap();
+    let since = run(&["keylog", "verify", "-k", public_key.trim(), "--since", &seen]);
+    assert_eq!(since.lines().count(), 3, "two changes and the new head:\n{}", since);
+
+    // Contacts edited without going through sodix no longer match the log
+    let contacts = scratch.0.join("sodix").join("contacts.toml");
+    let saved = fs::read_to_string(&contacts).unwrap();
+    fs::write(&contacts, "").unwrap();
+    fails_with(&["keylog", "verify"], "do not match the key log");
+    fs::write(&contacts, saved).unwrap();
+
+    // A changed entry breaks the chain; a shortened log no longer extends the head seen
+    let text = fs::read_to_string(&log).unwrap();
+    fs::write(&log, text.replacen(" bob ", " eve ", 1)).unwrap();
+    fails_with(&["keylog", "verify"], "the previous hash does not match");
+    fs::write(&log, text.lines().take(2).map(|l| format!("{}\n", l)).collect::<String>()).unwrap();
+    fails_with(&["keylog", "verify", "--since", &seen], "does not extend");
+}
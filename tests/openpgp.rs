@@ -0,0 +1,31 @@
+// OpenPGP detached signatures made with the sodix signing key, checked against the
+// exported certificate and against the bare sodix public key.
+#![cfg(all(feature = "full", feature = "openpgp"))]
+
+mod common;
+
+use common::Scratch;
+use std::fs;
+
+#[test]
+fn signature_checks_against_certificate_and_key() {
+    let scratch = Scratch::new("openpgp");
+    let run = |args: &[&str]| {
+        let output = scratch.sodix().current_dir(&scratch.0).args(args).output().unwrap();
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    };
+    run(&["generate"]);
+    fs::write(scratch.0.join("app.tar"), b"release\n").unwrap();
+    run(&["sign", "--format", "openpgp", "-f", "app.tar"]);
+    assert!(fs::read_to_string(scratch.0.join("app.tar.asc")).unwrap().starts_with("-----BEGIN PGP SIGNATURE-----"));
+    run(&["key", "export", "--format", "openpgp", "-o", "pgp", "--user-id", "Alice <alice@example.com>"]);
+
+    let check = |key: &[&str]| run(&[&["check", "--format", "openpgp", "-f", "app.tar"][..], key].concat());
+    assert_eq!(check(&[]).trim(), "valid");
+    assert_eq!(check(&["-k", "pgp/sign_public.asc"]).trim(), "valid");
+
+    fs::write(scratch.0.join("app.tar"), b"tampered\n").unwrap();
+    assert_eq!(check(&[]).trim(), "invalid");
+    assert_eq!(check(&["-k", "pgp/sign_public.asc"]).trim(), "invalid");
+}